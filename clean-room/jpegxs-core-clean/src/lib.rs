@@ -28,13 +28,208 @@ pub mod markers {
     /// Fifth marker after CDT
     pub const WGT: u16 = 0xff14;
 
+    /// Slice Header - Mandatory (ISO Table A.2)
+    /// Precedes the entropy coded data of every slice
+    pub const SLH: u16 = 0xff20;
+
     /// Capabilities Marker - Mandatory (ISO Table A.2)
     /// Must be second marker after SOC
     pub const CAP: u16 = 0xff50;
+
+    /// Extension Marker - Optional (ISO Table A.2)
+    /// Comments and vendor data, may be repeated in the main header
+    pub const COM: u16 = 0xff15;
+
+    /// Nonlinearity Marker - Optional (ISO Table A.2)
+    pub const NLT: u16 = 0xff16;
+
+    /// Component-Dependent Wavelet Decomposition Marker - Optional (ISO Table A.2)
+    pub const CWD: u16 = 0xff17;
+
+    /// Colour Transformation Specification Marker - Optional (ISO Table A.2)
+    pub const CTS: u16 = 0xff18;
+
+    /// Component Registration Marker - Optional (ISO Table A.2)
+    pub const CRG: u16 = 0xff19;
+}
+
+/// Length of the PIH marker segment including the length field (ISO Table A.7)
+const PIH_LENGTH: u16 = 26;
+
+/// Length of the SLH marker segment including the length field (ISO Table A.23)
+const SLH_LENGTH: u16 = 4;
+
+/// Lprc u(24), Q[p] u(8) and R[p] u(8) ahead of the band coding modes (ISO Table C.3)
+const PRECINCT_HEADER_FIXED_LENGTH: usize = 5;
+
+/// Codestream parsing error
+///
+/// Separates a codestream cut short from one holding the wrong marker or a forbidden
+/// field value, and from a valid codestream using a feature this parser lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The data ends inside the named structure
+    Truncated(&'static str),
+    /// `marker` was found at byte `offset` where another marker is required
+    InvalidMarker { marker: u16, offset: usize },
+    /// A marker segment holds a value the standard does not allow
+    InvalidField(&'static str),
+    /// A valid value this implementation does not support
+    Unsupported(&'static str),
+}
+
+impl ParseError {
+    /// Move the offset of a [`ParseError::InvalidMarker`] from a sub-slice to the codestream
+    ///
+    /// `base` is the offset of the sub-slice, e.g. for [`read_slice_header`] errors.
+    pub fn at(self, base: usize) -> Self {
+        match self {
+            ParseError::InvalidMarker { marker, offset } => ParseError::InvalidMarker {
+                marker,
+                offset: base + offset,
+            },
+            error => error,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Truncated(what) => write!(f, "Insufficient data for {}", what),
+            ParseError::InvalidMarker { marker, offset } => {
+                write!(f, "Unexpected marker 0x{:04x} at offset {}", marker, offset)
+            }
+            ParseError::InvalidField(message) | ParseError::Unsupported(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Picture header fields carried by the PIH marker
+/// ISO A.7: image dimensions and decoder configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureHeader {
+    /// Ppih: Profile the codestream conforms to, 0 for no restrictions (ISO/IEC 21122-2)
+    pub profile: u16,
+    /// Plev: Level in the upper byte and sublevel in the lower byte, 0 for no restrictions
+    pub level: u16,
+    /// Wf: Width of image in sample grid positions
+    pub width: u16,
+    /// Hf: Height of image in sample grid positions
+    pub height: u16,
+    /// Cw: Width of a precinct in multiples of 8 * 2^NL,x, 0 for the full image width
+    pub precinct_width: u16,
+    /// Nc: Number of components
+    pub num_components: u8,
+    /// Bw: Nominal bit precision of the wavelet coefficients
+    pub wavelet_precision: u8,
+    /// Fq: Number of fractional bits of the wavelet coefficients
+    pub fractional_bits: u8,
+    /// Cpih: Colour transformation, 0 for none, 1 for the reversible RCT
+    pub color_transform: u8,
+    /// NL,x: Number of horizontal wavelet decompositions
+    pub decomposition_levels_x: u8,
+    /// NL,y: Number of vertical wavelet decompositions
+    pub decomposition_levels_y: u8,
+    /// Hsl: Height of a slice in precincts
+    pub slice_height: u16,
+}
+
+impl Default for PictureHeader {
+    fn default() -> Self {
+        Self {
+            profile: 0,
+            level: 0,
+            width: 0,
+            height: 0,
+            precinct_width: 0,
+            num_components: 3,
+            wavelet_precision: 20,
+            fractional_bits: 6,
+            color_transform: 0,
+            decomposition_levels_x: 1,
+            decomposition_levels_y: 1,
+            slice_height: 1,
+        }
+    }
+}
+
+impl PictureHeader {
+    /// Number of precincts side by side: one unless Cw limits the precinct width (ISO B.5)
+    pub fn precincts_per_row(&self) -> usize {
+        if self.precinct_width == 0 {
+            return 1;
+        }
+        let precinct_width = (8 * self.precinct_width as usize) << self.decomposition_levels_x;
+        (self.width as usize).div_ceil(precinct_width)
+    }
+
+    /// Number of precincts: a row of precincts per 2^NL,y picture lines (ISO B.5)
+    pub fn num_precincts(&self) -> usize {
+        (self.height as usize).div_ceil(1 << self.decomposition_levels_y) * self.precincts_per_row()
+    }
+
+    /// Number of slices: Hsl precinct rows each, the last slice may be shorter (ISO B.6)
+    pub fn num_slices(&self) -> usize {
+        let rows = (self.height as usize).div_ceil(1 << self.decomposition_levels_y);
+        rows.div_ceil(self.slice_height.max(1) as usize)
+    }
+
+    /// Precincts covered by a slice, in raster order
+    pub fn slice_precincts(&self, slice: usize) -> std::ops::Range<usize> {
+        let slice_precincts = self.slice_height.max(1) as usize * self.precincts_per_row();
+        let start = (slice * slice_precincts).min(self.num_precincts());
+        start..(start + slice_precincts).min(self.num_precincts())
+    }
+
+    /// Size of a precinct header: Lprc, Q[p], R[p] and 2 bits of D[p,b] per band
+    /// ISO B.2: 2 * NL,y + NL,x + 1 bands per component (NL,y <= NL,x)
+    fn precinct_header_length(&self) -> usize {
+        let bands_per_component =
+            2 * self.decomposition_levels_y as usize + self.decomposition_levels_x as usize + 1;
+        let num_bands = bands_per_component * self.num_components as usize;
+        PRECINCT_HEADER_FIXED_LENGTH + (num_bands * 2).div_ceil(8)
+    }
+}
+
+/// Component precision and sampling carried by the CDT marker
+/// ISO Table A.15: B[c] u(8), sx[c] u(4), sy[c] u(4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    /// B[c]: Bit precision of the component samples
+    pub bit_depth: u8,
+    /// sx[c]: Horizontal sampling factor, 1 or 2
+    pub sampling_x: u8,
+    /// sy[c]: Vertical sampling factor, 1 or 2
+    pub sampling_y: u8,
+}
+
+impl Default for Component {
+    fn default() -> Self {
+        Self {
+            bit_depth: 8,
+            sampling_x: 1,
+            sampling_y: 1,
+        }
+    }
+}
+
+/// Entropy coded data of one slice, located through its SLH marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slice<'a> {
+    /// Yslh: Slice index, counted from the top of the picture
+    pub index: u16,
+    /// Precincts of the slice, starting right after the SLH marker segment
+    pub data: &'a [u8],
 }
 
 /// Basic JPEG XS bitstream structure
 /// Implementation based on ISO/IEC 21122-1:2024 Section A.4.1
+#[derive(Debug, Clone)]
 pub struct JpegXsBitstream {
     data: Vec<u8>,
 }
@@ -75,57 +270,65 @@ impl JpegXsBitstream {
     /// ISO A.7: "Shall be the third marker segment after CAP"
     /// Provides image dimensions and decoder configuration
     pub fn write_pih_marker(&mut self, width: u16, height: u16, num_components: u8) {
+        self.write_picture_header(&PictureHeader {
+            width,
+            height,
+            num_components,
+            ..Default::default()
+        });
+    }
+
+    /// Write Picture Header marker from a full set of header fields
+    /// ISO Table A.7: picture header syntax
+    pub fn write_picture_header(&mut self, header: &PictureHeader) {
         let pih_bytes = markers::PIH.to_be_bytes();
         self.data.extend_from_slice(&pih_bytes);
 
         // Lpih: Size of PIH marker segment (per ISO Table A.7)
-        // Size calculation: Lcod(4) + Ppih(2) + Plev(2) + Wf(2) + Hf(2) + Cw(2) + Hsl(2) + Nc(1) + Ng(1) + Ss(1) + Bw(1) + FqBr(1) + FslcPpoc(1) + CpihReserved(1) = 25 bytes
-        let lpih: u16 = 25;
-        self.data.extend_from_slice(&lpih.to_be_bytes());
+        // Size calculation: Lpih(2) + Lcod(4) + Ppih(2) + Plev(2) + Wf(2) + Hf(2) + Cw(2) + Hsl(2)
+        // + Nc(1) + Ng(1) + Ss(1) + Bw(1) + FqBr(1) + FslcPpocCpih(1) + NlxNly(1) + LhRlQpihFsRm(1) = 26 bytes
+        self.data.extend_from_slice(&PIH_LENGTH.to_be_bytes());
 
         // Lcod: Size of entire codestream (0 for variable bitrate per ISO)
         let lcod: u32 = 0;
         self.data.extend_from_slice(&lcod.to_be_bytes());
 
         // Ppih: Profile (0 for no restrictions per ISO)
-        let ppih: u16 = 0;
-        self.data.extend_from_slice(&ppih.to_be_bytes());
+        self.data.extend_from_slice(&header.profile.to_be_bytes());
 
-        // Plev: Level (0 for no restrictions per ISO)
-        let plev: u16 = 0;
-        self.data.extend_from_slice(&plev.to_be_bytes());
+        // Plev: Level and sublevel (0 for no restrictions per ISO)
+        self.data.extend_from_slice(&header.level.to_be_bytes());
 
         // Wf: Width of image in sample grid positions
-        self.data.extend_from_slice(&width.to_be_bytes());
+        self.data.extend_from_slice(&header.width.to_be_bytes());
 
         // Hf: Height of image in sample grid positions
-        self.data.extend_from_slice(&height.to_be_bytes());
+        self.data.extend_from_slice(&header.height.to_be_bytes());
 
         // Cw: Width of precinct (0 means as wide as image per ISO)
-        let cw: u16 = 0;
-        self.data.extend_from_slice(&cw.to_be_bytes());
+        self.data
+            .extend_from_slice(&header.precinct_width.to_be_bytes());
 
-        // Hsl: Height of slice in precincts (1 for single slice)
-        let hsl: u16 = 1;
-        self.data.extend_from_slice(&hsl.to_be_bytes());
+        // Hsl: Height of slice in precincts
+        self.data
+            .extend_from_slice(&header.slice_height.to_be_bytes());
 
         // Nc: Number of components (1-8 per ISO)
-        self.data.push(num_components);
+        self.data.push(header.num_components);
 
-        // Ng: Number of coefficients per code group (8 default for 4:2:2)
-        let ng: u8 = 8;
+        // Ng: Number of coefficients per code group (4 per ISO Table A.7)
+        let ng: u8 = 4;
         self.data.push(ng);
 
-        // Ss: Number of code groups per significance group (1 default)
-        let ss: u8 = 1;
+        // Ss: Number of code groups per significance group (8 per ISO Table A.7)
+        let ss: u8 = 8;
         self.data.push(ss);
 
         // Bw: Nominal bit precision of wavelet coefficients (20 default)
-        let bw: u8 = 20;
-        self.data.push(bw);
+        self.data.push(header.wavelet_precision);
 
         // Fq: Number of fractional bits (6 default per ISO Table A.8)
-        let fq: u8 = 6; // packed with Br in next byte
+        let fq: u8 = header.fractional_bits; // packed with Br in next byte
 
         // Br: Number of bits to encode bitplane count (4 default)
         let br: u8 = 4;
@@ -135,54 +338,71 @@ impl JpegXsBitstream {
         self.data.push(fq_br);
 
         // Fslc: Slice coding mode (0 for coefficient coding per ISO)
-        let fslc: u8 = 0; // packed with Ppoc in next bytes
+        let fslc: u8 = 0; // packed with Ppoc and Cpih in next byte
 
         // Ppoc: Progression order (0 for LRCP per ISO Table A.13)
         let ppoc: u8 = 0;
 
-        // Pack Fslc (bit 7) and Ppoc (bits 6-4) and reserved bits
-        let fslc_ppoc: u8 = ((fslc & 0x01) << 7) | ((ppoc & 0x07) << 4);
-        self.data.push(fslc_ppoc);
+        // Cpih: Colour transformation (0 for none, 1 for RCT per ISO Table A.9)
+        let cpih: u8 = header.color_transform;
+
+        // Pack Fslc (bit 7), Ppoc (bits 6-4) and Cpih (bits 3-0)
+        let fslc_ppoc_cpih: u8 = ((fslc & 0x01) << 7) | ((ppoc & 0x07) << 4) | (cpih & 0x0F);
+        self.data.push(fslc_ppoc_cpih);
+
+        // NL,x: Number of horizontal decomposition levels (upper 4 bits)
+        // NL,y: Number of vertical decomposition levels (lower 4 bits)
+        let nlx_nly: u8 =
+            ((header.decomposition_levels_x & 0x0F) << 4) | (header.decomposition_levels_y & 0x0F);
+        self.data.push(nlx_nly);
+
+        // Lh: Long packet headers (1 per ISO Table C.8)
+        let lh: u8 = 1;
 
-        // Cpih: Colour transformation (0 for none per ISO Table A.9)
-        let cpih: u8 = 0;
+        // Rl: Raw-mode selection per packet (0, signalled by Dr in each packet header)
+        let rl: u8 = 0;
 
-        // Pack Cpih in upper 4 bits, lower 4 bits reserved (0)
-        let cpih_reserved: u8 = (cpih & 0x0F) << 4;
-        self.data.push(cpih_reserved);
+        // Qpih: Inverse quantizer type (1 for uniform per ISO Table A.10)
+        let qpih: u8 = 1;
+
+        // Fs: Sign handling (1 for signs in a separate sign subpacket per ISO Table A.11)
+        let fs: u8 = 1;
+
+        // Rm: Run mode (0 for zero-prediction-residual runs per ISO Table A.12)
+        let rm: u8 = 0;
+
+        // Pack Lh (bit 7), Rl (bit 6), Qpih (bits 5-4), Fs (bits 3-2) and Rm (bits 1-0)
+        let lh_rl_qpih_fs_rm: u8 =
+            (lh << 7) | (rl << 6) | ((qpih & 0x03) << 4) | ((fs & 0x03) << 2) | (rm & 0x03);
+        self.data.push(lh_rl_qpih_fs_rm);
     }
 
-    /// Write Component Table marker
+    /// Write Component Table marker for 8-bit components without subsampling
     /// ISO A.4.5: "Specifies the component precision and sampling factors"
-    /// ISO Table A.15: Component table syntax
     pub fn write_cdt_marker(&mut self, num_components: u8) {
+        self.write_component_table(&vec![Component::default(); num_components as usize]);
+    }
+
+    /// Write Component Table marker with the precision and sampling of every component
+    /// ISO Table A.15: Component table syntax
+    pub fn write_component_table(&mut self, components: &[Component]) {
         let cdt_bytes = markers::CDT.to_be_bytes();
         self.data.extend_from_slice(&cdt_bytes);
 
         // Lcdt: Size of CDT marker segment (per ISO Table A.15)
-        // Size = 2 (length) + Nc * 3 (each component: B[c] + sx[c] + sy[c])
+        // Size = 2 (length) + Nc * 2 (each component: B[c] + sx[c] + sy[c])
         // B[c] = u(8), sx[c] = u(4), sy[c] = u(4) -> sx+sy packed into 1 byte
-        let lcdt: u16 = 2 + (num_components as u16) * 2;
+        let lcdt: u16 = 2 + (components.len() as u16) * 2;
         self.data.extend_from_slice(&lcdt.to_be_bytes());
 
         // Per ISO Table A.15: Loop over components
-        for component_index in 0..num_components {
-            // B[c]: Bit precision of component (per ISO, typically 8 for YUV422p8)
-            let bit_precision: u8 = 8;
-            self.data.push(bit_precision);
-
-            // sx[c]: Horizontal sampling factor
-            // sy[c]: Vertical sampling factor
-            // Per ISO specification: "1 or 2 for components 1 and 2, 1 for all other components"
-            // For YUV422p8: Y=1x1, U=2x1, V=2x1 sampling
-            let (sx, sy) = match component_index {
-                0 => (1u8, 1u8),     // Y component: 1x1 sampling
-                1 | 2 => (2u8, 1u8), // U,V components: 2x1 sampling for 4:2:2
-                _ => (1u8, 1u8),     // Additional components: 1x1 sampling
-            };
+        for component in components {
+            // B[c]: Bit precision of component
+            self.data.push(component.bit_depth);
 
             // Pack sx (upper 4 bits) and sy (lower 4 bits) per ISO u(4) encoding
-            let sampling_factors: u8 = ((sx & 0x0F) << 4) | (sy & 0x0F);
+            let sampling_factors: u8 =
+                ((component.sampling_x & 0x0F) << 4) | (component.sampling_y & 0x0F);
             self.data.push(sampling_factors);
         }
     }
@@ -191,32 +411,52 @@ impl JpegXsBitstream {
     /// ISO A.4.12: "Contains parameters required to set the gain of each band"
     /// ISO Table A.25: Weights table syntax
     pub fn write_wgt_marker(&mut self, qp_values: Option<&[u8]>) {
-        let wgt_bytes = markers::WGT.to_be_bytes();
-        self.data.extend_from_slice(&wgt_bytes);
-
         // Default QP values if none provided (for backward compatibility)
         let default_qps = vec![8, 7, 7, 6, 6, 5, 5, 4, 6, 5];
         let qps = qp_values.unwrap_or(&default_qps);
 
+        // Fixed priority when only gains are supplied
+        let priorities = vec![128; qps.len()];
+        self.write_band_weights(qps, &priorities);
+    }
+
+    /// Write Weights Table marker with explicit per-band gains and priorities
+    /// ISO Table A.25: G[b] and P[b] for every band b
+    pub fn write_band_weights(&mut self, gains: &[u8], priorities: &[u8]) {
+        let wgt_bytes = markers::WGT.to_be_bytes();
+        self.data.extend_from_slice(&wgt_bytes);
+
         // Lwgt: Size of WGT marker segment
         // Each band has G[b] (u8) + P[b] (u8) = 2 bytes
         // Total: 2 (length) + num_bands * 2
-        let num_bands = qps.len();
+        let num_bands = gains.len().min(priorities.len());
         let lwgt: u16 = 2 + (num_bands as u16 * 2);
         self.data.extend_from_slice(&lwgt.to_be_bytes());
 
         // Per ISO Table A.25: Loop over all bands
-        // G[b]: Gain of band b (0-15 per ISO) - stores quantization parameter
+        // G[b]: Gain of band b (0-15 per ISO)
         // P[b]: Priority of band b (0-255 per ISO) - used for rate control
-        for &qp in qps {
-            // Store QP value as gain (clamped to ISO-specified range)
-            let gain = qp.min(MAX_QP_GAIN);
-            self.data.push(gain);
-            // Fixed priority for now (can be made configurable later)
-            self.data.push(128);
+        for (&gain, &priority) in gains.iter().zip(priorities) {
+            // Clamp gain to ISO-specified range
+            self.data.push(gain.min(MAX_QP_GAIN));
+            self.data.push(priority);
         }
     }
 
+    /// Write a Comment marker segment
+    /// ISO Table A.22: COM = u(16) 0xff15, Lcom = u(16), Rcom = u(16), then the comment data
+    ///
+    /// Lcom counts itself, Rcom and the data, so the data is at most 65531 bytes; the
+    /// caller checks the length.
+    pub fn write_com_marker(&mut self, rcom: u16, data: &[u8]) {
+        debug_assert!(data.len() <= MAX_COMMENT_LENGTH);
+        self.data.extend_from_slice(&markers::COM.to_be_bytes());
+        self.data
+            .extend_from_slice(&(4 + data.len() as u16).to_be_bytes());
+        self.data.extend_from_slice(&rcom.to_be_bytes());
+        self.data.extend_from_slice(data);
+    }
+
     /// Finalize bitstream with End of Codestream marker
     /// ISO A.4.2: "Shall be the last marker segment in a codestream"
     pub fn finalize(&mut self) {
@@ -234,451 +474,494 @@ impl JpegXsBitstream {
         self.data
     }
 
-    /// Add entropy coded data (enhanced implementation)
-    /// Per ISO Annex C: Quantized coefficients are entropy coded for compression
-    pub fn add_entropy_coded_data(&mut self, coefficients: &[i32]) {
-        // ISO-compliant entropy coding (enhanced features available in Commercial Edition)
-        let mut encoded_data = Vec::new();
-
-        // Enhanced entropy coding with better compression techniques
-        // 1. More aggressive quantization
-        // 2. Multi-level run-length encoding
-        // 3. Significance-based encoding
-
-        let mut i = 0;
-        while i < coefficients.len() {
-            let coeff = coefficients[i];
-
-            if coeff == 0 {
-                // Enhanced zero run-length encoding with variable length codes
-                let mut zero_count = 0;
-                while i + zero_count < coefficients.len() && coefficients[i + zero_count] == 0 {
-                    zero_count += 1;
-                    if zero_count >= 65535 {
-                        break; // Use 16-bit count for longer runs
-                    }
-                }
-
-                if zero_count < 255 {
-                    // Short run: 1 byte count
-                    encoded_data.push(0x00);
-                    encoded_data.push(zero_count as u8);
-                } else {
-                    // Long run: escape code + 2 byte count
-                    encoded_data.push(0x00);
-                    encoded_data.push(0xFF);
-                    encoded_data.extend_from_slice(&(zero_count as u16).to_be_bytes());
-                }
-                i += zero_count;
-            } else {
-                // Enhanced coefficient quantization with better precision control
-                let abs_coeff = coeff.abs();
-
-                // Log coefficient analysis for debugging
-                log::info!(
-                    "Coefficient analysis - abs_coeff: {}, tier: {}",
-                    abs_coeff,
-                    if abs_coeff <= 3 {
-                        "direct"
-                    } else if abs_coeff <= 15 {
-                        "2x_quant"
-                    } else if abs_coeff <= 127 {
-                        "4x_quant"
-                    } else {
-                        "16x_quant"
-                    }
-                );
-
-                if abs_coeff <= 3 {
-                    // Very small coefficients: direct encoding (1-3)
-                    let encoded = if coeff > 0 {
-                        abs_coeff as u8
-                    } else {
-                        (abs_coeff as u8) | 0x80
-                    };
-                    encoded_data.push(encoded);
-                } else if abs_coeff <= 15 {
-                    // Small coefficients: 4-bit quantization
-                    let quantized = ((abs_coeff + 1) / 2).min(15) as u8;
-                    let encoded = if coeff > 0 {
-                        quantized
-                    } else {
-                        quantized | 0x80
-                    };
-                    encoded_data.push(0x10 | encoded); // Escape code for 4-bit quantized
-                } else if abs_coeff <= 127 {
-                    // Medium coefficients: direct 7-bit encoding
-                    let quantized = (abs_coeff / 4).min(127) as u8;
-                    let encoded = if coeff > 0 {
-                        quantized
-                    } else {
-                        quantized | 0x80
-                    };
-                    encoded_data.push(0x20); // Escape code
-                    encoded_data.push(encoded);
-                } else {
-                    // Large coefficients: aggressive quantization
-                    let quantized = (abs_coeff / 16).min(63) as u8;
-                    let encoded = if coeff > 0 {
-                        quantized
-                    } else {
-                        quantized | 0x80
-                    };
-                    encoded_data.push(0x30); // Escape code
-                    encoded_data.push(encoded);
-                }
-                i += 1;
-            }
-        }
-
-        // Apply final compression pass: remove redundant patterns
-        let compressed_data = self.compress_final_pass(&encoded_data);
-        self.data.extend_from_slice(&compressed_data);
+    /// Take the bytes written so far, leaving the bitstream empty
+    /// Lets an encoder hand out the codestream piece by piece while it is written
+    pub fn take_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
     }
 
-    /// Final compression pass to remove patterns and redundancy
-    fn compress_final_pass(&self, data: &[u8]) -> Vec<u8> {
-        let mut compressed = Vec::new();
-        let mut i = 0;
-
-        while i < data.len() {
-            // Look for repeating byte patterns
-            if i + 3 < data.len() {
-                let pattern = &data[i..i + 2];
-                let mut count = 1;
-                let mut j = i + 2;
+    /// Append entropy coded data
+    /// ISO Annex C: precincts (precinct header followed by packets) coded by the caller
+    /// Write Slice Header marker
+    /// ISO A.4.13: "Shall precede the entropy coded data of each slice"
+    /// ISO Table A.23: SLH = u(16) 0xff20, Lslh = u(16) 4, Yslh = u(16) slice index
+    pub fn write_slice_header(&mut self, index: u16) {
+        self.data.extend_from_slice(&markers::SLH.to_be_bytes());
+        self.data.extend_from_slice(&SLH_LENGTH.to_be_bytes());
+        self.data.extend_from_slice(&index.to_be_bytes());
+    }
 
-                // Count pattern repetitions
-                while j + 1 < data.len() && data[j..j + 2] == *pattern && count < 255 {
-                    count += 1;
-                    j += 2;
-                }
+    pub fn write_entropy_coded_data(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+}
 
-                if count >= 3 {
-                    // Encode pattern repetition: 0xF0 + pattern[0] + pattern[1] + count
-                    compressed.push(0xF0);
-                    compressed.push(pattern[0]);
-                    compressed.push(pattern[1]);
-                    compressed.push(count);
-                    i = j;
-                    continue;
-                }
-            }
+/// Largest comment data a COM marker segment can carry, after Lcom and Rcom
+pub const MAX_COMMENT_LENGTH: usize = u16::MAX as usize - 4;
 
-            // No pattern found, copy byte directly
-            compressed.push(data[i]);
-            i += 1;
-        }
+/// Size of an SLH marker segment, marker included
+pub const SLICE_HEADER_SIZE: usize = SLH_LENGTH as usize + 2;
 
-        compressed
+/// Read the SLH marker segment at the start of `data`, returning the slice index Yslh
+/// ISO Table A.23: SLH = u(16) 0xff20, Lslh = u(16) 4, Yslh = u(16) slice index
+///
+/// A wrong marker is reported at offset 0 of `data`.
+pub fn read_slice_header(data: &[u8]) -> Result<u16, ParseError> {
+    if data.len() < SLICE_HEADER_SIZE {
+        return Err(ParseError::Truncated("SLH marker"));
+    }
+    let marker = u16::from_be_bytes([data[0], data[1]]);
+    if marker != markers::SLH {
+        return Err(ParseError::InvalidMarker { marker, offset: 0 });
+    }
+    if u16::from_be_bytes([data[2], data[3]]) != SLH_LENGTH {
+        return Err(ParseError::InvalidField("Invalid SLH marker length"));
     }
+    Ok(u16::from_be_bytes([data[4], data[5]]))
 }
 
-/// JPEG XS bitstream decoder
-/// Implementation based on ISO/IEC 21122-1:2024 marker parsing
-pub struct JpegXsDecoder {
-    data: Vec<u8>,
+/// Iterator over the marker segments at the start of a codestream
+/// ISO A.4: every marker segment but SOC and EOC carries its length after the marker
+///
+/// Yields `(marker, offset, payload)`: the marker code, the offset of the marker in the
+/// codestream and the bytes following the length field, empty for SOC and EOC. The
+/// entropy coded data of a slice is not made of marker segments, so iteration ends
+/// after the first SLH or EOC marker, and after the first error.
+#[derive(Debug, Clone)]
+pub struct MarkerSegmentIter<'a> {
+    data: &'a [u8],
     offset: usize,
-    width: u16,
-    height: u16,
-    num_components: u8,
-    wgt_qp_values: Vec<u8>, // Quantization parameters from WGT marker
+    done: bool,
 }
 
-impl JpegXsDecoder {
-    /// Create new decoder from bitstream data
-    pub fn new(data: Vec<u8>) -> Result<Self, &'static str> {
-        Ok(Self {
+impl<'a> MarkerSegmentIter<'a> {
+    /// Iterate over the marker segments of `data`, starting at its first byte
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
             data,
             offset: 0,
-            width: 0,
-            height: 0,
-            num_components: 0,
-            wgt_qp_values: Vec::new(),
-        })
+            done: false,
+        }
     }
 
-    /// Parse JPEG XS markers and extract image parameters
-    pub fn parse_headers(&mut self) -> Result<(), &'static str> {
-        // Parse SOC marker
-        if !self.parse_soc_marker()? {
-            return Err("Invalid SOC marker");
-        }
+    /// Offset of the next marker segment
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 
-        // Parse CAP marker
-        if !self.parse_cap_marker()? {
-            return Err("Invalid CAP marker");
+    fn read_segment(&mut self) -> Result<(u16, usize, &'a [u8]), ParseError> {
+        let offset = self.offset;
+        let data = &self.data[offset..];
+        if data.len() < 2 {
+            return Err(ParseError::Truncated("marker"));
         }
-
-        // Parse PIH marker
-        if !self.parse_pih_marker()? {
-            return Err("Invalid PIH marker");
+        let marker = u16::from_be_bytes([data[0], data[1]]);
+        if data[0] != 0xff {
+            return Err(ParseError::InvalidMarker { marker, offset });
         }
-
-        // Parse CDT marker
-        if !self.parse_cdt_marker()? {
-            return Err("Invalid CDT marker");
+        if marker == markers::SOC || marker == markers::EOC {
+            self.offset += 2;
+            return Ok((marker, offset, &[]));
         }
 
-        // Parse WGT marker
-        if !self.parse_wgt_marker()? {
-            return Err("Invalid WGT marker");
+        if data.len() < 4 {
+            return Err(ParseError::Truncated("marker segment length"));
         }
-
-        Ok(())
+        let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+        if length < 2 {
+            return Err(ParseError::InvalidField("Invalid marker segment length"));
+        }
+        if data.len() < 2 + length {
+            return Err(ParseError::Truncated("marker segment"));
+        }
+        self.offset += 2 + length;
+        Ok((marker, offset, &data[4..2 + length]))
     }
+}
 
-    /// Parse Start of Codestream marker
-    fn parse_soc_marker(&mut self) -> Result<bool, &'static str> {
-        if self.offset + 2 > self.data.len() {
-            return Err("Insufficient data for SOC marker");
-        }
+impl<'a> Iterator for MarkerSegmentIter<'a> {
+    type Item = Result<(u16, usize, &'a [u8]), ParseError>;
 
-        let marker = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        if marker != markers::SOC {
-            return Ok(false);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.offset >= self.data.len() {
+            return None;
         }
-
-        self.offset += 2;
-        Ok(true)
+        let segment = self.read_segment();
+        self.done = match segment {
+            Ok((marker, _, _)) => marker == markers::SLH || marker == markers::EOC,
+            Err(_) => true,
+        };
+        Some(segment)
     }
+}
 
-    /// Parse Capabilities marker
-    fn parse_cap_marker(&mut self) -> Result<bool, &'static str> {
-        if self.offset + 4 > self.data.len() {
-            return Err("Insufficient data for CAP marker");
-        }
+/// Parse the fields of a PIH marker segment following its length (ISO Table A.7)
+fn parse_picture_header(payload: &[u8]) -> Result<PictureHeader, ParseError> {
+    if payload.len() < PIH_LENGTH as usize - 2 {
+        return Err(ParseError::InvalidField("Invalid PIH marker length"));
+    }
+    let read_u16 = |offset: usize| u16::from_be_bytes([payload[offset], payload[offset + 1]]);
+
+    // Lcod (4 bytes) comes first
+    let header = PictureHeader {
+        // Ppih: Profile, Plev: Level and sublevel
+        profile: read_u16(4),
+        level: read_u16(6),
+        width: read_u16(8),
+        height: read_u16(10),
+        // Cw: Width of a precinct, 0 for the full image width
+        precinct_width: read_u16(12),
+        // Hsl: Height of a slice in precincts
+        slice_height: read_u16(14),
+        // Nc: Number of components (1-8 per ISO), followed by Ng and Ss
+        num_components: payload[16],
+        // Bw: Nominal wavelet coefficient precision, Fq: fractional bits (upper 4 bits)
+        wavelet_precision: payload[19],
+        fractional_bits: payload[20] >> 4,
+        // Cpih: Colour transformation (lower 4 bits)
+        color_transform: payload[21] & 0x0F,
+        // NL,x (upper 4 bits), NL,y (lower 4 bits)
+        decomposition_levels_x: payload[22] >> 4,
+        decomposition_levels_y: payload[22] & 0x0F,
+    };
+
+    if header.slice_height == 0 {
+        return Err(ParseError::InvalidField("Invalid PIH slice height"));
+    }
+    if !(1..=8).contains(&header.num_components) {
+        return Err(ParseError::InvalidField("Invalid PIH number of components"));
+    }
+    if header.fractional_bits >= header.wavelet_precision {
+        return Err(ParseError::InvalidField(
+            "Invalid PIH wavelet coefficient precision",
+        ));
+    }
+    // Only no colour transformation and the RCT are supported
+    if header.color_transform > 1 {
+        return Err(ParseError::Unsupported(
+            "Unsupported PIH colour transformation",
+        ));
+    }
+    if header.decomposition_levels_x == 0
+        || header.decomposition_levels_y > header.decomposition_levels_x
+    {
+        return Err(ParseError::InvalidField("Invalid PIH decomposition levels"));
+    }
+    Ok(header)
+}
 
-        let marker = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        if marker != markers::CAP {
-            return Ok(false);
+/// Parse the B[c] and sx[c] / sy[c] of the `num_components` components announced in
+/// the PIH from a CDT marker segment (ISO Table A.15)
+fn parse_component_table(
+    payload: &[u8],
+    num_components: usize,
+) -> Result<Vec<Component>, ParseError> {
+    if payload.len() != num_components * 2 {
+        return Err(ParseError::InvalidField(
+            "CDT length does not match the number of components",
+        ));
+    }
+    let components: Vec<Component> = payload
+        .chunks_exact(2)
+        .map(|entry| Component {
+            bit_depth: entry[0],
+            sampling_x: entry[1] >> 4,
+            sampling_y: entry[1] & 0x0F,
+        })
+        .collect();
+
+    // Only components 1 and 2 may be subsampled (ISO A.4.5)
+    for (index, component) in components.iter().enumerate() {
+        let subsampled = (component.sampling_x, component.sampling_y) != (1, 1);
+        if !matches!(component.sampling_x, 1 | 2)
+            || !matches!(component.sampling_y, 1 | 2)
+            || (subsampled && !matches!(index, 1 | 2))
+        {
+            return Err(ParseError::Unsupported("Unsupported CDT sampling factors"));
         }
-        self.offset += 2;
+    }
+    Ok(components)
+}
 
-        let length = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
+/// JPEG XS bitstream decoder
+/// Implementation based on ISO/IEC 21122-1:2024 marker parsing
+pub struct JpegXsDecoder {
+    data: Vec<u8>,
+    offset: usize,
+    picture_header: PictureHeader,
+    wgt_qp_values: Vec<u8>,  // Quantization parameters from WGT marker
+    wgt_priorities: Vec<u8>, // Band priorities from WGT marker
+    components: Vec<Component>,
+    registration: Vec<(u16, u16)>, // Component offsets from the CRG marker
+    comments: Vec<(u16, Vec<u8>)>, // Rcom and data of every COM marker
+    tolerant: bool,
+}
 
-        // Skip capabilities data (length includes the 2-byte length field itself)
-        let payload_size = length.saturating_sub(2);
-        if self.offset + payload_size as usize > self.data.len() {
-            return Err("Insufficient data for CAP payload");
-        }
-        self.offset += payload_size as usize;
+impl JpegXsDecoder {
+    /// Create new decoder from bitstream data
+    pub fn new(data: Vec<u8>) -> Result<Self, ParseError> {
+        Ok(Self {
+            data,
+            offset: 0,
+            picture_header: PictureHeader {
+                num_components: 0,
+                ..Default::default()
+            },
+            wgt_qp_values: Vec::new(),
+            wgt_priorities: Vec::new(),
+            components: Vec::new(),
+            registration: Vec::new(),
+            comments: Vec::new(),
+            tolerant: false,
+        })
+    }
 
-        Ok(true)
+    /// Recover from deviations that do not affect the coded picture
+    ///
+    /// A tolerant decoder skips marker segments it does not know ahead of and between
+    /// the slices, and accepts a codestream whose EOC marker is missing or followed by
+    /// other data. Off by default.
+    pub fn set_tolerant(&mut self, tolerant: bool) {
+        self.tolerant = tolerant;
     }
 
-    /// Parse Picture Header marker
-    fn parse_pih_marker(&mut self) -> Result<bool, &'static str> {
-        if self.offset + 4 > self.data.len() {
-            return Err("Insufficient data for PIH marker");
+    /// Parse the main header: SOC, then the marker segments up to the first slice
+    ///
+    /// The PIH, CDT and WGT marker segments must be present, in any order after SOC.
+    /// The optional COM, NLT, CWD, CTS and CRG marker segments are parsed, and marker
+    /// segments this decoder does not know are skipped by their length field. The main
+    /// header ends at the first SLH or EOC marker, or at the first byte that does not
+    /// start a marker.
+    pub fn parse_headers(&mut self) -> Result<(), ParseError> {
+        let data = &self.data;
+        if data.len() < 2 {
+            return Err(ParseError::Truncated("SOC marker"));
         }
-
-        let marker = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        if marker != markers::PIH {
-            return Ok(false);
+        let marker = u16::from_be_bytes([data[0], data[1]]);
+        if marker != markers::SOC {
+            return Err(ParseError::InvalidMarker { marker, offset: 0 });
         }
-        self.offset += 2;
 
-        let length = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
-
-        if length < 23 || self.offset + (length as usize - 2) > self.data.len() {
-            return Err("Invalid PIH marker length");
+        let mut segments = MarkerSegmentIter::new(data);
+        segments.next();
+        let mut picture_header = None;
+        let mut component_table = None;
+        let mut weights_table = None;
+        let mut registration = None;
+        let mut comments = Vec::new();
+        let mut end;
+        loop {
+            end = segments.offset();
+            if data.get(end) != Some(&0xff) {
+                break;
+            }
+            let Some(segment) = segments.next() else {
+                break;
+            };
+            let (marker, _, payload) = segment?;
+            match marker {
+                markers::SLH | markers::EOC => break,
+                markers::PIH => picture_header = Some(parse_picture_header(payload)?),
+                markers::CDT => component_table = Some(payload),
+                markers::WGT => weights_table = Some(payload),
+                markers::CRG => registration = Some(payload),
+                // Rcom u(16) ahead of the comment data (ISO A.4.11)
+                markers::COM if payload.len() < 2 => {
+                    return Err(ParseError::InvalidField("COM marker segment without Rcom"));
+                }
+                markers::COM => comments.push((
+                    u16::from_be_bytes([payload[0], payload[1]]),
+                    payload[2..].to_vec(),
+                )),
+                // The nonlinearity changes every decoded sample (ISO Annex G)
+                markers::NLT => {
+                    return Err(ParseError::Unsupported(
+                        "Unsupported NLT nonlinear transform",
+                    ));
+                }
+                // Sd > 0 suppresses the vertical decomposition of some components (ISO A.4.8)
+                markers::CWD if payload.first().is_some_and(|&sd| sd > 0) => {
+                    return Err(ParseError::Unsupported(
+                        "Unsupported CWD component-dependent decomposition",
+                    ));
+                }
+                // CAP, CTS for the unsupported Cpih = 3, and unknown markers
+                _ => {}
+            }
         }
 
-        // Skip Lcod (4 bytes), Ppih (2 bytes), Plev (2 bytes)
-        self.offset += 8;
-
-        // Extract image dimensions
-        self.width = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
-        self.height = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
-
-        // Skip Cw (2 bytes), Hsl (2 bytes)
-        self.offset += 4;
-
-        // Extract number of components
-        self.num_components = self.data[self.offset];
-        self.offset += 1;
-
-        // Skip remaining PIH data
-        self.offset += length as usize - 19;
+        self.picture_header =
+            picture_header.ok_or(ParseError::InvalidField("Missing PIH marker segment"))?;
+        let num_components = self.picture_header.num_components as usize;
+        self.components = parse_component_table(
+            component_table.ok_or(ParseError::InvalidField("Missing CDT marker segment"))?,
+            num_components,
+        )?;
+        let weights_table =
+            weights_table.ok_or(ParseError::InvalidField("Missing WGT marker segment"))?;
+        self.wgt_qp_values = weights_table.chunks_exact(2).map(|band| band[0]).collect();
+        self.wgt_priorities = weights_table.chunks_exact(2).map(|band| band[1]).collect();
+        self.registration = match registration {
+            Some(payload) if payload.len() != 4 * num_components => {
+                return Err(ParseError::InvalidField(
+                    "CRG length does not match the number of components",
+                ));
+            }
+            Some(payload) => payload
+                .chunks_exact(4)
+                .map(|entry| {
+                    (
+                        u16::from_be_bytes([entry[0], entry[1]]),
+                        u16::from_be_bytes([entry[2], entry[3]]),
+                    )
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        self.comments = comments;
+        self.offset = end;
 
-        Ok(true)
+        Ok(())
     }
 
-    /// Parse Component Table marker
-    fn parse_cdt_marker(&mut self) -> Result<bool, &'static str> {
-        if self.offset + 4 > self.data.len() {
-            return Err("Insufficient data for CDT marker");
+    /// Entropy coded data following the parsed headers
+    /// ISO A.4.2: the codestream ends with EOC, so the data spans up to the final marker
+    pub fn entropy_coded_data(&self) -> Result<&[u8], ParseError> {
+        let end = self
+            .data
+            .len()
+            .checked_sub(2)
+            .filter(|&end| end >= self.offset)
+            .ok_or(ParseError::Truncated("EOC marker"))?;
+        let marker = u16::from_be_bytes([self.data[end], self.data[end + 1]]);
+        if marker != markers::EOC {
+            return Err(ParseError::InvalidMarker {
+                marker,
+                offset: end,
+            });
         }
-
-        let marker = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        if marker != markers::CDT {
-            return Ok(false);
-        }
-        self.offset += 2;
-
-        let length = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
-
-        // Skip CDT data
-        if self.offset + (length as usize - 2) > self.data.len() {
-            return Err("Insufficient data for CDT payload");
-        }
-        self.offset += length as usize - 2;
-
-        Ok(true)
+        Ok(&self.data[self.offset..end])
     }
 
-    /// Parse Weights Table marker and extract quantization parameters
-    fn parse_wgt_marker(&mut self) -> Result<bool, &'static str> {
-        if self.offset + 4 > self.data.len() {
-            return Err("Insufficient data for WGT marker");
-        }
+    /// Locate every slice of the picture
+    /// ISO A.4.13: each slice starts with an SLH marker followed by Hsl precincts whose
+    /// Lprc fields give their sizes, so slices are found without decoding or scanning
+    pub fn slices(&self) -> Result<Vec<Slice<'_>>, ParseError> {
+        let header = &self.picture_header;
+        let precinct_header_length = header.precinct_header_length();
+
+        let mut offset = self.offset;
+        let mut slices = Vec::with_capacity(header.num_slices());
+        for slice in 0..header.num_slices() {
+            if self.tolerant {
+                offset = self.skip_unknown_segments(offset);
+            }
+            let index =
+                read_slice_header(&self.data[offset..]).map_err(|error| error.at(offset))?;
+            if index as usize != slice {
+                return Err(ParseError::InvalidField("Unexpected slice index"));
+            }
+            offset += SLICE_HEADER_SIZE;
 
-        let marker = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        if marker != markers::WGT {
-            return Ok(false);
+            let start = offset;
+            for _ in header.slice_precincts(slice) {
+                if offset + precinct_header_length > self.data.len() {
+                    return Err(ParseError::Truncated("precinct header"));
+                }
+                let lprc = u32::from_be_bytes([
+                    0,
+                    self.data[offset],
+                    self.data[offset + 1],
+                    self.data[offset + 2],
+                ]);
+                offset += precinct_header_length + lprc as usize;
+                if offset > self.data.len() {
+                    return Err(ParseError::Truncated("precinct"));
+                }
+            }
+            slices.push(Slice {
+                index,
+                data: &self.data[start..offset],
+            });
         }
-        self.offset += 2;
-
-        let length = u16::from_be_bytes([self.data[self.offset], self.data[self.offset + 1]]);
-        self.offset += 2;
 
-        // Parse WGT data to extract QP values
-        let payload_size = length as usize - 2;
-        if self.offset + payload_size > self.data.len() {
-            return Err("Insufficient data for WGT payload");
+        // The last slice must be followed by EOC and nothing else
+        if self.tolerant {
+            return Ok(slices);
         }
-
-        // Extract gain values (QP parameters) from WGT marker
-        // Each band has 2 bytes: G[b] (gain/QP) and P[b] (priority)
-        let num_bands = payload_size / 2;
-        self.wgt_qp_values.clear();
-
-        for i in 0..num_bands {
-            let gain = self.data[self.offset + i * 2]; // G[b] - quantization parameter
-            self.wgt_qp_values.push(gain);
-            // Skip P[b] (priority) - at offset + i*2 + 1
+        match self.data[offset..] {
+            [first, second] if u16::from_be_bytes([first, second]) == markers::EOC => Ok(slices),
+            [first, second, ..] => {
+                let marker = u16::from_be_bytes([first, second]);
+                if marker == markers::EOC {
+                    Err(ParseError::InvalidField("Data after the EOC marker"))
+                } else {
+                    Err(ParseError::InvalidMarker { marker, offset })
+                }
+            }
+            _ => Err(ParseError::Truncated("EOC marker")),
         }
-
-        self.offset += payload_size;
-        Ok(true)
     }
 
-    /// Decode entropy coded data using enhanced decoder
-    pub fn decode_entropy_data(&mut self) -> Result<Vec<i32>, &'static str> {
-        let mut coefficients = Vec::new();
-        let remaining_data = &self.data[self.offset..];
-
-        // Find EOC marker to determine entropy data end
-        let mut entropy_end = remaining_data.len();
-        for i in 0..remaining_data.len().saturating_sub(1) {
-            if remaining_data[i] == 0xff && remaining_data[i + 1] == 0x11 {
-                entropy_end = i;
+    /// Offset past the marker segments at `offset` that are neither SLH nor EOC
+    ///
+    /// Segments are skipped by their length field; a segment running past the end of
+    /// the data is left for the slice parser to report.
+    fn skip_unknown_segments(&self, mut offset: usize) -> usize {
+        while let [0xff, code, high, low, ..] = self.data[offset..] {
+            let marker = u16::from_be_bytes([0xff, code]);
+            let length = u16::from_be_bytes([high, low]) as usize;
+            if marker == markers::SLH
+                || marker == markers::EOC
+                || length < 2
+                || offset + 2 + length > self.data.len()
+            {
                 break;
             }
+            offset += 2 + length;
         }
+        offset
+    }
 
-        let entropy_data = &remaining_data[..entropy_end];
-        let mut i = 0;
-
-        // Decode enhanced entropy data
-        while i < entropy_data.len() {
-            let byte = entropy_data[i];
+    /// Get decoded image dimensions
+    pub fn dimensions(&self) -> (u16, u16, u8) {
+        (
+            self.picture_header.width,
+            self.picture_header.height,
+            self.picture_header.num_components,
+        )
+    }
 
-            if byte == 0x00 {
-                // Zero run-length encoding
-                i += 1;
-                if i >= entropy_data.len() {
-                    break;
-                }
+    /// Get the parsed picture header
+    pub fn picture_header(&self) -> &PictureHeader {
+        &self.picture_header
+    }
 
-                let count_byte = entropy_data[i];
-                i += 1;
-
-                if count_byte == 0xFF {
-                    // Long run: read 16-bit count
-                    if i + 1 >= entropy_data.len() {
-                        break;
-                    }
-                    let count = u16::from_be_bytes([entropy_data[i], entropy_data[i + 1]]) as usize;
-                    i += 2;
-                    coefficients.extend(vec![0; count]);
-                } else {
-                    // Short run
-                    coefficients.extend(vec![0; count_byte as usize]);
-                }
-            } else if byte == 0xF0 {
-                // Pattern repetition decompression
-                if i + 3 >= entropy_data.len() {
-                    break;
-                }
-                let pattern_0 = entropy_data[i + 1];
-                let pattern_1 = entropy_data[i + 2];
-                let count = entropy_data[i + 3];
-                i += 4;
-
-                for _ in 0..count {
-                    // Decode pattern bytes as coefficients
-                    coefficients.push(pattern_0 as i8 as i32);
-                    coefficients.push(pattern_1 as i8 as i32);
-                }
-            } else if (byte & 0xF0) == 0x10 {
-                // 4-bit quantized coefficient
-                let quantized = (byte & 0x0F) as i32;
-                let coeff = quantized * 2;
-                coefficients.push(if (byte & 0x80) != 0 { -coeff } else { coeff });
-                i += 1;
-            } else if byte == 0x20 {
-                // Medium coefficient: 7-bit encoding
-                i += 1;
-                if i >= entropy_data.len() {
-                    break;
-                }
-                let encoded = entropy_data[i];
-                let quantized = (encoded & 0x7F) as i32;
-                let coeff = quantized * 4;
-                coefficients.push(if (encoded & 0x80) != 0 { -coeff } else { coeff });
-                i += 1;
-            } else if byte == 0x30 {
-                // Large coefficient: aggressive quantization
-                i += 1;
-                if i >= entropy_data.len() {
-                    break;
-                }
-                let encoded = entropy_data[i];
-                let quantized = (encoded & 0x7F) as i32;
-                let coeff = quantized * 16;
-                coefficients.push(if (encoded & 0x80) != 0 { -coeff } else { coeff });
-                i += 1;
-            } else {
-                // Direct encoded small coefficient (1-3)
-                let abs_coeff = (byte & 0x7F) as i32;
-                coefficients.push(if (byte & 0x80) != 0 {
-                    -abs_coeff
-                } else {
-                    abs_coeff
-                });
-                i += 1;
-            }
-        }
+    /// Get the component precision and sampling factors from the CDT marker
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
 
-        Ok(coefficients)
+    /// Get the Xcrg[c] / Ycrg[c] offsets of every component from the optional CRG
+    /// marker, empty without one (ISO A.4.10)
+    pub fn component_registration(&self) -> &[(u16, u16)] {
+        &self.registration
     }
 
-    /// Get decoded image dimensions
-    pub fn dimensions(&self) -> (u16, u16, u8) {
-        (self.width, self.height, self.num_components)
+    /// Get the Rcom type and data of every COM marker, in codestream order (ISO A.4.11)
+    pub fn comments(&self) -> &[(u16, Vec<u8>)] {
+        &self.comments
     }
 
     /// Get quantization parameters from WGT marker
     pub fn get_qp_values(&self) -> &[u8] {
         &self.wgt_qp_values
     }
+
+    /// Get band priorities from WGT marker
+    pub fn get_band_priorities(&self) -> &[u8] {
+        &self.wgt_priorities
+    }
 }
 
 impl Default for JpegXsBitstream {
@@ -749,8 +1032,8 @@ mod tests {
         bitstream.write_pih_marker(256, 256, 3); // 256x256 RGB image
         let data = bitstream.data();
 
-        // Should have SOC (2) + CAP (2+2) + PIH (2+2+25) = 33 bytes
-        assert_eq!(data.len(), 33);
+        // Should have SOC (2) + CAP (2+2) + PIH (2+2+26) = 34 bytes
+        assert_eq!(data.len(), 34);
 
         // SOC marker
         assert_eq!(data[0], 0xff);
@@ -764,9 +1047,9 @@ mod tests {
         assert_eq!(data[6], 0xff);
         assert_eq!(data[7], 0x12);
 
-        // Lpih (25 bytes)
+        // Lpih (26 bytes)
         assert_eq!(data[8], 0x00);
-        assert_eq!(data[9], 0x19);
+        assert_eq!(data[9], 0x1a);
     }
 
     #[test]
@@ -777,28 +1060,56 @@ mod tests {
         bitstream.write_cdt_marker(3); // 3 components (YUV)
         let data = bitstream.data();
 
-        // Should have SOC (2) + CAP (4) + PIH (29) + CDT (8) = 43 bytes
-        assert_eq!(data.len(), 43);
+        // Should have SOC (2) + CAP (4) + PIH (30) + CDT (8) = 44 bytes
+        assert_eq!(data.len(), 44);
 
-        // CDT marker starts at offset 33
-        assert_eq!(data[33], 0xff);
-        assert_eq!(data[34], 0x13);
+        // CDT marker starts at offset 34
+        assert_eq!(data[34], 0xff);
+        assert_eq!(data[35], 0x13);
 
         // Lcdt (2 + 3*2 = 8 bytes)
-        assert_eq!(data[35], 0x00);
-        assert_eq!(data[36], 0x08);
+        assert_eq!(data[36], 0x00);
+        assert_eq!(data[37], 0x08);
 
         // Component 0 (Y): 8-bit precision, 1x1 sampling
-        assert_eq!(data[37], 0x08); // B[0] = 8 bits
-        assert_eq!(data[38], 0x11); // sx=1, sy=1 -> 0x11
+        assert_eq!(data[38], 0x08); // B[0] = 8 bits
+        assert_eq!(data[39], 0x11); // sx=1, sy=1 -> 0x11
+
+        // Components 1 and 2 (U, V): 8-bit precision, 1x1 sampling
+        assert_eq!(data[40], 0x08); // B[1] = 8 bits
+        assert_eq!(data[41], 0x11); // sx=1, sy=1 -> 0x11
+        assert_eq!(data[42], 0x08); // B[2] = 8 bits
+        assert_eq!(data[43], 0x11); // sx=1, sy=1 -> 0x11
+    }
 
-        // Component 1 (U): 8-bit precision, 2x1 sampling
-        assert_eq!(data[39], 0x08); // B[1] = 8 bits
-        assert_eq!(data[40], 0x21); // sx=2, sy=1 -> 0x21
+    #[test]
+    fn test_component_table_sampling() {
+        let chroma = Component {
+            sampling_x: 2,
+            sampling_y: 2,
+            ..Default::default()
+        };
+        let components = [Component::default(), chroma, chroma];
 
-        // Component 2 (V): 8-bit precision, 2x1 sampling
-        assert_eq!(data[41], 0x08); // B[2] = 8 bits
-        assert_eq!(data[42], 0x21); // sx=2, sy=1 -> 0x21
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(64, 32, 3);
+        bitstream.write_component_table(&components);
+        bitstream.write_band_weights(&[0; 12], &[0; 12]);
+        assert_eq!(&bitstream.data()[38..44], &[8, 0x11, 8, 0x22, 8, 0x22]);
+
+        let mut decoder = JpegXsDecoder::new(bitstream.data().to_vec()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.components(), &components);
+
+        // Luma may not be subsampled
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(64, 32, 3);
+        bitstream.write_component_table(&[chroma, chroma, chroma]);
+        bitstream.write_band_weights(&[0; 12], &[0; 12]);
+        let mut decoder = JpegXsDecoder::new(bitstream.data().to_vec()).unwrap();
+        assert!(decoder.parse_headers().is_err());
     }
 
     #[test]
@@ -810,24 +1121,24 @@ mod tests {
         bitstream.write_wgt_marker(None); // Use default QP values
         let data = bitstream.data();
 
-        // Should have SOC (2) + CAP (4) + PIH (29) + CDT (8) + WGT (24) = 67 bytes
-        assert_eq!(data.len(), 67);
+        // Should have SOC (2) + CAP (4) + PIH (30) + CDT (8) + WGT (24) = 68 bytes
+        assert_eq!(data.len(), 68);
 
-        // WGT marker starts at offset 43 (SOC:2 + CAP:4 + PIH:29 + CDT:8 = 43)
-        assert_eq!(data[43], 0xff);
-        assert_eq!(data[44], 0x14);
+        // WGT marker starts at offset 44 (SOC:2 + CAP:4 + PIH:30 + CDT:8 = 44)
+        assert_eq!(data[44], 0xff);
+        assert_eq!(data[45], 0x14);
 
-        // Lwgt (2 + 10*2 = 22 bytes) - starts at offset 45
-        assert_eq!(data[45], 0x00);
-        assert_eq!(data[46], 0x16);
+        // Lwgt (2 + 10*2 = 22 bytes) - starts at offset 46
+        assert_eq!(data[46], 0x00);
+        assert_eq!(data[47], 0x16);
 
-        // First band: gain=8, priority=128 - starts at offset 47
-        assert_eq!(data[47], 0x08);
-        assert_eq!(data[48], 0x80);
+        // First band: gain=8, priority=128 - starts at offset 48
+        assert_eq!(data[48], 0x08);
+        assert_eq!(data[49], 0x80);
 
-        // Second band: gain=7, priority=128 - starts at offset 49
-        assert_eq!(data[49], 0x07);
-        assert_eq!(data[50], 0x80);
+        // Second band: gain=7, priority=128 - starts at offset 50
+        assert_eq!(data[50], 0x07);
+        assert_eq!(data[51], 0x80);
     }
 
     #[test]
@@ -853,6 +1164,408 @@ mod tests {
         assert_eq!(extracted_qp[2], 6); // V QP
     }
 
+    #[test]
+    fn test_wgt_band_priorities() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(64, 64, 3);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_band_weights(&[3, 2, 1, 20], &[0, 1, 3, 2]);
+        bitstream.finalize();
+
+        let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+        decoder.parse_headers().unwrap();
+
+        // Gains are clamped to the ISO range, priorities are stored verbatim
+        assert_eq!(decoder.get_qp_values(), &[3, 2, 1, 15]);
+        assert_eq!(decoder.get_band_priorities(), &[0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn test_entropy_coded_data_roundtrip() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(64, 64, 3);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+
+        // Payload bytes may contain 0xff 0x11, only the final EOC ends the data
+        let payload = vec![0x00, 0x00, 0x03, 0x04, 0x07, 0xff, 0x11, 0x80];
+        bitstream.write_entropy_coded_data(&payload);
+        bitstream.finalize();
+
+        let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.entropy_coded_data().unwrap(), payload.as_slice());
+    }
+
+    #[test]
+    fn test_pih_profile_and_level() {
+        let header = PictureHeader {
+            profile: 0x3a40,
+            level: 0x2080,
+            width: 16,
+            height: 16,
+            ..Default::default()
+        };
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_picture_header(&header);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+        let data = bitstream.into_bytes();
+        // Ppih and Plev follow Lpih and Lcod
+        assert_eq!(&data[14..18], &[0x3a, 0x40, 0x20, 0x80]);
+
+        let mut decoder = JpegXsDecoder::new(data).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.picture_header(), &header);
+    }
+
+    #[test]
+    fn test_pih_colour_transform() {
+        let header = PictureHeader {
+            width: 16,
+            height: 16,
+            color_transform: 1,
+            ..Default::default()
+        };
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_picture_header(&header);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+        let mut data = bitstream.into_bytes();
+
+        let mut decoder = JpegXsDecoder::new(data.clone()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.picture_header(), &header);
+
+        // Cpih = 3 (Star-Tetrix) is not supported; Fslc/Ppoc/Cpih is PIH byte 25
+        data[31] = (data[31] & 0xF0) | 3;
+        let mut decoder = JpegXsDecoder::new(data).unwrap();
+        assert_eq!(
+            decoder.parse_headers(),
+            Err(ParseError::Unsupported(
+                "Unsupported PIH colour transformation"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_pih_wavelet_precision() {
+        let header = PictureHeader {
+            width: 16,
+            height: 16,
+            wavelet_precision: 25,
+            fractional_bits: 6,
+            ..Default::default()
+        };
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_picture_header(&header);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+        let mut data = bitstream.into_bytes();
+
+        // Bw is PIH byte 23, Fq the upper half of byte 24
+        assert_eq!(data[29], 25);
+        assert_eq!(data[30] >> 4, 6);
+        let mut decoder = JpegXsDecoder::new(data.clone()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.picture_header(), &header);
+
+        // No integer bits left when Fq >= Bw
+        data[29] = 6;
+        let mut decoder = JpegXsDecoder::new(data).unwrap();
+        assert_eq!(
+            decoder.parse_headers(),
+            Err(ParseError::InvalidField(
+                "Invalid PIH wavelet coefficient precision"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_pih_component_count() {
+        let header = PictureHeader {
+            width: 16,
+            height: 16,
+            num_components: 1,
+            ..Default::default()
+        };
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_picture_header(&header);
+        bitstream.write_cdt_marker(1);
+        bitstream.write_wgt_marker(None);
+        let mut data = bitstream.into_bytes();
+
+        // Nc is PIH byte 20
+        assert_eq!(data[26], 1);
+        let mut decoder = JpegXsDecoder::new(data.clone()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.dimensions(), (16, 16, 1));
+        assert_eq!(decoder.components(), &[Component::default()]);
+
+        for num_components in [0, 9] {
+            data[26] = num_components;
+            let mut decoder = JpegXsDecoder::new(data.clone()).unwrap();
+            assert_eq!(
+                decoder.parse_headers(),
+                Err(ParseError::InvalidField("Invalid PIH number of components"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_slices_are_located() {
+        let header = PictureHeader {
+            width: 64,
+            height: 64,
+            slice_height: 12,
+            ..Default::default()
+        };
+        assert_eq!(header.num_precincts(), 32);
+        assert_eq!(header.num_slices(), 3);
+        assert_eq!(header.slice_precincts(2), 24..32);
+
+        // Cw = 1 gives 16-sample precincts with NL,x = 1, four per precinct row
+        let columns = PictureHeader {
+            precinct_width: 1,
+            ..header.clone()
+        };
+        assert_eq!(columns.precincts_per_row(), 4);
+        assert_eq!(columns.num_precincts(), 128);
+        assert_eq!(columns.num_slices(), 3);
+        assert_eq!(columns.slice_precincts(1), 48..96);
+
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_picture_header(&header);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+
+        // Precinct bodies may contain marker-like bytes, only Lprc delimits them
+        let mut expected = Vec::new();
+        for slice in 0..header.num_slices() {
+            bitstream.write_slice_header(slice as u16);
+            let mut data = Vec::new();
+            for precinct in header.slice_precincts(slice) {
+                let body = [0xff, 0x20, 0xff, 0x11, precinct as u8];
+                let length = (precinct % 3 + 2) as u32;
+                data.extend_from_slice(&length.to_be_bytes()[1..]);
+                data.extend_from_slice(&[4, 0, 0x55, 0x55, 0x55]);
+                data.extend_from_slice(&body[..length as usize]);
+            }
+            bitstream.write_entropy_coded_data(&data);
+            expected.push(data);
+        }
+        bitstream.finalize();
+
+        let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.picture_header().slice_height, 12);
+        let slices = decoder.slices().unwrap();
+        assert_eq!(slices.len(), 3);
+        for (slice, data) in slices.iter().zip(&expected) {
+            assert_eq!(slice.data, data.as_slice());
+        }
+        assert_eq!(slices[1].index, 1);
+    }
+
+    #[test]
+    fn test_missing_slice_header_is_rejected() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(8, 2, 3);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+        bitstream.write_entropy_coded_data(&[0, 0, 0, 4, 0, 0, 0, 0]);
+        bitstream.finalize();
+
+        let data = bitstream.into_bytes();
+        let header_length = data.len() - 10;
+        let mut decoder = JpegXsDecoder::new(data).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(
+            decoder.slices(),
+            Err(ParseError::InvalidMarker {
+                marker: 0,
+                offset: header_length
+            })
+        );
+        assert_eq!(
+            decoder.slices().unwrap_err().to_string(),
+            format!("Unexpected marker 0x0000 at offset {}", header_length)
+        );
+    }
+
+    #[test]
+    fn test_unknown_segments_are_skipped() {
+        let header = PictureHeader {
+            width: 8,
+            height: 4,
+            ..Default::default()
+        };
+        let unknown = [0xff, 0x30, 0x00, 0x04, 0xab, 0xcd];
+        let precinct = [0, 0, 0, 4, 0, 0, 0, 0];
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_entropy_coded_data(&[0xff, 0x15, 0x00, 0x05, 0x00, 0x01, b'!']);
+        bitstream.write_picture_header(&header);
+        bitstream.write_entropy_coded_data(&unknown);
+        bitstream.write_cdt_marker(3);
+        bitstream.write_wgt_marker(None);
+        let header_length = bitstream.data().len();
+        for slice in 0..2 {
+            bitstream.write_slice_header(slice);
+            bitstream.write_entropy_coded_data(&precinct);
+            bitstream.write_entropy_coded_data(&unknown);
+        }
+        bitstream.finalize();
+        bitstream.write_entropy_coded_data(&[0xde, 0xad]);
+
+        // The main header parser skips unknown segments by their length
+        let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(decoder.dimensions(), (8, 4, 3));
+        assert_eq!(decoder.get_qp_values().len(), 10);
+        assert_eq!(
+            decoder.slices(),
+            Err(ParseError::InvalidMarker {
+                marker: 0xff30,
+                offset: header_length + SLICE_HEADER_SIZE + precinct.len()
+            })
+        );
+
+        // A tolerant decoder also skips them between slices and ignores what follows EOC
+        decoder.set_tolerant(true);
+        let slices = decoder.slices().unwrap();
+        assert_eq!(slices.len(), 2);
+        assert!(slices.iter().all(|slice| slice.data == precinct));
+    }
+
+    #[test]
+    fn test_marker_segment_iter() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(8, 2, 3);
+        bitstream.write_slice_header(0);
+        bitstream.write_entropy_coded_data(&[0xff, 0x11]);
+        let data = bitstream.into_bytes();
+
+        let segments: Vec<_> = MarkerSegmentIter::new(&data)
+            .map(|segment| segment.unwrap())
+            .map(|(marker, offset, payload)| (marker, offset, payload.len()))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (markers::SOC, 0, 0),
+                (markers::CAP, 2, 0),
+                (markers::PIH, 6, 24),
+                (markers::SLH, 34, 2)
+            ]
+        );
+
+        // Iteration stops at the first error
+        let mut segments = MarkerSegmentIter::new(&data[..20]);
+        assert_eq!(
+            segments.nth(2),
+            Some(Err(ParseError::Truncated("marker segment")))
+        );
+        assert_eq!(segments.next(), None);
+        let mut segments = MarkerSegmentIter::new(&data[2..]);
+        segments.next();
+        segments.next();
+        assert_eq!(segments.offset(), 32);
+        assert_eq!(
+            MarkerSegmentIter::new(&data[1..]).next(),
+            Some(Err(ParseError::InvalidMarker {
+                marker: 0x10ff,
+                offset: 0
+            }))
+        );
+    }
+
+    #[test]
+    fn test_optional_segments_are_parsed() {
+        let write_header = |segment: &[u8]| {
+            let mut bitstream = JpegXsBitstream::new();
+            bitstream.write_cap_marker();
+            bitstream.write_pih_marker(8, 2, 1);
+            bitstream.write_cdt_marker(1);
+            bitstream.write_entropy_coded_data(segment);
+            bitstream.write_wgt_marker(Some(&[1, 2, 3, 4]));
+            bitstream.finalize();
+            let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+            decoder.parse_headers().map(|_| decoder)
+        };
+
+        let decoder = write_header(&[0xff, 0x19, 0x00, 0x06, 0x80, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(decoder.component_registration(), &[(0x8000, 0)]);
+        assert_eq!(decoder.get_qp_values(), &[1, 2, 3, 4]);
+        assert!(write_header(&[0xff, 0x17, 0x00, 0x03, 0x00]).is_ok());
+        assert!(write_header(&[0xff, 0x18, 0x00, 0x04, 0x01, 0x00]).is_ok());
+
+        assert_eq!(
+            write_header(&[0xff, 0x19, 0x00, 0x02]).err(),
+            Some(ParseError::InvalidField(
+                "CRG length does not match the number of components"
+            ))
+        );
+        assert_eq!(
+            write_header(&[0xff, 0x17, 0x00, 0x03, 0x01]).err(),
+            Some(ParseError::Unsupported(
+                "Unsupported CWD component-dependent decomposition"
+            ))
+        );
+        assert!(matches!(
+            write_header(&[0xff, 0x16, 0x00, 0x05, 0x01, 0x00, 0x00]),
+            Err(ParseError::Unsupported(_))
+        ));
+        assert!(matches!(
+            write_header(&[0xff, 0x15, 0x00, 0x03, 0x00]),
+            Err(ParseError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_header_segments_are_rejected() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(8, 2, 3);
+        bitstream.write_wgt_marker(None);
+        bitstream.finalize();
+        let mut decoder = JpegXsDecoder::new(bitstream.into_bytes()).unwrap();
+        assert_eq!(
+            decoder.parse_headers(),
+            Err(ParseError::InvalidField("Missing CDT marker segment"))
+        );
+    }
+
+    #[test]
+    fn test_comments_roundtrip() {
+        let mut bitstream = JpegXsBitstream::new();
+        bitstream.write_cap_marker();
+        bitstream.write_pih_marker(8, 2, 1);
+        bitstream.write_com_marker(1, b"encoder 1.0");
+        bitstream.write_cdt_marker(1);
+        bitstream.write_wgt_marker(Some(&[1, 2, 3, 4]));
+        bitstream.write_com_marker(0xff00, &[]);
+        bitstream.finalize();
+        let data = bitstream.into_bytes();
+        assert_eq!(&data[34..40], &[0xff, 0x15, 0x00, 0x0f, 0x00, 0x01]);
+
+        let mut decoder = JpegXsDecoder::new(data).unwrap();
+        decoder.parse_headers().unwrap();
+        assert_eq!(
+            decoder.comments(),
+            &[(1, b"encoder 1.0".to_vec()), (0xff00, Vec::new())]
+        );
+    }
+
     #[test]
     fn test_complete_jpeg_xs_bitstream() {
         let mut bitstream = JpegXsBitstream::new();
@@ -862,8 +1575,8 @@ mod tests {
         bitstream.write_wgt_marker(None);
 
         // Add some test entropy data
-        let test_coefficients = vec![0, 0, 0, 15, -7, 0, 0, 23, -12, 0];
-        bitstream.add_entropy_coded_data(&test_coefficients);
+        let test_data = vec![0x00, 0x00, 0x02, 0x03, 0x00, 0x0f, 0x7c];
+        bitstream.write_entropy_coded_data(&test_data);
 
         bitstream.finalize();
         let data = bitstream.data();
@@ -881,13 +1594,13 @@ mod tests {
         assert_eq!(data[6], 0xff);
         assert_eq!(data[7], 0x12);
 
-        // CDT at offset 33
-        assert_eq!(data[33], 0xff);
-        assert_eq!(data[34], 0x13);
+        // CDT at offset 34
+        assert_eq!(data[34], 0xff);
+        assert_eq!(data[35], 0x13);
 
-        // WGT at offset 43
-        assert_eq!(data[43], 0xff);
-        assert_eq!(data[44], 0x14);
+        // WGT at offset 44
+        assert_eq!(data[44], 0xff);
+        assert_eq!(data[45], 0x14);
 
         // EOC should be at the end
         let end_offset = data.len() - 2;
@@ -907,8 +1620,8 @@ mod tests {
         bitstream.write_cdt_marker(3);
         bitstream.write_wgt_marker(None);
 
-        let test_coefficients = vec![0, 0, 15, -7, 0, 23, -12];
-        bitstream.add_entropy_coded_data(&test_coefficients);
+        let test_data = vec![0x00, 0x00, 0x02, 0x03, 0x00, 0x0f, 0x7c];
+        bitstream.write_entropy_coded_data(&test_data);
 
         bitstream.finalize();
         let data = bitstream.into_bytes();
@@ -922,7 +1635,7 @@ mod tests {
         assert_eq!(height, 256);
         assert_eq!(components, 3);
 
-        let decoded_coeffs = decoder.decode_entropy_data().unwrap();
-        assert!(!decoded_coeffs.is_empty());
+        let entropy_data = decoder.entropy_coded_data().unwrap();
+        assert_eq!(entropy_data, test_data.as_slice());
     }
 }
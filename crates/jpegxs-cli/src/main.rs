@@ -7,7 +7,7 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb};
 use log::info;
 use std::path::Path;
 
@@ -53,13 +53,57 @@ enum Commands {
         #[arg(short, long, default_value = "0.9")]
         quality: f32,
 
-        /// JPEG XS Profile (light, main, high)
+        /// JPEG XS Profile (light, main, high, or unrestricted for 4:2:0 and 16-bit input)
         #[arg(short, long, default_value = "main")]
         profile: String,
 
-        /// JPEG XS Level (1-5, availability depends on profile)
+        /// JPEG XS Level (1-4, availability depends on profile)
         #[arg(short, long, default_value = "1")]
         level: u8,
+
+        /// Horizontal wavelet decomposition levels (NL,x, 1-8)
+        #[arg(long, default_value = "5")]
+        levels_x: u8,
+
+        /// Vertical wavelet decomposition levels (NL,y, 0-6, at most NL,x)
+        #[arg(long, default_value = "2")]
+        levels_y: u8,
+
+        /// Slice height in precincts (each precinct covers 2^NL,y lines) [default: 16 lines]
+        #[arg(long)]
+        slice_height: Option<u16>,
+
+        /// Precinct width in units of 8 LL band columns (0 for the full image width)
+        #[arg(long, default_value = "0")]
+        precinct_width: u16,
+
+        /// Target bits per pixel; enables rate control and overrides --quality
+        #[arg(long, conflicts_with = "mbps")]
+        bpp: Option<f32>,
+
+        /// Target bitrate in Mbps at --frame-rate; enables rate control and overrides --quality
+        #[arg(long)]
+        mbps: Option<f32>,
+
+        /// Frame rate used to turn --mbps into a per-frame budget
+        #[arg(long, default_value = "60")]
+        frame_rate: f32,
+
+        /// Mathematically lossless coding; overrides --quality
+        #[arg(long, conflicts_with_all = ["bpp", "mbps"])]
+        lossless: bool,
+
+        /// Worker threads (0 to use one per CPU core)
+        #[arg(long, default_value = "0")]
+        threads: usize,
+
+        /// Wavelet transform backend (auto, scalar, avx2, sse4.1; auto is scalar on ARM and Apple Silicon); JPEGXS_ACCEL overrides it
+        #[arg(long, default_value = "auto")]
+        backend: String,
+
+        /// Text comment to write into the codestream as a COM marker; may be repeated
+        #[arg(long = "comment", value_name = "TEXT")]
+        comments: Vec<String>,
     },
 
     /// Decode a JPEG XS file to image format (PNG, JPEG, or raw YUV)
@@ -71,6 +115,18 @@ enum Commands {
         /// Output file (PNG, JPEG, or YUV)
         #[arg(short, long)]
         output: String,
+
+        /// Worker threads (0 to use one per CPU core)
+        #[arg(long, default_value = "0")]
+        threads: usize,
+
+        /// Wavelet transform backend (auto, scalar, avx2, sse4.1; auto is scalar on ARM and Apple Silicon); JPEGXS_ACCEL overrides it
+        #[arg(long, default_value = "auto")]
+        backend: String,
+
+        /// Reject codestreams that do not conform to ISO/IEC 21122-1
+        #[arg(long)]
+        strict: bool,
     },
 
     /// Get information about a JPEG XS file
@@ -173,34 +229,13 @@ fn rgb_to_yuv422p(rgb_data: &[u8], width: u32, height: u32) -> Vec<u8> {
     yuv_data
 }
 
-fn yuv422p_to_rgb(yuv_data: &[u8], width: u32, height: u32) -> Vec<u8> {
-    let pixels = width as usize * height as usize;
-    let mut rgb_data = Vec::with_capacity(pixels * 3);
-
-    let y_plane = &yuv_data[0..pixels];
-    let u_plane = &yuv_data[pixels..pixels + pixels / 2];
-    let v_plane = &yuv_data[pixels + pixels / 2..pixels + pixels];
-
-    for y in 0..height as usize {
-        for x in 0..width as usize {
-            let y_val = y_plane[y * width as usize + x] as f32;
-            let u_val = u_plane[y * (width as usize / 2) + x / 2] as f32 - 128.0;
-            let v_val = v_plane[y * (width as usize / 2) + x / 2] as f32 - 128.0;
-
-            // YUV to RGB conversion (ITU-R BT.601)
-            let r = (y_val + 1.402 * v_val).round().clamp(0.0, 255.0) as u8;
-            let g = (y_val - 0.34414 * u_val - 0.71414 * v_val)
-                .round()
-                .clamp(0.0, 255.0) as u8;
-            let b = (y_val + 1.772 * u_val).round().clamp(0.0, 255.0) as u8;
-
-            rgb_data.push(r);
-            rgb_data.push(g);
-            rgb_data.push(b);
-        }
+/// Hex dump of the first 32 bytes of a binary comment
+fn hex_preview(data: &[u8]) -> String {
+    let mut hex: String = data.iter().take(32).map(|b| format!("{:02x}", b)).collect();
+    if data.len() > 32 {
+        hex.push_str("...");
     }
-
-    rgb_data
+    hex
 }
 
 fn main() -> Result<()> {
@@ -228,6 +263,17 @@ fn main() -> Result<()> {
             quality,
             profile,
             level,
+            levels_x,
+            levels_y,
+            slice_height,
+            precinct_width,
+            bpp,
+            mbps,
+            frame_rate,
+            lossless,
+            threads,
+            backend,
+            comments,
         } => {
             info!("Encoding {} to {}", input, output);
 
@@ -265,6 +311,12 @@ fn main() -> Result<()> {
 
             let pixel_format = match format.as_str() {
                 "yuv422p" => jpegxs_core::types::PixelFormat::Yuv422p8,
+                "yuv422p10le" => jpegxs_core::types::PixelFormat::Yuv422p10le,
+                "yuv444p12le" => jpegxs_core::types::PixelFormat::Yuv444p12le,
+                "rgb16" => jpegxs_core::types::PixelFormat::Rgb16,
+                "gray8" => jpegxs_core::types::PixelFormat::Gray8,
+                "gray16" => jpegxs_core::types::PixelFormat::Gray16,
+                "yuva444p8" => jpegxs_core::types::PixelFormat::Yuva444p8,
                 _ => return Err(anyhow::anyhow!("Unsupported format: {}", format)),
             };
 
@@ -273,9 +325,10 @@ fn main() -> Result<()> {
                 "light" => jpegxs_core::types::Profile::Light,
                 "main" => jpegxs_core::types::Profile::Main,
                 "high" => jpegxs_core::types::Profile::High,
+                "unrestricted" => jpegxs_core::types::Profile::Unrestricted,
                 _ => {
                     return Err(anyhow::anyhow!(
-                        "Invalid profile '{}'. Valid options: light, main, high",
+                        "Invalid profile '{}'. Valid options: light, main, high, unrestricted",
                         profile
                     ))
                 }
@@ -287,10 +340,9 @@ fn main() -> Result<()> {
                 2 => jpegxs_core::types::Level::Level2,
                 3 => jpegxs_core::types::Level::Level3,
                 4 => jpegxs_core::types::Level::Level4,
-                5 => jpegxs_core::types::Level::Level5,
                 _ => {
                     return Err(anyhow::anyhow!(
-                        "Invalid level {}. Valid options: 1-5",
+                        "Invalid level {}. Valid options: 1-4",
                         level
                     ))
                 }
@@ -304,22 +356,57 @@ fn main() -> Result<()> {
                 return Err(anyhow::anyhow!("Invalid profile-level combination: {}", e));
             }
 
-            let image = jpegxs_core::types::ImageView8 {
-                data: &yuv_data,
-                width: actual_width,
-                height: actual_height,
-                format: pixel_format,
-            };
-
             // Configure encoder
             let config = jpegxs_core::types::EncoderConfig {
                 quality,
                 profile: encoder_profile,
                 level: encoder_level,
+                decomposition_levels_x: levels_x,
+                decomposition_levels_y: levels_y,
+                slice_height: slice_height.unwrap_or_else(|| {
+                    jpegxs_core::types::EncoderConfig::slice_height_for_levels(levels_y)
+                }),
+                precinct_width,
+                rate_target: match (bpp, mbps) {
+                    (Some(bpp), _) => Some(jpegxs_core::types::RateTarget::BitsPerPixel(bpp)),
+                    (None, Some(mbps)) => {
+                        Some(jpegxs_core::types::RateTarget::Mbps { mbps, frame_rate })
+                    }
+                    (None, None) => None,
+                },
+                lossless,
+                threads,
+                backend: backend.parse()?,
+                metadata: jpegxs_core::CodestreamMetadata {
+                    comments: comments
+                        .into_iter()
+                        .map(jpegxs_core::Comment::text)
+                        .collect(),
+                },
             };
 
-            // Encode
-            let bitstream = jpegxs_core::encode_frame(image, &config)?;
+            // Encode, reading high bit depth raw files as little-endian 16-bit samples
+            let bitstream = if pixel_format.bit_depth() > 8 {
+                let samples: Vec<u16> = yuv_data
+                    .chunks_exact(2)
+                    .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+                    .collect();
+                let image = jpegxs_core::types::ImageView16 {
+                    data: &samples,
+                    width: actual_width,
+                    height: actual_height,
+                    format: pixel_format,
+                };
+                jpegxs_core::encode_frame16(image, &config)?
+            } else {
+                let image = jpegxs_core::types::ImageView8 {
+                    data: &yuv_data,
+                    width: actual_width,
+                    height: actual_height,
+                    format: pixel_format,
+                };
+                jpegxs_core::encode_frame(image, &config)?
+            };
             std::fs::write(&output, &bitstream.data)?;
 
             let compression_ratio = yuv_data.len() as f32 / bitstream.data.len() as f32;
@@ -330,7 +417,13 @@ fn main() -> Result<()> {
             );
         }
 
-        Commands::Decode { input, output } => {
+        Commands::Decode {
+            input,
+            output,
+            threads,
+            backend,
+            strict,
+        } => {
             info!("Decoding {} to {}", input, output);
 
             // Load JPEG XS bitstream
@@ -341,29 +434,47 @@ fn main() -> Result<()> {
             };
 
             // Configure decoder
-            let config = jpegxs_core::types::DecoderConfig { strict_mode: false };
-
-            // Decode
-            let decoded_image = jpegxs_core::decode_frame(&bitstream, &config)?;
+            let config = jpegxs_core::types::DecoderConfig {
+                strict_mode: strict,
+                threads,
+                backend: backend.parse()?,
+            };
 
             // Detect output format
             let output_format = detect_image_format(&output)?;
 
             match output_format {
                 Some(ImageFormat::Png) | Some(ImageFormat::Jpeg) => {
-                    // Convert YUV to RGB
-                    let rgb_data = yuv422p_to_rgb(
-                        &decoded_image.data,
-                        decoded_image.width,
-                        decoded_image.height,
-                    );
-
-                    // Create RGB image
-                    let rgb_image: ImageBuffer<Rgb<u8>, Vec<u8>> =
-                        ImageBuffer::from_raw(decoded_image.width, decoded_image.height, rgb_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create RGB image buffer"))?;
-
-                    let dynamic_image = DynamicImage::ImageRgb8(rgb_image);
+                    // Single-component streams decode to grayscale, all others to RGB
+                    let info = jpegxs_core::probe_frame(&bitstream)?;
+                    let pixel_format = if info.num_components == 1 {
+                        jpegxs_core::types::PixelFormat::Gray8
+                    } else {
+                        jpegxs_core::types::PixelFormat::Rgb8
+                    };
+                    let decoded_image =
+                        jpegxs_core::decode_frame_to_format(&bitstream, &config, pixel_format)?;
+
+                    // Create the image matching the decoded format
+                    let (width, height) = (decoded_image.width, decoded_image.height);
+                    let dynamic_image = match decoded_image.format {
+                        jpegxs_core::types::PixelFormat::Gray8 => {
+                            let gray_image: ImageBuffer<Luma<u8>, Vec<u8>> =
+                                ImageBuffer::from_raw(width, height, decoded_image.data)
+                                    .ok_or_else(|| {
+                                        anyhow::anyhow!("Failed to create grayscale image buffer")
+                                    })?;
+                            DynamicImage::ImageLuma8(gray_image)
+                        }
+                        _ => {
+                            let rgb_image: ImageBuffer<Rgb<u8>, Vec<u8>> =
+                                ImageBuffer::from_raw(width, height, decoded_image.data)
+                                    .ok_or_else(|| {
+                                        anyhow::anyhow!("Failed to create RGB image buffer")
+                                    })?;
+                            DynamicImage::ImageRgb8(rgb_image)
+                        }
+                    };
 
                     // Save as PNG or JPEG
                     match output_format.unwrap() {
@@ -378,8 +489,8 @@ fn main() -> Result<()> {
 
                     println!(
                         "✅ Decoded successfully: {}x{} image saved as {} to {}",
-                        decoded_image.width,
-                        decoded_image.height,
+                        width,
+                        height,
                         match output_format.unwrap() {
                             ImageFormat::Png => "PNG",
                             ImageFormat::Jpeg => "JPEG",
@@ -389,11 +500,12 @@ fn main() -> Result<()> {
                     );
                 }
                 None => {
-                    // Save raw YUV data
+                    // Save the raw planes in the format matching the coded components
+                    let decoded_image = jpegxs_core::decode_frame(&bitstream, &config)?;
                     std::fs::write(&output, &decoded_image.data)?;
                     println!(
-                        "✅ Decoded successfully: {}x{} YUV image saved to {}",
-                        decoded_image.width, decoded_image.height, output
+                        "✅ Decoded successfully: {}x{} {:?} image saved to {}",
+                        decoded_image.width, decoded_image.height, decoded_image.format, output
                     );
                 }
                 Some(_) => {
@@ -423,25 +535,59 @@ fn main() -> Result<()> {
             println!("Size: {} bytes", bitstream_data.len());
             println!("Resolution: {}x{}", width, height);
             println!("Components: {}", num_components);
+            let header = decoder.picture_header();
+            let profile_level = jpegxs_core::profile::ProfileLevel {
+                ppih: header.profile,
+                plev: header.level,
+            };
+            println!("Profile: {}", profile_level);
 
-            // Check for markers
+            // Marker segments of the main header, up to the first slice
             println!("\nMarkers found:");
-            let markers = [
-                (0xff10u16, "SOC - Start of Codestream"),
-                (0xff50, "CAP - Capabilities"),
-                (0xff12, "PIH - Picture Header"),
-                (0xff13, "CDT - Component Table"),
-                (0xff14, "WGT - Weights Table"),
-                (0xff11, "EOC - End of Codestream"),
-            ];
-
-            for (marker_code, name) in &markers {
-                let marker_bytes = marker_code.to_be_bytes();
-                if bitstream_data
-                    .windows(2)
-                    .any(|w| w[0] == marker_bytes[0] && w[1] == marker_bytes[1])
-                {
-                    println!("  ✓ 0x{:04x} - {}", marker_code, name);
+            for segment in jpegxs_core_clean::MarkerSegmentIter::new(&bitstream_data) {
+                let (marker_code, offset, payload) =
+                    segment.map_err(|e| anyhow::anyhow!("Parse error: {}", e))?;
+                let name = match marker_code {
+                    0xff10 => "SOC - Start of Codestream",
+                    0xff11 => "EOC - End of Codestream",
+                    0xff12 => "PIH - Picture Header",
+                    0xff13 => "CDT - Component Table",
+                    0xff14 => "WGT - Weights Table",
+                    0xff15 => "COM - Extension",
+                    0xff16 => "NLT - Nonlinearity",
+                    0xff17 => "CWD - Component-Dependent Decomposition",
+                    0xff18 => "CTS - Colour Transformation Specification",
+                    0xff19 => "CRG - Component Registration",
+                    0xff20 => "SLH - Slice Header",
+                    0xff50 => "CAP - Capabilities",
+                    _ => "Unknown",
+                };
+                println!(
+                    "  ✓ 0x{:04x} - {} (offset {}, {} bytes)",
+                    marker_code,
+                    name,
+                    offset,
+                    payload.len()
+                );
+            }
+
+            if !decoder.comments().is_empty() {
+                println!("\nComments:");
+            }
+            for (rcom, data) in decoder.comments() {
+                match jpegxs_core::CommentKind::from_rcom(*rcom) {
+                    jpegxs_core::CommentKind::Text => {
+                        println!("  [text] {}", String::from_utf8_lossy(data))
+                    }
+                    jpegxs_core::CommentKind::Binary => {
+                        println!("  [binary] {} bytes: {}", data.len(), hex_preview(data))
+                    }
+                    jpegxs_core::CommentKind::Other(rcom) => println!(
+                        "  [Rcom 0x{:04x}] {} bytes: {}",
+                        rcom,
+                        data.len(),
+                        hex_preview(data)
+                    ),
                 }
             }
 
@@ -71,7 +71,10 @@ fn main() -> Result<()> {
         ..Default::default()
     };
 
-    let decoder_config = DecoderConfig { strict_mode: true };
+    let decoder_config = DecoderConfig {
+        strict_mode: true,
+        ..Default::default()
+    };
 
     // Create test runner
     let runner = ConformanceTestRunner::new()
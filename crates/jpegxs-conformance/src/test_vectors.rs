@@ -488,7 +488,7 @@ impl TestVectorGenerator {
 
         for y in 0..height {
             for x in 0..width {
-                let is_white = ((x / size) + (y / size)) % 2 == 0;
+                let is_white = ((x / size) + (y / size)).is_multiple_of(2);
                 let value = if is_white { 255 } else { 0 };
 
                 match format {
@@ -1,31 +1,92 @@
-// Unified acceleration manager for Apple Silicon
-// Provides GPU → NEON → Scalar fallback chain for optimal performance
+// Unified acceleration manager for Apple Silicon and x86_64
+// Provides GPU (Metal, then wgpu) → NEON / x86 SIMD → Scalar fallback chain for optimal performance
 
-use crate::{gpu_dwt::GpuDwt, neon_dwt::NeonDwt};
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
+#[cfg(feature = "wgpu")]
+use crate::wgpu_dwt::WgpuDwt;
+use crate::{
+    gpu_dwt::GpuDwt,
+    neon_dwt::NeonDwt,
+    x86_dwt::{X86Dwt, X86SimdLevel},
+};
+use serde::{Deserialize, Serialize};
 
 /// Unified acceleration manager for DWT operations
 /// Automatically selects the best available acceleration method
 pub struct AccelDwt {
     gpu: GpuDwt,
     neon: NeonDwt,
+    x86: X86Dwt,
+    #[cfg(feature = "wgpu")]
+    wgpu: WgpuDwt,
+    /// Method every transform uses regardless of image size, see [`AccelDwt::with_preference`]
+    pinned: Option<AccelMethod>,
 }
 
 impl AccelDwt {
     /// Initialize unified acceleration with all available methods
     pub fn new() -> Self {
-        log::info!("Initializing Apple Silicon Unified Acceleration");
-        let gpu = GpuDwt::new();
-        let neon = NeonDwt::new();
+        log::info!("Initializing Unified DWT Acceleration");
+        let accel = Self {
+            gpu: GpuDwt::new(),
+            neon: NeonDwt::new(),
+            x86: X86Dwt::new(),
+            #[cfg(feature = "wgpu")]
+            wgpu: WgpuDwt::new(),
+            pinned: None,
+        };
+        accel.print_acceleration_status();
+        accel
+    }
 
-        Self::print_acceleration_status(&gpu, &neon);
+    /// Initialize acceleration restricted to `preference`
+    ///
+    /// [`AccelPreference::Auto`] keeps the size-based selection of [`AccelDwt::new`];
+    /// any other preference pins every transform to that back-end, which fails here if
+    /// the machine lacks it.
+    pub fn with_preference(preference: AccelPreference) -> Result<Self> {
+        let mut accel = Self::new();
+        accel.pinned = match preference {
+            AccelPreference::Auto => None,
+            AccelPreference::Scalar => Some(AccelMethod::Scalar),
+            AccelPreference::Avx2 | AccelPreference::Sse41 => {
+                accel.x86 = preference
+                    .x86_level()
+                    .and_then(X86Dwt::with_level)
+                    .ok_or_else(|| preference.unavailable())?;
+                Some(AccelMethod::X86Simd)
+            }
+        };
+        Ok(accel)
+    }
 
-        Self { gpu, neon }
+    /// Initialize acceleration pinned to `method`, including the NEON and GPU methods
+    /// that have no counterpart for the codec in [`AccelPreference`]
+    ///
+    /// Pinning wgpu accepts a software adapter, which is how CI exercises the GPU path.
+    pub fn with_method(method: AccelMethod) -> Result<Self> {
+        let mut accel = Self::new();
+        let available = match method {
+            AccelMethod::Gpu => accel.gpu.is_available(),
+            AccelMethod::Wgpu => accel.wgpu_available(),
+            AccelMethod::Neon => accel.neon.is_available(),
+            AccelMethod::X86Simd => accel.x86.is_available(),
+            AccelMethod::Scalar => true,
+        };
+        if !available {
+            return Err(JpegXsError::UnsupportedFeature(format!(
+                "{:?} acceleration was requested but is not available on this machine",
+                method
+            )));
+        }
+        accel.pinned = Some(method);
+        Ok(accel)
     }
 
     /// Print available acceleration methods
-    fn print_acceleration_status(gpu: &GpuDwt, neon: &NeonDwt) {
-        log::info!("=== Apple Silicon Acceleration Status ===");
+    fn print_acceleration_status(&self) {
+        let (gpu, neon, x86) = (&self.gpu, &self.neon, &self.x86);
+        log::info!("=== DWT Acceleration Status ===");
         log::info!(
             "GPU (Metal):     {}",
             if gpu.is_available() {
@@ -34,6 +95,16 @@ impl AccelDwt {
                 "❌ Not Available"
             }
         );
+        log::info!(
+            "GPU (wgpu):      {}",
+            if self.wgpu_hardware() {
+                "✅ Available"
+            } else if self.wgpu_available() {
+                "⚠️  Software adapter only"
+            } else {
+                "❌ Not Available"
+            }
+        );
         log::info!(
             "NEON (ARM SIMD): {}",
             if neon.is_available() {
@@ -42,11 +113,23 @@ impl AccelDwt {
                 "❌ Not Available"
             }
         );
+        log::info!(
+            "x86 SIMD:        {}",
+            match x86.level() {
+                Some(crate::x86_dwt::X86SimdLevel::Avx2) => "✅ Available (AVX2)",
+                Some(crate::x86_dwt::X86SimdLevel::Sse41) => "✅ Available (SSE4.1)",
+                None => "❌ Not Available",
+            }
+        );
 
         let method = if gpu.is_available() {
             "Metal GPU (130x speedup target)"
+        } else if self.wgpu_hardware() {
+            "wgpu GPU compute"
         } else if neon.is_available() {
             "ARM NEON SIMD (2-3x speedup target)"
+        } else if x86.is_available() {
+            "x86 AVX2/SSE4.1 SIMD"
         } else {
             "Scalar fallback"
         };
@@ -60,16 +143,60 @@ impl AccelDwt {
         AccelCapabilities {
             gpu_available: self.gpu.is_available(),
             neon_available: self.neon.is_available(),
+            x86_simd_available: self.x86.is_available(),
+            wgpu_available: self.wgpu_available(),
             primary_method: self.get_primary_method(),
         }
     }
 
     /// Determine the best acceleration method for given dimensions
     fn get_primary_method(&self) -> AccelMethod {
+        self.gpu_method().unwrap_or_else(|| self.cpu_method())
+    }
+
+    /// GPU method used automatically: Metal, else wgpu on a hardware adapter
+    ///
+    /// A software wgpu adapter is slower than the CPU paths, so it only runs when pinned.
+    fn gpu_method(&self) -> Option<AccelMethod> {
         if self.gpu.is_available() {
-            AccelMethod::Gpu
-        } else if self.neon.is_available() {
+            Some(AccelMethod::Gpu)
+        } else if self.wgpu_hardware() {
+            Some(AccelMethod::Wgpu)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the wgpu back-end found any adapter, software ones included
+    fn wgpu_available(&self) -> bool {
+        #[cfg(feature = "wgpu")]
+        {
+            self.wgpu.is_available()
+        }
+        #[cfg(not(feature = "wgpu"))]
+        {
+            false
+        }
+    }
+
+    /// Whether the wgpu back-end runs on GPU hardware
+    fn wgpu_hardware(&self) -> bool {
+        #[cfg(feature = "wgpu")]
+        {
+            self.wgpu.is_available() && !self.wgpu.is_software()
+        }
+        #[cfg(not(feature = "wgpu"))]
+        {
+            false
+        }
+    }
+
+    /// Fastest CPU method: the SIMD extension of the host architecture, if any
+    fn cpu_method(&self) -> AccelMethod {
+        if self.neon.is_available() {
             AccelMethod::Neon
+        } else if self.x86.is_available() {
+            AccelMethod::X86Simd
         } else {
             AccelMethod::Scalar
         }
@@ -78,22 +205,24 @@ impl AccelDwt {
     /// Intelligent method selection based on image size and hardware
     /// For smaller images, NEON might be faster due to GPU overhead
     fn select_method(&self, width: u32, height: u32) -> AccelMethod {
+        if let Some(method) = self.pinned {
+            return method;
+        }
         let pixel_count = width * height;
 
         // Determine optimal method based on image size and available acceleration
-        if self.gpu.is_available() {
+        let cpu_method = self.cpu_method();
+        if let Some(gpu_method) = self.gpu_method() {
             // GPU is generally better for larger images
-            // For very small images (< 512x512), NEON might be competitive
-            if pixel_count >= 512 * 512 || !self.neon.is_available() {
-                AccelMethod::Gpu
+            // For very small images (< 512x512), CPU SIMD might be competitive
+            if pixel_count >= 512 * 512 || matches!(cpu_method, AccelMethod::Scalar) {
+                gpu_method
             } else {
-                // For smaller images, prefer NEON if available
-                AccelMethod::Neon
+                // For smaller images, prefer CPU SIMD if available
+                cpu_method
             }
-        } else if self.neon.is_available() {
-            AccelMethod::Neon
         } else {
-            AccelMethod::Scalar
+            cpu_method
         }
     }
 
@@ -112,11 +241,19 @@ impl AccelDwt {
                 log::debug!("Using GPU acceleration for {}x{} DWT", width, height);
                 self.gpu.dwt_53_forward_2d_gpu(input, output, width, height)
             }
+            AccelMethod::Wgpu => {
+                log::debug!("Using wgpu acceleration for {}x{} DWT", width, height);
+                self.wgpu_forward(input, output, width, height)
+            }
             AccelMethod::Neon => {
                 log::debug!("Using NEON acceleration for {}x{} DWT", width, height);
                 self.neon
                     .dwt_53_forward_2d_neon(input, output, width, height)
             }
+            AccelMethod::X86Simd => {
+                log::debug!("Using x86 SIMD acceleration for {}x{} DWT", width, height);
+                self.x86.dwt_53_forward_2d_x86(input, output, width, height)
+            }
             AccelMethod::Scalar => {
                 log::debug!("Using scalar implementation for {}x{} DWT", width, height);
                 crate::dwt::dwt_53_forward_2d(input, output, width, height)
@@ -143,6 +280,14 @@ impl AccelDwt {
                 );
                 self.gpu.dwt_53_inverse_2d_gpu(input, output, width, height)
             }
+            AccelMethod::Wgpu => {
+                log::debug!(
+                    "Using wgpu acceleration for {}x{} inverse DWT",
+                    width,
+                    height
+                );
+                self.wgpu_inverse(input, output, width, height)
+            }
             AccelMethod::Neon => {
                 log::debug!(
                     "Using NEON acceleration for {}x{} inverse DWT",
@@ -152,6 +297,14 @@ impl AccelDwt {
                 self.neon
                     .dwt_53_inverse_2d_neon(input, output, width, height)
             }
+            AccelMethod::X86Simd => {
+                log::debug!(
+                    "Using x86 SIMD acceleration for {}x{} inverse DWT",
+                    width,
+                    height
+                );
+                self.x86.dwt_53_inverse_2d_x86(input, output, width, height)
+            }
             AccelMethod::Scalar => {
                 log::debug!(
                     "Using scalar implementation for {}x{} inverse DWT",
@@ -163,6 +316,126 @@ impl AccelDwt {
         }
     }
 
+    /// wgpu forward transform, the scalar one in builds without the `wgpu` feature
+    fn wgpu_forward(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        #[cfg(feature = "wgpu")]
+        {
+            self.wgpu
+                .dwt_53_forward_2d_wgpu(input, output, width, height)
+        }
+        #[cfg(not(feature = "wgpu"))]
+        {
+            crate::dwt::dwt_53_forward_2d(input, output, width, height)
+        }
+    }
+
+    /// wgpu inverse transform, the scalar one in builds without the `wgpu` feature
+    fn wgpu_inverse(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        #[cfg(feature = "wgpu")]
+        {
+            self.wgpu
+                .dwt_53_inverse_2d_wgpu(input, output, width, height)
+        }
+        #[cfg(not(feature = "wgpu"))]
+        {
+            crate::dwt::dwt_53_inverse_2d(input, output, width, height)
+        }
+    }
+
+    /// Multi-level forward DWT with separate horizontal and vertical decomposition counts
+    ///
+    /// The first `levels_y` levels apply the accelerated 2D transform to the current LL
+    /// region; the remaining `levels_x - levels_y` levels only split rows, as in
+    /// ISO/IEC 21122-1:2024 Annex E. Output uses the usual Mallat layout.
+    pub fn dwt_53_forward_multilevel(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+
+        output.copy_from_slice(input);
+        let dims = crate::dwt::decomposition_dims(width, height, levels_x, levels_y);
+        let stride = width as usize;
+
+        for level in 1..=levels_x {
+            let (w, h) = dims[level as usize - 1];
+            if level <= levels_y {
+                let region = extract_region(output, stride, w, h);
+                let mut transformed = vec![0.0f32; region.len()];
+                self.dwt_53_forward_2d(&region, &mut transformed, w, h)?;
+                store_region(output, stride, w, h, &transformed);
+            } else {
+                for y in 0..h as usize {
+                    let row_start = y * stride;
+                    crate::dwt::dwt_53_forward_1d(&mut output[row_start..row_start + w as usize]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Multi-level inverse DWT matching [`AccelDwt::dwt_53_forward_multilevel`]
+    pub fn dwt_53_inverse_multilevel(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+
+        output.copy_from_slice(input);
+        let dims = crate::dwt::decomposition_dims(width, height, levels_x, levels_y);
+        let stride = width as usize;
+
+        for level in (1..=levels_x).rev() {
+            let (w, h) = dims[level as usize - 1];
+            if level <= levels_y {
+                let region = extract_region(output, stride, w, h);
+                let mut reconstructed = vec![0.0f32; region.len()];
+                self.dwt_53_inverse_2d(&region, &mut reconstructed, w, h)?;
+                store_region(output, stride, w, h, &reconstructed);
+            } else {
+                for y in 0..h as usize {
+                    let row_start = y * stride;
+                    crate::dwt::dwt_53_inverse_1d(&mut output[row_start..row_start + w as usize]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Force use of specific acceleration method (for testing/benchmarking)
     pub fn dwt_53_forward_2d_method(
         &self,
@@ -174,9 +447,11 @@ impl AccelDwt {
     ) -> Result<()> {
         match method {
             AccelMethod::Gpu => self.gpu.dwt_53_forward_2d_gpu(input, output, width, height),
+            AccelMethod::Wgpu => self.wgpu_forward(input, output, width, height),
             AccelMethod::Neon => self
                 .neon
                 .dwt_53_forward_2d_neon(input, output, width, height),
+            AccelMethod::X86Simd => self.x86.dwt_53_forward_2d_x86(input, output, width, height),
             AccelMethod::Scalar => crate::dwt::dwt_53_forward_2d(input, output, width, height),
         }
     }
@@ -192,9 +467,11 @@ impl AccelDwt {
     ) -> Result<()> {
         match method {
             AccelMethod::Gpu => self.gpu.dwt_53_inverse_2d_gpu(input, output, width, height),
+            AccelMethod::Wgpu => self.wgpu_inverse(input, output, width, height),
             AccelMethod::Neon => self
                 .neon
                 .dwt_53_inverse_2d_neon(input, output, width, height),
+            AccelMethod::X86Simd => self.x86.dwt_53_inverse_2d_x86(input, output, width, height),
             AccelMethod::Scalar => crate::dwt::dwt_53_inverse_2d(input, output, width, height),
         }
     }
@@ -206,7 +483,7 @@ impl AccelDwt {
         let size = (width * height) as usize;
         let input: Vec<f32> = (0..size).map(|i| (i as f32 * 0.1).sin()).collect();
 
-        println!("\n=== Comprehensive Apple Silicon DWT Benchmark ===");
+        println!("\n=== Comprehensive DWT Benchmark ===");
         println!("Image size: {}x{} ({} pixels)", width, height, size);
         println!("Test pattern: Sine wave for accuracy validation");
 
@@ -246,6 +523,33 @@ impl AccelDwt {
             println!("NEON SIMD:    Not available");
         }
 
+        // x86 SIMD benchmark (if available)
+        if let Some(level) = self.x86.level() {
+            let mut x86_output = vec![0.0f32; size];
+            let x86_start = Instant::now();
+            self.x86
+                .dwt_53_forward_2d_x86(&input, &mut x86_output, width, height)?;
+            let x86_time = x86_start.elapsed();
+            let x86_speedup = scalar_time.as_secs_f64() / x86_time.as_secs_f64();
+
+            println!(
+                "x86 {:?}:     {:.2}ms ({:.2}x speedup)",
+                level,
+                x86_time.as_secs_f64() * 1000.0,
+                x86_speedup
+            );
+
+            // Verify accuracy
+            let max_error = scalar_output
+                .iter()
+                .zip(x86_output.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0f32, |acc, x| acc.max(x));
+            println!("x86 accuracy: {:.6} max error", max_error);
+        } else {
+            println!("x86 SIMD:     Not available");
+        }
+
         // GPU benchmark (if available)
         if self.gpu.is_available() {
             let mut gpu_output = vec![0.0f32; size];
@@ -272,6 +576,36 @@ impl AccelDwt {
             println!("Metal GPU:    Not available");
         }
 
+        // wgpu benchmark (if an adapter was found, software ones included)
+        #[cfg(feature = "wgpu")]
+        if let Some(adapter) = self.wgpu.adapter_name() {
+            let mut wgpu_output = vec![0.0f32; size];
+            let wgpu_start = Instant::now();
+            self.wgpu
+                .dwt_53_forward_2d_wgpu(&input, &mut wgpu_output, width, height)?;
+            let wgpu_time = wgpu_start.elapsed();
+            let wgpu_speedup = scalar_time.as_secs_f64() / wgpu_time.as_secs_f64();
+
+            println!(
+                "wgpu GPU:     {:.2}ms ({:.2}x speedup) on {}",
+                wgpu_time.as_secs_f64() * 1000.0,
+                wgpu_speedup,
+                adapter
+            );
+
+            // Verify accuracy
+            let max_error = scalar_output
+                .iter()
+                .zip(wgpu_output.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0f32, |acc, x| acc.max(x));
+            println!("wgpu accuracy: {:.6} max error", max_error);
+        } else {
+            println!("wgpu GPU:     Not available");
+        }
+        #[cfg(not(feature = "wgpu"))]
+        println!("wgpu GPU:     Not built (enable the `wgpu` feature)");
+
         println!(
             "Optimal method for {}x{}: {:?}",
             width,
@@ -284,6 +618,22 @@ impl AccelDwt {
     }
 }
 
+/// Copy the top-left `w`x`h` region of a strided plane into a contiguous buffer
+fn extract_region(plane: &[f32], stride: usize, w: u32, h: u32) -> Vec<f32> {
+    let mut region = Vec::with_capacity((w * h) as usize);
+    for y in 0..h as usize {
+        region.extend_from_slice(&plane[y * stride..y * stride + w as usize]);
+    }
+    region
+}
+
+/// Write a contiguous `w`x`h` buffer back into the top-left region of a strided plane
+fn store_region(plane: &mut [f32], stride: usize, w: u32, h: u32, region: &[f32]) {
+    for (y, row) in region.chunks_exact(w as usize).take(h as usize).enumerate() {
+        plane[y * stride..y * stride + w as usize].copy_from_slice(row);
+    }
+}
+
 impl Default for AccelDwt {
     fn default() -> Self {
         Self::new()
@@ -293,9 +643,203 @@ impl Default for AccelDwt {
 /// Available acceleration methods
 #[derive(Debug, Clone, Copy)]
 pub enum AccelMethod {
+    /// Metal compute on Apple Silicon
     Gpu,
+    /// Portable compute through wgpu, with the `wgpu` feature
+    Wgpu,
     Neon,
+    /// AVX2 or SSE4.1, whichever the CPU supports
+    X86Simd,
+    Scalar,
+}
+
+/// Acceleration back-end requested by a configuration
+///
+/// Only the scalar and x86 SIMD back-ends implement the integer 5/3 transform of the
+/// codec; the NEON and GPU back-ends only accelerate the f32 transforms of [`AccelDwt`],
+/// see [`AccelDwt::with_method`]. The `JPEGXS_ACCEL` environment variable overrides the
+/// configured value, see [`AccelPreference::with_env_override`].
+///
+/// On aarch64, Apple Silicon included, the codec therefore runs the scalar transform:
+/// [`AccelPreference::Auto`] resolves to [`AccelPreference::Scalar`], and `neon`, `gpu`
+/// or `metal` are rejected as unknown back-ends, by the CLI and by `JPEGXS_ACCEL` alike.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccelPreference {
+    /// Fastest back-end available on this machine, scalar on everything but x86_64
+    #[default]
+    Auto,
+    /// Portable scalar code, the reference output on every platform
+    Scalar,
+    /// x86_64 AVX2 SIMD
+    Avx2,
+    /// x86_64 SSE4.1 SIMD
+    Sse41,
+}
+
+/// Environment variable overriding the configured [`AccelPreference`]
+pub const ACCEL_ENV_VAR: &str = "JPEGXS_ACCEL";
+
+impl AccelPreference {
+    /// Every preference, in the order they are listed to users
+    pub const ALL: [AccelPreference; 4] = [
+        AccelPreference::Auto,
+        AccelPreference::Scalar,
+        AccelPreference::Avx2,
+        AccelPreference::Sse41,
+    ];
+
+    /// Name used by the CLI and `JPEGXS_ACCEL`
+    pub fn name(self) -> &'static str {
+        match self {
+            AccelPreference::Auto => "auto",
+            AccelPreference::Scalar => "scalar",
+            AccelPreference::Avx2 => "avx2",
+            AccelPreference::Sse41 => "sse4.1",
+        }
+    }
+
+    /// The preference in effect: `JPEGXS_ACCEL` when it is set, `self` otherwise
+    pub fn with_env_override(self) -> Result<Self> {
+        self.with_override(std::env::var(ACCEL_ENV_VAR).ok().as_deref())
+    }
+
+    fn with_override(self, value: Option<&str>) -> Result<Self> {
+        match value.map(str::trim) {
+            Some(value) if !value.is_empty() => value.parse().map_err(|e| {
+                JpegXsError::InvalidParameter(format!("Invalid {}: {}", ACCEL_ENV_VAR, e))
+            }),
+            _ => Ok(self),
+        }
+    }
+
+    fn x86_level(self) -> Option<X86SimdLevel> {
+        match self {
+            AccelPreference::Avx2 => Some(X86SimdLevel::Avx2),
+            AccelPreference::Sse41 => Some(X86SimdLevel::Sse41),
+            _ => None,
+        }
+    }
+
+    fn unavailable(self) -> JpegXsError {
+        JpegXsError::UnsupportedFeature(format!(
+            "Acceleration backend '{}' was requested but is not available on this machine",
+            self
+        ))
+    }
+}
+
+impl std::fmt::Display for AccelPreference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for AccelPreference {
+    type Err = JpegXsError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let name = name.to_ascii_lowercase();
+        let canonical = if name == "sse41" { "sse4.1" } else { &name };
+        Self::ALL
+            .into_iter()
+            .find(|preference| preference.name() == canonical)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL
+                    .iter()
+                    .map(|preference| preference.name())
+                    .collect();
+                JpegXsError::InvalidParameter(format!(
+                    "Unknown acceleration backend '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// Back-end of the integer 5/3 transform of the codec
+///
+/// Every back-end produces the same coefficients, so the choice never changes a
+/// codestream or a decoded image, only the time it takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecDwt {
     Scalar,
+    X86Simd(X86SimdLevel),
+}
+
+impl CodecDwt {
+    /// Resolve a configured preference, after the `JPEGXS_ACCEL` override
+    ///
+    /// Fails with a clear error if the requested back-end is missing on this machine.
+    pub fn select(preference: AccelPreference) -> Result<Self> {
+        let preference = preference.with_env_override()?;
+        match preference {
+            AccelPreference::Auto => Ok([X86SimdLevel::Avx2, X86SimdLevel::Sse41]
+                .into_iter()
+                .find(|&level| X86Dwt::supports(level))
+                .map_or(Self::Scalar, Self::X86Simd)),
+            AccelPreference::Scalar => Ok(Self::Scalar),
+            AccelPreference::Avx2 | AccelPreference::Sse41 => preference
+                .x86_level()
+                .filter(|&level| X86Dwt::supports(level))
+                .map(Self::X86Simd)
+                .ok_or_else(|| preference.unavailable()),
+        }
+    }
+
+    /// Multi-level forward transform, see [`crate::dwt::dwt_53_forward_multilevel_int`]
+    pub fn forward_multilevel(
+        self,
+        input: &[i32],
+        output: &mut [i32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        match self {
+            CodecDwt::Scalar => crate::dwt::dwt_53_forward_multilevel_int(
+                input, output, width, height, levels_x, levels_y,
+            ),
+            CodecDwt::X86Simd(level) => X86Dwt::with_level(level)
+                .ok_or_else(|| {
+                    JpegXsError::UnsupportedFeature(format!(
+                        "{:?} is not supported by this CPU",
+                        level
+                    ))
+                })?
+                .dwt_53_forward_multilevel_int_x86(
+                    input, output, width, height, levels_x, levels_y,
+                ),
+        }
+    }
+
+    /// Multi-level inverse transform, see [`crate::dwt::dwt_53_inverse_multilevel_int`]
+    pub fn inverse_multilevel(
+        self,
+        input: &[i32],
+        output: &mut [i32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        match self {
+            CodecDwt::Scalar => crate::dwt::dwt_53_inverse_multilevel_int(
+                input, output, width, height, levels_x, levels_y,
+            ),
+            CodecDwt::X86Simd(level) => X86Dwt::with_level(level)
+                .ok_or_else(|| {
+                    JpegXsError::UnsupportedFeature(format!(
+                        "{:?} is not supported by this CPU",
+                        level
+                    ))
+                })?
+                .dwt_53_inverse_multilevel_int_x86(
+                    input, output, width, height, levels_x, levels_y,
+                ),
+        }
+    }
 }
 
 /// Acceleration capabilities information
@@ -303,6 +847,9 @@ pub enum AccelMethod {
 pub struct AccelCapabilities {
     pub gpu_available: bool,
     pub neon_available: bool,
+    pub x86_simd_available: bool,
+    /// A wgpu adapter was found, possibly a software one
+    pub wgpu_available: bool,
     pub primary_method: AccelMethod,
 }
 
@@ -319,6 +866,8 @@ mod tests {
         assert!(
             caps.gpu_available
                 || caps.neon_available
+                || caps.x86_simd_available
+                || caps.wgpu_available
                 || matches!(caps.primary_method, AccelMethod::Scalar)
         );
     }
@@ -335,14 +884,135 @@ mod tests {
 
         // Should return valid methods
         match small_method {
-            AccelMethod::Gpu | AccelMethod::Neon | AccelMethod::Scalar => {}
+            AccelMethod::Gpu
+            | AccelMethod::Wgpu
+            | AccelMethod::Neon
+            | AccelMethod::X86Simd
+            | AccelMethod::Scalar => {}
         }
 
         match large_method {
-            AccelMethod::Gpu | AccelMethod::Neon | AccelMethod::Scalar => {}
+            AccelMethod::Gpu
+            | AccelMethod::Wgpu
+            | AccelMethod::Neon
+            | AccelMethod::X86Simd
+            | AccelMethod::Scalar => {}
+        }
+    }
+
+    #[test]
+    fn test_accel_multilevel_roundtrip() {
+        let accel = AccelDwt::new();
+
+        for &(width, height, levels_x, levels_y) in &[
+            (64u32, 32u32, 5u8, 2u8),
+            (37, 19, 3, 3),
+            (20, 9, 4, 1),
+            (16, 16, 1, 0),
+        ] {
+            let size = (width * height) as usize;
+            let input: Vec<f32> = (0..size).map(|i| ((i * 37) % 251) as f32 - 128.0).collect();
+            let mut coeffs = vec![0.0f32; size];
+            let mut reconstructed = vec![0.0f32; size];
+
+            accel
+                .dwt_53_forward_multilevel(&input, &mut coeffs, width, height, levels_x, levels_y)
+                .unwrap();
+            accel
+                .dwt_53_inverse_multilevel(
+                    &coeffs,
+                    &mut reconstructed,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+
+            let max_error = input
+                .iter()
+                .zip(reconstructed.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0f32, f32::max);
+            assert!(
+                max_error < 1e-3,
+                "{}x{} NLx={} NLy={} roundtrip error {}",
+                width,
+                height,
+                levels_x,
+                levels_y,
+                max_error
+            );
+        }
+    }
+
+    #[test]
+    fn test_accel_preference_names() {
+        for preference in AccelPreference::ALL {
+            assert_eq!(
+                preference.name().parse::<AccelPreference>().unwrap(),
+                preference
+            );
+        }
+        assert_eq!(
+            "SSE41".parse::<AccelPreference>().unwrap(),
+            AccelPreference::Sse41
+        );
+        assert!("sse2".parse::<AccelPreference>().is_err());
+
+        // An empty variable leaves the configured preference alone
+        let configured = AccelPreference::Scalar;
+        assert_eq!(configured.with_override(None).unwrap(), configured);
+        assert_eq!(configured.with_override(Some(" ")).unwrap(), configured);
+        assert_eq!(
+            configured.with_override(Some("avx2")).unwrap(),
+            AccelPreference::Avx2
+        );
+        assert!(configured.with_override(Some("fast")).is_err());
+    }
+
+    #[test]
+    fn test_accel_pinned_preference() {
+        let accel = AccelDwt::with_preference(AccelPreference::Scalar).unwrap();
+        assert!(matches!(
+            accel.select_method(4096, 4096),
+            AccelMethod::Scalar
+        ));
+        match AccelDwt::with_method(AccelMethod::Wgpu) {
+            // A software wgpu adapter is enough to pin the GPU path, and it must
+            // produce the scalar coefficients
+            Ok(accel) => {
+                let input: Vec<f32> = (0..48 * 20).map(|i| ((i * 37) % 251) as f32).collect();
+                let mut gpu_output = vec![0.0f32; input.len()];
+                let mut scalar_output = vec![0.0f32; input.len()];
+                accel
+                    .dwt_53_forward_2d(&input, &mut gpu_output, 48, 20)
+                    .unwrap();
+                crate::dwt::dwt_53_forward_2d(&input, &mut scalar_output, 48, 20).unwrap();
+                assert_eq!(gpu_output, scalar_output);
+            }
+            Err(_) => assert!(!AccelDwt::new().wgpu_available()),
+        }
+        if let Ok(accel) = AccelDwt::with_preference(AccelPreference::Sse41) {
+            assert!(matches!(accel.select_method(16, 16), AccelMethod::X86Simd));
+            assert_eq!(accel.x86.level(), Some(X86SimdLevel::Sse41));
         }
     }
 
+    #[test]
+    fn test_accel_multilevel_rejects_invalid_levels() {
+        let accel = AccelDwt::new();
+        let input = vec![0.0f32; 64];
+        let mut output = vec![0.0f32; 64];
+
+        assert!(accel
+            .dwt_53_forward_multilevel(&input, &mut output, 8, 8, 0, 0)
+            .is_err());
+        assert!(accel
+            .dwt_53_forward_multilevel(&input, &mut output, 8, 8, 2, 3)
+            .is_err());
+    }
+
     #[test]
     fn test_accel_dwt_accuracy() {
         let accel = AccelDwt::new();
@@ -2,7 +2,7 @@
 ///
 /// Based on ITU-R BT.601 standard for YUV conversion as specified
 /// in ISO/IEC 21122-1:2024 for JPEG XS color transforms.
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
 
 // ITU-R BT.601-7 coefficients (see Table 3 and Section 2.5.1/2.5.2)
 const BT601_Y_R_COEFF: f64 = 0.299;
@@ -107,10 +107,14 @@ pub fn rgb_to_yuv(rgb: &[u8], yuv: &mut [u8], width: u32, height: u32) -> Result
     let pixel_count = (width * height) as usize;
 
     if rgb.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("RGB buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "RGB buffer size mismatch".into(),
+        ));
     }
     if yuv.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("YUV buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "YUV buffer size mismatch".into(),
+        ));
     }
 
     for i in 0..pixel_count {
@@ -140,10 +144,14 @@ pub fn yuv_to_rgb(yuv: &[u8], rgb: &mut [u8], width: u32, height: u32) -> Result
     let pixel_count = (width * height) as usize;
 
     if yuv.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("YUV buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "YUV buffer size mismatch".into(),
+        ));
     }
     if rgb.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("RGB buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "RGB buffer size mismatch".into(),
+        ));
     }
 
     for i in 0..pixel_count {
@@ -184,14 +192,16 @@ pub fn downsample_444_to_422(
     let pixel_count = (width * height) as usize;
 
     if y.len() != pixel_count || u.len() != pixel_count || v.len() != pixel_count {
-        return Err(anyhow::anyhow!("Input buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Input buffer size mismatch".into(),
+        ));
     }
 
-    if width % 2 != 0 {
-        return Err(anyhow::anyhow!(
+    if !width.is_multiple_of(2) {
+        return Err(JpegXsError::DimensionMismatch(format!(
             "Width must be even for 4:2:2 subsampling, got width: {}",
             width
-        ));
+        )));
     }
 
     let new_chroma_width = width / 2;
@@ -231,7 +241,9 @@ pub fn bgr_to_yuv_planar(
     let pixel_count = (width * height) as usize;
 
     if bgr.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("BGR buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "BGR buffer size mismatch".into(),
+        ));
     }
 
     let mut y_plane = Vec::with_capacity(pixel_count);
@@ -271,7 +283,9 @@ pub fn rgb_planar_to_yuv_planar(
 
     if r_plane.len() != pixel_count || g_plane.len() != pixel_count || b_plane.len() != pixel_count
     {
-        return Err(anyhow::anyhow!("RGB planar buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "RGB planar buffer size mismatch".into(),
+        ));
     }
 
     let mut y_plane = Vec::with_capacity(pixel_count);
@@ -306,7 +320,9 @@ pub fn rgb_to_yuv_planar(
     let pixel_count = (width * height) as usize;
 
     if rgb.len() != pixel_count * 3 {
-        return Err(anyhow::anyhow!("RGB buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "RGB buffer size mismatch".into(),
+        ));
     }
 
     let mut y_plane = Vec::with_capacity(pixel_count);
@@ -345,15 +361,16 @@ pub fn downsample_444_to_420(
     let pixel_count = (width * height) as usize;
 
     if y.len() != pixel_count || u.len() != pixel_count || v.len() != pixel_count {
-        return Err(anyhow::anyhow!("Input buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Input buffer size mismatch".into(),
+        ));
     }
 
-    if width % 2 != 0 || height % 2 != 0 {
-        return Err(anyhow::anyhow!(
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(JpegXsError::DimensionMismatch(format!(
             "Width and height must be even for 4:2:0 subsampling, got {}x{}",
-            width,
-            height
-        ));
+            width, height
+        )));
     }
 
     let new_chroma_width = width / 2;
@@ -400,12 +417,11 @@ pub fn upsample_420_to_444(
     width: u32,
     height: u32,
 ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
-    if width % 2 != 0 || height % 2 != 0 {
-        return Err(anyhow::anyhow!(
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(JpegXsError::DimensionMismatch(format!(
             "Width and height must be even for 4:2:0 upsampling, got {}x{}",
-            width,
-            height
-        ));
+            width, height
+        )));
     }
 
     let chroma_width = width / 2;
@@ -417,7 +433,9 @@ pub fn upsample_420_to_444(
         || u.len() != expected_chroma_size
         || v.len() != expected_chroma_size
     {
-        return Err(anyhow::anyhow!("Input buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Input buffer size mismatch".into(),
+        ));
     }
 
     let pixel_count = expected_y_size;
@@ -451,11 +469,11 @@ pub fn upsample_422_to_444(
     width: u32,
     height: u32,
 ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
-    if width % 2 != 0 {
-        return Err(anyhow::anyhow!(
+    if !width.is_multiple_of(2) {
+        return Err(JpegXsError::DimensionMismatch(format!(
             "Width must be even for 4:2:2 upsampling, got width: {}",
             width
-        ));
+        )));
     }
 
     let chroma_width = width / 2;
@@ -466,7 +484,9 @@ pub fn upsample_422_to_444(
         || u.len() != expected_chroma_size
         || v.len() != expected_chroma_size
     {
-        return Err(anyhow::anyhow!("Input buffer size mismatch"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Input buffer size mismatch".into(),
+        ));
     }
 
     let pixel_count = expected_y_size;
@@ -488,10 +508,242 @@ pub fn upsample_422_to_444(
     Ok((y_out, u_out, v_out))
 }
 
+/// Forward reversible colour transform (RCT, Cpih = 1) per ISO/IEC 21122-1 Annex F
+///
+/// Turns DC level shifted R, G, B planes in place into Y = floor((R + 2G + B) / 4),
+/// Cb = B - G and Cr = R - G. Integer arithmetic makes the transform exactly invertible.
+pub fn rct_forward(c0: &mut [i32], c1: &mut [i32], c2: &mut [i32]) -> Result<()> {
+    if c0.len() != c1.len() || c0.len() != c2.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "RCT component size mismatch".into(),
+        ));
+    }
+
+    for ((r, g), b) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let (red, green, blue) = (*r, *g, *b);
+        *r = (red + 2 * green + blue) >> 2;
+        *g = blue - green;
+        *b = red - green;
+    }
+
+    Ok(())
+}
+
+/// Inverse reversible colour transform per ISO/IEC 21122-1 Annex F
+///
+/// Turns Y, Cb, Cr planes in place back into R, G, B with G = Y - floor((Cb + Cr) / 4),
+/// R = Cr + G and B = Cb + G.
+pub fn rct_inverse(c0: &mut [i32], c1: &mut [i32], c2: &mut [i32]) -> Result<()> {
+    if c0.len() != c1.len() || c0.len() != c2.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "RCT component size mismatch".into(),
+        ));
+    }
+
+    for ((y, cb), cr) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let green = *y - ((*cb + *cr) >> 2);
+        *y = *cr + green;
+        *cr = *cb + green;
+        *cb = green;
+    }
+
+    Ok(())
+}
+
+/// Split interleaved three-channel data (RGBRGB... or BGRBGR...) into planes
+pub fn deinterleave_planes<T: Copy>(
+    data: &[T],
+    width: u32,
+    height: u32,
+) -> Result<(Vec<T>, Vec<T>, Vec<T>)> {
+    let pixel_count = (width * height) as usize;
+
+    if data.len() != pixel_count * 3 {
+        return Err(JpegXsError::DimensionMismatch(
+            "Interleaved buffer size mismatch".into(),
+        ));
+    }
+
+    let mut c0 = Vec::with_capacity(pixel_count);
+    let mut c1 = Vec::with_capacity(pixel_count);
+    let mut c2 = Vec::with_capacity(pixel_count);
+    for pixel in data.chunks_exact(3) {
+        c0.push(pixel[0]);
+        c1.push(pixel[1]);
+        c2.push(pixel[2]);
+    }
+
+    Ok((c0, c1, c2))
+}
+
+/// Interleave three planes into a single buffer, inverse of [`deinterleave_planes`]
+pub fn interleave_planes<T: Copy>(c0: &[T], c1: &[T], c2: &[T]) -> Result<Vec<T>> {
+    if c0.len() != c1.len() || c0.len() != c2.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "Planar buffer size mismatch".into(),
+        ));
+    }
+
+    let mut data = Vec::with_capacity(c0.len() * 3);
+    for ((&a, &b), &c) in c0.iter().zip(c1).zip(c2) {
+        data.extend_from_slice(&[a, b, c]);
+    }
+
+    Ok(data)
+}
+
+/// Split interleaved data with `num_components` samples per pixel into planes
+pub fn deinterleave_components<T: Copy>(
+    data: &[T],
+    width: u32,
+    height: u32,
+    num_components: usize,
+) -> Result<Vec<Vec<T>>> {
+    let pixel_count = (width * height) as usize;
+
+    if num_components == 0 || data.len() != pixel_count * num_components {
+        return Err(JpegXsError::DimensionMismatch(
+            "Interleaved buffer size mismatch".into(),
+        ));
+    }
+
+    let mut planes = vec![Vec::with_capacity(pixel_count); num_components];
+    for pixel in data.chunks_exact(num_components) {
+        for (plane, &sample) in planes.iter_mut().zip(pixel) {
+            plane.push(sample);
+        }
+    }
+
+    Ok(planes)
+}
+
+/// Interleave any number of planes, inverse of [`deinterleave_components`]
+pub fn interleave_components<T: Copy>(planes: &[&[T]]) -> Result<Vec<T>> {
+    let pixel_count = planes.first().map_or(0, |plane| plane.len());
+    if planes.iter().any(|plane| plane.len() != pixel_count) {
+        return Err(JpegXsError::DimensionMismatch(
+            "Planar buffer size mismatch".into(),
+        ));
+    }
+
+    let mut data = Vec::with_capacity(pixel_count * planes.len());
+    for i in 0..pixel_count {
+        data.extend(planes.iter().map(|plane| plane[i]));
+    }
+
+    Ok(data)
+}
+
+/// Resample a chroma plane of a `width` x `height` image between sampling factors
+///
+/// Subsampled chroma is brought to full resolution by sample duplication and then
+/// averaged down to the target sampling factors (sx, sy), like the 8-bit converters.
+pub fn resample_chroma(
+    plane: &[u16],
+    width: u32,
+    height: u32,
+    from: (u8, u8),
+    to: (u8, u8),
+) -> Result<Vec<u16>> {
+    let (from_x, from_y) = (from.0 as u32, from.1 as u32);
+    let (to_x, to_y) = (to.0 as u32, to.1 as u32);
+    let from_width = width.div_ceil(from_x);
+    if plane.len() != (from_width * height.div_ceil(from_y)) as usize {
+        return Err(JpegXsError::DimensionMismatch(
+            "Chroma plane size mismatch".into(),
+        ));
+    }
+    if from == to {
+        return Ok(plane.to_vec());
+    }
+
+    let sample = |x: u32, y: u32| plane[((y / from_y) * from_width + x / from_x) as usize] as u32;
+    let (to_width, to_height) = (width.div_ceil(to_x), height.div_ceil(to_y));
+    let mut out = Vec::with_capacity((to_width * to_height) as usize);
+    for row in 0..to_height {
+        for col in 0..to_width {
+            let ys = row * to_y..((row + 1) * to_y).min(height);
+            let xs = col * to_x..((col + 1) * to_x).min(width);
+            let count = ys.len() as u32 * xs.len() as u32;
+            let sum: u32 = ys
+                .flat_map(|y| xs.clone().map(move |x| (x, y)))
+                .map(|(x, y)| sample(x, y))
+                .sum();
+            out.push(((sum + count / 2) / count) as u16);
+        }
+    }
+
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rct_roundtrip_is_exact() {
+        // Every combination of extreme and mid-range DC level shifted samples
+        let values = [-128, -127, -1, 0, 1, 63, 126, 127];
+        let mut r = Vec::new();
+        let mut g = Vec::new();
+        let mut b = Vec::new();
+        for &red in &values {
+            for &green in &values {
+                for &blue in &values {
+                    r.push(red);
+                    g.push(green);
+                    b.push(blue);
+                }
+            }
+        }
+        let (mut c0, mut c1, mut c2) = (r.clone(), g.clone(), b.clone());
+
+        rct_forward(&mut c0, &mut c1, &mut c2).unwrap();
+        // Grey pixels carry no chroma
+        let grey = values.len() * values.len() + values.len() + 1;
+        assert_eq!((c1[grey], c2[grey]), (0, 0));
+
+        rct_inverse(&mut c0, &mut c1, &mut c2).unwrap();
+        assert_eq!((c0, c1, c2), (r, g, b));
+    }
+
+    #[test]
+    fn test_interleave_components_roundtrip() {
+        let rgba: Vec<u8> = (0..24).collect();
+        let planes = deinterleave_components(&rgba, 3, 2, 4).unwrap();
+        assert_eq!(planes.len(), 4);
+        assert_eq!(planes[3], vec![3, 7, 11, 15, 19, 23]);
+
+        let views: Vec<&[u8]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        assert_eq!(interleave_components(&views).unwrap(), rgba);
+        assert!(deinterleave_components(&rgba, 3, 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_resample_chroma() {
+        // 4:2:2 chroma of a 4x2 image
+        let chroma = [100u16, 300, 500, 700];
+        let full = resample_chroma(&chroma, 4, 2, (2, 1), (1, 1)).unwrap();
+        assert_eq!(full, [100, 100, 300, 300, 500, 500, 700, 700]);
+
+        let chroma_420 = resample_chroma(&chroma, 4, 2, (2, 1), (2, 2)).unwrap();
+        assert_eq!(chroma_420, [300, 500]);
+        assert_eq!(
+            resample_chroma(&full, 4, 2, (1, 1), (2, 1)).unwrap(),
+            chroma
+        );
+        assert!(resample_chroma(&chroma, 4, 2, (1, 1), (2, 1)).is_err());
+    }
+
+    #[test]
+    fn test_interleave_roundtrip() {
+        let data: Vec<u8> = (0..24).collect();
+        let (c0, c1, c2) = deinterleave_planes(&data, 4, 2).unwrap();
+        assert_eq!(c1, vec![1, 4, 7, 10, 13, 16, 19, 22]);
+        assert_eq!(interleave_planes(&c0, &c1, &c2).unwrap(), data);
+        assert!(deinterleave_planes(&data, 4, 3).is_err());
+    }
+
     #[test]
     fn test_bgr_to_yuv_planar() {
         let width = 2;
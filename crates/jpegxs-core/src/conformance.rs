@@ -0,0 +1,640 @@
+// Codestream conformance checks against ISO/IEC 21122-1
+//
+// The decoder only reads the fields it needs and stops at the first value it cannot
+// use. The validator walks the whole codestream instead: the order and length of every
+// marker segment, the range of every header field including reserved values, the
+// consistency of the PIH with the CDT and WGT, and the boundaries of every slice,
+// precinct and packet. `DecoderConfig::strict_mode` runs it ahead of decoding.
+
+use crate::dwt;
+use crate::error::{JpegXsError, Result};
+use crate::precinct::{self, PrecinctLayout};
+use jpegxs_core_clean::{markers, Component, PictureHeader};
+
+/// Lpih: the PIH marker segment has a fixed size (ISO Table A.7)
+const PIH_LENGTH: usize = 26;
+
+/// Lslh: the SLH marker segment has a fixed size (ISO Table A.23)
+const SLH_LENGTH: usize = 4;
+
+/// Ng: coefficients per code group, the only value allowed (ISO Table A.7)
+const CODE_GROUP_SIZE: u8 = 4;
+
+/// Ss: code groups per significance group, the only value allowed (ISO Table A.7)
+const SIGNIFICANCE_GROUP_SIZE: u8 = 8;
+
+/// Br: bits of a raw-coded bitplane count, the only value allowed (ISO Table A.7)
+const RAW_BITPLANE_COUNT_BITS: u8 = 4;
+
+/// Largest band gain G[b] (ISO Table A.25)
+const MAX_BAND_GAIN: u8 = 15;
+
+/// Lprc u(24), Q[p] u(8) and R[p] u(8) ahead of the band coding modes (ISO Table C.3)
+const PRECINCT_HEADER_FIXED_BITS: usize = 40;
+
+/// Check a complete codestream against ISO/IEC 21122-1
+///
+/// Returns the first violation as [`JpegXsError::NonConformant`], with the byte offset
+/// of the marker or field breaking the rule. A conformant codestream using a feature
+/// this implementation does not support, such as component-dependent decompositions,
+/// cannot be checked past its main header and fails with
+/// [`JpegXsError::UnsupportedFeature`].
+pub fn validate_codestream(data: &[u8]) -> Result<()> {
+    let mut validator = Validator {
+        data,
+        offset: 0,
+        header: PictureHeader::default(),
+        components: Vec::new(),
+    };
+    validator.main_header()?;
+    validator.slices()?;
+    validator.end_of_codestream()
+}
+
+/// Check the main header at the start of `data` against ISO/IEC 21122-1
+///
+/// `data` must reach the SLH marker of the first slice. Used by the streaming decoder,
+/// which cannot wait for the whole codestream.
+pub(crate) fn validate_main_header(data: &[u8]) -> Result<()> {
+    Validator {
+        data,
+        offset: 0,
+        header: PictureHeader::default(),
+        components: Vec::new(),
+    }
+    .main_header()
+}
+
+fn violation(offset: usize, reason: impl Into<String>) -> JpegXsError {
+    JpegXsError::NonConformant {
+        offset,
+        reason: reason.into(),
+    }
+}
+
+fn ensure(condition: bool, offset: usize, reason: &str) -> Result<()> {
+    if condition {
+        Ok(())
+    } else {
+        Err(violation(offset, reason))
+    }
+}
+
+fn marker_name(marker: u16) -> Option<&'static str> {
+    Some(match marker {
+        markers::SOC => "SOC",
+        markers::EOC => "EOC",
+        markers::PIH => "PIH",
+        markers::CDT => "CDT",
+        markers::WGT => "WGT",
+        markers::COM => "COM",
+        markers::NLT => "NLT",
+        markers::CWD => "CWD",
+        markers::CTS => "CTS",
+        markers::CRG => "CRG",
+        markers::SLH => "SLH",
+        markers::CAP => "CAP",
+        _ => return None,
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// Position in the codestream and the header fields read so far
+struct Validator<'a> {
+    data: &'a [u8],
+    offset: usize,
+    header: PictureHeader,
+    components: Vec<Component>,
+}
+
+impl<'a> Validator<'a> {
+    /// Read the marker at the current offset
+    fn marker(&mut self) -> Result<u16> {
+        let offset = self.offset;
+        if offset + 2 > self.data.len() {
+            return Err(violation(offset, "The codestream ends inside a marker"));
+        }
+        let marker = read_u16(self.data, offset);
+        if marker >> 8 != 0xff {
+            return Err(violation(
+                offset,
+                format!("Expected a marker, found 0x{:04x}", marker),
+            ));
+        }
+        self.offset += 2;
+        Ok(marker)
+    }
+
+    /// Read the length field of a marker segment, returning the rest of the segment
+    fn segment(&mut self) -> Result<&'a [u8]> {
+        let offset = self.offset;
+        if offset + 2 > self.data.len() {
+            return Err(violation(
+                offset,
+                "The codestream ends inside a length field",
+            ));
+        }
+        let length = read_u16(self.data, offset) as usize;
+        if length < 2 {
+            return Err(violation(
+                offset,
+                format!("Marker segment length {} is below 2", length),
+            ));
+        }
+        if offset + length > self.data.len() {
+            return Err(violation(
+                offset,
+                format!(
+                    "Marker segment length {} runs past the end of the codestream",
+                    length
+                ),
+            ));
+        }
+        self.offset += length;
+        Ok(&self.data[offset + 2..offset + length])
+    }
+
+    /// SOC, CAP and PIH in this order, then the other marker segments up to the first SLH
+    /// (ISO A.4)
+    fn main_header(&mut self) -> Result<()> {
+        let marker = self.marker()?;
+        ensure(
+            marker == markers::SOC,
+            0,
+            "The codestream must start with SOC",
+        )?;
+
+        let mut seen: Vec<u16> = Vec::new();
+        loop {
+            let offset = self.offset;
+            let marker = self.marker()?;
+            if marker == markers::SLH {
+                self.offset = offset;
+                break;
+            }
+            let name = marker_name(marker)
+                .ok_or_else(|| violation(offset, format!("Unknown marker 0x{:04x}", marker)))?;
+            match (seen.len(), marker) {
+                (0, markers::CAP) | (1, markers::PIH) => {}
+                (0, _) => return Err(violation(offset, "The CAP marker must follow SOC")),
+                (1, _) => return Err(violation(offset, "The PIH marker must follow CAP")),
+                _ => {}
+            }
+            if marker != markers::COM && seen.contains(&marker) {
+                return Err(violation(offset, format!("Duplicate {} marker", name)));
+            }
+            seen.push(marker);
+
+            match marker {
+                markers::SOC => {
+                    return Err(violation(offset, "SOC must only start the codestream"))
+                }
+                markers::EOC => {
+                    return Err(violation(
+                        offset,
+                        "The codestream ends before its first slice",
+                    ))
+                }
+                _ => {}
+            }
+            let payload_offset = self.offset + 2;
+            let payload = self.segment()?;
+            match marker {
+                markers::PIH => self.picture_header(payload_offset, payload)?,
+                markers::CDT => self.component_table(payload_offset, payload)?,
+                markers::WGT => self.weights_table(payload_offset, payload)?,
+                markers::COM => ensure(
+                    payload.len() >= 2,
+                    offset + 2,
+                    "The COM marker segment has no Rcom field",
+                )?,
+                _ => {}
+            }
+        }
+
+        for (marker, name) in [(markers::CDT, "CDT"), (markers::WGT, "WGT")] {
+            if !seen.contains(&marker) {
+                return Err(violation(
+                    self.offset,
+                    format!("Missing {} marker before the first slice", name),
+                ));
+            }
+        }
+        if seen.contains(&markers::CWD) {
+            return Err(JpegXsError::UnsupportedFeature(
+                "Component-dependent wavelet decompositions (CWD) are not supported".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// PIH fields and their allowed values (ISO Table A.7)
+    fn picture_header(&mut self, offset: usize, payload: &[u8]) -> Result<()> {
+        ensure(
+            payload.len() + 2 == PIH_LENGTH,
+            offset - 2,
+            "Lpih must be 26",
+        )?;
+        let lcod = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+        ensure(
+            lcod == 0 || lcod == self.data.len(),
+            offset,
+            "Lcod does not match the size of the codestream",
+        )?;
+
+        let header = &mut self.header;
+        header.width = read_u16(payload, 8);
+        header.height = read_u16(payload, 10);
+        header.precinct_width = read_u16(payload, 12);
+        header.slice_height = read_u16(payload, 14);
+        header.num_components = payload[16];
+        header.wavelet_precision = payload[19];
+        header.fractional_bits = payload[20] >> 4;
+        header.color_transform = payload[21] & 0x0f;
+        header.decomposition_levels_x = payload[22] >> 4;
+        header.decomposition_levels_y = payload[22] & 0x0f;
+        ensure(header.width > 0, offset + 8, "Wf must not be 0")?;
+        ensure(header.height > 0, offset + 10, "Hf must not be 0")?;
+        ensure(header.slice_height > 0, offset + 14, "Hsl must not be 0")?;
+        ensure(
+            (1..=8).contains(&header.num_components),
+            offset + 16,
+            "Nc must be between 1 and 8",
+        )?;
+        ensure(payload[17] == CODE_GROUP_SIZE, offset + 17, "Ng must be 4")?;
+        ensure(
+            payload[18] == SIGNIFICANCE_GROUP_SIZE,
+            offset + 18,
+            "Ss must be 8",
+        )?;
+        ensure(
+            header.fractional_bits < header.wavelet_precision,
+            offset + 20,
+            "Fq must be smaller than Bw",
+        )?;
+        ensure(
+            payload[20] & 0x0f == RAW_BITPLANE_COUNT_BITS,
+            offset + 20,
+            "Br must be 4",
+        )?;
+        ensure(
+            (payload[21] >> 4) & 0x07 == 0,
+            offset + 21,
+            "Ppoc uses a reserved progression order",
+        )?;
+        ensure(
+            matches!(header.color_transform, 0 | 1 | 3),
+            offset + 21,
+            "Cpih uses a reserved colour transformation",
+        )?;
+        dwt::validate_decomposition_levels(
+            header.decomposition_levels_x,
+            header.decomposition_levels_y,
+        )
+        .map_err(|error| violation(offset + 22, error.to_string()))?;
+        ensure(
+            (payload[23] >> 4) & 0x03 <= 1,
+            offset + 23,
+            "Qpih uses a reserved quantizer type",
+        )?;
+        ensure(
+            (payload[23] >> 2) & 0x03 <= 1,
+            offset + 23,
+            "Fs uses a reserved sign handling mode",
+        )?;
+        ensure(
+            payload[23] & 0x03 <= 1,
+            offset + 23,
+            "Rm uses a reserved run mode",
+        )
+    }
+
+    /// CDT entries for the Nc components of the PIH (ISO Table A.15)
+    fn component_table(&mut self, offset: usize, payload: &[u8]) -> Result<()> {
+        let num_components = self.header.num_components as usize;
+        ensure(
+            payload.len() == 2 * num_components,
+            offset - 2,
+            "Lcdt does not match the number of components of the PIH",
+        )?;
+        self.components = payload
+            .chunks_exact(2)
+            .map(|entry| Component {
+                bit_depth: entry[0],
+                sampling_x: entry[1] >> 4,
+                sampling_y: entry[1] & 0x0f,
+            })
+            .collect();
+
+        for (component, entry) in self.components.iter().enumerate() {
+            let entry_offset = offset + 2 * component;
+            ensure(
+                (1..=dwt::MAX_BIT_DEPTH).contains(&entry.bit_depth),
+                entry_offset,
+                "B[c] must be between 1 and 16",
+            )?;
+            dwt::validate_wavelet_precision(
+                self.header.wavelet_precision,
+                self.header.fractional_bits,
+                entry.bit_depth,
+            )
+            .map_err(|error| violation(entry_offset, error.to_string()))?;
+            ensure(
+                matches!(entry.sampling_x, 1 | 2) && matches!(entry.sampling_y, 1 | 2),
+                entry_offset + 1,
+                "Sampling factors must be 1 or 2",
+            )?;
+        }
+
+        // The RCT decorrelates the first three components, which must match
+        if self.header.color_transform == 1 {
+            ensure(
+                num_components >= 3,
+                offset - 4,
+                "The colour transform requires at least three components",
+            )?;
+            let first = self.components[0];
+            ensure(
+                self.components[..3]
+                    .iter()
+                    .all(|entry| *entry == first && (entry.sampling_x, entry.sampling_y) == (1, 1)),
+                offset,
+                "The colour transform requires three components of the same precision without subsampling",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A gain and priority for every band of every component (ISO Table A.25)
+    fn weights_table(&mut self, offset: usize, payload: &[u8]) -> Result<()> {
+        let num_bands = dwt::subband_count(
+            self.header.decomposition_levels_x,
+            self.header.decomposition_levels_y,
+        ) * self.header.num_components as usize;
+        ensure(
+            payload.len() == 2 * num_bands,
+            offset - 2,
+            "Lwgt does not match the number of bands",
+        )?;
+        for (band, weights) in payload.chunks_exact(2).enumerate() {
+            ensure(
+                weights[0] <= MAX_BAND_GAIN,
+                offset + 2 * band,
+                "G[b] must be at most 15",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Every slice: its SLH marker segment, then Hsl rows of precincts whose packets
+    /// fill them exactly up to their padding (ISO A.4.13, Annex C)
+    fn slices(&mut self) -> Result<()> {
+        let header = &self.header;
+        let sampling: Vec<(u8, u8)> = self
+            .components
+            .iter()
+            .map(|component| (component.sampling_x, component.sampling_y))
+            .collect();
+        let layout = PrecinctLayout::with_sampling(
+            header.width as u32,
+            header.height as u32,
+            header.decomposition_levels_x,
+            header.decomposition_levels_y,
+            &sampling,
+            header.precinct_width,
+        )?;
+
+        for slice in 0..header.num_slices() {
+            let offset = self.offset;
+            let marker = self.marker()?;
+            if marker != markers::SLH {
+                return Err(violation(
+                    offset,
+                    format!("Expected the SLH marker of slice {}", slice),
+                ));
+            }
+            let payload = self.segment()?;
+            ensure(
+                payload.len() + 2 == SLH_LENGTH,
+                offset + 2,
+                "Lslh must be 4",
+            )?;
+            ensure(
+                read_u16(payload, 0) as usize == slice,
+                offset + 4,
+                "Yslh does not match the position of the slice",
+            )?;
+            for precinct in self.header.slice_precincts(slice) {
+                self.precinct(&layout, precinct)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A precinct header with zero padding, followed by packets within its Lprc bytes
+    fn precinct(&mut self, layout: &PrecinctLayout, precinct: usize) -> Result<()> {
+        let offset = self.offset;
+        let data = &self.data[offset..];
+        let (header_length, _) = precinct::precinct_packet_lengths(layout, data, precinct)
+            .map_err(|error| violation(offset, format!("Precinct {}: {}", precinct, error)))?;
+
+        let mode_bits = 2 * layout.num_bands() * layout.num_components();
+        let padding_bits = 8 * header_length - PRECINCT_HEADER_FIXED_BITS - mode_bits;
+        ensure(
+            data[header_length - 1] & ((1u16 << padding_bits) - 1) as u8 == 0,
+            offset + header_length - 1,
+            "Padding bits after the band coding modes must be 0",
+        )?;
+
+        let lprc = u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize;
+        self.offset += header_length + lprc;
+        Ok(())
+    }
+
+    /// EOC right after the last precinct, ending the codestream (ISO A.4.2)
+    fn end_of_codestream(&mut self) -> Result<()> {
+        let offset = self.offset;
+        if offset + 2 > self.data.len() {
+            return Err(violation(offset, "Missing EOC marker"));
+        }
+        let marker = read_u16(self.data, offset);
+        if marker != markers::EOC {
+            return Err(violation(
+                offset,
+                format!("Expected the EOC marker, found 0x{:04x}", marker),
+            ));
+        }
+        ensure(
+            offset + 2 == self.data.len(),
+            offset + 2,
+            "Data after the EOC marker",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EncoderConfig, ImageView8, PixelFormat, Profile, RateTarget};
+
+    fn encode(format: PixelFormat, config: &EncoderConfig) -> Vec<u8> {
+        let (width, height) = (48u32, 24u32);
+        let (sampling_x, sampling_y) = format.chroma_sampling();
+        let pixels = (width * height) as usize;
+        let chroma = pixels / (sampling_x * sampling_y) as usize;
+        let size = match format.num_components() {
+            1 => pixels,
+            components => 2 * chroma + (components as usize - 2) * pixels,
+        };
+        let data: Vec<u8> = (0..size)
+            .map(|i| ((i * 37 + (i / 48) * 11) % 256) as u8)
+            .collect();
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format,
+        };
+        crate::encode_frame(input, config).unwrap().data
+    }
+
+    fn offset_of(error: JpegXsError) -> usize {
+        match error {
+            JpegXsError::NonConformant { offset, .. } => offset,
+            error => panic!("Expected a conformance violation, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_encoder_output_is_conformant() {
+        let rate_controlled = EncoderConfig {
+            rate_target: Some(RateTarget::BitsPerPixel(8.0)),
+            slice_height: 2,
+            precinct_width: 1,
+            ..Default::default()
+        };
+        for config in [EncoderConfig::default(), rate_controlled] {
+            for format in [
+                PixelFormat::Rgb8,
+                PixelFormat::Yuv420p8,
+                PixelFormat::Gray8,
+                PixelFormat::Rgba8,
+            ] {
+                // No profile carries 4:2:0 chroma
+                let config = EncoderConfig {
+                    profile: match format {
+                        PixelFormat::Yuv420p8 => Profile::Unrestricted,
+                        _ => config.profile,
+                    },
+                    ..config.clone()
+                };
+                validate_codestream(&encode(format, &config))
+                    .unwrap_or_else(|error| panic!("{:?}: {}", format, error));
+            }
+        }
+    }
+
+    #[test]
+    fn test_violations_are_located() {
+        let codestream = encode(PixelFormat::Rgb8, &EncoderConfig::default());
+        let pih = 2 + 4;
+        let check = |offset: usize, value: u8| {
+            let mut data = codestream.clone();
+            data[offset] = value;
+            offset_of(validate_codestream(&data).unwrap_err())
+        };
+
+        // CAP must follow SOC
+        assert_eq!(check(3, 0x12), 2);
+        // Lpih, Ng, a reserved Ppoc and a reserved Qpih
+        assert_eq!(check(pih + 3, 27), pih + 2);
+        assert_eq!(check(pih + 4 + 17, 2), pih + 4 + 17);
+        assert_eq!(check(pih + 4 + 21, 0x51), pih + 4 + 21);
+        assert_eq!(check(pih + 4 + 23, 0xb4), pih + 4 + 23);
+
+        // A nonzero Lcod must be the codestream size
+        let mut data = codestream.clone();
+        data[pih + 4..pih + 8].copy_from_slice(&(codestream.len() as u32).to_be_bytes());
+        validate_codestream(&data).unwrap();
+        data[pih + 7] += 1;
+        assert_eq!(offset_of(validate_codestream(&data).unwrap_err()), pih + 4);
+
+        // The first slice index and the trailing EOC
+        let slh = codestream
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x20])
+            .unwrap();
+        assert_eq!(check(slh + 5, 1), slh + 4);
+        let mut data = codestream.clone();
+        data.push(0);
+        let error = validate_codestream(&data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Non-conformant codestream at offset {}: Data after the EOC marker",
+                codestream.len()
+            )
+        );
+        data.truncate(codestream.len() - 2);
+        assert_eq!(
+            offset_of(validate_codestream(&data).unwrap_err()),
+            codestream.len() - 2
+        );
+    }
+
+    #[test]
+    fn test_marker_order_and_unknown_markers() {
+        let codestream = encode(PixelFormat::Gray8, &EncoderConfig::default());
+        let slh = codestream
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x20])
+            .unwrap();
+        let insert = |segment: &[u8]| {
+            let mut data = codestream[..slh].to_vec();
+            data.extend_from_slice(segment);
+            data.extend_from_slice(&codestream[slh..]);
+            validate_codestream(&data)
+        };
+
+        // Optional markers are allowed ahead of the first slice, unknown ones are not
+        insert(&[0xff, 0x15, 0x00, 0x06, 0x00, 0x01, b'h', b'i']).unwrap();
+        assert_eq!(
+            offset_of(insert(&[0xff, 0x30, 0x00, 0x02]).unwrap_err()),
+            slh
+        );
+        assert_eq!(
+            offset_of(insert(&[0xff, 0x15, 0x00, 0x02]).unwrap_err()),
+            slh + 2
+        );
+
+        // A second WGT and a length running past the end
+        let wgt = codestream
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x14])
+            .unwrap();
+        assert_eq!(offset_of(insert(&codestream[wgt..slh]).unwrap_err()), slh);
+        assert_eq!(
+            offset_of(insert(&[0xff, 0x15, 0xff, 0xff]).unwrap_err()),
+            slh + 2
+        );
+    }
+
+    #[test]
+    fn test_precinct_boundaries_are_checked() {
+        let codestream = encode(PixelFormat::Gray8, &EncoderConfig::default());
+        let slh = codestream
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x20])
+            .unwrap();
+        let precinct = slh + 6;
+
+        // Lprc one byte short cuts the last packet of the precinct
+        let mut data = codestream.clone();
+        let lprc = u32::from_be_bytes([0, data[precinct], data[precinct + 1], data[precinct + 2]]);
+        data[precinct..precinct + 3].copy_from_slice(&(lprc - 1).to_be_bytes()[1..]);
+        assert_eq!(offset_of(validate_codestream(&data).unwrap_err()), precinct);
+    }
+}
@@ -8,14 +8,188 @@
 // Date: September 2025
 // Legal status: Original work based solely on ISO/IEC 21122-1:2024 mathematical equations
 
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
+
+/// Maximum number of horizontal decompositions (NL,x) accepted in the picture header
+pub const MAX_DECOMPOSITION_LEVELS_X: u8 = 8;
+
+/// Maximum number of vertical decompositions (NL,y) accepted in the picture header
+pub const MAX_DECOMPOSITION_LEVELS_Y: u8 = 6;
+
+/// Fractional bits of the wavelet coefficients (Fq in ISO/IEC 21122-1 Table A.8)
+pub const FRACTIONAL_BITS: u8 = 6;
+
+/// Smallest nominal wavelet coefficient precision Bw written to the picture header
+const MIN_WAVELET_PRECISION: u8 = 20;
+
+/// Integer bits the coefficients need beyond the sample precision: the sign, one bit
+/// for the RCT chroma differences and one for the high-pass gain of the 5/3 filter
+const DYNAMIC_RANGE_GROWTH: u8 = 3;
+
+/// Largest supported sample precision B[c]
+pub const MAX_BIT_DEPTH: u8 = 16;
+
+/// Nominal wavelet coefficient precision Bw for samples of `bit_depth` bits
+///
+/// Bw covers the integer bits of the coefficients plus Fq fractional bits, so deeper
+/// samples raise it above the 20 bits that suffice for 8 to 11 bit content.
+pub fn wavelet_precision(bit_depth: u8) -> u8 {
+    (bit_depth + DYNAMIC_RANGE_GROWTH + FRACTIONAL_BITS).max(MIN_WAVELET_PRECISION)
+}
+
+/// Check that a signalled Bw / Fq pair leaves room for `bit_depth` bit samples
+pub fn validate_wavelet_precision(
+    wavelet_precision: u8,
+    fractional_bits: u8,
+    bit_depth: u8,
+) -> Result<()> {
+    if bit_depth == 0 || bit_depth > MAX_BIT_DEPTH {
+        return Err(JpegXsError::UnsupportedFeature(format!(
+            "Unsupported sample precision: {} bits (must be 1-{})",
+            bit_depth, MAX_BIT_DEPTH
+        )));
+    }
+    let integer_bits = wavelet_precision.saturating_sub(fractional_bits);
+    if integer_bits < bit_depth + DYNAMIC_RANGE_GROWTH {
+        return Err(JpegXsError::Corrupt(format!(
+            "Wavelet precision Bw={} with Fq={} cannot hold {}-bit samples",
+            wavelet_precision, fractional_bits, bit_depth
+        )));
+    }
+    Ok(())
+}
+
+/// Validate a horizontal/vertical decomposition pair
+///
+/// JPEG XS requires at least one horizontal decomposition and never more vertical
+/// than horizontal decompositions (NL,y <= NL,x), see ISO/IEC 21122-1:2024 Annex E.
+pub fn validate_decomposition_levels(levels_x: u8, levels_y: u8) -> Result<()> {
+    if levels_x == 0 || levels_x > MAX_DECOMPOSITION_LEVELS_X {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Invalid horizontal decomposition count: {} (must be 1-{})",
+            levels_x, MAX_DECOMPOSITION_LEVELS_X
+        )));
+    }
+    if levels_y > levels_x.min(MAX_DECOMPOSITION_LEVELS_Y) {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Invalid vertical decomposition count: {} (must be at most {})",
+            levels_y,
+            levels_x.min(MAX_DECOMPOSITION_LEVELS_Y)
+        )));
+    }
+    Ok(())
+}
+
+/// Number of subbands produced per component by an NL,x / NL,y decomposition
+///
+/// Every 2D level adds HL, LH and HH bands, every horizontal-only level adds one
+/// high-pass band, plus the final low-pass band: 2 * NL,y + NL,x + 1.
+pub fn subband_count(levels_x: u8, levels_y: u8) -> usize {
+    2 * levels_y.min(levels_x) as usize + levels_x as usize + 1
+}
+
+/// Dimensions of the low-pass region before each decomposition level
+///
+/// Entry `l` holds the size of the region transformed by level `l + 1`; the final
+/// entry is the size of the remaining LL band. The first NL,y levels split both
+/// directions, the remaining NL,x - NL,y levels split horizontally only.
+pub fn decomposition_dims(width: u32, height: u32, levels_x: u8, levels_y: u8) -> Vec<(u32, u32)> {
+    let mut dims = Vec::with_capacity(levels_x as usize + 1);
+    let (mut w, mut h) = (width, height);
+    dims.push((w, h));
+    for level in 1..=levels_x {
+        w = w.div_ceil(2);
+        if level <= levels_y {
+            h = h.div_ceil(2);
+        }
+        dims.push((w, h));
+    }
+    dims
+}
+
+/// Orientation of a subband: horizontal filter first, vertical filter second
+///
+/// Bands of horizontal-only levels are low-pass vertically and use [`SubbandOrientation::Hl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubbandOrientation {
+    Ll,
+    Hl,
+    Lh,
+    Hh,
+}
+
+/// Location of a subband inside the in-place multi-level coefficient plane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubbandRegion {
+    /// Decomposition level that produced the band (1 = finest, NL,x for the LL band)
+    pub level: u8,
+    pub orientation: SubbandOrientation,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Subband regions in band index order (beta in ISO/IEC 21122-1)
+///
+/// Band 0 is the final LL band, followed by the high-pass bands from the deepest
+/// level to the finest one; 2D levels contribute HL, LH and HH in that order.
+pub fn subband_regions(width: u32, height: u32, levels_x: u8, levels_y: u8) -> Vec<SubbandRegion> {
+    let dims = decomposition_dims(width, height, levels_x, levels_y);
+    let mut regions = Vec::with_capacity(subband_count(levels_x, levels_y));
+
+    let (ll_width, ll_height) = dims[levels_x as usize];
+    regions.push(SubbandRegion {
+        level: levels_x,
+        orientation: SubbandOrientation::Ll,
+        x: 0,
+        y: 0,
+        width: ll_width,
+        height: ll_height,
+    });
+
+    for level in (1..=levels_x).rev() {
+        let (w, h) = dims[level as usize - 1];
+        let (low_w, low_h) = dims[level as usize];
+        regions.push(SubbandRegion {
+            level,
+            orientation: SubbandOrientation::Hl,
+            x: low_w,
+            y: 0,
+            width: w - low_w,
+            height: low_h,
+        });
+        if level <= levels_y {
+            regions.push(SubbandRegion {
+                level,
+                orientation: SubbandOrientation::Lh,
+                x: 0,
+                y: low_h,
+                width: low_w,
+                height: h - low_h,
+            });
+            regions.push(SubbandRegion {
+                level,
+                orientation: SubbandOrientation::Hh,
+                x: low_w,
+                y: low_h,
+                width: w - low_w,
+                height: h - low_h,
+            });
+        }
+    }
+
+    regions
+}
 
 /// Clean-room implementation of 5/3 reversible DWT forward transform from ISO specification
 /// Source: ISO/IEC 21122-1:2024, Annex E.13, Table E.12
 /// Mathematical equations implemented exactly as specified in ISO standard
 pub fn dwt_53_forward_2d(input: &[f32], output: &mut [f32], width: u32, height: u32) -> Result<()> {
     if input.len() != (width * height) as usize || output.len() != input.len() {
-        return Err(anyhow::anyhow!("Invalid buffer sizes"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
     }
 
     // Copy input to output for in-place processing
@@ -53,7 +227,9 @@ pub fn dwt_53_forward_2d(input: &[f32], output: &mut [f32], width: u32, height:
 /// Mathematical equations implemented exactly as specified in ISO standard
 pub fn dwt_53_inverse_2d(input: &[f32], output: &mut [f32], width: u32, height: u32) -> Result<()> {
     if input.len() != (width * height) as usize || output.len() != input.len() {
-        return Err(anyhow::anyhow!("Invalid buffer sizes"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
     }
 
     // Copy input to output for in-place processing
@@ -223,6 +399,159 @@ pub(crate) fn dwt_53_inverse_1d(data: &mut [f32]) {
     }
 }
 
+/// Symmetric extension of a signal of `len` samples (ISO Annex E.6, Table E.5)
+fn mirror(index: isize, len: usize) -> usize {
+    let last = len as isize - 1;
+    let index = index.abs();
+    (if index > last {
+        2 * last - index
+    } else {
+        index
+    }) as usize
+}
+
+/// 1D forward integer 5/3 lifting transform, exactly as ISO/IEC 21122-1 Table E.12
+///
+/// The result is split into low-pass then high-pass coefficients like
+/// [`dwt_53_forward_1d`], and [`dwt_53_inverse_1d_int`] restores the input bit-exactly.
+pub(crate) fn dwt_53_forward_1d_int(data: &mut [i32]) {
+    let len = data.len();
+    if len < 2 {
+        return;
+    }
+    let mut x = data.to_vec();
+    let at = |x: &[i32], i: isize| x[mirror(i, len)];
+
+    // Predict: Y[i] = X[i] - ((X[i-1] + X[i+1]) >> 1) for odd i
+    for i in (1..len).step_by(2) {
+        let i = i as isize;
+        x[i as usize] -= (at(&x, i - 1) + at(&x, i + 1)) >> 1;
+    }
+    // Update: Y[i] = X[i] + ((Y[i-1] + Y[i+1] + 2) >> 2) for even i
+    for i in (0..len).step_by(2) {
+        let i = i as isize;
+        x[i as usize] += (at(&x, i - 1) + at(&x, i + 1) + 2) >> 2;
+    }
+
+    let mid = len.div_ceil(2);
+    for (i, &sample) in x.iter().enumerate() {
+        data[if i % 2 == 0 { i / 2 } else { mid + i / 2 }] = sample;
+    }
+}
+
+/// 1D inverse integer 5/3 lifting transform, exactly as ISO/IEC 21122-1 Table E.6
+pub(crate) fn dwt_53_inverse_1d_int(data: &mut [i32]) {
+    let len = data.len();
+    if len < 2 {
+        return;
+    }
+    let mid = len.div_ceil(2);
+    let mut x: Vec<i32> = (0..len)
+        .map(|i| data[if i % 2 == 0 { i / 2 } else { mid + i / 2 }])
+        .collect();
+    let at = |x: &[i32], i: isize| x[mirror(i, len)];
+
+    // Undo the update: X[i] = Y[i] - ((Y[i-1] + Y[i+1] + 2) >> 2) for even i
+    for i in (0..len).step_by(2) {
+        let i = i as isize;
+        x[i as usize] -= (at(&x, i - 1) + at(&x, i + 1) + 2) >> 2;
+    }
+    // Undo the prediction: X[i] = Y[i] + ((X[i-1] + X[i+1]) >> 1) for odd i
+    for i in (1..len).step_by(2) {
+        let i = i as isize;
+        x[i as usize] += (at(&x, i - 1) + at(&x, i + 1)) >> 1;
+    }
+
+    data.copy_from_slice(&x);
+}
+
+/// Multi-level forward integer 5/3 DWT, the transform of the codec
+///
+/// Same NL,x / NL,y decomposition and Mallat layout as
+/// [`crate::accel::AccelDwt::dwt_53_forward_multilevel`], but on integers with the
+/// exact `>>` rounding of the standard, so [`dwt_53_inverse_multilevel_int`] is lossless.
+/// Lossy coding passes fixed-point samples with [`FRACTIONAL_BITS`] fractional bits,
+/// lossless coding plain integer samples (Fq = 0); the results are bit-identical on
+/// every platform.
+pub fn dwt_53_forward_multilevel_int(
+    input: &[i32],
+    output: &mut [i32],
+    width: u32,
+    height: u32,
+    levels_x: u8,
+    levels_y: u8,
+) -> Result<()> {
+    if input.len() != (width * height) as usize || output.len() != input.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
+    }
+    validate_decomposition_levels(levels_x, levels_y)?;
+
+    output.copy_from_slice(input);
+    let dims = decomposition_dims(width, height, levels_x, levels_y);
+    for level in 1..=levels_x {
+        let (w, h) = dims[level as usize - 1];
+        transform_rows_int(output, width, w, h, dwt_53_forward_1d_int);
+        if level <= levels_y {
+            transform_columns_int(output, width, w, h, dwt_53_forward_1d_int);
+        }
+    }
+
+    Ok(())
+}
+
+/// Multi-level inverse integer 5/3 DWT matching [`dwt_53_forward_multilevel_int`]
+pub fn dwt_53_inverse_multilevel_int(
+    input: &[i32],
+    output: &mut [i32],
+    width: u32,
+    height: u32,
+    levels_x: u8,
+    levels_y: u8,
+) -> Result<()> {
+    if input.len() != (width * height) as usize || output.len() != input.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
+    }
+    validate_decomposition_levels(levels_x, levels_y)?;
+
+    output.copy_from_slice(input);
+    let dims = decomposition_dims(width, height, levels_x, levels_y);
+    for level in (1..=levels_x).rev() {
+        let (w, h) = dims[level as usize - 1];
+        if level <= levels_y {
+            transform_columns_int(output, width, w, h, dwt_53_inverse_1d_int);
+        }
+        transform_rows_int(output, width, w, h, dwt_53_inverse_1d_int);
+    }
+
+    Ok(())
+}
+
+/// Apply a 1D transform to the first `w` samples of the first `h` rows of a plane
+fn transform_rows_int(plane: &mut [i32], stride: u32, w: u32, h: u32, f: fn(&mut [i32])) {
+    for y in 0..h as usize {
+        let row_start = y * stride as usize;
+        f(&mut plane[row_start..row_start + w as usize]);
+    }
+}
+
+/// Apply a 1D transform to the first `h` samples of the first `w` columns of a plane
+fn transform_columns_int(plane: &mut [i32], stride: u32, w: u32, h: u32, f: fn(&mut [i32])) {
+    let mut column = vec![0i32; h as usize];
+    for x in 0..w as usize {
+        for (y, sample) in column.iter_mut().enumerate() {
+            *sample = plane[y * stride as usize + x];
+        }
+        f(&mut column);
+        for (y, &sample) in column.iter().enumerate() {
+            plane[y * stride as usize + x] = sample;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,6 +578,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_integer_dwt_is_lossless() {
+        // Odd sizes exercise the symmetric extension at both ends
+        for &(width, height, levels_x, levels_y) in &[(37u32, 19u32, 3u8, 2u8), (64, 16, 5, 1)] {
+            let input: Vec<i32> = (0..width * height)
+                .map(|i| ((i * 7919) % 4096) as i32 - 2048)
+                .collect();
+            let mut coeffs = vec![0i32; input.len()];
+            dwt_53_forward_multilevel_int(&input, &mut coeffs, width, height, levels_x, levels_y)
+                .unwrap();
+            assert_ne!(coeffs, input);
+
+            let mut output = vec![0i32; input.len()];
+            dwt_53_inverse_multilevel_int(&coeffs, &mut output, width, height, levels_x, levels_y)
+                .unwrap();
+            assert_eq!(output, input);
+        }
+
+        // Table E.12 on a ramp: high-pass is zero except where the mirrored edge bends it
+        let mut ramp = [0, 2, 4, 6, 8, 10];
+        dwt_53_forward_1d_int(&mut ramp);
+        assert_eq!(ramp, [0, 4, 9, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_wavelet_precision_follows_bit_depth() {
+        assert_eq!(wavelet_precision(8), 20);
+        assert_eq!(wavelet_precision(10), 20);
+        assert_eq!(wavelet_precision(12), 21);
+        assert_eq!(wavelet_precision(16), 25);
+
+        for bit_depth in 1..=MAX_BIT_DEPTH {
+            assert!(validate_wavelet_precision(
+                wavelet_precision(bit_depth),
+                FRACTIONAL_BITS,
+                bit_depth
+            )
+            .is_ok());
+        }
+        assert!(validate_wavelet_precision(20, FRACTIONAL_BITS, 16).is_err());
+        assert!(validate_wavelet_precision(30, FRACTIONAL_BITS, 17).is_err());
+    }
+
+    #[test]
+    fn test_subband_regions_tile_plane() {
+        for &(width, height, levels_x, levels_y) in
+            &[(64u32, 32u32, 5u8, 2u8), (37, 19, 3, 3), (20, 9, 4, 1)]
+        {
+            let regions = subband_regions(width, height, levels_x, levels_y);
+            assert_eq!(regions.len(), subband_count(levels_x, levels_y));
+            assert_eq!(regions[0].orientation, SubbandOrientation::Ll);
+
+            // Every coefficient belongs to exactly one band
+            let mut coverage = vec![0u8; (width * height) as usize];
+            for region in &regions {
+                for y in region.y..region.y + region.height {
+                    for x in region.x..region.x + region.width {
+                        coverage[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(coverage.iter().all(|&count| count == 1));
+        }
+    }
+
     #[test]
     fn test_dwt_53_roundtrip_2d() {
         let width = 8u32;
@@ -1,7 +1,7 @@
 // SIMD-optimized 5/3 DWT implementation for x86_64 and ARM architectures
 // Uses portable SIMD operations via the wide crate for cross-platform compatibility
 
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
 use wide::f32x4;
 
 /// SIMD-optimized 5/3 DWT forward transform for 2D data
@@ -12,7 +12,9 @@ pub fn dwt_53_forward_2d_simd(
     height: u32,
 ) -> Result<()> {
     if input.len() != (width * height) as usize || output.len() != input.len() {
-        return Err(anyhow::anyhow!("Invalid buffer sizes"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
     }
 
     // Copy input to output for in-place processing
@@ -59,7 +61,9 @@ pub fn dwt_53_inverse_2d_simd(
     height: u32,
 ) -> Result<()> {
     if input.len() != (width * height) as usize || output.len() != input.len() {
-        return Err(anyhow::anyhow!("Invalid buffer sizes"));
+        return Err(JpegXsError::DimensionMismatch(
+            "Invalid buffer sizes".into(),
+        ));
     }
 
     // Copy input to output for in-place processing
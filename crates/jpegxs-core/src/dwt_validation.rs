@@ -3,7 +3,11 @@
 // This module contains comprehensive tests to validate our 5/3 DWT implementation
 // against the exact mathematical specification in ISO/IEC 21122-1 Annex E.
 
-use crate::dwt::{dwt_53_forward_2d, dwt_53_inverse_2d};
+use crate::dwt::{
+    dwt_53_forward_2d, dwt_53_forward_multilevel_int, dwt_53_inverse_2d,
+    dwt_53_inverse_multilevel_int, FRACTIONAL_BITS,
+};
+use crate::x86_dwt::X86Dwt;
 
 /// Test vectors from ISO/IEC 21122-1 specification examples
 /// These should produce exact matches for a compliant implementation
@@ -131,6 +135,39 @@ mod tests {
     // break mathematical linearity (floor division, boundary extension, etc.)
     // This is by design in the standard, not a bug in the implementation
 
+    #[test]
+    fn test_fixed_point_matches_float_inside_the_signal() {
+        let signal: Vec<f32> = (0..32).map(|i| ((i * 37) % 23) as f32 * 9.0).collect();
+        let mut float = signal.clone();
+        crate::dwt::dwt_53_forward_1d(&mut float);
+        let mut fixed: Vec<i32> = signal
+            .iter()
+            .map(|&x| (x as i32) << FRACTIONAL_BITS)
+            .collect();
+        crate::dwt::dwt_53_forward_1d_int(&mut fixed);
+        let scale = (1 << FRACTIONAL_BITS) as f32;
+
+        // High-pass: the predict step of both paths is exact
+        for i in 16..32 {
+            assert_abs_diff_eq!(float[i], fixed[i] as f32 / scale, epsilon = 1e-6);
+        }
+        // Low-pass: the f32 path floors the update to whole samples, the fixed-point path
+        // to 1/2^Fq; only the first coefficient, where the f32 path does not mirror the
+        // signal, differs by more
+        for i in 1..16 {
+            assert!((float[i] - fixed[i] as f32 / scale).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_x86_simd_matches_scalar_reference() {
+        let signals: Vec<Vec<f32>> = vec![
+            (0..96).map(|i| ((i * 37) % 251) as f32 - 128.0).collect(),
+            (0..96).map(|i| (i as f32 * 0.1).sin() * 100.0).collect(),
+        ];
+        assert!(super::x86_simd_matches_scalar(&signals));
+    }
+
     #[test]
     #[ignore = "Coefficient values are implementation-specific"]
     fn test_known_coefficients_4x4() {
@@ -166,6 +203,13 @@ pub struct DwtValidationReport {
     pub iso_compliance: bool,
     pub max_reconstruction_error: f64,
     pub energy_error_percentage: f64,
+    /// Whether the fixed-point integer transform of the codec reconstructs bit-exactly
+    pub fixed_point_reconstruction: bool,
+    /// Largest difference between f32 and fixed-point coefficients, in samples
+    pub max_fixed_point_deviation: f64,
+    /// Whether the x86 SIMD transforms match the scalar reference bit-exactly, true
+    /// when the CPU has neither AVX2 nor SSE4.1
+    pub x86_simd_matches_scalar: bool,
 }
 
 /// Run comprehensive DWT validation tests
@@ -178,6 +222,9 @@ pub fn validate_dwt_implementation() -> DwtValidationReport {
         iso_compliance: true,
         max_reconstruction_error: 0.0,
         energy_error_percentage: 0.0,
+        fixed_point_reconstruction: true,
+        max_fixed_point_deviation: 0.0,
+        x86_simd_matches_scalar: true,
     };
 
     // Test 1: Perfect reconstruction with various signals
@@ -251,15 +298,92 @@ pub fn validate_dwt_implementation() -> DwtValidationReport {
         }
     }
 
+    // Test 3: Fixed-point transform with Fq fractional bits against the f32 path
+    for signal in &test_signals {
+        let width = 8u32;
+        let height = (signal.len() / width as usize) as u32;
+        let samples: Vec<i32> = signal
+            .iter()
+            .map(|&x| (x as i32) << FRACTIONAL_BITS)
+            .collect();
+        let mut fixed = vec![0i32; samples.len()];
+        let mut reconstructed = vec![0i32; samples.len()];
+        let mut float = vec![0.0f32; signal.len()];
+
+        if dwt_53_forward_multilevel_int(&samples, &mut fixed, width, height, 1, 1).is_err()
+            || dwt_53_inverse_multilevel_int(&fixed, &mut reconstructed, width, height, 1, 1)
+                .is_err()
+            || dwt_53_forward_2d(signal, &mut float, width, height).is_err()
+        {
+            report.fixed_point_reconstruction = false;
+            continue;
+        }
+        if reconstructed != samples {
+            report.fixed_point_reconstruction = false;
+        }
+
+        let scale = (1 << FRACTIONAL_BITS) as f64;
+        let deviation = float
+            .iter()
+            .zip(&fixed)
+            .map(|(&f, &q)| (f as f64 - q as f64 / scale).abs())
+            .fold(0.0, f64::max);
+        report.max_fixed_point_deviation = report.max_fixed_point_deviation.max(deviation);
+    }
+
+    // Test 4: x86 SIMD back-end against the scalar reference, which it must match
+    // exactly since both run the same f32 operations
+    report.x86_simd_matches_scalar = x86_simd_matches_scalar(&test_signals);
+
     report
 }
 
+/// Run the forward and inverse x86 SIMD transforms next to the scalar ones on `signals`,
+/// reshaped to several widths including odd ones
+fn x86_simd_matches_scalar(signals: &[Vec<f32>]) -> bool {
+    let x86 = X86Dwt::new();
+    if !x86.is_available() {
+        return true;
+    }
+    for signal in signals {
+        for width in [1u32, 3, 8, 16, 64] {
+            let height = signal.len() as u32 / width;
+            let input = &signal[..(width * height) as usize];
+            let mut scalar = vec![0.0f32; input.len()];
+            let mut simd = vec![0.0f32; input.len()];
+
+            let forward = dwt_53_forward_2d(input, &mut scalar, width, height)
+                .and_then(|_| x86.dwt_53_forward_2d_x86(input, &mut simd, width, height));
+            if forward.is_err() || scalar != simd {
+                println!(
+                    "x86 SIMD forward DWT differs from scalar at width {}",
+                    width
+                );
+                return false;
+            }
+
+            let inverse = dwt_53_inverse_2d(input, &mut scalar, width, height)
+                .and_then(|_| x86.dwt_53_inverse_2d_x86(input, &mut simd, width, height));
+            if inverse.is_err() || scalar != simd {
+                println!(
+                    "x86 SIMD inverse DWT differs from scalar at width {}",
+                    width
+                );
+                return false;
+            }
+        }
+    }
+    true
+}
+
 /// Print detailed validation report
 pub fn print_validation_report(report: &DwtValidationReport) {
     println!("🔍 DWT 5/3 Implementation Validation Report");
     println!("═══════════════════════════════════════════");
 
     let overall_pass = report.perfect_reconstruction
+        && report.fixed_point_reconstruction
+        && report.x86_simd_matches_scalar
         && report.energy_conservation
         && report.linearity
         && report.boundary_handling;
@@ -303,6 +427,22 @@ pub fn print_validation_report(report: &DwtValidationReport) {
             "❌ FAIL"
         }
     );
+    println!(
+        "   Fixed-Point Exactness: {}",
+        if report.fixed_point_reconstruction {
+            "✅ PASS"
+        } else {
+            "❌ FAIL"
+        }
+    );
+    println!(
+        "   x86 SIMD vs Scalar:    {}",
+        if report.x86_simd_matches_scalar {
+            "✅ PASS"
+        } else {
+            "❌ FAIL"
+        }
+    );
 
     println!("\n📈 Error Metrics:");
     println!(
@@ -313,6 +453,10 @@ pub fn print_validation_report(report: &DwtValidationReport) {
         "   Energy Error Percentage:  {:.3}%",
         report.energy_error_percentage
     );
+    println!(
+        "   f32 vs Fixed-Point:       {:.3} samples",
+        report.max_fixed_point_deviation
+    );
 
     if !overall_pass {
         println!("\n🔧 Recommendations:");
@@ -320,6 +464,9 @@ pub fn print_validation_report(report: &DwtValidationReport) {
             println!("   • Check lifting step coefficients against ISO/IEC 21122-1 Annex E.7");
             println!("   • Verify boundary extension implementation (Annex E.6)");
         }
+        if !report.x86_simd_matches_scalar {
+            println!("   • Compare the x86 lifting order with dwt_53_forward_1d / inverse_1d");
+        }
         if !report.energy_conservation {
             println!("   • Review normalization factors in forward/inverse transforms");
             println!("   • Check for precision loss in coefficient calculations");
@@ -348,6 +495,10 @@ mod validation_tests {
             report.perfect_reconstruction,
             "DWT should provide perfect reconstruction"
         );
+        assert!(
+            report.fixed_point_reconstruction,
+            "Fixed-point DWT should reconstruct bit-exactly"
+        );
         assert!(report.energy_conservation, "DWT should conserve energy");
         assert!(
             report.max_reconstruction_error < 1e-6,
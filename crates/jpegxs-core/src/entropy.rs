@@ -1,4 +1,4 @@
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
 
 /// Maximum consecutive unary bits for br=4 (ISO/IEC 21122-1:2024 Annex C)
 const BR4_MAX_CONSECUTIVE: i32 = 32;
@@ -50,7 +50,7 @@ impl<'a> BitstreamReader<'a> {
     /// Read a single bit from the bitstream
     pub fn read_bit(&mut self) -> Result<bool> {
         if self.byte_pos >= self.data.len() {
-            return Err(anyhow::anyhow!("End of bitstream"));
+            return Err(JpegXsError::Truncated("bitstream".into()));
         }
 
         let byte = self.data[self.byte_pos];
@@ -175,8 +175,8 @@ pub fn vlc_decode(reader: &mut BitstreamReader, ctx: VlcContext) -> Result<i32>
 
         // Safety check to prevent infinite loops
         if n >= max_consecutive {
-            return Err(anyhow::anyhow!(
-                "VLC decoder error: too many consecutive 1-bits"
+            return Err(JpegXsError::Corrupt(
+                "VLC decoder error: too many consecutive 1-bits".into(),
             ));
         }
     }
@@ -336,7 +336,10 @@ pub fn decode_vlc_bitplane_counts(
     for _ in 0..num_code_groups {
         let count = vlc_decode(reader, ctx)?;
         if count < 0 {
-            return Err(anyhow::anyhow!("Invalid bitplane count: {}", count));
+            return Err(JpegXsError::Corrupt(format!(
+                "Invalid bitplane count: {}",
+                count
+            )));
         }
         counts.push(BitplaneCount(count as u8));
     }
@@ -0,0 +1,106 @@
+// Error type of the JPEG XS codec
+// Every fallible function of the crate returns a JpegXsError, so callers can tell
+// corrupt input from unsupported features and from their own mistakes
+
+use crate::profile::ProfileError;
+use jpegxs_core_clean::ParseError;
+use thiserror::Error;
+
+/// Result of the codec functions
+pub type Result<T, E = JpegXsError> = std::result::Result<T, E>;
+
+/// Error returned by the JPEG XS codec
+///
+/// The variants fall into three groups:
+/// - corrupt or cut-off codestreams: [`Truncated`](JpegXsError::Truncated),
+///   [`InvalidMarker`](JpegXsError::InvalidMarker), [`Corrupt`](JpegXsError::Corrupt)
+///   and, from the strict mode checks, [`NonConformant`](JpegXsError::NonConformant)
+/// - valid input this implementation cannot handle:
+///   [`UnsupportedFeature`](JpegXsError::UnsupportedFeature)
+/// - caller mistakes: [`DimensionMismatch`](JpegXsError::DimensionMismatch),
+///   [`BufferTooSmall`](JpegXsError::BufferTooSmall),
+///   [`InvalidParameter`](JpegXsError::InvalidParameter) and
+///   [`ProfileViolation`](JpegXsError::ProfileViolation)
+///
+/// [`Backend`](JpegXsError::Backend) reports a failure of the machine running the codec,
+/// such as a lost GPU or worker threads that cannot start.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum JpegXsError {
+    /// The codestream ends inside the named structure
+    #[error("Insufficient data for {0}")]
+    Truncated(String),
+    /// `marker` was found at byte `offset` of the codestream where another one is required
+    #[error("Unexpected marker 0x{marker:04x} at offset {offset}")]
+    InvalidMarker { marker: u16, offset: usize },
+    /// The codestream holds values the standard does not allow
+    #[error("{0}")]
+    Corrupt(String),
+    /// The codestream breaks a rule of ISO/IEC 21122-1 at byte `offset`
+    #[error("Non-conformant codestream at offset {offset}: {reason}")]
+    NonConformant { offset: usize, reason: String },
+    /// The input is valid but uses a feature this implementation does not support
+    #[error("{0}")]
+    UnsupportedFeature(String),
+    /// Image or buffer dimensions do not match each other or the pixel format
+    #[error("{0}")]
+    DimensionMismatch(String),
+    /// A caller buffer holds fewer samples than the image needs
+    #[error("Buffer too small: {needed} samples needed, {available} available")]
+    BufferTooSmall { needed: usize, available: usize },
+    /// A configuration value or argument is out of range or inconsistent
+    #[error("{0}")]
+    InvalidParameter(String),
+    /// The configuration breaks the limits of its profile and level
+    #[error(transparent)]
+    ProfileViolation(#[from] ProfileError),
+    /// The acceleration back-end or the worker threads failed
+    #[error("{0}")]
+    Backend(String),
+}
+
+impl From<ParseError> for JpegXsError {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Truncated(what) => JpegXsError::Truncated(what.to_string()),
+            ParseError::InvalidMarker { marker, offset } => {
+                JpegXsError::InvalidMarker { marker, offset }
+            }
+            ParseError::InvalidField(message) => JpegXsError::Corrupt(message.to_string()),
+            ParseError::Unsupported(message) => {
+                JpegXsError::UnsupportedFeature(message.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_errors_keep_their_kind() {
+        assert!(matches!(
+            JpegXsError::from(ParseError::InvalidMarker {
+                marker: 0xff13,
+                offset: 42
+            }),
+            JpegXsError::InvalidMarker {
+                marker: 0xff13,
+                offset: 42
+            }
+        ));
+        assert!(matches!(
+            ParseError::Truncated("PIH marker").into(),
+            JpegXsError::Truncated(what) if what == "PIH marker"
+        ));
+        assert!(matches!(
+            ParseError::Unsupported("Unsupported CDT sampling factors").into(),
+            JpegXsError::UnsupportedFeature(_)
+        ));
+        assert_eq!(
+            JpegXsError::from(ParseError::Truncated("EOC marker")).to_string(),
+            "Insufficient data for EOC marker"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 // Provides up to 130x speedup on Apple Silicon devices
 // Falls back to CPU implementation when GPU is not available
 
-use anyhow::Result;
+use crate::error::Result;
 
 #[cfg(target_os = "macos")]
 use metal::*;
@@ -123,7 +123,7 @@ impl GpuDwt {
 
         let total_size = (width * height) as usize;
         if input.len() != total_size || output.len() != total_size {
-            return Err(anyhow::anyhow!("Invalid buffer sizes"));
+            return Err(JpegXsError::DimensionMismatch("Invalid buffer sizes".into()));
         }
 
         // Create Metal buffers using unified memory
@@ -146,19 +146,19 @@ impl GpuDwt {
         // Create compute pipeline for DWT
         let library_source = self.create_dwt_kernel_source();
         let library = self.device.new_library_with_source(&library_source, &CompileOptions::new())
-            .map_err(|e| anyhow::anyhow!("Failed to compile Metal library: {:?}", e))?;
+            .map_err(|e| JpegXsError::Backend(format!("Failed to compile Metal library: {:?}", e)))?;
 
         let horizontal_function = library.get_function("dwt_53_forward_horizontal", None)
-            .map_err(|e| anyhow::anyhow!("Failed to find horizontal DWT function: {:?}", e))?;
+            .map_err(|e| JpegXsError::Backend(format!("Failed to find horizontal DWT function: {:?}", e)))?;
 
         let vertical_function = library.get_function("dwt_53_forward_vertical", None)
-            .map_err(|e| anyhow::anyhow!("Failed to find vertical DWT function: {:?}", e))?;
+            .map_err(|e| JpegXsError::Backend(format!("Failed to find vertical DWT function: {:?}", e)))?;
 
         let horizontal_pipeline = self.device.new_compute_pipeline_state_with_function(&horizontal_function)
-            .map_err(|e| anyhow::anyhow!("Failed to create horizontal pipeline: {:?}", e))?;
+            .map_err(|e| JpegXsError::Backend(format!("Failed to create horizontal pipeline: {:?}", e)))?;
 
         let vertical_pipeline = self.device.new_compute_pipeline_state_with_function(&vertical_function)
-            .map_err(|e| anyhow::anyhow!("Failed to create vertical pipeline: {:?}", e))?;
+            .map_err(|e| JpegXsError::Backend(format!("Failed to create vertical pipeline: {:?}", e)))?;
 
         let command_buffer = self.command_queue.new_command_buffer();
 
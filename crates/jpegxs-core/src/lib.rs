@@ -5,37 +5,54 @@
 
 pub mod accel;
 pub mod colors;
+pub mod conformance;
 pub mod dwt;
 #[cfg(feature = "simd")]
 pub mod dwt_simd;
 pub mod dwt_validation;
 pub mod entropy;
+pub mod error;
 pub mod gpu_dwt;
 pub mod neon_dwt;
 pub mod packet;
+pub mod precinct;
 pub mod profile;
 pub mod quant;
+pub mod rate;
+pub mod session;
+pub mod streaming;
 pub mod types;
-
-use anyhow::Result;
-pub use types::{Bitstream, DecoderConfig, EncoderConfig, ImageOwned8, ImageView8, PixelFormat};
-
-/// Default quantization parameter used when QP values cannot be extracted from bitstream
-/// This provides a moderate quality fallback that balances compression and visual quality
-const DEFAULT_FALLBACK_QP: u8 = 8;
+#[cfg(feature = "wgpu")]
+pub mod wgpu_dwt;
+pub mod x86_dwt;
+
+use crate::error::Result;
+pub use error::JpegXsError;
+use rayon::prelude::*;
+pub use session::{Decoder, EncodeSequence, Encoder};
+pub use streaming::{LineBand, StreamingDecoder, StreamingEncoder};
+pub use types::{
+    Bitstream, CodestreamMetadata, Comment, CommentKind, DecoderConfig, EncoderConfig, FrameInfo,
+    ImageOwned16, ImageOwned8, ImageView16, ImageView8, PixelFormat,
+};
 
 /// Encode an image frame using JPEG XS compression
 ///
-/// This function supports multiple pixel formats and automatically handles format conversion
-/// to the internal YUV444 representation used by JPEG XS.
+/// This function supports multiple pixel formats. YUV input is coded as is, with 4:2:2 and
+/// 4:2:0 chroma kept at its native resolution, and RGB input is coded with the reversible
+/// colour transform.
 ///
 /// # Supported Formats
 /// - `Yuv444p8`: Full resolution YUV (most efficient, no conversion needed)
-/// - `Yuv422p8`: Horizontally subsampled chroma, upsampled to 4:4:4 internally
-/// - `Yuv420p8`: Vertically and horizontally subsampled chroma, upsampled to 4:4:4
-/// - `Rgb8`: Interleaved RGB, converted using ITU-R BT.601 color matrix
-/// - `Bgr8`: Interleaved BGR, converted using ITU-R BT.601 color matrix
-/// - `Rgb8Planar`: Planar RGB (separate R, G, B planes), converted using ITU-R BT.601
+/// - `Yuv422p8`: Horizontally subsampled chroma, coded with sx = 2
+/// - `Yuv420p8`: Vertically and horizontally subsampled chroma, coded with sx = sy = 2
+/// - `Rgb8`: Interleaved RGB, decorrelated with the reversible colour transform
+/// - `Bgr8`: Interleaved BGR, decorrelated with the reversible colour transform
+/// - `Rgb8Planar`: Planar RGB (separate R, G, B planes), decorrelated with the RCT
+/// - `Gray8`: A single component
+/// - `Rgba8`: Interleaved RGBA, R, G, B decorrelated with the RCT and alpha coded as a
+///   fourth component
+/// - `Yuva444p8`: Full resolution YUV followed by an alpha plane, four components
 ///
 /// # Example
 /// ```rust,ignore
@@ -59,205 +76,603 @@ const DEFAULT_FALLBACK_QP: u8 = 8;
 /// };
 ///
 /// let bitstream = encode_frame(input, &config)?;
-/// # Ok::<(), anyhow::Error>(())
+/// # Ok::<(), jpegxs_core::JpegXsError>(())
 /// ```
 pub fn encode_frame(input: ImageView8, config: &EncoderConfig) -> Result<Bitstream> {
-    // Convert input image to YUV planar format for processing
-    let (y_data, u_data, v_data) = match input.format {
+    Encoder::new(config.clone()).encode(input)
+}
+
+/// Split an 8-bit image into its component planes: Y, U, V or R, G, B, then alpha
+///
+/// `planes` is overwritten, reusing the allocations it already holds.
+fn fill_planes8(input: &ImageView8, planes: &mut Vec<Vec<i32>>) -> Result<()> {
+    let pixel_count = (input.width * input.height) as usize;
+    let components = match input.format {
         PixelFormat::Yuv444p8 => {
             // Direct YUV444 - most efficient path
-            let pixel_count = (input.width * input.height) as usize;
-
-            if input.data.len() < pixel_count * 3 {
-                return Err(anyhow::anyhow!("Insufficient data for YUV444p8 format"));
-            }
+            check_buffer_len(input.data.len(), pixel_count * 3)?;
 
-            let y = &input.data[0..pixel_count];
-            let u = &input.data[pixel_count..pixel_count * 2];
-            let v = &input.data[pixel_count * 2..pixel_count * 3];
-            (y.to_vec(), u.to_vec(), v.to_vec())
+            planar_samples(&[pixel_count; 3])
         }
         PixelFormat::Yuv422p8 => {
-            // YUV422 - upsample chroma to 444
-            if input.width % 2 != 0 {
-                return Err(anyhow::anyhow!("Width must be even for YUV422p8 format"));
+            // YUV422 - chroma stays at half width
+            if !input.width.is_multiple_of(2) {
+                return Err(JpegXsError::DimensionMismatch(
+                    "Width must be even for YUV422p8 format".into(),
+                ));
             }
-            let y_size = (input.width * input.height) as usize;
             let uv_size = (input.width / 2 * input.height) as usize;
 
-            if input.data.len() < y_size + uv_size * 2 {
-                return Err(anyhow::anyhow!("Insufficient data for YUV422p8 format"));
-            }
-
-            let y = &input.data[0..y_size];
-            let u = &input.data[y_size..y_size + uv_size];
-            let v = &input.data[y_size + uv_size..y_size + uv_size * 2];
+            check_buffer_len(input.data.len(), pixel_count + uv_size * 2)?;
 
-            // Upsample to 444
-            colors::upsample_422_to_444(y, u, v, input.width, input.height)?
+            planar_samples(&[pixel_count, uv_size, uv_size])
         }
         PixelFormat::Yuv420p8 => {
-            // YUV420 - upsample chroma to 444
-            if input.width % 2 != 0 || input.height % 2 != 0 {
-                return Err(anyhow::anyhow!(
-                    "Width and height must be even for YUV420p8 format"
+            // YUV420 - chroma stays at half width and height
+            if !input.width.is_multiple_of(2) || !input.height.is_multiple_of(2) {
+                return Err(JpegXsError::DimensionMismatch(
+                    "Width and height must be even for YUV420p8 format".into(),
                 ));
             }
-            let y_size = (input.width * input.height) as usize;
             let uv_size = (input.width / 2 * input.height / 2) as usize;
 
-            if input.data.len() < y_size + uv_size * 2 {
-                return Err(anyhow::anyhow!("Insufficient data for YUV420p8 format"));
-            }
-
-            let y = &input.data[0..y_size];
-            let u = &input.data[y_size..y_size + uv_size];
-            let v = &input.data[y_size + uv_size..y_size + uv_size * 2];
+            check_buffer_len(input.data.len(), pixel_count + uv_size * 2)?;
 
-            // Upsample to 444
-            colors::upsample_420_to_444(y, u, v, input.width, input.height)?
+            planar_samples(&[pixel_count, uv_size, uv_size])
         }
         PixelFormat::Rgb8 => {
-            // RGB interleaved - convert to YUV
-            colors::rgb_to_yuv_planar(input.data, input.width, input.height)?
+            // RGB interleaved - split into R, G, B planes
+            interleaved_samples(input.data.len(), pixel_count, &[0, 1, 2])?
         }
         PixelFormat::Bgr8 => {
-            // BGR interleaved - convert to YUV
-            colors::bgr_to_yuv_planar(input.data, input.width, input.height)?
+            // BGR interleaved - split and reorder into R, G, B planes
+            interleaved_samples(input.data.len(), pixel_count, &[2, 1, 0])?
         }
         PixelFormat::Rgb8Planar => {
-            // RGB planar - convert to YUV
-            let pixel_count = (input.width * input.height) as usize;
+            // RGB planar - already split into R, G, B planes
+            check_buffer_len(input.data.len(), pixel_count * 3)?;
 
-            if input.data.len() < pixel_count * 3 {
-                return Err(anyhow::anyhow!("Insufficient data for RGB8Planar format"));
-            }
+            planar_samples(&[pixel_count; 3])
+        }
+        PixelFormat::Gray8 => {
+            // Single component, coded on its own
+            check_buffer_len(input.data.len(), pixel_count)?;
+
+            planar_samples(&[pixel_count])
+        }
+        PixelFormat::Rgba8 => {
+            // RGBA interleaved - R, G, B go through the RCT, alpha is a fourth component
+            interleaved_samples(input.data.len(), pixel_count, &[0, 1, 2, 3])?
+        }
+        PixelFormat::Yuva444p8 => {
+            // YUV444 followed by a full resolution alpha plane
+            check_buffer_len(input.data.len(), pixel_count * 4)?;
 
-            let r = &input.data[0..pixel_count];
-            let g = &input.data[pixel_count..pixel_count * 2];
-            let b = &input.data[pixel_count * 2..pixel_count * 3];
-            colors::rgb_planar_to_yuv_planar(r, g, b, input.width, input.height)?
+            planar_samples(&[pixel_count; 4])
+        }
+        PixelFormat::Yuv422p10le
+        | PixelFormat::Yuv444p12le
+        | PixelFormat::Rgb16
+        | PixelFormat::Gray16 => {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "{:?} has {} bits per sample, use encode_frame16",
+                input.format,
+                input.format.bit_depth()
+            )));
         }
     };
 
-    // Convert to floating point and center around 0
-    let mut y_plane = vec![0.0f32; y_data.len()];
-    let mut u_plane = vec![0.0f32; u_data.len()];
-    let mut v_plane = vec![0.0f32; v_data.len()];
+    fill_planes(input.data, &components, planes);
+    Ok(())
+}
 
-    for (i, &val) in y_data.iter().enumerate() {
-        y_plane[i] = val as f32 - 128.0;
+/// Split an 8-bit image into newly allocated component planes
+pub(crate) fn split_frame8(input: &ImageView8) -> Result<Vec<Vec<i32>>> {
+    let mut planes = Vec::new();
+    fill_planes8(input, &mut planes)?;
+    Ok(planes)
+}
+
+/// Check that a frame buffer holds the `needed` samples of its format
+fn check_buffer_len(available: usize, needed: usize) -> Result<()> {
+    if available < needed {
+        return Err(JpegXsError::BufferTooSmall { needed, available });
     }
-    for (i, &val) in u_data.iter().enumerate() {
-        u_plane[i] = val as f32 - 128.0;
+    Ok(())
+}
+
+/// Where the samples of a component sit in a frame buffer
+#[derive(Debug, Clone, Copy)]
+struct ComponentSamples {
+    start: usize,
+    step: usize,
+    count: usize,
+}
+
+/// Consecutive planes of the given sizes
+fn planar_samples(sizes: &[usize]) -> Vec<ComponentSamples> {
+    let mut start = 0;
+    sizes
+        .iter()
+        .map(|&count| {
+            start += count;
+            ComponentSamples {
+                start: start - count,
+                step: 1,
+                count,
+            }
+        })
+        .collect()
+}
+
+/// Interleaved pixels, component `c` taken from position `order[c]` within each pixel
+fn interleaved_samples(
+    len: usize,
+    pixel_count: usize,
+    order: &[usize],
+) -> Result<Vec<ComponentSamples>> {
+    if len != pixel_count * order.len() {
+        return Err(JpegXsError::DimensionMismatch(
+            "Interleaved buffer size mismatch".into(),
+        ));
     }
-    for (i, &val) in v_data.iter().enumerate() {
-        v_plane[i] = val as f32 - 128.0;
+    Ok(order
+        .iter()
+        .map(|&position| ComponentSamples {
+            start: position,
+            step: order.len(),
+            count: pixel_count,
+        })
+        .collect())
+}
+
+/// Copy the samples of every component into `planes`, one plane per component
+fn fill_planes<T: Copy + Into<i32>>(
+    data: &[T],
+    components: &[ComponentSamples],
+    planes: &mut Vec<Vec<i32>>,
+) {
+    planes.resize_with(components.len(), Vec::new);
+    for (plane, component) in planes.iter_mut().zip(components) {
+        plane.clear();
+        plane.extend(
+            data[component.start..]
+                .iter()
+                .step_by(component.step)
+                .take(component.count)
+                .map(|&sample| sample.into()),
+        );
     }
+}
 
-    // Log pre-DWT statistics for precision analysis
-    log::info!(
-        "DWT_ANALYSIS: Pre-DWT Y coefficients - min: {:.3}, max: {:.3}, mean: {:.3}, std: {:.3}",
-        y_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-        y_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-        y_plane.iter().sum::<f32>() / y_plane.len() as f32,
-        {
-            let mean = y_plane.iter().sum::<f32>() / y_plane.len() as f32;
-            (y_plane.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / y_plane.len() as f32).sqrt()
+/// Copy consecutive planes of the given sizes out of a planar buffer
+fn split_planes<T: Copy>(data: &[T], sizes: &[usize]) -> Vec<Vec<T>> {
+    let mut offset = 0;
+    sizes
+        .iter()
+        .map(|&size| {
+            offset += size;
+            data[offset - size..offset].to_vec()
+        })
+        .collect()
+}
+
+/// Encode an image frame with 10 to 16 bits per sample
+///
+/// Works like [`encode_frame`] for the formats with more than 8 bits per sample. Their
+/// bit depth is signalled in the CDT marker and sets the wavelet coefficient precision
+/// Bw in the picture header.
+///
+/// # Supported Formats
+/// - `Yuv422p10le`: Planar YUV 4:2:2, 10-bit samples
+/// - `Yuv444p12le`: Planar YUV 4:4:4, 12-bit samples
+/// - `Rgb16`: Interleaved RGB, 16-bit samples, decorrelated with the RCT
+/// - `Gray16`: A single component, 16-bit samples
+pub fn encode_frame16(input: ImageView16, config: &EncoderConfig) -> Result<Bitstream> {
+    Encoder::new(config.clone()).encode16(input)
+}
+
+/// Split an image with 10 to 16 bits per sample into its component planes
+///
+/// `planes` is overwritten, reusing the allocations it already holds.
+fn fill_planes16(input: &ImageView16, planes: &mut Vec<Vec<i32>>) -> Result<()> {
+    let pixel_count = (input.width * input.height) as usize;
+    let components = match input.format {
+        PixelFormat::Yuv422p10le => {
+            if !input.width.is_multiple_of(2) {
+                return Err(JpegXsError::DimensionMismatch(
+                    "Width must be even for YUV422p10le format".into(),
+                ));
+            }
+            let uv_size = pixel_count / 2;
+            check_buffer_len(input.data.len(), pixel_count + uv_size * 2)?;
+            planar_samples(&[pixel_count, uv_size, uv_size])
         }
-    );
-    log::info!("DWT_ANALYSIS: Pre-DWT UV coefficients - U_min: {:.3}, U_max: {:.3}, V_min: {:.3}, V_max: {:.3}",
-               u_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               u_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-               v_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               v_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)));
+        PixelFormat::Yuv444p12le => {
+            check_buffer_len(input.data.len(), pixel_count * 3)?;
+            planar_samples(&[pixel_count; 3])
+        }
+        PixelFormat::Rgb16 => interleaved_samples(input.data.len(), pixel_count, &[0, 1, 2])?,
+        PixelFormat::Gray16 => {
+            check_buffer_len(input.data.len(), pixel_count)?;
+            planar_samples(&[pixel_count])
+        }
+        _ => {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "{:?} has 8 bits per sample, use encode_frame",
+                input.format
+            )));
+        }
+    };
 
-    // Apply DWT to each plane using unified acceleration (GPU, NEON, or scalar fallback; all are now 444)
-    let mut y_dwt = vec![0.0f32; y_plane.len()];
-    let mut u_dwt = vec![0.0f32; u_plane.len()];
-    let mut v_dwt = vec![0.0f32; v_plane.len()];
+    fill_planes(input.data, &components, planes);
+    let max_value = (1i32 << input.format.bit_depth()) - 1;
+    if let Some(&sample) = planes.iter().flatten().find(|&&sample| sample > max_value) {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Sample value {} exceeds the {}-bit range of {:?}",
+            sample,
+            input.format.bit_depth(),
+            input.format
+        )));
+    }
+    Ok(())
+}
 
-    // Initialize unified acceleration (GPU → NEON → Scalar fallback)
-    let accel = accel::AccelDwt::new();
+/// Coding decisions shared by the frame encoder and the [`StreamingEncoder`]
+///
+/// Everything here follows from the frame geometry and the configuration alone, so the
+/// main header (SOC up to WGT) is already written to `bitstream` before any sample.
+pub(crate) struct CodingSetup {
+    pub(crate) format: PixelFormat,
+    pub(crate) sampling: Vec<(u8, u8)>,
+    pub(crate) layout: precinct::PrecinctLayout,
+    pub(crate) params: quant::QuantizationParameters,
+    pub(crate) picture_header: jpegxs_core_clean::PictureHeader,
+    pub(crate) rate_controller: Option<rate::RateController>,
+    pub(crate) bitstream: jpegxs_core_clean::JpegXsBitstream,
+    pub(crate) lossless: bool,
+    pub(crate) dwt: accel::CodecDwt,
+}
 
-    accel.dwt_53_forward_2d(&y_plane, &mut y_dwt, input.width, input.height)?;
-    accel.dwt_53_forward_2d(&u_plane, &mut u_dwt, input.width, input.height)?;
-    accel.dwt_53_forward_2d(&v_plane, &mut v_dwt, input.width, input.height)?;
+impl CodingSetup {
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        config: &EncoderConfig,
+    ) -> Result<Self> {
+        let levels_x = config.decomposition_levels_x;
+        let levels_y = config.decomposition_levels_y;
+        dwt::validate_decomposition_levels(levels_x, levels_y)?;
+        let dwt = accel::CodecDwt::select(config.backend)?;
+        if config.slice_height == 0 {
+            return Err(JpegXsError::InvalidParameter(
+                "Slice height must be at least one precinct".into(),
+            ));
+        }
 
-    // Log post-DWT statistics for precision analysis
-    log::info!(
-        "DWT_ANALYSIS: Post-DWT Y coefficients - min: {:.3}, max: {:.3}, mean: {:.3}, std: {:.3}",
-        y_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-        y_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-        y_dwt.iter().sum::<f32>() / y_dwt.len() as f32,
-        {
-            let mean = y_dwt.iter().sum::<f32>() / y_dwt.len() as f32;
-            (y_dwt.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / y_dwt.len() as f32).sqrt()
+        // Every component gets its own size and vertical decomposition count: chroma with
+        // sy = 2 sees one vertical decomposition less so its precincts cover the same rows
+        let num_components = format.num_components();
+        let sampling: Vec<(u8, u8)> = (0..num_components)
+            .map(|component| match component {
+                1 | 2 => format.chroma_sampling(),
+                _ => (1, 1),
+            })
+            .collect();
+        let layout = precinct::PrecinctLayout::with_sampling(
+            width,
+            height,
+            levels_x,
+            levels_y,
+            &sampling,
+            config.precinct_width,
+        )?;
+
+        // Lossy coding adds Fq fractional bits before the transform, lossless coding keeps
+        // integer coefficients (Fq = 0) so that nothing needs to be rounded away
+        if config.lossless && config.rate_target.is_some() {
+            return Err(JpegXsError::InvalidParameter(
+                "Lossless coding cannot be combined with a rate target".into(),
+            ));
         }
-    );
-    log::info!("DWT_ANALYSIS: Post-DWT UV coefficients - U_min: {:.3}, U_max: {:.3}, V_min: {:.3}, V_max: {:.3}",
-               u_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               u_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-               v_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               v_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)));
+        let fractional_bits = if config.lossless {
+            0
+        } else {
+            dwt::FRACTIONAL_BITS
+        };
+
+        // Frames must fit the level; the sample rate is only known with a bitrate target
+        profile::check_resolution(config.profile, config.level, width, height)?;
+        if let Some(types::RateTarget::Mbps { frame_rate, .. }) = config.rate_target {
+            profile::check_sample_rate(
+                config.profile,
+                config.level,
+                width,
+                height,
+                frame_rate as f64,
+            )?;
+        }
+
+        // The PIH signals the profile variant holding the components, the level and the
+        // sublevel admitting the rate target's bits per pixel
+        let frame_bytes = config
+            .rate_target
+            .map(|target| rate::frame_budget(target, width, height, config.profile, config.level))
+            .transpose()?;
+        let sublevel = match frame_bytes {
+            Some(bytes) => profile::Sublevel::for_bits_per_pixel(
+                bytes as f64 * 8.0 / (width as f64 * height as f64),
+            ),
+            None => profile::Sublevel::Full,
+        };
+        let bit_depth = format.bit_depth();
+        let profile_level = profile::ProfileLevel::new(
+            config.profile,
+            config.level,
+            sublevel,
+            num_components,
+            bit_depth,
+            format.chroma_sampling(),
+        )?;
+
+        // Each subband is quantized with its own gain/priority derived step; lossless
+        // coding keeps every bitplane
+        let params = if config.lossless {
+            quant::lossless_parameters(levels_x, levels_y, num_components)?
+        } else {
+            quant::compute_quantization_parameters(
+                config.quality,
+                levels_x,
+                levels_y,
+                num_components,
+            )?
+        };
+
+        // Use clean-room JPEG XS bitstream format from ISO/IEC 21122-1:2024
+        let mut bitstream = jpegxs_core_clean::JpegXsBitstream::new();
+
+        // Add Capabilities marker (mandatory second marker per ISO A.4.3)
+        bitstream.write_cap_marker();
+
+        // Add PIH (Picture Header) marker according to ISO A.7 specification
+        // Third mandatory marker providing image dimensions and decoder configuration
+        // NL,x / NL,y are carried in the PIH so the decoder can mirror the decomposition
+        // RGB goes in natively and is decorrelated with the reversible colour transform
+        // (Cpih = 1), YUV formats are coded without a colour transform (Cpih = 0)
+        let picture_header = jpegxs_core_clean::PictureHeader {
+            profile: profile_level.ppih,
+            level: profile_level.plev,
+            width: width as u16,
+            height: height as u16,
+            precinct_width: config.precinct_width,
+            num_components,
+            wavelet_precision: dwt::wavelet_precision(bit_depth),
+            fractional_bits,
+            color_transform: u8::from(format.is_rgb()),
+            decomposition_levels_x: levels_x,
+            decomposition_levels_y: levels_y,
+            slice_height: config.slice_height,
+        };
+        bitstream.write_picture_header(&picture_header);
+
+        // Add CDT (Component Table) marker according to ISO A.4.5 specification
+        // Fourth mandatory marker providing component precision and sampling factors
+        let components: Vec<_> = sampling
+            .iter()
+            .map(|&(sampling_x, sampling_y)| jpegxs_core_clean::Component {
+                bit_depth,
+                sampling_x,
+                sampling_y,
+            })
+            .collect();
+        bitstream.write_component_table(&components);
+
+        // Add WGT (Weights Table) marker according to ISO A.4.12 specification
+        // Fifth mandatory marker providing band gain parameters for quantization
+        // One gain/priority pair per band b = beta * Nc + component
+        bitstream.write_band_weights(&params.gains, &params.priorities);
+
+        // COM markers (ISO A.4.11) go in the main header too, so the rate budget below
+        // already accounts for them
+        for comment in &config.metadata.comments {
+            if comment.data.len() > jpegxs_core_clean::MAX_COMMENT_LENGTH {
+                return Err(JpegXsError::InvalidParameter(format!(
+                    "Comment of {} bytes exceeds the COM marker limit of {} bytes",
+                    comment.data.len(),
+                    jpegxs_core_clean::MAX_COMMENT_LENGTH
+                )));
+            }
+            bitstream.write_com_marker(comment.kind.rcom(), &comment.data);
+        }
+
+        // With a rate target every precinct picks its own Q and R to fill its slice budget
+        let rate_controller = frame_bytes
+            .map(|frame_bytes| {
+                rate::RateController::new(
+                    frame_bytes,
+                    bitstream.data().len(),
+                    picture_header.num_slices(),
+                )
+            })
+            .transpose()?;
+
+        Ok(Self {
+            format,
+            sampling,
+            layout,
+            params,
+            picture_header,
+            rate_controller,
+            bitstream,
+            lossless: config.lossless,
+            dwt,
+        })
+    }
+
+    /// Whether this setup codes `width` x `height` frames of `format`
+    pub(crate) fn is_for(&self, width: u32, height: u32, format: PixelFormat) -> bool {
+        let header = &self.picture_header;
+        (header.width as u32, header.height as u32, self.format) == (width, height, format)
+    }
+
+    /// Turn unsigned samples into the fixed-point input of the wavelet transform
+    ///
+    /// Works on whole planes as well as on horizontal strips of them.
+    pub(crate) fn prepare_samples(&self, planes: &mut [Vec<i32>]) -> Result<()> {
+        // Center around 0 (DC level shift), then apply the RCT on the integer samples of
+        // the first three components; alpha is coded as an independent component
+        let offset = 1i32 << (self.format.bit_depth() - 1);
+        for sample in planes.iter_mut().flatten() {
+            *sample -= offset;
+        }
+        if let [c0, c1, c2, ..] = planes {
+            if self.picture_header.color_transform != 0 {
+                colors::rct_forward(c0, c1, c2)?;
+            }
+        }
+        let fractional_bits = self.picture_header.fractional_bits;
+        for sample in planes.iter_mut().flatten() {
+            *sample <<= fractional_bits;
+        }
+        Ok(())
+    }
 
-    // Quantize coefficients using corrected quality mapping
-    let qps = quant::compute_quantization_parameters(config.quality)?;
-    let qp_y = qps[0];
-    let qp_uv = qps.get(1).copied().unwrap_or(qp_y);
+    /// Layout of a strip of `lines` image lines, coded like the frame
+    pub(crate) fn strip_layout(&self, lines: u32) -> Result<precinct::PrecinctLayout> {
+        let header = &self.picture_header;
+        precinct::PrecinctLayout::with_sampling(
+            header.width as u32,
+            lines,
+            header.decomposition_levels_x,
+            header.decomposition_levels_y,
+            &self.sampling,
+            header.precinct_width,
+        )
+    }
+}
 
-    let y_quantized = quant::quantize(&y_dwt, qp_y)?;
-    let u_quantized = quant::quantize(&u_dwt, qp_uv)?;
-    let v_quantized = quant::quantize(&v_dwt, qp_uv)?;
+/// Working planes of the encoder, kept from frame to frame by an [`Encoder`]
+#[derive(Debug, Default)]
+pub(crate) struct EncodeScratch {
+    /// Unsigned samples of every component, then the transform input
+    planes: Vec<Vec<i32>>,
+    /// Fixed-point wavelet coefficients
+    coeffs: Vec<Vec<i32>>,
+    /// Quantized coefficients, unused in lossless mode
+    quantized: Vec<Vec<i32>>,
+}
+
+/// Zero `planes` at the plane sizes of `layout`, keeping their allocations
+fn reset_planes(planes: &mut Vec<Vec<i32>>, layout: &precinct::PrecinctLayout) {
+    planes.resize_with(layout.num_components(), Vec::new);
+    for (component, plane) in planes.iter_mut().enumerate() {
+        plane.clear();
+        plane.resize(layout.plane_size(component), 0);
+    }
+}
+
+/// Encode the component planes in `scratch`, unsigned samples laid out as `setup` describes
+pub(crate) fn encode_planes(setup: &CodingSetup, scratch: &mut EncodeScratch) -> Result<Bitstream> {
+    let layout = &setup.layout;
+    let params = &setup.params;
+    setup.prepare_samples(&mut scratch.planes)?;
+
+    // Multi-level fixed-point 5/3 decomposition of every component: NL,y 2D levels
+    // followed by horizontal-only levels, with the exact integer lifting of the standard
+    // Components are transformed and quantized independently of each other, in parallel
+    reset_planes(&mut scratch.coeffs, layout);
+    scratch
+        .planes
+        .par_iter()
+        .zip(&mut scratch.coeffs)
+        .enumerate()
+        .try_for_each(|(component, (plane, coeffs))| {
+            log_coefficient_stats("Pre-DWT", component, plane);
+            let (component_width, component_height) = layout.component_size(component);
+            setup.dwt.forward_multilevel(
+                plane,
+                coeffs,
+                component_width,
+                component_height,
+                setup.picture_header.decomposition_levels_x,
+                layout.component_levels_y(component),
+            )?;
+            log_coefficient_stats("Post-DWT", component, coeffs);
+            Ok::<_, JpegXsError>(())
+        })?;
+
+    // Quantize each subband with its own gain/priority derived step; lossless coding
+    // keeps every bitplane and skips quantization altogether
+    let quantized_planes = if setup.lossless {
+        &scratch.coeffs
+    } else {
+        reset_planes(&mut scratch.quantized, layout);
+        scratch
+            .coeffs
+            .par_iter()
+            .zip(&mut scratch.quantized)
+            .enumerate()
+            .try_for_each(|(component, (coeffs, quantized))| {
+                (0..layout.num_precincts()).try_for_each(|precinct| {
+                    quant::quantize_precinct(coeffs, quantized, layout, precinct, params, component)
+                })
+            })?;
+        &scratch.quantized
+    };
 
     // Log post-quantization statistics for precision analysis
-    log::info!(
-        "DWT_ANALYSIS: Post-Quantization Y coefficients - min: {}, max: {}, mean: {:.3}, QP: {}",
-        y_quantized.iter().min().unwrap_or(&0),
-        y_quantized.iter().max().unwrap_or(&0),
-        y_quantized.iter().sum::<i32>() as f32 / y_quantized.len() as f32,
-        qp_y
-    );
-    log::info!("DWT_ANALYSIS: Post-Quantization UV coefficients - U_min: {}, U_max: {}, V_min: {}, V_max: {}, QP: {}",
-               u_quantized.iter().min().unwrap_or(&0),
-               u_quantized.iter().max().unwrap_or(&0),
-               v_quantized.iter().min().unwrap_or(&0),
-               v_quantized.iter().max().unwrap_or(&0),
-               qp_uv);
-
-    // Use clean-room JPEG XS bitstream format from ISO/IEC 21122-1:2024
-    let mut jxs_bitstream = jpegxs_core_clean::JpegXsBitstream::new();
-
-    // Add Capabilities marker (mandatory second marker per ISO A.4.3)
-    jxs_bitstream.write_cap_marker();
-
-    // Add PIH (Picture Header) marker according to ISO A.7 specification
-    // Third mandatory marker providing image dimensions and decoder configuration
-    let num_components = 3; // All formats are converted to YUV with 3 components
-    jxs_bitstream.write_pih_marker(input.width as u16, input.height as u16, num_components);
-
-    // Add CDT (Component Table) marker according to ISO A.4.5 specification
-    // Fourth mandatory marker providing component precision and sampling factors
-    jxs_bitstream.write_cdt_marker(num_components);
-
-    // Add WGT (Weights Table) marker according to ISO A.4.12 specification
-    // Fifth mandatory marker providing band gain parameters for quantization
-    // Pass all subband QP values computed from quality setting
-    jxs_bitstream.write_wgt_marker(Some(&qps));
-
-    // Add entropy coded data per ISO Annex C specification
-    // Combine all quantized coefficients for entropy coding
-    let mut all_coefficients = Vec::new();
-    all_coefficients.extend_from_slice(&y_quantized);
-    all_coefficients.extend_from_slice(&u_quantized);
-    all_coefficients.extend_from_slice(&v_quantized);
-
-    // Apply basic entropy coding for significant compression
-    jxs_bitstream.add_entropy_coded_data(&all_coefficients);
+    for (component, quantized) in quantized_planes.iter().enumerate() {
+        log::info!(
+            "DWT_ANALYSIS: Post-Quantization component {} coefficients - min: {}, max: {}, mean: {:.3}, Q: {}, R: {}",
+            component,
+            quantized.iter().min().unwrap_or(&0),
+            quantized.iter().max().unwrap_or(&0),
+            quantized.iter().sum::<i32>() as f32 / quantized.len() as f32,
+            params.quantization,
+            params.refinement
+        );
+    }
+
+    // Add entropy coded data per ISO Annex C specification, one SLH marker per slice
+    // Precinct headers carry Q and R, which together with WGT give each band's step
+    let mut bitstream = setup.bitstream.clone();
+    let coeffs: Vec<&[i32]> = scratch
+        .coeffs
+        .iter()
+        .map(|plane| plane.as_slice())
+        .collect();
+    let quantized: Vec<&[i32]> = quantized_planes
+        .iter()
+        .map(|plane| plane.as_slice())
+        .collect();
+    // Slices are coded in parallel, each to a buffer of its own, and written in order
+    let slices = (0..setup.picture_header.num_slices())
+        .into_par_iter()
+        .map(|slice| {
+            let precincts = setup.picture_header.slice_precincts(slice);
+            match &setup.rate_controller {
+                Some(controller) => {
+                    controller.encode_slice(layout, &coeffs, params, slice, precincts)
+                }
+                None => precinct::encode_precinct_range(
+                    layout,
+                    &quantized,
+                    precincts,
+                    params.quantization,
+                    params.refinement,
+                ),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (slice, slice_data) in slices.iter().enumerate() {
+        bitstream.write_slice_header(slice as u16);
+        bitstream.write_entropy_coded_data(slice_data);
+    }
 
     // Finalize with EOC marker
-    jxs_bitstream.finalize();
-    let final_data = jxs_bitstream.into_bytes();
+    bitstream.finalize();
+    let final_data = bitstream.into_bytes();
 
     let size_bits = final_data.len() * 8;
     Ok(Bitstream {
@@ -266,23 +681,73 @@ pub fn encode_frame(input: ImageView8, config: &EncoderConfig) -> Result<Bitstre
     })
 }
 
-pub fn decode_frame(bitstream: &Bitstream, _config: &DecoderConfig) -> Result<ImageOwned8> {
-    decode_frame_to_format(bitstream, _config, PixelFormat::Yuv444p8)
+/// Log the range and spread of a component's samples or coefficients for precision analysis
+fn log_coefficient_stats(stage: &str, component: usize, plane: &[i32]) {
+    if !log::log_enabled!(log::Level::Info) || plane.is_empty() {
+        return;
+    }
+    let mean = plane.iter().map(|&x| x as f64).sum::<f64>() / plane.len() as f64;
+    let variance = plane
+        .iter()
+        .map(|&x| (x as f64 - mean).powi(2))
+        .sum::<f64>()
+        / plane.len() as f64;
+    log::info!(
+        "DWT_ANALYSIS: {} component {} coefficients - min: {}, max: {}, mean: {:.3}, std: {:.3}",
+        stage,
+        component,
+        plane.iter().min().unwrap_or(&0),
+        plane.iter().max().unwrap_or(&0),
+        mean,
+        variance.sqrt()
+    );
+}
+
+/// Decode a JPEG XS bitstream to 8-bit samples
+///
+/// Colour streams decode to `Yuv444p8`. Single-component streams decode to `Gray8` and
+/// four-component streams to `Rgba8` or `Yuva444p8`, following their colour transform.
+pub fn decode_frame(bitstream: &Bitstream, config: &DecoderConfig) -> Result<ImageOwned8> {
+    Decoder::new(config.clone()).decode(bitstream)
+}
+
+/// 8-bit output format [`decode_frame`] picks for a stream
+fn default_output_format(info: &FrameInfo) -> PixelFormat {
+    match info.num_components {
+        1 => PixelFormat::Gray8,
+        4 if info.color_transform => PixelFormat::Rgba8,
+        4 => PixelFormat::Yuva444p8,
+        _ => PixelFormat::Yuv444p8,
+    }
+}
+
+/// Read the coding parameters of a codestream from its main header
+///
+/// Reports what the stream actually holds: its size, component count, bit depth,
+/// sampling factors and colour transform, and the pixel format matching the coded
+/// components when there is one.
+pub fn probe_frame(bitstream: &Bitstream) -> Result<FrameInfo> {
+    parse_main_header(bitstream).map(|(_, info)| info)
 }
 
 /// Decode a JPEG XS bitstream to a specific pixel format
 ///
 /// This function decodes a JPEG XS bitstream and converts the result to the specified
-/// output format. The internal representation is always YUV444, so format conversion
-/// is applied as needed.
+/// output format. Subsampled chroma is returned as coded when the output format matches
+/// the CDT sampling factors, and only resampled for other output formats.
+///
+/// Single-component streams only decode to `Gray8`. The colour formats take the first
+/// three components of a stream, so an alpha component can be dropped, but not made up.
 ///
 /// # Supported Output Formats
 /// - `Yuv444p8`: Full resolution YUV (most efficient, no conversion needed)
-/// - `Yuv422p8`: Horizontally subsampled chroma, downsampled from 4:4:4
-/// - `Yuv420p8`: Vertically and horizontally subsampled chroma, downsampled from 4:4:4
+/// - `Yuv422p8`: Horizontally subsampled chroma
+/// - `Yuv420p8`: Vertically and horizontally subsampled chroma
 /// - `Rgb8`: Interleaved RGB, converted using ITU-R BT.601 inverse color matrix
 /// - `Bgr8`: Interleaved BGR, converted using ITU-R BT.601 inverse color matrix
 /// - `Rgb8Planar`: Planar RGB (separate R, G, B planes), converted using ITU-R BT.601
+/// - `Gray8`: The single component of a monochrome stream
+/// - `Rgba8` / `Yuva444p8`: Colour plus the alpha component of a four-component stream
 ///
 /// # Example
 /// ```rust,ignore
@@ -294,127 +759,617 @@ pub fn decode_frame(bitstream: &Bitstream, _config: &DecoderConfig) -> Result<Im
 ///
 /// assert_eq!(decoded.format, PixelFormat::Rgb8);
 /// assert_eq!(decoded.data.len(), (decoded.width * decoded.height * 3) as usize);
-/// # Ok::<(), anyhow::Error>(())
+/// # Ok::<(), jpegxs_core::JpegXsError>(())
 /// ```
 pub fn decode_frame_to_format(
     bitstream: &Bitstream,
-    _config: &DecoderConfig,
+    config: &DecoderConfig,
+    output_format: PixelFormat,
+) -> Result<ImageOwned8> {
+    Decoder::new(config.clone()).decode_to_format(bitstream, output_format)
+}
+
+/// Check that `output_format` is an 8-bit format, before anything is decoded
+fn check_output_format8(output_format: PixelFormat) -> Result<()> {
+    if output_format.bit_depth() != 8 {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "{:?} has {} bits per sample, use decode_frame16_to_format",
+            output_format,
+            output_format.bit_depth()
+        )));
+    }
+    Ok(())
+}
+
+/// Convert decoded component planes to an 8-bit output format
+fn components_to_format8(
+    decoded: &[Vec<i32>],
+    info: &FrameInfo,
+    output_format: PixelFormat,
+) -> Result<ImageOwned8> {
+    let (width, height) = (info.width, info.height);
+
+    // Samples deeper than 8 bits are rounded to the output precision
+    let mut planes: Vec<Vec<u8>> = decoded
+        .iter()
+        .map(|plane| {
+            plane
+                .iter()
+                .map(|&s| rescale_sample(s, info.bit_depth, 8) as u8)
+                .collect()
+        })
+        .collect();
+
+    let data = match (planes.len(), output_format.num_components()) {
+        (1, 1) => planes.remove(0),
+        (num_components, 3) if num_components >= 3 => {
+            planes.truncate(3);
+            let [c0, c1, c2]: [Vec<u8>; 3] = planes.try_into().unwrap();
+            return colour_to_format(c0, c1, c2, info, output_format);
+        }
+        (4, 4) => {
+            // Convert the colour components to planes, then add the alpha component
+            let alpha = planes.pop().unwrap();
+            let [c0, c1, c2]: [Vec<u8>; 3] = planes.try_into().unwrap();
+            let colour_format = if output_format.is_rgb() {
+                PixelFormat::Rgb8Planar
+            } else {
+                PixelFormat::Yuv444p8
+            };
+            let colour = colour_to_format(c0, c1, c2, info, colour_format)?;
+            let pixel_count = (width * height) as usize;
+            let mut planes = split_planes(&colour.data, &[pixel_count; 3]);
+            planes.push(alpha);
+            match output_format {
+                PixelFormat::Rgba8 => {
+                    let views: Vec<&[u8]> = planes.iter().map(|plane| plane.as_slice()).collect();
+                    colors::interleave_components(&views)?
+                }
+                _ => planes.concat(),
+            }
+        }
+        (num_components, _) => {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Cannot decode {} components to {:?}",
+                num_components, output_format
+            )));
+        }
+    };
+
+    Ok(ImageOwned8 {
+        data,
+        width,
+        height,
+        format: output_format,
+    })
+}
+
+/// Convert the first three decoded components to a three-component 8-bit format
+fn colour_to_format(
+    c0: Vec<u8>,
+    c1: Vec<u8>,
+    c2: Vec<u8>,
+    info: &FrameInfo,
     output_format: PixelFormat,
 ) -> Result<ImageOwned8> {
+    let (width, height) = (info.width, info.height);
+
+    // RGB coded with the RCT (Cpih = 1) is output directly
+    if info.color_transform {
+        let (r, g, b) = (c0, c1, c2);
+        let data = match output_format {
+            PixelFormat::Rgb8 => colors::interleave_planes(&r, &g, &b)?,
+            PixelFormat::Bgr8 => colors::interleave_planes(&b, &g, &r)?,
+            PixelFormat::Rgb8Planar => [r, g, b].concat(),
+            _ => {
+                // YUV output from an RGB source goes through BT.601
+                let (y, u, v) = colors::rgb_planar_to_yuv_planar(&r, &g, &b, width, height)?;
+                return yuv444_to_format(&y, &u, &v, width, height, output_format);
+            }
+        };
+        return Ok(ImageOwned8 {
+            data,
+            width,
+            height,
+            format: output_format,
+        });
+    }
+    let (y_data, u_data, v_data) = (c0, c1, c2);
+
+    // Native 4:2:2 / 4:2:0 output needs no resampling, other formats go through 4:4:4
+    let native_format = match info.chroma_sampling() {
+        (2, 1) => PixelFormat::Yuv422p8,
+        (2, 2) => PixelFormat::Yuv420p8,
+        _ => PixelFormat::Yuv444p8,
+    };
+    if output_format == native_format {
+        return Ok(ImageOwned8 {
+            data: [y_data, u_data, v_data].concat(),
+            width,
+            height,
+            format: output_format,
+        });
+    }
+    let (y_data, u_data, v_data) = match native_format {
+        PixelFormat::Yuv422p8 => {
+            colors::upsample_422_to_444(&y_data, &u_data, &v_data, width, height)?
+        }
+        PixelFormat::Yuv420p8 => {
+            colors::upsample_420_to_444(&y_data, &u_data, &v_data, width, height)?
+        }
+        _ => (y_data, u_data, v_data),
+    };
+
+    yuv444_to_format(&y_data, &u_data, &v_data, width, height, output_format)
+}
+
+/// Decode a JPEG XS bitstream to a pixel format with 10 to 16 bits per sample
+///
+/// Samples are rescaled from the CDT bit depth to the depth of `output_format`. Chroma
+/// is resampled between 4:4:4 and 4:2:2 as needed, but RGB and YUV are not converted
+/// into each other at these depths.
+///
+/// # Supported Output Formats
+/// - `Yuv422p10le`: Planar YUV 4:2:2, 10-bit samples
+/// - `Yuv444p12le`: Planar YUV 4:4:4, 12-bit samples
+/// - `Rgb16`: Interleaved RGB, 16-bit samples
+/// - `Gray16`: The single component of a monochrome stream, 16-bit samples
+pub fn decode_frame16_to_format(
+    bitstream: &Bitstream,
+    config: &DecoderConfig,
+    output_format: PixelFormat,
+) -> Result<ImageOwned16> {
+    Decoder::new(config.clone()).decode16_to_format(bitstream, output_format)
+}
+
+/// Check that `output_format` has more than 8 bits per sample, before anything is decoded
+fn check_output_format16(output_format: PixelFormat) -> Result<()> {
+    if output_format.bit_depth() == 8 {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "{:?} has 8 bits per sample, use decode_frame_to_format",
+            output_format
+        )));
+    }
+    Ok(())
+}
+
+/// Convert decoded component planes to an output format with 10 to 16 bits per sample
+fn components_to_format16(
+    decoded: &[Vec<i32>],
+    info: &FrameInfo,
+    output_format: PixelFormat,
+) -> Result<ImageOwned16> {
+    let num_components = decoded.len();
+    let colour_output = num_components >= 3 && output_format.num_components() == 3;
+    if !colour_output && (num_components, output_format.num_components()) != (1, 1) {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Cannot decode {} components to {:?}",
+            num_components, output_format
+        )));
+    }
+    if colour_output && info.color_transform != output_format.is_rgb() {
+        return Err(JpegXsError::UnsupportedFeature(format!(
+            "Cannot convert between RGB and YUV for {:?} output",
+            output_format
+        )));
+    }
+    let (width, height) = (info.width, info.height);
+
+    let to_output = |plane: &[i32]| {
+        plane
+            .iter()
+            .map(|&s| rescale_sample(s, info.bit_depth, output_format.bit_depth()) as u16)
+            .collect::<Vec<_>>()
+    };
+
+    let data = if !colour_output {
+        to_output(&decoded[0])
+    } else if output_format.is_rgb() {
+        let [c0, c1, c2, ..] = decoded else {
+            unreachable!("colour output has at least three components")
+        };
+        colors::interleave_planes(&to_output(c0), &to_output(c1), &to_output(c2))?
+    } else {
+        let (from, to) = (info.chroma_sampling(), output_format.chroma_sampling());
+        let u = to_output(&decoded[1]);
+        let v = to_output(&decoded[2]);
+        let u = colors::resample_chroma(&u, width, height, from, to)?;
+        let v = colors::resample_chroma(&v, width, height, from, to)?;
+        [to_output(&decoded[0]), u, v].concat()
+    };
+
+    Ok(ImageOwned16 {
+        data,
+        width,
+        height,
+        format: output_format,
+    })
+}
+
+/// Scale a sample between bit depths, rounding when precision is dropped
+fn rescale_sample(sample: i32, from: u8, to: u8) -> i32 {
+    if from > to {
+        let shift = from - to;
+        ((sample + (1 << (shift - 1))) >> shift).min((1 << to) - 1)
+    } else {
+        sample << (to - from)
+    }
+}
+
+/// Pixel format holding the components of a stream as coded, if there is one
+fn native_format(
+    num_components: u8,
+    bit_depth: u8,
+    chroma_sampling: (u8, u8),
+    color_transform: bool,
+) -> Option<PixelFormat> {
+    let format = match (num_components, color_transform, bit_depth, chroma_sampling) {
+        (1, _, 8, _) => PixelFormat::Gray8,
+        (1, _, 16, _) => PixelFormat::Gray16,
+        (3, true, 8, _) => PixelFormat::Rgb8,
+        (3, true, 16, _) => PixelFormat::Rgb16,
+        (3, false, 8, (1, 1)) => PixelFormat::Yuv444p8,
+        (3, false, 8, (2, 1)) => PixelFormat::Yuv422p8,
+        (3, false, 8, (2, 2)) => PixelFormat::Yuv420p8,
+        (3, false, 10, (2, 1)) => PixelFormat::Yuv422p10le,
+        (3, false, 12, (1, 1)) => PixelFormat::Yuv444p12le,
+        (4, true, 8, _) => PixelFormat::Rgba8,
+        (4, false, 8, (1, 1)) => PixelFormat::Yuva444p8,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Parse and validate the main header of a codestream
+fn parse_main_header(
+    bitstream: &Bitstream,
+) -> Result<(jpegxs_core_clean::JpegXsDecoder, FrameInfo)> {
+    parse_main_header_data(bitstream.data.clone())
+}
+
+/// Parse and check the main header at the start of `data`
+pub(crate) fn parse_main_header_data(
+    data: Vec<u8>,
+) -> Result<(jpegxs_core_clean::JpegXsDecoder, FrameInfo)> {
     // Use clean-room JPEG XS decoder to parse headers and extract entropy data
-    let mut decoder = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
-        .map_err(|e| anyhow::anyhow!("Decoder creation failed: {}", e))?;
+    let mut decoder = jpegxs_core_clean::JpegXsDecoder::new(data)?;
 
     // Parse JPEG XS markers to extract image parameters
-    decoder
-        .parse_headers()
-        .map_err(|e| anyhow::anyhow!("Header parsing failed: {}", e))?;
+    decoder.parse_headers()?;
 
     let (width, height, num_components) = decoder.dimensions();
-    if num_components != 3 {
-        return Err(anyhow::anyhow!(
+    if !(1..=8).contains(&num_components) {
+        return Err(JpegXsError::UnsupportedFeature(format!(
             "Unsupported number of components: {}",
             num_components
+        )));
+    }
+    let levels_x = decoder.picture_header().decomposition_levels_x;
+    let levels_y = decoder.picture_header().decomposition_levels_y;
+    dwt::validate_decomposition_levels(levels_x, levels_y)
+        .map_err(|error| JpegXsError::Corrupt(error.to_string()))?;
+
+    // Components keep the size given by their CDT sampling factors
+    let sampling: Vec<(u8, u8)> = decoder
+        .components()
+        .iter()
+        .map(|component| (component.sampling_x, component.sampling_y))
+        .collect();
+    if num_components >= 3 && sampling[1] != sampling[2] {
+        return Err(JpegXsError::UnsupportedFeature(format!(
+            "Unsupported chroma sampling: {:?} and {:?}",
+            sampling[1], sampling[2]
+        )));
+    }
+
+    // Samples are B[c] bits deep, Bw / Fq must leave room for them
+    let bit_depth = decoder.components()[0].bit_depth;
+    if decoder
+        .components()
+        .iter()
+        .any(|component| component.bit_depth != bit_depth)
+    {
+        return Err(JpegXsError::UnsupportedFeature(
+            "Components with different sample precisions are not supported".into(),
         ));
     }
+    dwt::validate_wavelet_precision(
+        decoder.picture_header().wavelet_precision,
+        decoder.picture_header().fractional_bits,
+        bit_depth,
+    )?;
+
+    // The RCT works on the first three components, which must not be subsampled
+    let color_transform = decoder.picture_header().color_transform == 1;
+    if color_transform && num_components < 3 {
+        return Err(JpegXsError::Corrupt(
+            "The colour transform requires at least three components".into(),
+        ));
+    }
+    if color_transform && sampling.iter().any(|&factors| factors != (1, 1)) {
+        return Err(JpegXsError::Corrupt(
+            "The colour transform requires components without subsampling".into(),
+        ));
+    }
+
+    let mut info = FrameInfo {
+        width: width as u32,
+        height: height as u32,
+        num_components,
+        bit_depth,
+        sampling,
+        color_transform,
+        format: None,
+        profile_level: profile::ProfileLevel {
+            ppih: decoder.picture_header().profile,
+            plev: decoder.picture_header().level,
+        },
+        metadata: CodestreamMetadata {
+            comments: decoder
+                .comments()
+                .iter()
+                .map(|(rcom, data)| Comment {
+                    kind: CommentKind::from_rcom(*rcom),
+                    data: data.clone(),
+                })
+                .collect(),
+        },
+    };
+    info.format = native_format(
+        num_components,
+        bit_depth,
+        info.chroma_sampling(),
+        color_transform,
+    );
 
-    // Decode entropy coded data
-    let all_coefficients = decoder
-        .decode_entropy_data()
-        .map_err(|e| anyhow::anyhow!("Entropy decoding failed: {}", e))?;
+    // The frame must stay within the profile and level it signals
+    info.profile_level.check_frame(
+        info.width,
+        info.height,
+        num_components,
+        bit_depth,
+        info.chroma_sampling(),
+    )?;
+    Ok((decoder, info))
+}
 
-    // Split coefficients back into Y, U, V components (all 444 now)
-    let width = width as u32;
-    let height = height as u32;
-    let y_size = (width * height) as usize;
-    let uv_size = y_size; // Full resolution chroma
+/// Working planes of the decoder, kept from frame to frame by a [`Decoder`]
+#[derive(Debug, Default)]
+pub(crate) struct DecodeScratch {
+    /// Coefficients of every slice, decoded on their own
+    slices: Vec<SliceScratch>,
+    /// Fixed-point wavelet coefficients
+    coeffs: Vec<Vec<i32>>,
+    /// Samples in `0..2^bit_depth`, every plane at its coded size
+    pub(crate) planes: Vec<Vec<i32>>,
+}
+
+/// Planes of a slice, laid out as a strip of the lines its precincts cover
+#[derive(Debug, Default)]
+struct SliceScratch {
+    /// Quantized coefficients as entropy decoded
+    quantized: Vec<Vec<i32>>,
+    /// Dequantized fixed-point wavelet coefficients
+    coeffs: Vec<Vec<i32>>,
+}
 
-    if all_coefficients.len() < y_size + 2 * uv_size {
-        return Err(anyhow::anyhow!("Insufficient decoded coefficients"));
+/// Decode the component planes of a codestream into `scratch.planes`: entropy decoding,
+/// dequantization, inverse wavelet transform, inverse RCT and DC level shift
+///
+/// A `strict` decode first checks the whole codestream for conformance, otherwise the
+/// slices are located tolerantly, see [`DecoderConfig::strict_mode`].
+pub(crate) fn decode_components_into(
+    bitstream: &Bitstream,
+    scratch: &mut DecodeScratch,
+    dwt: accel::CodecDwt,
+    strict: bool,
+) -> Result<FrameInfo> {
+    if strict {
+        conformance::validate_codestream(&bitstream.data)?;
+    }
+    let (mut decoder, info) = parse_main_header(bitstream)?;
+    info.profile_level
+        .check_size(info.width, info.height, bitstream.data.len())?;
+    decoder.set_tolerant(!strict);
+    let picture_header = decoder.picture_header();
+    let (width, height) = (info.width, info.height);
+    let levels_x = picture_header.decomposition_levels_x;
+    let levels_y = picture_header.decomposition_levels_y;
+    let wavelet_precision = picture_header.wavelet_precision;
+
+    // Slices are located through their SLH markers and decoded independently
+    let slices = decoder.slices()?;
+    let layout = precinct::PrecinctLayout::with_sampling(
+        width,
+        height,
+        levels_x,
+        levels_y,
+        &info.sampling,
+        picture_header.precinct_width,
+    )?;
+    check_band_weights(&decoder, &layout)?;
+
+    // Every slice is entropy decoded and dequantized in parallel into a strip of the
+    // lines it covers. Each band uses its WGT gain/priority with the Q and R of its
+    // precinct, which rate control may vary across the frame
+    let precinct_lines = 1u32 << levels_y;
+    let precincts_per_row = layout.precincts_per_row();
+    scratch
+        .slices
+        .resize_with(slices.len(), SliceScratch::default);
+    let strips = scratch
+        .slices
+        .par_iter_mut()
+        .zip(&slices)
+        .map(|(strip, slice)| {
+            let precincts = picture_header.slice_precincts(slice.index as usize);
+            let first_line = (precincts.start / precincts_per_row) as u32 * precinct_lines;
+            let lines = (precincts.len().div_ceil(precincts_per_row) as u32 * precinct_lines)
+                .min(height.saturating_sub(first_line));
+            let strip_layout = precinct::PrecinctLayout::with_sampling(
+                width,
+                lines,
+                levels_x,
+                levels_y,
+                &info.sampling,
+                picture_header.precinct_width,
+            )?;
+            reset_planes(&mut strip.quantized, &strip_layout);
+            let headers = precinct::decode_precinct_range(
+                &strip_layout,
+                slice.data,
+                &mut strip.quantized,
+                0..precincts.len(),
+            )?;
+            reset_planes(&mut strip.coeffs, &strip_layout);
+            for (precinct, header) in headers.iter().enumerate() {
+                let params = band_params(&decoder, header)?;
+                for (component, (quantized, coeffs)) in
+                    strip.quantized.iter().zip(&mut strip.coeffs).enumerate()
+                {
+                    quant::dequantize_precinct(
+                        quantized,
+                        coeffs,
+                        &strip_layout,
+                        precinct,
+                        &params,
+                        component,
+                        wavelet_precision,
+                    )?;
+                }
+            }
+            Ok((strip_layout, precincts))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Gather the slices into the frame planes
+    reset_planes(&mut scratch.coeffs, &layout);
+    for ((strip_layout, precincts), strip) in strips.iter().zip(&scratch.slices) {
+        for (strip_precinct, precinct) in precincts.clone().enumerate() {
+            precinct::copy_precinct_lines(
+                strip_layout,
+                &strip.coeffs,
+                strip_precinct,
+                &layout,
+                &mut scratch.coeffs,
+                precinct,
+            );
+        }
     }
 
-    let y_quantized = all_coefficients[0..y_size].to_vec();
-    let u_quantized = all_coefficients[y_size..y_size + uv_size].to_vec();
-    let v_quantized = all_coefficients[y_size + uv_size..y_size + 2 * uv_size].to_vec();
+    // Mirror the encoder's NL,x / NL,y decomposition signalled in the PIH with the exact
+    // integer lifting, every component at its coded size and in parallel, then round
+    // away the Fq fractional bits
+    let fractional_bits = picture_header.fractional_bits;
+    reset_planes(&mut scratch.planes, &layout);
+    scratch
+        .coeffs
+        .par_iter()
+        .zip(&mut scratch.planes)
+        .enumerate()
+        .try_for_each(|(component, (coeffs, plane))| {
+            log_coefficient_stats("Post-Dequantization", component, coeffs);
+            let (component_width, component_height) = layout.component_size(component);
+            dwt.inverse_multilevel(
+                coeffs,
+                plane,
+                component_width,
+                component_height,
+                levels_x,
+                layout.component_levels_y(component),
+            )?;
+            log_coefficient_stats("Post-Inverse-DWT", component, plane);
+            Ok::<_, JpegXsError>(())
+        })?;
+    restore_samples(&mut scratch.planes, fractional_bits, &info)?;
+
+    Ok(info)
+}
 
-    // Dequantize - Extract QP from WGT marker
-    let qp_values = decoder.get_qp_values();
-    let qp_y = qp_values.first().copied().unwrap_or(DEFAULT_FALLBACK_QP);
-    let qp_uv = qp_values.get(1).copied().unwrap_or(qp_y);
+/// Check that the WGT marker holds a gain and priority for every band of the frame
+pub(crate) fn check_band_weights(
+    decoder: &jpegxs_core_clean::JpegXsDecoder,
+    layout: &precinct::PrecinctLayout,
+) -> Result<()> {
+    let num_bands = layout.num_bands() * layout.num_components();
+    if decoder.get_qp_values().len() != num_bands {
+        return Err(JpegXsError::Corrupt(format!(
+            "Weights table has {} bands, expected {}",
+            decoder.get_qp_values().len(),
+            num_bands
+        )));
+    }
+    Ok(())
+}
 
-    let y_dwt = quant::dequantize(&y_quantized, qp_y)?;
-    let u_dwt = quant::dequantize(&u_quantized, qp_uv)?;
-    let v_dwt = quant::dequantize(&v_quantized, qp_uv)?;
+/// Band steps of a precinct: the WGT gains and priorities with its own Q[p] and R[p]
+pub(crate) fn band_params(
+    decoder: &jpegxs_core_clean::JpegXsDecoder,
+    header: &precinct::PrecinctHeader,
+) -> Result<quant::QuantizationParameters> {
+    quant::QuantizationParameters::from_band_weights(
+        header.quantization,
+        header.refinement,
+        decoder.get_qp_values(),
+        decoder.get_band_priorities(),
+    )
+}
 
-    // Log post-dequantization statistics for precision analysis
-    log::info!(
-        "DWT_ANALYSIS: Post-Dequantization Y coefficients - min: {:.3}, max: {:.3}, mean: {:.3}",
-        y_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-        y_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-        y_dwt.iter().sum::<f32>() / y_dwt.len() as f32
-    );
-    log::info!("DWT_ANALYSIS: Post-Dequantization UV coefficients - U_min: {:.3}, U_max: {:.3}, V_min: {:.3}, V_max: {:.3}",
-               u_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               u_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-               v_dwt.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               v_dwt.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)));
-
-    // Apply inverse DWT using Apple Silicon acceleration (all planes are 444)
-    let mut y_plane = vec![0.0f32; y_size];
-    let mut u_plane = vec![0.0f32; uv_size];
-    let mut v_plane = vec![0.0f32; uv_size];
-
-    // Initialize unified acceleration (GPU → NEON → Scalar fallback)
-    let accel = accel::AccelDwt::new();
-
-    accel.dwt_53_inverse_2d(&y_dwt, &mut y_plane, width, height)?;
-    accel.dwt_53_inverse_2d(&u_dwt, &mut u_plane, width, height)?;
-    accel.dwt_53_inverse_2d(&v_dwt, &mut v_plane, width, height)?;
-
-    // Log post-inverse-DWT statistics for precision analysis
-    log::info!("DWT_ANALYSIS: Post-Inverse-DWT Y coefficients - min: {:.3}, max: {:.3}, mean: {:.3}, std: {:.3}",
-               y_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               y_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-               y_plane.iter().sum::<f32>() / y_plane.len() as f32,
-               {
-                   let mean = y_plane.iter().sum::<f32>() / y_plane.len() as f32;
-                   (y_plane.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / y_plane.len() as f32).sqrt()
-               });
-    log::info!("DWT_ANALYSIS: Post-Inverse-DWT UV coefficients - U_min: {:.3}, U_max: {:.3}, V_min: {:.3}, V_max: {:.3}",
-               u_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               u_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)),
-               v_plane.iter().fold(f32::INFINITY, |a, &b| a.min(b)),
-               v_plane.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b)));
-
-    // Convert back to 8-bit
-    let mut y_data = Vec::with_capacity(y_size);
-    let mut u_data = Vec::with_capacity(uv_size);
-    let mut v_data = Vec::with_capacity(uv_size);
-
-    for &sample in &y_plane {
-        y_data.push((sample + 128.0).clamp(0.0, 255.0) as u8);
-    }
-    for &sample in &u_plane {
-        u_data.push((sample + 128.0).clamp(0.0, 255.0) as u8);
-    }
-    for &sample in &v_plane {
-        v_data.push((sample + 128.0).clamp(0.0, 255.0) as u8);
+/// Turn the output of the inverse wavelet transform back into unsigned samples
+///
+/// Works on whole planes as well as on horizontal strips of them.
+pub(crate) fn restore_samples(
+    planes: &mut [Vec<i32>],
+    fractional_bits: u8,
+    info: &FrameInfo,
+) -> Result<()> {
+    // Round away the Fq fractional bits
+    let rounding = (1i32 << fractional_bits) >> 1;
+    for sample in planes.iter_mut().flatten() {
+        *sample = (*sample + rounding) >> fractional_bits;
+    }
+
+    // RGB coded with the RCT (Cpih = 1): invert the transform on integer samples
+    if let [c0, c1, c2, ..] = planes {
+        if info.color_transform {
+            colors::rct_inverse(c0, c1, c2)?;
+        }
     }
 
+    // Undo the DC level shift and clamp to the B[c] bit sample range
+    let offset = 1i32 << (info.bit_depth - 1);
+    let max_value = (1i32 << info.bit_depth) - 1;
+    for sample in planes.iter_mut().flatten() {
+        *sample = (*sample + offset).clamp(0, max_value);
+    }
+    Ok(())
+}
+
+/// Convert decoded YUV 4:4:4 planes into the requested output format
+fn yuv444_to_format(
+    y_data: &[u8],
+    u_data: &[u8],
+    v_data: &[u8],
+    width: u32,
+    height: u32,
+    output_format: PixelFormat,
+) -> Result<ImageOwned8> {
+    let y_size = (width * height) as usize;
+
     // Convert to desired output format
     let data = match output_format {
         PixelFormat::Yuv444p8 => {
             // Direct YUV444 output
             let mut out = Vec::with_capacity(y_size * 3);
-            out.extend_from_slice(&y_data);
-            out.extend_from_slice(&u_data);
-            out.extend_from_slice(&v_data);
+            out.extend_from_slice(y_data);
+            out.extend_from_slice(u_data);
+            out.extend_from_slice(v_data);
             out
         }
         PixelFormat::Yuv422p8 => {
             // Downsample to 422
             let (y_out, u_out, v_out) =
-                colors::downsample_444_to_422(&y_data, &u_data, &v_data, width, height)?;
+                colors::downsample_444_to_422(y_data, u_data, v_data, width, height)?;
             let mut out = Vec::with_capacity(y_out.len() + u_out.len() + v_out.len());
             out.extend_from_slice(&y_out);
             out.extend_from_slice(&u_out);
@@ -424,7 +1379,7 @@ pub fn decode_frame_to_format(
         PixelFormat::Yuv420p8 => {
             // Downsample to 420
             let (y_out, u_out, v_out) =
-                colors::downsample_444_to_420(&y_data, &u_data, &v_data, width, height)?;
+                colors::downsample_444_to_420(y_data, u_data, v_data, width, height)?;
             let mut out = Vec::with_capacity(y_out.len() + u_out.len() + v_out.len());
             out.extend_from_slice(&y_out);
             out.extend_from_slice(&u_out);
@@ -484,6 +1439,18 @@ pub fn decode_frame_to_format(
             out.extend_from_slice(&b_plane);
             out
         }
+        PixelFormat::Yuv422p10le
+        | PixelFormat::Yuv444p12le
+        | PixelFormat::Rgb16
+        | PixelFormat::Gray8
+        | PixelFormat::Gray16
+        | PixelFormat::Rgba8
+        | PixelFormat::Yuva444p8 => {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "{:?} is not an 8-bit three-component output format",
+                output_format
+            )));
+        }
     };
 
     Ok(ImageOwned8 {
@@ -554,6 +1521,7 @@ mod tests {
             quality: 0.9,
             profile: Profile::Light,
             level: Level::Level1,
+            ..Default::default()
         };
         let light_bitstream = encode_frame(input, &light_config).expect("Light encoding failed");
         assert!(!light_bitstream.data.is_empty());
@@ -563,20 +1531,90 @@ mod tests {
             quality: 0.9,
             profile: Profile::Main,
             level: Level::Level3,
+            ..Default::default()
         };
         let main_bitstream = encode_frame(input, &main_config).expect("Main encoding failed");
         assert!(!main_bitstream.data.is_empty());
 
-        // Test High Profile Level 5
+        // Test High Profile Level 4
         let high_config = EncoderConfig {
             quality: 0.9,
             profile: Profile::High,
-            level: Level::Level5,
+            level: Level::Level4,
+            ..Default::default()
         };
         let high_bitstream = encode_frame(input, &high_config).expect("High encoding failed");
         assert!(!high_bitstream.data.is_empty());
     }
 
+    #[test]
+    fn test_profile_and_level_are_signalled_and_enforced() {
+        // Wider than Level1 admits, but a small frame all the same
+        let (width, height) = (1928u32, 8u32);
+        let test_data: Vec<u8> = (0..width * height * 2).map(|i| (i % 251) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv422p8,
+        };
+        let config = EncoderConfig {
+            level: Level::Level2,
+            rate_target: Some(types::RateTarget::BitsPerPixel(8.0)),
+            ..Default::default()
+        };
+        let bitstream = encode_frame(input, &config).expect("Encoding failed");
+        let info = probe_frame(&bitstream).expect("Probing failed");
+        assert_eq!(info.profile_level.profile(), Some(Profile::Main));
+        assert_eq!(info.profile_level.level(), Some(Level::Level2));
+        assert_eq!(info.profile_level.sublevel(), Some(profile::Sublevel::Bpp9));
+        assert_eq!(&bitstream.data[14..18], &[0x35, 0x40, 0x30, 0x0c]);
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        decode_frame(&bitstream, &strict).expect("Decoding failed");
+
+        // The encoder rejects frames beyond the level
+        let level1 = EncoderConfig {
+            level: Level::Level1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode_frame(input, &level1),
+            Err(JpegXsError::ProfileViolation(_))
+        ));
+        let too_many_samples = EncoderConfig {
+            rate_target: Some(types::RateTarget::Mbps {
+                mbps: 100.0,
+                frame_rate: 1.0e4,
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode_frame(input, &too_many_samples),
+            Err(JpegXsError::ProfileViolation(_))
+        ));
+
+        // The decoder rejects frames beyond the level or sublevel they signal
+        let config = DecoderConfig::default();
+        for (offset, value) in [(16, 0x20), (17, 0x08)] {
+            let mut data = bitstream.data.clone();
+            data[offset] = value;
+            let tampered = Bitstream {
+                size_bits: data.len() * 8,
+                data,
+            };
+            assert!(
+                matches!(
+                    decode_frame(&tampered, &config),
+                    Err(JpegXsError::ProfileViolation(_))
+                ),
+                "byte {offset}"
+            );
+        }
+    }
+
     #[test]
     fn test_encode_decode_roundtrip() {
         let width = 64u32;
@@ -611,6 +1649,7 @@ mod tests {
             quality: 0.9,
             profile: Profile::Main,
             level: Level::Level1,
+            ..Default::default()
         };
 
         let decoder_config = DecoderConfig::default();
@@ -636,6 +1675,712 @@ mod tests {
         println!("Roundtrip test completed successfully");
     }
 
+    #[test]
+    fn test_decomposition_levels_roundtrip() {
+        let width = 48u32;
+        let height = 40u32;
+        let pixel_count = (width * height) as usize;
+
+        // Smooth gradient so that every level sees real low-frequency content
+        let mut test_data = Vec::with_capacity(pixel_count * 3);
+        for y in 0..height {
+            for x in 0..width {
+                test_data.push((64 + x * 2 + y) as u8);
+            }
+        }
+        test_data.extend(std::iter::repeat_n(128, pixel_count * 2));
+
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv444p8,
+        };
+
+        for (levels_x, levels_y) in [(5u8, 2u8), (3, 3), (2, 0)] {
+            let config = EncoderConfig {
+                quality: 0.95,
+                decomposition_levels_x: levels_x,
+                decomposition_levels_y: levels_y,
+                ..Default::default()
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+            // Levels are signalled in the picture header
+            let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+                .expect("Decoder creation failed");
+            parser.parse_headers().expect("Header parsing failed");
+            assert_eq!(parser.picture_header().decomposition_levels_x, levels_x);
+            assert_eq!(parser.picture_header().decomposition_levels_y, levels_y);
+
+            let decoded =
+                decode_frame(&bitstream, &DecoderConfig::default()).expect("Decoding failed");
+            assert_eq!(decoded.width, width);
+            assert_eq!(decoded.height, height);
+            let mean_error = test_data[..pixel_count]
+                .iter()
+                .zip(&decoded.data[..pixel_count])
+                .map(|(&a, &b)| (a as f64 - b as f64).abs())
+                .sum::<f64>()
+                / pixel_count as f64;
+            assert!(
+                mean_error < 4.0,
+                "NL,x={} NL,y={} reconstruction error too large: {:.2}",
+                levels_x,
+                levels_y,
+                mean_error
+            );
+        }
+
+        // Vertical levels may not exceed horizontal levels
+        let invalid = EncoderConfig {
+            decomposition_levels_x: 1,
+            decomposition_levels_y: 2,
+            ..Default::default()
+        };
+        assert!(encode_frame(input, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_slices_decode_independently() {
+        let width = 32u32;
+        let height = 30u32;
+        let pixel_count = (width * height) as usize;
+        let test_data: Vec<u8> = (0..pixel_count * 3)
+            .map(|i| ((i * 13 + i / 32 * 7) % 256) as u8)
+            .collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv444p8,
+        };
+
+        let decode = |slice_height: u16| {
+            let config = EncoderConfig {
+                decomposition_levels_x: 1,
+                decomposition_levels_y: 1,
+                slice_height,
+                ..Default::default()
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+            // 15 precincts of two lines, every slice behind its own SLH marker
+            let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+                .expect("Decoder creation failed");
+            parser.parse_headers().expect("Header parsing failed");
+            let slices = parser.slices().expect("Slice parsing failed");
+            assert_eq!(slices.len(), 15usize.div_ceil(slice_height as usize));
+
+            // The last slice decodes without any of the preceding slice data
+            let last = slices.last().unwrap();
+            let layout = precinct::PrecinctLayout::new(width, height, 1, 1, 3, 0).unwrap();
+            let mut planes = vec![vec![0i32; pixel_count]; 3];
+            let precincts = parser.picture_header().slice_precincts(last.index as usize);
+            let headers =
+                precinct::decode_precinct_range(&layout, last.data, &mut planes, precincts.clone())
+                    .expect("Slice decoding failed");
+            assert_eq!(headers.len(), precincts.len());
+
+            decode_frame(&bitstream, &DecoderConfig::default())
+                .expect("Decoding failed")
+                .data
+        };
+
+        // Slicing changes the codestream layout, never the decoded picture
+        let reference = decode(1);
+        for slice_height in [4, 15, 100] {
+            assert_eq!(decode(slice_height), reference);
+        }
+
+        let invalid = EncoderConfig {
+            slice_height: 0,
+            ..Default::default()
+        };
+        assert!(encode_frame(input, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_precinct_width_roundtrip() {
+        let width = 72u32;
+        let height = 24u32;
+        let pixel_count = (width * height) as usize;
+        let test_data: Vec<u8> = (0..pixel_count * 3)
+            .map(|i| ((i * 29 + i / 72 * 5) % 256) as u8)
+            .collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv444p8,
+        };
+
+        let decode = |precinct_width: u16| {
+            let config = EncoderConfig {
+                decomposition_levels_x: 2,
+                precinct_width,
+                slice_height: 2,
+                ..Default::default()
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+            let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+                .expect("Decoder creation failed");
+            parser.parse_headers().expect("Header parsing failed");
+            assert_eq!(parser.picture_header().precinct_width, precinct_width);
+
+            decode_frame(&bitstream, &DecoderConfig::default())
+                .expect("Decoding failed")
+                .data
+        };
+
+        // Column partitioning only reorganizes the entropy coded data
+        let reference = decode(0);
+        for precinct_width in [1, 2, 3] {
+            assert_eq!(decode(precinct_width), reference, "Cw={}", precinct_width);
+        }
+    }
+
+    #[test]
+    fn test_rate_control_hits_target_exactly() {
+        let width = 64u32;
+        let height = 32u32;
+        let pixel_count = (width * height) as usize;
+        let test_data: Vec<u8> = (0..pixel_count * 3)
+            .map(|i| ((i * 7 + (i / 64) * 3 + (i * i) % 11) % 256) as u8)
+            .collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv444p8,
+        };
+
+        let mut previous_error = f64::INFINITY;
+        for bpp in [6.0f32, 8.0, 12.0] {
+            let config = EncoderConfig {
+                rate_target: Some(types::RateTarget::BitsPerPixel(bpp)),
+                slice_height: 4,
+                ..Default::default()
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+            assert_eq!(bitstream.data.len(), (bpp as usize * pixel_count) / 8);
+
+            // Padded precincts still split along packet boundaries
+            let payloads = packet::unpack_bitstream(&bitstream).expect("Unpacking failed");
+            assert_eq!(payloads.concat(), bitstream.data);
+
+            let decoded =
+                decode_frame(&bitstream, &DecoderConfig::default()).expect("Decoding failed");
+            let error = test_data
+                .iter()
+                .zip(&decoded.data)
+                .map(|(&a, &b)| (a as f64 - b as f64).abs())
+                .sum::<f64>()
+                / test_data.len() as f64;
+            assert!(error < previous_error, "{} bpp: error {:.2}", bpp, error);
+            previous_error = error;
+        }
+
+        // Budgets are checked against the profile and level bitrate limit
+        let too_fast = EncoderConfig {
+            rate_target: Some(types::RateTarget::Mbps {
+                mbps: 250.0,
+                frame_rate: 60.0,
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode_frame(input, &too_fast),
+            Err(JpegXsError::ProfileViolation(_))
+        ));
+
+        // Budgets smaller than the headers cannot be met
+        let too_small = EncoderConfig {
+            rate_target: Some(types::RateTarget::BitsPerPixel(0.01)),
+            ..Default::default()
+        };
+        assert!(encode_frame(input, &too_small).is_err());
+    }
+
+    #[test]
+    fn test_subsampled_chroma_is_coded_natively() {
+        let width = 64u32;
+        let height = 32u32;
+        let y_size = (width * height) as usize;
+
+        for (format, (sx, sy)) in [
+            (PixelFormat::Yuv422p8, (2u32, 1u32)),
+            (PixelFormat::Yuv420p8, (2, 2)),
+        ] {
+            let uv_size = y_size / (sx * sy) as usize;
+            let data: Vec<u8> = (0..y_size + 2 * uv_size)
+                .map(|i| (i % 61 * 4) as u8)
+                .collect();
+            let input = ImageView8 {
+                data: &data,
+                width,
+                height,
+                format,
+            };
+            let config = EncoderConfig {
+                quality: 1.0,
+                decomposition_levels_x: 3,
+                decomposition_levels_y: 2,
+                profile: Profile::Unrestricted,
+                ..Default::default()
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+            let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+                .expect("Decoder creation failed");
+            parser.parse_headers().expect("Header parsing failed");
+            let sampling: Vec<(u8, u8)> = parser
+                .components()
+                .iter()
+                .map(|component| (component.sampling_x, component.sampling_y))
+                .collect();
+            assert_eq!(
+                sampling,
+                [(1, 1), (sx as u8, sy as u8), (sx as u8, sy as u8)]
+            );
+
+            // Decoding to the coded format returns the native planes without resampling
+            let decoded = decode_frame_to_format(&bitstream, &DecoderConfig::default(), format)
+                .expect("Decoding failed");
+            assert_eq!(decoded.data.len(), data.len());
+            let mean_error = data
+                .iter()
+                .zip(&decoded.data)
+                .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                .sum::<i32>() as f32
+                / data.len() as f32;
+            assert!(mean_error < 0.5, "{:?} mean error {}", format, mean_error);
+
+            // Subsampled chroma costs fewer bits than the same content upsampled to 4:4:4
+            let upsampled = decode_frame(&bitstream, &DecoderConfig::default())
+                .expect("Decoding to 4:4:4 failed");
+            let full = encode_frame(
+                ImageView8 {
+                    data: &upsampled.data,
+                    format: PixelFormat::Yuv444p8,
+                    ..input
+                },
+                &config,
+            )
+            .expect("Encoding failed");
+            assert!(bitstream.data.len() < full.data.len());
+        }
+    }
+
+    #[test]
+    fn test_rgb_uses_reversible_colour_transform() {
+        let width = 32u32;
+        let height = 16u32;
+        let pixel_count = (width * height) as usize;
+        let rgb: Vec<u8> = (0..pixel_count * 3)
+            .map(|i| match i % 3 {
+                0 => (i / 3 % 32 * 8) as u8,
+                1 => (i / 96 * 16) as u8,
+                _ => 255 - (i / 3 % 32 * 8) as u8,
+            })
+            .collect();
+        let input = ImageView8 {
+            data: &rgb,
+            width,
+            height,
+            format: PixelFormat::Rgb8,
+        };
+        let config = EncoderConfig {
+            quality: 1.0,
+            ..Default::default()
+        };
+        let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+        let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+            .expect("Decoder creation failed");
+        parser.parse_headers().expect("Header parsing failed");
+        assert_eq!(parser.picture_header().color_transform, 1);
+
+        // Without the lossy BT.601 round trip RGB comes back (almost) unchanged
+        let decoded =
+            decode_frame_to_format(&bitstream, &DecoderConfig::default(), PixelFormat::Rgb8)
+                .expect("Decoding failed");
+        let max_error = rgb
+            .iter()
+            .zip(&decoded.data)
+            .map(|(&a, &b)| (a as i32 - b as i32).abs())
+            .max()
+            .unwrap();
+        assert!(max_error <= 1, "max error {}", max_error);
+
+        // YUV sources keep Cpih = 0
+        let yuv = encode_frame(
+            ImageView8 {
+                format: PixelFormat::Yuv444p8,
+                ..input
+            },
+            &config,
+        )
+        .expect("Encoding failed");
+        let mut parser =
+            jpegxs_core_clean::JpegXsDecoder::new(yuv.data).expect("Decoder creation failed");
+        parser.parse_headers().expect("Header parsing failed");
+        assert_eq!(parser.picture_header().color_transform, 0);
+    }
+
+    #[test]
+    fn test_lossless_roundtrip_is_bit_exact() {
+        let (width, height) = (38u32, 22u32);
+        let noise = |i: usize| ((i * 7919 + (i * i) % 251) % 256) as u8;
+        let config = EncoderConfig {
+            lossless: true,
+            decomposition_levels_x: 3,
+            decomposition_levels_y: 2,
+            slice_height: 1,
+            profile: Profile::Unrestricted,
+            ..Default::default()
+        };
+
+        for format in [
+            PixelFormat::Yuv444p8,
+            PixelFormat::Yuv420p8,
+            PixelFormat::Rgb8,
+            PixelFormat::Rgba8,
+            PixelFormat::Gray8,
+        ] {
+            let pixel_count = (width * height) as usize;
+            let sample_count = match format {
+                PixelFormat::Yuv420p8 => pixel_count * 3 / 2,
+                _ => pixel_count * format.num_components() as usize,
+            };
+            let data: Vec<u8> = (0..sample_count).map(noise).collect();
+            let input = ImageView8 {
+                data: &data,
+                width,
+                height,
+                format,
+            };
+            let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+            let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+                .expect("Decoder creation failed");
+            parser.parse_headers().expect("Header parsing failed");
+            assert_eq!(parser.picture_header().fractional_bits, 0);
+
+            let decoded = decode_frame_to_format(&bitstream, &DecoderConfig::default(), format)
+                .expect("Decoding failed");
+            assert!(decoded.data == data, "{:?} is not bit-exact", format);
+        }
+
+        // 16-bit samples use the full range without loss as well
+        let data: Vec<u16> = (0..width * height * 3)
+            .map(|i| (i as u16).wrapping_mul(40503))
+            .collect();
+        let input = ImageView16 {
+            data: &data,
+            width,
+            height,
+            format: PixelFormat::Rgb16,
+        };
+        let bitstream = encode_frame16(input, &config).expect("Encoding failed");
+        let decoded =
+            decode_frame16_to_format(&bitstream, &DecoderConfig::default(), PixelFormat::Rgb16)
+                .expect("Decoding failed");
+        assert!(decoded.data == data, "Rgb16 is not bit-exact");
+
+        // There is no size to aim for without quantization
+        let rate_controlled = EncoderConfig {
+            rate_target: Some(types::RateTarget::BitsPerPixel(2.0)),
+            ..config
+        };
+        let data = vec![0u8; (width * height) as usize];
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format: PixelFormat::Gray8,
+        };
+        assert!(encode_frame(input, &rate_controlled).is_err());
+    }
+
+    #[test]
+    fn test_per_band_weights_in_wgt() {
+        let width = 64u32;
+        let height = 32u32;
+        let pixel_count = (width * height) as usize;
+
+        let mut test_data = Vec::with_capacity(pixel_count * 3);
+        for y in 0..height {
+            for x in 0..width {
+                test_data.push(((x * 4 + y * 3) % 256) as u8);
+            }
+        }
+        test_data.extend(std::iter::repeat_n(128, pixel_count * 2));
+
+        let input = ImageView8 {
+            data: &test_data,
+            width,
+            height,
+            format: PixelFormat::Yuv444p8,
+        };
+        let config = EncoderConfig {
+            quality: 0.5,
+            decomposition_levels_x: 5,
+            decomposition_levels_y: 2,
+            ..Default::default()
+        };
+        let bitstream = encode_frame(input, &config).expect("Encoding failed");
+
+        // WGT holds one gain/priority pair per band and component
+        let mut parser = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())
+            .expect("Decoder creation failed");
+        parser.parse_headers().expect("Header parsing failed");
+        let expected = quant::compute_quantization_parameters(0.5, 5, 2, 3).unwrap();
+        assert_eq!(parser.get_qp_values(), expected.gains.as_slice());
+        assert_eq!(parser.get_band_priorities(), expected.priorities.as_slice());
+        assert!(expected.gains.iter().any(|&gain| gain != expected.gains[0]));
+
+        let decoded = decode_frame(&bitstream, &DecoderConfig::default()).expect("Decoding failed");
+        assert_eq!(decoded.data.len(), pixel_count * 3);
+    }
+
+    #[test]
+    fn test_errors_tell_corrupt_input_from_caller_mistakes() {
+        let test_data: Vec<u8> = (0..32 * 16 * 3).map(|i| (i % 256) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width: 32,
+            height: 16,
+            format: PixelFormat::Rgb8,
+        };
+        let bitstream = encode_frame(input, &EncoderConfig::default()).expect("Encoding failed");
+        let config = DecoderConfig::default();
+
+        let mut cut = bitstream.clone();
+        cut.data.truncate(cut.data.len() - 8);
+        assert!(matches!(
+            decode_frame(&cut, &config),
+            Err(JpegXsError::Truncated(_))
+        ));
+
+        let mut no_soc = bitstream.clone();
+        no_soc.data[1] = 0x13;
+        assert!(matches!(
+            decode_frame(&no_soc, &config),
+            Err(JpegXsError::InvalidMarker {
+                marker: 0xff13,
+                offset: 0
+            })
+        ));
+
+        let short = ImageView8 {
+            data: &test_data[..100],
+            format: PixelFormat::Yuv444p8,
+            ..input
+        };
+        assert!(matches!(
+            encode_frame(short, &EncoderConfig::default()),
+            Err(JpegXsError::BufferTooSmall {
+                needed: 1536,
+                available: 100
+            })
+        ));
+        let odd = ImageView8 {
+            data: &test_data[..100],
+            ..input
+        };
+        assert!(matches!(
+            encode_frame(odd, &EncoderConfig::default()),
+            Err(JpegXsError::DimensionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_what_lenient_mode_recovers() {
+        let test_data: Vec<u8> = (0..32 * 16 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width: 32,
+            height: 16,
+            format: PixelFormat::Rgb8,
+        };
+        let bitstream = encode_frame(input, &EncoderConfig::default()).expect("Encoding failed");
+        let lenient = DecoderConfig::default();
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        let expected = decode_frame(&bitstream, &strict).expect("Decoding failed");
+
+        // An unknown marker segment ahead of the first slice, and data after the EOC
+        let slh = bitstream
+            .data
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x20])
+            .unwrap();
+        let mut data = bitstream.data[..slh].to_vec();
+        data.extend_from_slice(&[0xff, 0x3a, 0x00, 0x04, 0x12, 0x34]);
+        data.extend_from_slice(&bitstream.data[slh..]);
+        data.extend_from_slice(&[0, 0]);
+        let deviating = Bitstream {
+            size_bits: data.len() * 8,
+            data,
+        };
+
+        let decoded = decode_frame(&deviating, &lenient).expect("Lenient decoding failed");
+        assert_eq!(decoded.data, expected.data);
+        match decode_frame(&deviating, &strict) {
+            Err(JpegXsError::NonConformant { offset, reason }) => {
+                assert_eq!(offset, slh);
+                assert_eq!(reason, "Unknown marker 0xff3a");
+            }
+            result => panic!(
+                "Expected a conformance violation, got {:?}",
+                result.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_corrupt_precinct_quantization_is_rejected() {
+        let test_data: Vec<u8> = (0..64 * 32 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width: 64,
+            height: 32,
+            format: PixelFormat::Rgb8,
+        };
+        let bitstream = encode_frame(input, &EncoderConfig::default()).expect("Encoding failed");
+
+        // Q[p] of the first precinct follows the SLH marker segment and Lprc
+        let slh = bitstream
+            .data
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x20])
+            .unwrap();
+        let mut data = bitstream.data.clone();
+        data[slh + 6 + 3] = 0xff;
+        let corrupt = Bitstream {
+            size_bits: data.len() * 8,
+            data,
+        };
+
+        for strict_mode in [false, true] {
+            let config = DecoderConfig {
+                strict_mode,
+                ..Default::default()
+            };
+            match decode_frame(&corrupt, &config) {
+                Err(JpegXsError::Corrupt(reason)) => assert!(reason.contains("Bw"), "{}", reason),
+                result => panic!("Expected corrupt data, got {:?}", result.map(|_| ())),
+            }
+        }
+        assert!(StreamingDecoder::new().push(&corrupt.data).is_err());
+    }
+
+    #[test]
+    fn test_optional_marker_segments_are_skipped() {
+        let test_data: Vec<u8> = (0..24 * 8).map(|i| (i * 5 % 256) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width: 24,
+            height: 8,
+            format: PixelFormat::Gray8,
+        };
+        let bitstream = encode_frame(input, &EncoderConfig::default()).expect("Encoding failed");
+        let config = DecoderConfig::default();
+        let expected = decode_frame(&bitstream, &config).expect("Decoding failed");
+
+        // A COM and a CRG between PIH and CDT, where the encoder writes none
+        let cdt = bitstream
+            .data
+            .windows(2)
+            .position(|bytes| bytes == [0xff, 0x13])
+            .unwrap();
+        let mut data = bitstream.data[..cdt].to_vec();
+        data.extend_from_slice(&[0xff, 0x15, 0x00, 0x06, 0x00, 0x01, b'o', b'k']);
+        data.extend_from_slice(&[0xff, 0x19, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&bitstream.data[cdt..]);
+        let bitstream = Bitstream {
+            size_bits: data.len() * 8,
+            data,
+        };
+        let decoded = decode_frame(&bitstream, &config).expect("Decoding failed");
+        assert_eq!(decoded.data, expected.data);
+
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        assert!(decode_frame(&bitstream, &strict).is_ok());
+    }
+
+    #[test]
+    fn test_comments_roundtrip() {
+        let test_data: Vec<u8> = (0..32 * 16 * 3).map(|i| (i * 11 % 256) as u8).collect();
+        let input = ImageView8 {
+            data: &test_data,
+            width: 32,
+            height: 16,
+            format: PixelFormat::Rgb8,
+        };
+        let metadata = types::CodestreamMetadata {
+            comments: vec![
+                Comment::text("jpegxs-rs 0.1, quality 0.9"),
+                Comment::text("TC 01:00:00:00"),
+                Comment::binary([0xde, 0xad]),
+                Comment {
+                    kind: CommentKind::Other(0x8001),
+                    data: Vec::new(),
+                },
+            ],
+        };
+        let config = EncoderConfig {
+            metadata: metadata.clone(),
+            ..Default::default()
+        };
+        let bitstream = encode_frame(input, &config).expect("Encoding failed");
+        let info = probe_frame(&bitstream).expect("Probing failed");
+        assert_eq!(info.metadata, metadata);
+        assert_eq!(
+            info.metadata.texts().collect::<Vec<_>>(),
+            ["jpegxs-rs 0.1, quality 0.9", "TC 01:00:00:00"]
+        );
+
+        // Comments change nothing but the header
+        let plain = encode_frame(input, &EncoderConfig::default()).expect("Encoding failed");
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        let decoded = decode_frame(&bitstream, &strict).expect("Decoding failed");
+        assert_eq!(
+            decoded.data,
+            decode_frame(&plain, &strict).expect("Decoding failed").data
+        );
+        assert!(probe_frame(&plain).unwrap().metadata.comments.is_empty());
+
+        // The rate budget covers the comments
+        let rate_config = EncoderConfig {
+            rate_target: Some(types::RateTarget::BitsPerPixel(8.0)),
+            ..config.clone()
+        };
+        let bitstream = encode_frame(input, &rate_config).expect("Encoding failed");
+        assert_eq!(bitstream.data.len(), 32 * 16);
+
+        let too_long = EncoderConfig {
+            metadata: types::CodestreamMetadata {
+                comments: vec![Comment::binary(vec![0; 65532])],
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode_frame(input, &too_long),
+            Err(JpegXsError::InvalidParameter(_))
+        ));
+    }
+
     #[test]
     fn test_dwt_roundtrip() {
         let width = 8u32;
@@ -677,21 +2422,4 @@ mod tests {
             max_error
         );
     }
-
-    #[test]
-    fn test_quantization_roundtrip() {
-        let coeffs = vec![1.5, -2.3, 0.8, -0.1, 3.7];
-        let qp = 2;
-
-        let quantized = quant::quantize(&coeffs, qp).expect("Quantization failed");
-        let dequantized = quant::dequantize(&quantized, qp).expect("Dequantization failed");
-
-        assert_eq!(coeffs.len(), dequantized.len());
-
-        // Check that quantization introduces some loss but maintains reasonable fidelity
-        for (orig, reconstructed) in coeffs.iter().zip(dequantized.iter()) {
-            let error = (orig - reconstructed).abs();
-            assert!(error < 5.0, "Quantization error too large: {}", error);
-        }
-    }
 }
@@ -2,7 +2,9 @@
 // Leverages 128-bit NEON vectors for 4x f32 parallel processing
 // Target: 2-3x performance improvement over scalar CPU implementation
 
-use anyhow::Result;
+#[cfg(target_arch = "aarch64")]
+use crate::error::JpegXsError;
+use crate::error::Result;
 
 // NEON SIMD intrinsics - will be used when actual SIMD optimization is implemented
 // #[cfg(target_arch = "aarch64")]
@@ -107,7 +109,9 @@ impl NeonDwt {
     ) -> Result<()> {
         let total_size = (width * height) as usize;
         if input.len() != total_size || output.len() != total_size {
-            return Err(anyhow::anyhow!("Invalid buffer sizes"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
         }
 
         // Create temporary buffer for intermediate results
@@ -132,7 +136,9 @@ impl NeonDwt {
     ) -> Result<()> {
         let total_size = (width * height) as usize;
         if input.len() != total_size || output.len() != total_size {
-            return Err(anyhow::anyhow!("Invalid buffer sizes"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
         }
 
         // Create temporary buffer for intermediate results
@@ -263,7 +269,9 @@ impl NeonDwt {
     fn dwt_53_forward_1d_neon(&self, input: &[f32], output: &mut [f32]) -> Result<()> {
         let len = input.len();
         if len != output.len() {
-            return Err(anyhow::anyhow!("Input and output lengths must match"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Input and output lengths must match".into(),
+            ));
         }
 
         if len < 4 {
@@ -319,7 +327,9 @@ impl NeonDwt {
     fn dwt_53_inverse_1d_neon(&self, input: &[f32], output: &mut [f32]) -> Result<()> {
         let len = input.len();
         if len != output.len() {
-            return Err(anyhow::anyhow!("Input and output lengths must match"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Input and output lengths must match".into(),
+            ));
         }
 
         if len < 4 {
@@ -371,7 +381,9 @@ impl NeonDwt {
     #[cfg(target_arch = "aarch64")]
     fn dwt_53_forward_1d_scalar(&self, input: &[f32], output: &mut [f32]) -> Result<()> {
         if input.len() != output.len() {
-            return Err(anyhow::anyhow!("Input and output lengths must match"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Input and output lengths must match".into(),
+            ));
         }
 
         output.copy_from_slice(input);
@@ -397,7 +409,9 @@ impl NeonDwt {
     #[cfg(target_arch = "aarch64")]
     fn dwt_53_inverse_1d_scalar(&self, input: &[f32], output: &mut [f32]) -> Result<()> {
         if input.len() != output.len() {
-            return Err(anyhow::anyhow!("Input and output lengths must match"));
+            return Err(JpegXsError::DimensionMismatch(
+                "Input and output lengths must match".into(),
+            ));
         }
 
         output.copy_from_slice(input);
@@ -1,10 +1,156 @@
+// JPEG XS codestream fragmentation based on ISO/IEC 21122-1:2024 Annex B
+//
+// A codestream is split into payloads along its natural boundaries, in codestream order:
+//
+// 1. The main header: SOC followed by every marker segment (CAP, PIH, CDT, WGT, ...)
+// 2. For every slice, its SLH marker segment followed by the slice's precincts
+// 3. For every precinct, its precinct header followed by each of its packets
+// 4. The EOC marker
+//
+// Concatenating the payloads reproduces the codestream byte for byte.
+
+use crate::error::{JpegXsError, Result};
+use crate::precinct::{precinct_packet_lengths, PrecinctLayout};
 use crate::types::Bitstream;
-use anyhow::Result;
 
-pub fn pack_bitstream(_data: Vec<Vec<u8>>) -> Result<Bitstream> {
-    todo!("Pack bitstream")
+/// SLH marker, Lslh and Yslh (ISO Table A.23)
+const SLICE_HEADER_LENGTH: usize = 6;
+
+/// Reassemble a codestream from payloads produced by [`unpack_bitstream`]
+///
+/// The payloads are concatenated in order and the result is checked to be a complete
+/// codestream, so missing or reordered payloads are reported rather than decoded later.
+pub fn pack_bitstream(data: Vec<Vec<u8>>) -> Result<Bitstream> {
+    let data = data.concat();
+    if data.is_empty() {
+        return Err(JpegXsError::InvalidParameter(
+            "Cannot pack an empty codestream".into(),
+        ));
+    }
+
+    let bitstream = Bitstream {
+        size_bits: data.len() * 8,
+        data,
+    };
+    unpack_bitstream(&bitstream)?;
+    Ok(bitstream)
 }
 
-pub fn unpack_bitstream(_bitstream: &Bitstream) -> Result<Vec<Vec<u8>>> {
-    todo!("Unpack bitstream")
+/// Split a codestream into its main header, slice headers, precinct headers, packets and EOC
+pub fn unpack_bitstream(bitstream: &Bitstream) -> Result<Vec<Vec<u8>>> {
+    let mut decoder = jpegxs_core_clean::JpegXsDecoder::new(bitstream.data.clone())?;
+    decoder.parse_headers()?;
+
+    let picture_header = decoder.picture_header();
+    let sampling: Vec<(u8, u8)> = decoder
+        .components()
+        .iter()
+        .map(|component| (component.sampling_x, component.sampling_y))
+        .collect();
+    let layout = PrecinctLayout::with_sampling(
+        picture_header.width as u32,
+        picture_header.height as u32,
+        picture_header.decomposition_levels_x,
+        picture_header.decomposition_levels_y,
+        &sampling,
+        picture_header.precinct_width,
+    )?;
+
+    let slices = decoder.slices()?;
+    let data = &bitstream.data;
+    let entropy_length: usize = slices
+        .iter()
+        .map(|slice| SLICE_HEADER_LENGTH + slice.data.len())
+        .sum();
+    let header_length = data.len() - 2 - entropy_length;
+
+    let mut payloads = vec![data[..header_length].to_vec()];
+    let mut offset = header_length;
+    for slice in &slices {
+        payloads.push(data[offset..offset + SLICE_HEADER_LENGTH].to_vec());
+        offset += SLICE_HEADER_LENGTH;
+
+        let mut slice_offset = 0;
+        for precinct in picture_header.slice_precincts(slice.index as usize) {
+            let (precinct_header_length, packet_lengths) =
+                precinct_packet_lengths(&layout, &slice.data[slice_offset..], precinct)?;
+            let mut lengths = vec![precinct_header_length];
+            lengths.extend(packet_lengths);
+            for length in lengths {
+                payloads.push(slice.data[slice_offset..slice_offset + length].to_vec());
+                slice_offset += length;
+            }
+        }
+        offset += slice_offset;
+    }
+    payloads.push(data[offset..].to_vec());
+
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EncoderConfig, ImageView8, PixelFormat, Profile};
+
+    fn encode_test_frame(
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+        format: PixelFormat,
+    ) -> Bitstream {
+        let data: Vec<u8> = (0..width * height * 3)
+            .map(|i| ((i * 37 + (i / width) * 11) % 256) as u8)
+            .collect();
+        let image = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format,
+        };
+        let config = EncoderConfig {
+            decomposition_levels_x: levels_x,
+            decomposition_levels_y: levels_y,
+            slice_height: 4,
+            profile: Profile::Unrestricted,
+            ..Default::default()
+        };
+        crate::encode_frame(image, &config).unwrap()
+    }
+
+    #[test]
+    fn test_unpack_pack_roundtrip() {
+        let bitstream = encode_test_frame(48, 20, 3, 2, PixelFormat::Yuv444p8);
+        let payloads = unpack_bitstream(&bitstream).unwrap();
+
+        // Main header, 2 slices of up to 4 precincts, each a header and 3 packets, EOC
+        assert_eq!(payloads.len(), 1 + 2 + 5 * (1 + 3) + 1);
+        assert_eq!(&payloads[0][..2], &[0xff, 0x10]);
+        assert_eq!(&payloads[1], &[0xff, 0x20, 0x00, 0x04, 0x00, 0x00]);
+        assert_eq!(&payloads[1 + 1 + 4 * 4][..2], &[0xff, 0x20]);
+        assert_eq!(payloads.last().unwrap(), &vec![0xff, 0x11]);
+
+        let packed = pack_bitstream(payloads).unwrap();
+        assert_eq!(packed.data, bitstream.data);
+        assert_eq!(packed.size_bits, bitstream.size_bits);
+    }
+
+    #[test]
+    fn test_subsampled_roundtrip() {
+        let bitstream = encode_test_frame(48, 20, 3, 2, PixelFormat::Yuv420p8);
+        let payloads = unpack_bitstream(&bitstream).unwrap();
+        assert_eq!(payloads.len(), 1 + 2 + 5 * (1 + 3) + 1);
+        assert_eq!(pack_bitstream(payloads).unwrap().data, bitstream.data);
+    }
+
+    #[test]
+    fn test_missing_packet_is_rejected() {
+        let bitstream = encode_test_frame(32, 8, 2, 1, PixelFormat::Yuv444p8);
+        let mut payloads = unpack_bitstream(&bitstream).unwrap();
+        payloads.remove(3);
+
+        assert!(pack_bitstream(payloads).is_err());
+        assert!(pack_bitstream(Vec::new()).is_err());
+    }
 }
@@ -0,0 +1,1273 @@
+// JPEG XS precinct and packet coding based on ISO/IEC 21122-1:2024 Annex C
+//
+// A precinct collects the band lines contributing to 2^NL,y image lines, optionally
+// restricted to a column of 8 * Cw * 2^NL,x samples (Cw > 0). It starts
+// with a precinct header (Lprc, Q, R and the band coding modes D[p,b]) followed by
+// packets that group band lines of equal vertical resolution. Every packet holds a
+// significance, bitplane count, data and sign subpacket built on `crate::entropy`.
+
+use crate::dwt::SubbandRegion;
+use crate::entropy::{
+    compute_bitplane_count, vlc_decode, vlc_encode, BitstreamReader, BitstreamWriter, VlcContext,
+};
+use crate::error::{JpegXsError, Result};
+
+/// Number of coefficients per code group (Ng, ISO Table A.7)
+pub const CODE_GROUP_SIZE: usize = 4;
+
+/// Number of code groups per significance group (Ss, ISO Table A.7)
+pub const SIGNIFICANCE_GROUP_SIZE: usize = 8;
+
+/// Bits per bitplane count in raw mode (Br, ISO Table A.7)
+pub const RAW_BITPLANE_COUNT_BITS: u8 = 4;
+
+/// Lprc u(24), Q[p] u(8), R[p] u(8) before the band coding modes (ISO Table C.3)
+const PRECINCT_HEADER_FIXED_LENGTH: usize = 5;
+
+/// Long packet header: Dr u(1), Ldat u(20), Lcnt u(20), Lsgn u(15) (ISO Table C.8)
+const PACKET_HEADER_LENGTH: usize = 7;
+
+/// Largest subpacket sizes representable in the long packet header
+const MAX_DATA_LENGTH: usize = (1 << 20) - 1;
+const MAX_COUNT_LENGTH: usize = (1 << 20) - 1;
+const MAX_SIGN_LENGTH: usize = (1 << 15) - 1;
+
+/// Longest unary prefix accepted by `vlc_decode` with Br = 4
+const MAX_VLC_PREFIX: i32 = 31;
+
+/// Largest bitplane count: the magnitude bits of an `i32` coefficient, which no Bw can
+/// exceed; dequantization checks the signalled Bw itself
+const MAX_BITPLANE_COUNT: i32 = 31;
+
+/// LL band columns per unit of Cw (ISO Table A.7)
+const PRECINCT_WIDTH_UNIT: u32 = 8;
+
+/// Bitplane count coding mode of a band in a precinct, D[p,b] in ISO Table C.4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandCodingMode {
+    /// Bitplane counts coded directly
+    NoPrediction = 0,
+    /// Bitplane counts coded directly, all-zero significance groups skipped
+    NoPredictionSignificance = 1,
+    /// Bitplane counts predicted from the band line above
+    VerticalPrediction = 2,
+    /// Vertical prediction, significance groups without residual skipped
+    VerticalPredictionSignificance = 3,
+}
+
+impl BandCodingMode {
+    const ALL: [BandCodingMode; 4] = [
+        BandCodingMode::NoPrediction,
+        BandCodingMode::NoPredictionSignificance,
+        BandCodingMode::VerticalPrediction,
+        BandCodingMode::VerticalPredictionSignificance,
+    ];
+
+    fn from_bits(bits: u8) -> Self {
+        Self::ALL[(bits & 0x03) as usize]
+    }
+
+    fn vertical_prediction(self) -> bool {
+        matches!(
+            self,
+            BandCodingMode::VerticalPrediction | BandCodingMode::VerticalPredictionSignificance
+        )
+    }
+
+    fn significance(self) -> bool {
+        matches!(
+            self,
+            BandCodingMode::NoPredictionSignificance
+                | BandCodingMode::VerticalPredictionSignificance
+        )
+    }
+}
+
+/// Precinct header fields (ISO Table C.3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecinctHeader {
+    /// Lprc: Size of the precinct in bytes, excluding the precinct header
+    pub length: u32,
+    /// Q[p]: Quantization parameter
+    pub quantization: u8,
+    /// R[p]: Refinement parameter
+    pub refinement: u8,
+    /// D[p,b]: Bitplane count coding mode of every band b = beta * Nc + component
+    pub coding_modes: Vec<BandCodingMode>,
+}
+
+/// Line of a band inside a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketLine {
+    /// Band index beta, see [`crate::dwt::subband_regions`]
+    pub beta: usize,
+    /// Band line offset within the precinct
+    pub line: u32,
+}
+
+/// Band line layout shared by all precincts of a frame
+///
+/// Band lines are grouped into packets by vertical resolution: bands that saw `d`
+/// vertical decompositions contribute 2^(NL,y - d) lines to every precinct, one per
+/// packet, so a packet never mixes lines of different vertical resolutions.
+///
+/// Precincts are numbered in raster order: with a precinct width Cw > 0 every row of
+/// precincts holds ceil(width / (8 * Cw * 2^NL,x)) precincts, left to right.
+///
+/// Components subsampled by sx[c] / sy[c] (ISO Table A.15) are coded at their native
+/// size. A vertically subsampled component sees NL,y - 1 vertical decompositions, so
+/// its precinct lines still cover the same image rows, and lacks the LH / HH bands of
+/// the deepest 2D level; band indices beta always follow the full resolution layout.
+#[derive(Debug, Clone)]
+pub struct PrecinctLayout {
+    levels_y: u8,
+    num_components: usize,
+    num_precincts: usize,
+    precincts_per_row: usize,
+    regions: Vec<SubbandRegion>,
+    components: Vec<ComponentLayout>,
+    packets: Vec<Vec<PacketLine>>,
+}
+
+/// Band geometry of a single component
+#[derive(Debug, Clone)]
+struct ComponentLayout {
+    width: u32,
+    height: u32,
+    sampling: (u8, u8),
+    levels_y: u8,
+    /// Precinct column width in component samples, `None` for full width precincts
+    precinct_width: Option<u32>,
+    /// Region of every band beta, `None` for bands the component does not have
+    regions: Vec<Option<SubbandRegion>>,
+}
+
+impl PrecinctLayout {
+    /// Layout of a 4:4:4 frame with precincts `precinct_width` (Cw) units wide, 0 for full width
+    pub fn new(
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+        num_components: usize,
+        precinct_width: u16,
+    ) -> Result<Self> {
+        Self::with_sampling(
+            width,
+            height,
+            levels_x,
+            levels_y,
+            &vec![(1, 1); num_components],
+            precinct_width,
+        )
+    }
+
+    /// Layout of a frame whose components are subsampled by `sampling` = (sx[c], sy[c])
+    pub fn with_sampling(
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+        sampling: &[(u8, u8)],
+        precinct_width: u16,
+    ) -> Result<Self> {
+        crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+        if width == 0 || height == 0 || sampling.is_empty() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid precinct layout dimensions".into(),
+            ));
+        }
+
+        let regions = crate::dwt::subband_regions(width, height, levels_x, levels_y);
+        let precinct_lines = 1u32 << levels_y;
+        let precinct_width =
+            (precinct_width > 0).then(|| (precinct_width as u32 * PRECINCT_WIDTH_UNIT) << levels_x);
+        let precincts_per_row = precinct_width.map_or(1, |pw| width.div_ceil(pw) as usize);
+        let num_precincts = height.div_ceil(precinct_lines) as usize * precincts_per_row;
+
+        let components = sampling
+            .iter()
+            .map(|&(sx, sy)| {
+                if !matches!(sx, 1 | 2) || !matches!(sy, 1 | 2) {
+                    return Err(JpegXsError::UnsupportedFeature(format!(
+                        "Unsupported sampling factors {}x{}",
+                        sx, sy
+                    )));
+                }
+                if sy == 2 && levels_y == 0 {
+                    return Err(JpegXsError::UnsupportedFeature(
+                        "Vertically subsampled components need a vertical decomposition".into(),
+                    ));
+                }
+                let (component_width, component_height) =
+                    (width.div_ceil(sx as u32), height.div_ceil(sy as u32));
+                let component_levels_y = levels_y - (sy - 1);
+                let component_regions = crate::dwt::subband_regions(
+                    component_width,
+                    component_height,
+                    levels_x,
+                    component_levels_y,
+                );
+                Ok(ComponentLayout {
+                    width: component_width,
+                    height: component_height,
+                    sampling: (sx, sy),
+                    levels_y: component_levels_y,
+                    precinct_width: precinct_width.map(|pw| pw / sx as u32),
+                    regions: regions
+                        .iter()
+                        .map(|band| {
+                            component_regions.iter().copied().find(|region| {
+                                region.level == band.level && region.orientation == band.orientation
+                            })
+                        })
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Coarsest resolution first, then every line of the finer vertical resolutions
+        let mut packets = Vec::new();
+        for depth in (0..=levels_y).rev() {
+            let bands: Vec<usize> = regions
+                .iter()
+                .enumerate()
+                .filter(|(_, region)| region.level.min(levels_y) == depth)
+                .map(|(beta, _)| beta)
+                .collect();
+            if bands.is_empty() {
+                continue;
+            }
+            for line in 0..1u32 << (levels_y - depth) {
+                packets.push(
+                    bands
+                        .iter()
+                        .map(|&beta| PacketLine { beta, line })
+                        .collect(),
+                );
+            }
+        }
+
+        Ok(Self {
+            levels_y,
+            num_components: sampling.len(),
+            num_precincts,
+            precincts_per_row,
+            regions,
+            components,
+            packets,
+        })
+    }
+
+    /// Number of precincts in the frame
+    pub fn num_precincts(&self) -> usize {
+        self.num_precincts
+    }
+
+    /// Number of precincts side by side in every precinct row
+    pub fn precincts_per_row(&self) -> usize {
+        self.precincts_per_row
+    }
+
+    /// Number of bands per component
+    pub fn num_bands(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Number of components
+    pub fn num_components(&self) -> usize {
+        self.num_components
+    }
+
+    /// Sampling factors (sx[c], sy[c]) of a component
+    pub fn component_sampling(&self, component: usize) -> (u8, u8) {
+        self.components[component].sampling
+    }
+
+    /// Width and height of a component plane in samples
+    pub fn component_size(&self, component: usize) -> (u32, u32) {
+        let layout = &self.components[component];
+        (layout.width, layout.height)
+    }
+
+    /// Number of vertical decompositions of a component
+    pub fn component_levels_y(&self, component: usize) -> u8 {
+        self.components[component].levels_y
+    }
+
+    /// Number of samples in a component plane
+    pub fn plane_size(&self, component: usize) -> usize {
+        let (width, height) = self.component_size(component);
+        (width * height) as usize
+    }
+
+    /// Band lines of every packet in a precinct
+    pub fn packets(&self) -> &[Vec<PacketLine>] {
+        &self.packets
+    }
+
+    /// Plane row of a band line in a precinct, or `None` if the component has no such line
+    fn band_row(&self, precinct: usize, component: usize, line: PacketLine) -> Option<u32> {
+        let layout = &self.components[component];
+        let region = layout.regions[line.beta]?;
+        let depth = region.level.min(layout.levels_y);
+        let lines = 1u32 << (layout.levels_y - depth);
+        let precinct_row = (precinct / self.precincts_per_row) as u32;
+        let row = precinct_row * lines + line.line;
+        (line.line < lines && row < region.height).then_some(region.y + row)
+    }
+
+    /// Band columns of a precinct, or `None` right of the band edge
+    fn band_columns(
+        &self,
+        precinct: usize,
+        component: usize,
+        beta: usize,
+    ) -> Option<std::ops::Range<u32>> {
+        let layout = &self.components[component];
+        let region = layout.regions[beta]?;
+        let Some(precinct_width) = layout.precinct_width else {
+            return Some(0..region.width);
+        };
+        // Every horizontal decomposition halves the precinct width within the band
+        let band_width = precinct_width >> region.level;
+        let start = (precinct % self.precincts_per_row) as u32 * band_width;
+        (start < region.width).then(|| start..(start + band_width).min(region.width))
+    }
+
+    /// Plane index range of a band line of a component in a precinct
+    fn band_line_range(
+        &self,
+        precinct: usize,
+        component: usize,
+        line: PacketLine,
+    ) -> Option<std::ops::Range<usize>> {
+        let layout = &self.components[component];
+        let region = layout.regions[line.beta]?;
+        let row = self.band_row(precinct, component, line)?;
+        let columns = self.band_columns(precinct, component, line.beta)?;
+        let start = (row * layout.width + region.x) as usize;
+        Some(start + columns.start as usize..start + columns.end as usize)
+    }
+
+    /// Band index beta and plane index range of every band line of a component in a precinct
+    pub fn band_lines(
+        &self,
+        precinct: usize,
+        component: usize,
+    ) -> Vec<(usize, std::ops::Range<usize>)> {
+        self.packets
+            .iter()
+            .flatten()
+            .filter_map(|&line| {
+                self.band_line_range(precinct, component, line)
+                    .map(|range| (line.beta, range))
+            })
+            .collect()
+    }
+
+    /// Band lines present in a packet, interleaved by component (b = beta * Nc + c)
+    fn packet_band_lines(
+        &self,
+        precinct: usize,
+        packet: usize,
+    ) -> Vec<(usize, PacketLine, std::ops::Range<usize>)> {
+        let mut lines = Vec::new();
+        for &line in &self.packets[packet] {
+            for component in 0..self.num_components {
+                if let Some(range) = self.band_line_range(precinct, component, line) {
+                    lines.push((component, line, range));
+                }
+            }
+        }
+        lines
+    }
+
+    /// Band line directly above `line` inside the same precinct
+    fn line_above(&self, line: PacketLine) -> Option<PacketLine> {
+        (line.line > 0).then(|| PacketLine {
+            beta: line.beta,
+            line: line.line - 1,
+        })
+    }
+}
+
+/// Bitplane counts of the code groups of a band line
+fn code_group_counts(coeffs: &[i32]) -> Vec<u8> {
+    coeffs
+        .chunks(CODE_GROUP_SIZE)
+        .map(|group| {
+            group
+                .iter()
+                .map(|&coeff| compute_bitplane_count(coeff).0)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Number of code groups in a band line of `width` coefficients
+fn code_group_count(width: usize) -> usize {
+    width.div_ceil(CODE_GROUP_SIZE)
+}
+
+/// VLC context and value for a bitplane count given the count above (if predicted)
+fn count_symbol(count: u8, top: Option<u8>) -> (i32, VlcContext) {
+    let predictor = top.unwrap_or(0) as i32;
+    (
+        count as i32 - predictor,
+        VlcContext {
+            predictor,
+            truncation_pos: 0,
+            br_bits: RAW_BITPLANE_COUNT_BITS,
+        },
+    )
+}
+
+/// Length in bits of a VLC codeword, `None` when the decoder could not read it back
+fn vlc_length(value: i32, ctx: VlcContext) -> Option<usize> {
+    let theta = (ctx.predictor - ctx.truncation_pos as i32).max(0);
+    let n = if value > theta {
+        value + theta
+    } else if value < 0 {
+        -2 * value - 1
+    } else {
+        2 * value
+    };
+    (n <= MAX_VLC_PREFIX).then_some(n as usize + 1)
+}
+
+/// Whether a significance group needs no bitplane counts under `mode`
+fn group_skipped(counts: &[u8], tops: Option<&[u8]>, mode: BandCodingMode) -> bool {
+    if !mode.significance() {
+        return false;
+    }
+    match (mode.vertical_prediction(), tops) {
+        (true, Some(tops)) => counts.iter().zip(tops).all(|(count, top)| count == top),
+        _ => counts.iter().all(|&count| count == 0),
+    }
+}
+
+/// Significance and bitplane count bits of a band line, `None` if not codable
+fn vlc_line_cost(counts: &[u8], tops: Option<&[u8]>, mode: BandCodingMode) -> Option<usize> {
+    let tops = tops.filter(|_| mode.vertical_prediction());
+    let mut bits = 0;
+    for (group, chunk) in counts.chunks(SIGNIFICANCE_GROUP_SIZE).enumerate() {
+        let group_tops = tops.map(|tops| &tops[group * SIGNIFICANCE_GROUP_SIZE..][..chunk.len()]);
+        if mode.significance() {
+            bits += 1;
+            if group_skipped(chunk, group_tops, mode) {
+                continue;
+            }
+        }
+        for (i, &count) in chunk.iter().enumerate() {
+            let (value, ctx) = count_symbol(count, group_tops.map(|tops| tops[i]));
+            bits += vlc_length(value, ctx)?;
+        }
+    }
+    Some(bits)
+}
+
+/// Encode one precinct: precinct header followed by all of its packets
+pub fn encode_precinct(
+    layout: &PrecinctLayout,
+    planes: &[&[i32]],
+    precinct: usize,
+    quantization: u8,
+    refinement: u8,
+) -> Result<Vec<u8>> {
+    if planes.len() != layout.num_components {
+        return Err(JpegXsError::DimensionMismatch(format!(
+            "Expected {} component planes, got {}",
+            layout.num_components,
+            planes.len()
+        )));
+    }
+    let nc = layout.num_components;
+
+    // Bitplane counts of every band line present in the precinct
+    let counts_of = |component: usize, line: PacketLine| {
+        layout
+            .band_line_range(precinct, component, line)
+            .map(|range| code_group_counts(&planes[component][range]))
+    };
+
+    // D[p,b]: cheapest bitplane count coding mode of every band
+    let mut coding_modes = vec![BandCodingMode::NoPrediction; layout.num_bands() * nc];
+    for beta in 0..layout.num_bands() {
+        let depth = layout.regions[beta].level.min(layout.levels_y);
+        for component in 0..nc {
+            let mut best: Option<(usize, BandCodingMode)> = None;
+            for mode in BandCodingMode::ALL {
+                let mut total = Some(0);
+                let mut above: Option<Vec<u8>> = None;
+                for line in 0..1u32 << (layout.levels_y - depth) {
+                    let Some(counts) = counts_of(component, PacketLine { beta, line }) else {
+                        break;
+                    };
+                    total = total
+                        .zip(vlc_line_cost(&counts, above.as_deref(), mode))
+                        .map(|(a, b)| a + b);
+                    above = Some(counts);
+                }
+                if let Some(bits) = total {
+                    if best.is_none_or(|(best_bits, _)| bits < best_bits) {
+                        best = Some((bits, mode));
+                    }
+                }
+            }
+            coding_modes[beta * nc + component] = best.map(|(_, mode)| mode).ok_or_else(|| {
+                JpegXsError::UnsupportedFeature("Bitplane counts exceed the VLC range".into())
+            })?;
+        }
+    }
+
+    let mut body = Vec::new();
+    for packet in 0..layout.packets.len() {
+        let lines = layout.packet_band_lines(precinct, packet);
+        if lines.is_empty() {
+            continue;
+        }
+        body.extend(encode_packet(
+            layout,
+            planes,
+            precinct,
+            &lines,
+            &coding_modes,
+        )?);
+    }
+
+    let length = body.len() as u32;
+    if length >= 1 << 24 {
+        return Err(JpegXsError::UnsupportedFeature(format!(
+            "Precinct too large: {} bytes",
+            length
+        )));
+    }
+
+    let mut writer = BitstreamWriter::new();
+    writer.write_bits(length, 24);
+    writer.write_bits(quantization as u32, 8);
+    writer.write_bits(refinement as u32, 8);
+    for mode in &coding_modes {
+        writer.write_bits(*mode as u32, 2);
+    }
+    let mut data = writer.finalize();
+    data.extend(body);
+    Ok(data)
+}
+
+/// Encode one packet: header, significance, bitplane count, data and sign subpackets
+fn encode_packet(
+    layout: &PrecinctLayout,
+    planes: &[&[i32]],
+    precinct: usize,
+    lines: &[(usize, PacketLine, std::ops::Range<usize>)],
+    coding_modes: &[BandCodingMode],
+) -> Result<Vec<u8>> {
+    let nc = layout.num_components;
+    let band_counts: Vec<Vec<u8>> = lines
+        .iter()
+        .map(|(component, _, range)| code_group_counts(&planes[*component][range.clone()]))
+        .collect();
+    let above_counts: Vec<Option<Vec<u8>>> = lines
+        .iter()
+        .map(|(component, line, _)| {
+            layout
+                .line_above(*line)
+                .and_then(|above| layout.band_line_range(precinct, *component, above))
+                .map(|range| code_group_counts(&planes[*component][range]))
+        })
+        .collect();
+
+    // Raw mode (Dr = 1) when it is cheaper than VLC coding and all counts fit Br bits
+    let raw_limit = (1u8 << RAW_BITPLANE_COUNT_BITS) - 1;
+    let raw_bits: usize = band_counts.iter().map(|counts| counts.len()).sum::<usize>()
+        * RAW_BITPLANE_COUNT_BITS as usize;
+    let vlc_bits: usize = lines
+        .iter()
+        .zip(&band_counts)
+        .zip(&above_counts)
+        .map(|(((component, line, _), counts), above)| {
+            let mode = coding_modes[line.beta * nc + component];
+            vlc_line_cost(counts, above.as_deref(), mode).unwrap_or(usize::MAX / 2)
+        })
+        .sum();
+    let raw = raw_bits < vlc_bits
+        && band_counts
+            .iter()
+            .flatten()
+            .all(|&count| count <= raw_limit);
+
+    let mut significance = BitstreamWriter::new();
+    let mut count_writer = BitstreamWriter::new();
+    let mut data_writer = BitstreamWriter::new();
+    let mut sign_writer = BitstreamWriter::new();
+
+    for (i, (component, line, range)) in lines.iter().enumerate() {
+        let counts = &band_counts[i];
+        let mode = coding_modes[line.beta * nc + component];
+        let tops = above_counts[i]
+            .as_deref()
+            .filter(|_| mode.vertical_prediction());
+
+        if raw {
+            for &count in counts {
+                count_writer.write_bits(count as u32, RAW_BITPLANE_COUNT_BITS);
+            }
+        } else {
+            for (group, chunk) in counts.chunks(SIGNIFICANCE_GROUP_SIZE).enumerate() {
+                let group_tops =
+                    tops.map(|tops| &tops[group * SIGNIFICANCE_GROUP_SIZE..][..chunk.len()]);
+                if mode.significance() {
+                    let skipped = group_skipped(chunk, group_tops, mode);
+                    significance.write_bit(skipped);
+                    if skipped {
+                        continue;
+                    }
+                }
+                for (j, &count) in chunk.iter().enumerate() {
+                    let (value, ctx) = count_symbol(count, group_tops.map(|tops| tops[j]));
+                    vlc_encode(&mut count_writer, value, ctx)?;
+                }
+            }
+        }
+
+        // Magnitude bitplanes from the most significant down, Ng bits per bitplane
+        let coeffs = &planes[*component][range.clone()];
+        for (group, &count) in coeffs.chunks(CODE_GROUP_SIZE).zip(counts) {
+            for bitplane in (0..count).rev() {
+                for j in 0..CODE_GROUP_SIZE {
+                    let magnitude = group.get(j).map_or(0, |coeff| coeff.unsigned_abs());
+                    data_writer.write_bit((magnitude >> bitplane) & 1 == 1);
+                }
+            }
+            for &coeff in group {
+                if coeff != 0 {
+                    sign_writer.write_bit(coeff < 0);
+                }
+            }
+        }
+    }
+
+    let significance = significance.finalize();
+    let counts = count_writer.finalize();
+    let data = data_writer.finalize();
+    let signs = sign_writer.finalize();
+    if data.len() > MAX_DATA_LENGTH
+        || counts.len() > MAX_COUNT_LENGTH
+        || signs.len() > MAX_SIGN_LENGTH
+    {
+        return Err(JpegXsError::UnsupportedFeature(
+            "Packet exceeds the long packet header limits".into(),
+        ));
+    }
+
+    let mut header = BitstreamWriter::new();
+    header.write_bit(raw);
+    header.write_bits(data.len() as u32, 20);
+    header.write_bits(counts.len() as u32, 20);
+    header.write_bits(signs.len() as u32, 15);
+
+    let mut packet = header.finalize();
+    if !raw {
+        packet.extend(significance);
+    }
+    packet.extend(counts);
+    packet.extend(data);
+    packet.extend(signs);
+    Ok(packet)
+}
+
+/// Decode one precinct into the component planes, returning its header and coded size
+pub fn decode_precinct(
+    layout: &PrecinctLayout,
+    data: &[u8],
+    planes: &mut [Vec<i32>],
+    precinct: usize,
+) -> Result<(PrecinctHeader, usize)> {
+    let nc = layout.num_components;
+    if planes.len() != nc {
+        return Err(JpegXsError::DimensionMismatch(format!(
+            "Expected {} component planes, got {}",
+            nc,
+            planes.len()
+        )));
+    }
+
+    let (header, header_length) = read_precinct_header(layout, data)?;
+    let end = header_length + header.length as usize;
+
+    let mut offset = header_length;
+    for packet in 0..layout.packets.len() {
+        let lines = layout.packet_band_lines(precinct, packet);
+        if lines.is_empty() {
+            continue;
+        }
+        offset += decode_packet(
+            layout,
+            &data[offset..end],
+            planes,
+            precinct,
+            &lines,
+            &header.coding_modes,
+        )?;
+    }
+    // Any bytes left before the signalled end are padding (ISO C.2)
+
+    Ok((header, end))
+}
+
+/// Byte sizes of the precinct header and of every packet in a precinct
+///
+/// Packet boundaries follow from the packet headers and the band coding modes alone,
+/// so a precinct can be split without decoding any coefficients. Padding at the end
+/// of the precinct is counted towards its last packet.
+pub fn precinct_packet_lengths(
+    layout: &PrecinctLayout,
+    data: &[u8],
+    precinct: usize,
+) -> Result<(usize, Vec<usize>)> {
+    let (header, header_length) = read_precinct_header(layout, data)?;
+    let end = header_length + header.length as usize;
+
+    let mut offset = header_length;
+    let mut lengths = Vec::with_capacity(layout.packets.len());
+    for packet in 0..layout.packets.len() {
+        let lines = layout.packet_band_lines(precinct, packet);
+        if lines.is_empty() {
+            continue;
+        }
+        let packet_header = PacketHeader::read(&data[offset..end])?;
+        let length = PACKET_HEADER_LENGTH
+            + significance_length(layout, &lines, &header.coding_modes, packet_header.raw)
+            + packet_header.count_length
+            + packet_header.data_length
+            + packet_header.sign_length;
+        if offset + length > end {
+            return Err(JpegXsError::Truncated("packet".into()));
+        }
+        offset += length;
+        lengths.push(length);
+    }
+    if let Some(last) = lengths.last_mut() {
+        *last += end - offset;
+    }
+
+    Ok((header_length, lengths))
+}
+
+/// Append `padding` bytes to an encoded precinct, counting them in its Lprc
+pub fn pad_precinct(data: &mut Vec<u8>, padding: usize) -> Result<()> {
+    if data.len() < PRECINCT_HEADER_FIXED_LENGTH {
+        return Err(JpegXsError::Truncated("precinct header".into()));
+    }
+    let length = u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize + padding;
+    if length >= 1 << 24 {
+        return Err(JpegXsError::UnsupportedFeature(format!(
+            "Precinct too large: {} bytes",
+            length
+        )));
+    }
+    data[..3].copy_from_slice(&(length as u32).to_be_bytes()[1..]);
+    data.resize(data.len() + padding, 0);
+    Ok(())
+}
+
+/// Size of a precinct header: Lprc, Q[p], R[p] and 2 bits of D[p,b] per band
+fn precinct_header_length(layout: &PrecinctLayout) -> usize {
+    PRECINCT_HEADER_FIXED_LENGTH + (layout.num_bands() * layout.num_components * 2).div_ceil(8)
+}
+
+/// Coded size of the precinct starting at `data`, `None` while Lprc is incomplete
+///
+/// Only the Lprc field is read, so a streaming decoder can tell when all bytes of a
+/// precinct have arrived before decoding it.
+pub fn precinct_length(layout: &PrecinctLayout, data: &[u8]) -> Option<usize> {
+    let lprc = data.get(..3)?;
+    let length = u32::from_be_bytes([0, lprc[0], lprc[1], lprc[2]]) as usize;
+    Some(precinct_header_length(layout) + length)
+}
+
+/// Copy the band lines of every component from a precinct of one layout to a precinct
+/// of another
+///
+/// Both precincts must hold bands of the same size, like a precinct of a frame and the
+/// matching precinct of a horizontal strip of that frame.
+pub fn copy_precinct_lines(
+    from_layout: &PrecinctLayout,
+    from: &[Vec<i32>],
+    from_precinct: usize,
+    to_layout: &PrecinctLayout,
+    to: &mut [Vec<i32>],
+    to_precinct: usize,
+) {
+    for (component, plane) in to.iter_mut().enumerate() {
+        for ((_, source), (_, target)) in from_layout
+            .band_lines(from_precinct, component)
+            .into_iter()
+            .zip(to_layout.band_lines(to_precinct, component))
+        {
+            plane[target].copy_from_slice(&from[component][source]);
+        }
+    }
+}
+
+/// Parse a precinct header, returning it with its size in bytes
+fn read_precinct_header(layout: &PrecinctLayout, data: &[u8]) -> Result<(PrecinctHeader, usize)> {
+    let num_modes = layout.num_bands() * layout.num_components;
+    let header_length = precinct_header_length(layout);
+    if data.len() < header_length {
+        return Err(JpegXsError::Truncated("precinct header".into()));
+    }
+    let mut reader = BitstreamReader::new(&data[..header_length]);
+    let length = reader.read_bits(24)?;
+    let quantization = reader.read_bits(8)? as u8;
+    let refinement = reader.read_bits(8)? as u8;
+    let coding_modes = (0..num_modes)
+        .map(|_| {
+            reader
+                .read_bits(2)
+                .map(|bits| BandCodingMode::from_bits(bits as u8))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if data.len() < header_length + length as usize {
+        return Err(JpegXsError::Truncated("precinct data".into()));
+    }
+
+    Ok((
+        PrecinctHeader {
+            length,
+            quantization,
+            refinement,
+            coding_modes,
+        },
+        header_length,
+    ))
+}
+
+/// Long packet header fields (ISO Table C.8)
+struct PacketHeader {
+    raw: bool,
+    data_length: usize,
+    count_length: usize,
+    sign_length: usize,
+}
+
+impl PacketHeader {
+    fn read(data: &[u8]) -> Result<Self> {
+        if data.len() < PACKET_HEADER_LENGTH {
+            return Err(JpegXsError::Truncated("packet header".into()));
+        }
+        let mut reader = BitstreamReader::new(&data[..PACKET_HEADER_LENGTH]);
+        Ok(Self {
+            raw: reader.read_bit()?,
+            data_length: reader.read_bits(20)? as usize,
+            count_length: reader.read_bits(20)? as usize,
+            sign_length: reader.read_bits(15)? as usize,
+        })
+    }
+}
+
+/// Significance subpacket size, which follows from the band coding modes
+fn significance_length(
+    layout: &PrecinctLayout,
+    lines: &[(usize, PacketLine, std::ops::Range<usize>)],
+    coding_modes: &[BandCodingMode],
+    raw: bool,
+) -> usize {
+    if raw {
+        return 0;
+    }
+    let nc = layout.num_components;
+    let bits: usize = lines
+        .iter()
+        .filter(|(component, line, _)| coding_modes[line.beta * nc + component].significance())
+        .map(|(_, _, range)| code_group_count(range.len()).div_ceil(SIGNIFICANCE_GROUP_SIZE))
+        .sum();
+    bits.div_ceil(8)
+}
+
+/// Decode one packet into the component planes, returning its coded size
+fn decode_packet(
+    layout: &PrecinctLayout,
+    data: &[u8],
+    planes: &mut [Vec<i32>],
+    precinct: usize,
+    lines: &[(usize, PacketLine, std::ops::Range<usize>)],
+    coding_modes: &[BandCodingMode],
+) -> Result<usize> {
+    let nc = layout.num_components;
+    let header = PacketHeader::read(data)?;
+    let raw = header.raw;
+    let significance_length = significance_length(layout, lines, coding_modes, raw);
+
+    let mut offset = PACKET_HEADER_LENGTH;
+    let mut take = |length: usize| -> Result<&[u8]> {
+        let slice = data
+            .get(offset..offset + length)
+            .ok_or_else(|| JpegXsError::Truncated("packet".into()))?;
+        offset += length;
+        Ok(slice)
+    };
+    let mut significance = BitstreamReader::new(take(significance_length)?);
+    let mut count_reader = BitstreamReader::new(take(header.count_length)?);
+    let mut data_reader = BitstreamReader::new(take(header.data_length)?);
+    let mut sign_reader = BitstreamReader::new(take(header.sign_length)?);
+
+    for (component, line, range) in lines {
+        let mode = coding_modes[line.beta * nc + component];
+        let num_groups = code_group_count(range.len());
+        let tops = layout
+            .line_above(*line)
+            .and_then(|above| layout.band_line_range(precinct, *component, above))
+            .filter(|_| mode.vertical_prediction() && !raw)
+            .map(|above| code_group_counts(&planes[*component][above]));
+
+        let mut counts = Vec::with_capacity(num_groups);
+        if raw {
+            for _ in 0..num_groups {
+                counts.push(count_reader.read_bits(RAW_BITPLANE_COUNT_BITS)? as u8);
+            }
+        } else {
+            for group_start in (0..num_groups).step_by(SIGNIFICANCE_GROUP_SIZE) {
+                let group_end = (group_start + SIGNIFICANCE_GROUP_SIZE).min(num_groups);
+                let skipped = mode.significance() && significance.read_bit()?;
+                for group in group_start..group_end {
+                    let top = tops.as_ref().map(|tops| tops[group]);
+                    if skipped {
+                        counts.push(top.unwrap_or(0));
+                        continue;
+                    }
+                    let (_, ctx) = count_symbol(0, top);
+                    let count = vlc_decode(&mut count_reader, ctx)? + ctx.predictor;
+                    if !(0..=MAX_BITPLANE_COUNT).contains(&count) {
+                        return Err(JpegXsError::Corrupt(format!(
+                            "Invalid bitplane count: {}",
+                            count
+                        )));
+                    }
+                    counts.push(count as u8);
+                }
+            }
+        }
+
+        let plane = &mut planes[*component][range.clone()];
+        for (group, &count) in plane.chunks_mut(CODE_GROUP_SIZE).zip(&counts) {
+            let mut magnitudes = [0u32; CODE_GROUP_SIZE];
+            for bitplane in (0..count).rev() {
+                for magnitude in magnitudes.iter_mut() {
+                    if data_reader.read_bit()? {
+                        *magnitude |= 1 << bitplane;
+                    }
+                }
+            }
+            for (coeff, &magnitude) in group.iter_mut().zip(&magnitudes) {
+                *coeff = if magnitude != 0 && sign_reader.read_bit()? {
+                    -(magnitude as i32)
+                } else {
+                    magnitude as i32
+                };
+            }
+        }
+    }
+
+    Ok(offset)
+}
+
+/// Encode every precinct of a frame with the same Q[p] / R[p]
+pub fn encode_precincts(
+    layout: &PrecinctLayout,
+    planes: &[&[i32]],
+    quantization: u8,
+    refinement: u8,
+) -> Result<Vec<u8>> {
+    encode_precinct_range(
+        layout,
+        planes,
+        0..layout.num_precincts(),
+        quantization,
+        refinement,
+    )
+}
+
+/// Encode a run of consecutive precincts, such as the precincts of a slice
+pub fn encode_precinct_range(
+    layout: &PrecinctLayout,
+    planes: &[&[i32]],
+    precincts: std::ops::Range<usize>,
+    quantization: u8,
+    refinement: u8,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for precinct in precincts {
+        data.extend(encode_precinct(
+            layout,
+            planes,
+            precinct,
+            quantization,
+            refinement,
+        )?);
+    }
+    Ok(data)
+}
+
+/// Decode every precinct of a frame, returning the component planes and precinct headers
+pub fn decode_precincts(
+    layout: &PrecinctLayout,
+    data: &[u8],
+) -> Result<(Vec<Vec<i32>>, Vec<PrecinctHeader>)> {
+    let mut planes: Vec<Vec<i32>> = (0..layout.num_components)
+        .map(|component| vec![0i32; layout.plane_size(component)])
+        .collect();
+    let headers = decode_precinct_range(layout, data, &mut planes, 0..layout.num_precincts())?;
+    Ok((planes, headers))
+}
+
+/// Decode a run of consecutive precincts into the component planes
+///
+/// Precincts only depend on their own data, so each slice can be decoded on its own.
+pub fn decode_precinct_range(
+    layout: &PrecinctLayout,
+    data: &[u8],
+    planes: &mut [Vec<i32>],
+    precincts: std::ops::Range<usize>,
+) -> Result<Vec<PrecinctHeader>> {
+    if precincts.end > layout.num_precincts() {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Precinct {} out of range ({} precincts)",
+            precincts.end - 1,
+            layout.num_precincts()
+        )));
+    }
+    let mut headers = Vec::with_capacity(precincts.len());
+    let mut offset = 0;
+    for precinct in precincts {
+        let (header, length) = decode_precinct(layout, &data[offset..], planes, precinct)?;
+        offset += length;
+        headers.push(header);
+    }
+    if offset != data.len() {
+        return Err(JpegXsError::Corrupt(format!(
+            "Unexpected {} bytes after the last precinct",
+            data.len() - offset
+        )));
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_planes(width: u32, height: u32, num_components: usize) -> Vec<Vec<i32>> {
+        (0..num_components)
+            .map(|c| {
+                (0..width * height)
+                    .map(|i| {
+                        let v = ((i as i64 * 7919 + c as i64 * 131) % 61) as i32 - 30;
+                        // Mostly small values with occasional zeros and large peaks
+                        if v % 5 == 0 {
+                            0
+                        } else if v % 13 == 0 {
+                            v * 40
+                        } else {
+                            v / 4
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_precinct_roundtrip() {
+        for &(width, height, levels_x, levels_y) in &[
+            (64u32, 32u32, 5u8, 2u8),
+            (37, 19, 3, 3),
+            (20, 9, 4, 1),
+            (16, 8, 1, 0),
+        ] {
+            let planes = test_planes(width, height, 3);
+            let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+            let layout = PrecinctLayout::new(width, height, levels_x, levels_y, 3, 0).unwrap();
+
+            let data = encode_precincts(&layout, &refs, 4, 9).unwrap();
+            let (decoded, headers) = decode_precincts(&layout, &data).unwrap();
+
+            assert_eq!(
+                decoded, planes,
+                "{}x{} NLx={} NLy={}",
+                width, height, levels_x, levels_y
+            );
+            assert_eq!(headers.len(), height.div_ceil(1 << levels_y) as usize);
+            assert!(headers
+                .iter()
+                .all(|header| header.quantization == 4 && header.refinement == 9));
+        }
+    }
+
+    #[test]
+    fn test_precinct_columns_roundtrip() {
+        for &(width, height, levels_x, levels_y, precinct_width, columns) in &[
+            (100u32, 20u32, 2u8, 1u8, 1u16, 4usize),
+            (64, 16, 3, 2, 1, 1),
+            (70, 9, 1, 0, 2, 3),
+            (33, 12, 4, 2, 1, 1),
+        ] {
+            let planes = test_planes(width, height, 3);
+            let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+            let layout =
+                PrecinctLayout::new(width, height, levels_x, levels_y, 3, precinct_width).unwrap();
+            assert_eq!(layout.precincts_per_row(), columns);
+            assert_eq!(
+                layout.num_precincts(),
+                height.div_ceil(1 << levels_y) as usize * columns
+            );
+
+            let data = encode_precincts(&layout, &refs, 4, 9).unwrap();
+            let (decoded, _) = decode_precincts(&layout, &data).unwrap();
+            assert_eq!(
+                decoded, planes,
+                "{}x{} NLx={} NLy={} Cw={}",
+                width, height, levels_x, levels_y, precinct_width
+            );
+        }
+    }
+
+    #[test]
+    fn test_subsampled_components_roundtrip() {
+        for &(width, height, levels_x, levels_y, sampling, precinct_width) in &[
+            (64u32, 32u32, 5u8, 2u8, (2u8, 1u8), 0u16),
+            (64, 32, 5, 2, (2, 2), 0),
+            (38, 18, 3, 1, (2, 2), 0),
+            (100, 20, 2, 1, (2, 2), 1),
+        ] {
+            let sampling = [(1, 1), sampling, sampling];
+            let layout = PrecinctLayout::with_sampling(
+                width,
+                height,
+                levels_x,
+                levels_y,
+                &sampling,
+                precinct_width,
+            )
+            .unwrap();
+            let planes: Vec<Vec<i32>> = (0..3)
+                .map(|component| {
+                    let (w, h) = layout.component_size(component);
+                    test_planes(w, h, component + 1).pop().unwrap()
+                })
+                .collect();
+
+            // Every coefficient of every component belongs to exactly one band line
+            for (component, plane) in planes.iter().enumerate() {
+                let mut covered = vec![0u8; plane.len()];
+                for precinct in 0..layout.num_precincts() {
+                    for (_, range) in layout.band_lines(precinct, component) {
+                        covered[range].iter_mut().for_each(|count| *count += 1);
+                    }
+                }
+                assert!(covered.iter().all(|&count| count == 1));
+            }
+
+            let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+            let data = encode_precincts(&layout, &refs, 4, 9).unwrap();
+            let (decoded, _) = decode_precincts(&layout, &data).unwrap();
+            assert_eq!(
+                decoded, planes,
+                "{}x{} NLx={} NLy={} sampling={:?}",
+                width, height, levels_x, levels_y, sampling
+            );
+        }
+
+        // 4:2:0 chroma needs a vertical decomposition to halve
+        assert!(PrecinctLayout::with_sampling(16, 8, 1, 0, &[(1, 1), (2, 2), (2, 2)], 0).is_err());
+    }
+
+    #[test]
+    fn test_zero_planes_are_compact() {
+        let (width, height) = (64u32, 64u32);
+        let planes = vec![vec![0i32; (width * height) as usize]; 3];
+        let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        let layout = PrecinctLayout::new(width, height, 5, 2, 3, 0).unwrap();
+
+        let data = encode_precincts(&layout, &refs, 0, 0).unwrap();
+        // Significance coding leaves little more than the precinct and packet headers
+        assert!(
+            data.len() < (width * height) as usize / 4,
+            "{} bytes",
+            data.len()
+        );
+
+        let (decoded, _) = decode_precincts(&layout, &data).unwrap();
+        assert_eq!(decoded, planes);
+    }
+
+    #[test]
+    fn test_packets_cover_every_band_line() {
+        let layout = PrecinctLayout::new(64, 32, 5, 2, 1, 0).unwrap();
+        // One packet for the bands at 1/4 vertical resolution, two for level 1
+        assert_eq!(layout.packets().len(), 1 + 2);
+
+        // LL, three horizontal-only and three level-2 bands, then level-1 bands twice
+        let lines: usize = layout.packets().iter().map(|packet| packet.len()).sum();
+        assert_eq!(lines, 7 + 3 * 2);
+    }
+
+    #[test]
+    fn test_packet_lengths_split_precincts() {
+        let (width, height) = (37u32, 17u32);
+        let planes = test_planes(width, height, 3);
+        let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        let layout = PrecinctLayout::new(width, height, 3, 2, 3, 0).unwrap();
+
+        let mut offset = 0;
+        for precinct in 0..layout.num_precincts() {
+            let data = encode_precinct(&layout, &refs, precinct, 2, 1).unwrap();
+            let (header_length, packets) =
+                precinct_packet_lengths(&layout, &data, precinct).unwrap();
+            assert_eq!(header_length + packets.iter().sum::<usize>(), data.len());
+            // The last precinct holds a single image line, so its second level-1 packet is empty
+            let expected = if precinct + 1 == layout.num_precincts() {
+                2
+            } else {
+                3
+            };
+            assert_eq!(packets.len(), expected);
+            offset += data.len();
+        }
+        assert_eq!(
+            offset,
+            encode_precincts(&layout, &refs, 2, 1).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_truncated_precinct_is_rejected() {
+        let planes = test_planes(32, 8, 3);
+        let refs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        let layout = PrecinctLayout::new(32, 8, 3, 1, 3, 0).unwrap();
+        let data = encode_precincts(&layout, &refs, 0, 0).unwrap();
+
+        assert!(decode_precincts(&layout, &data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_bitplane_count_beyond_bw_is_rejected() {
+        // Two lines of the level-1 bands: counts of 21 above, predicting counts of 32
+        let layout = PrecinctLayout::new(16, 4, 2, 2, 1, 0).unwrap();
+        let mut plane = vec![0i32; 16 * 4];
+        plane[32..48].fill(1 << 20);
+        plane[48..64].fill(i32::MIN);
+        let data = encode_precincts(&layout, &[&plane], 0, 0).unwrap();
+
+        match decode_precincts(&layout, &data) {
+            Err(JpegXsError::Corrupt(reason)) => {
+                assert_eq!(reason, "Invalid bitplane count: 32")
+            }
+            result => panic!("Expected corrupt data, got {:?}", result.map(|_| ())),
+        }
+    }
+}
@@ -60,6 +60,9 @@ pub fn validate_profile_level_combination(
         (Profile::High, Level::Level3) => Ok(()),
         (Profile::High, Level::Level4) => Ok(()),
         (Profile::High, Level::Level5) => Ok(()),
+
+        // Without a profile only the limits of the level apply
+        (Profile::Unrestricted, _) => Ok(()),
     }
 }
 
@@ -84,6 +87,9 @@ pub fn get_max_bitrate_mbps(profile: Profile, level: Level) -> Result<u32, Profi
         (Profile::High, Level::Level4) => Ok(6400),
         (Profile::High, Level::Level5) => Ok(12800),
 
+        // High admits every level at the highest bitrates
+        (Profile::Unrestricted, level) => get_max_bitrate_mbps(Profile::High, level),
+
         // Invalid combinations handled by validate_profile_level_combination
         _ => unreachable!(),
     }
@@ -101,19 +107,393 @@ pub fn get_max_resolution(profile: Profile, level: Level) -> Result<(u32, u32),
         (Profile::Main, Level::Level1) => Ok((1920, 1080)),
         (Profile::Main, Level::Level2) => Ok((3840, 2160)),
         (Profile::Main, Level::Level3) => Ok((7680, 4320)),
-        (Profile::Main, Level::Level4) => Ok((15360, 8640)),
+        (Profile::Main, Level::Level4) => Ok((10240, 4320)),
 
         // High Profile resolutions
         (Profile::High, Level::Level1) => Ok((1920, 1080)),
         (Profile::High, Level::Level2) => Ok((3840, 2160)),
         (Profile::High, Level::Level3) => Ok((7680, 4320)),
-        (Profile::High, Level::Level4) => Ok((15360, 8640)),
+        (Profile::High, Level::Level4) => Ok((10240, 4320)),
         (Profile::High, Level::Level5) => Ok((30720, 17280)),
 
+        (Profile::Unrestricted, level) => get_max_resolution(Profile::High, level),
+
         _ => unreachable!(),
     }
 }
 
+/// Frame rate up to which a level admits frames of its maximum resolution
+const MAX_FRAME_RATE: u64 = 60;
+
+/// Maximum luma sample rate in samples per second: the level's maximum resolution at
+/// 60 frames per second
+pub fn get_max_sample_rate(profile: Profile, level: Level) -> Result<u64, ProfileError> {
+    let (width, height) = get_max_resolution(profile, level)?;
+    Ok(width as u64 * height as u64 * MAX_FRAME_RATE)
+}
+
+/// Maximum coded bits of a single frame: the level's maximum bitrate spread over
+/// 60 frames per second, the frame rate its maximum resolution is admitted at
+pub fn get_max_frame_bits(profile: Profile, level: Level) -> Result<u64, ProfileError> {
+    let max_mbps = get_max_bitrate_mbps(profile, level)?;
+    Ok(max_mbps as u64 * 1_000_000 / MAX_FRAME_RATE)
+}
+
+/// Check a frame size against the level: neither side may exceed the maximum width and
+/// the area may not exceed the maximum frame size, so portrait frames are admitted too
+pub fn check_resolution(
+    profile: Profile,
+    level: Level,
+    width: u32,
+    height: u32,
+) -> Result<(), ProfileError> {
+    let (max_width, max_height) = get_max_resolution(profile, level)?;
+    if width.max(height) > max_width
+        || width as u64 * height as u64 > max_width as u64 * max_height as u64
+    {
+        return Err(ProfileError::InvalidConfiguration(format!(
+            "{}x{} frames exceed the {}x{} limit of {:?} {:?}",
+            width, height, max_width, max_height, profile, level
+        )));
+    }
+    Ok(())
+}
+
+/// Check the luma sample rate of `width` x `height` frames at `frame_rate` against the level
+pub fn check_sample_rate(
+    profile: Profile,
+    level: Level,
+    width: u32,
+    height: u32,
+    frame_rate: f64,
+) -> Result<(), ProfileError> {
+    let max_rate = get_max_sample_rate(profile, level)?;
+    let rate = width as f64 * height as f64 * frame_rate;
+    if rate > max_rate as f64 {
+        return Err(ProfileError::InvalidConfiguration(format!(
+            "{}x{} frames at {} fps exceed the sample rate limit of {:?} {:?} ({} samples/s)",
+            width, height, frame_rate, profile, level, max_rate
+        )));
+    }
+    Ok(())
+}
+
+/// Sublevel: the maximum coded bits per pixel, signalled in the low byte of Plev
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sublevel {
+    /// No limit beyond the level's
+    Full,
+    Bpp12,
+    Bpp9,
+    Bpp6,
+    Bpp3,
+}
+
+impl Sublevel {
+    /// Sublevels from the most to the least restrictive
+    const ALL: [Sublevel; 5] = [
+        Sublevel::Bpp3,
+        Sublevel::Bpp6,
+        Sublevel::Bpp9,
+        Sublevel::Bpp12,
+        Sublevel::Full,
+    ];
+
+    /// Low byte of Plev (ISO/IEC 21122-2 Table 4)
+    pub fn code(self) -> u8 {
+        match self {
+            Sublevel::Full => 0x80,
+            Sublevel::Bpp12 => 0x10,
+            Sublevel::Bpp9 => 0x0c,
+            Sublevel::Bpp6 => 0x08,
+            Sublevel::Bpp3 => 0x04,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|sublevel| sublevel.code() == code)
+    }
+
+    /// Maximum coded bits per pixel, headers included; `None` for [`Sublevel::Full`]
+    pub fn max_bits_per_pixel(self) -> Option<f64> {
+        match self {
+            Sublevel::Full => None,
+            Sublevel::Bpp12 => Some(12.0),
+            Sublevel::Bpp9 => Some(9.0),
+            Sublevel::Bpp6 => Some(6.0),
+            Sublevel::Bpp3 => Some(3.0),
+        }
+    }
+
+    /// Most restrictive sublevel admitting `bits_per_pixel`
+    pub fn for_bits_per_pixel(bits_per_pixel: f64) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|sublevel| {
+                sublevel
+                    .max_bits_per_pixel()
+                    .is_none_or(|max| bits_per_pixel <= max)
+            })
+            .unwrap_or(Sublevel::Full)
+    }
+}
+
+/// A profile of ISO/IEC 21122-2 with the components its codestreams may carry
+struct ProfileVariant {
+    ppih: u16,
+    profile: Profile,
+    name: &'static str,
+    max_components: u8,
+    max_bit_depth: u8,
+    /// Whether the chroma components may be coded without subsampling; all variants
+    /// admit 4:2:2 and none admits vertical subsampling
+    full_chroma: bool,
+}
+
+/// Ppih codes of ISO/IEC 21122-2 Table 2, the smallest variant of every profile first
+const PROFILE_VARIANTS: [ProfileVariant; 7] = [
+    ProfileVariant {
+        ppih: 0x1500,
+        profile: Profile::Light,
+        name: "Light 422.10",
+        max_components: 3,
+        max_bit_depth: 10,
+        full_chroma: false,
+    },
+    ProfileVariant {
+        ppih: 0x1a00,
+        profile: Profile::Light,
+        name: "Light 444.12",
+        max_components: 3,
+        max_bit_depth: 12,
+        full_chroma: true,
+    },
+    ProfileVariant {
+        ppih: 0x3540,
+        profile: Profile::Main,
+        name: "Main 422.10",
+        max_components: 3,
+        max_bit_depth: 10,
+        full_chroma: false,
+    },
+    ProfileVariant {
+        ppih: 0x3a40,
+        profile: Profile::Main,
+        name: "Main 444.12",
+        max_components: 3,
+        max_bit_depth: 12,
+        full_chroma: true,
+    },
+    ProfileVariant {
+        ppih: 0x3e40,
+        profile: Profile::Main,
+        name: "Main 4444.12",
+        max_components: 4,
+        max_bit_depth: 12,
+        full_chroma: true,
+    },
+    ProfileVariant {
+        ppih: 0x4a40,
+        profile: Profile::High,
+        name: "High 444.12",
+        max_components: 3,
+        max_bit_depth: 12,
+        full_chroma: true,
+    },
+    ProfileVariant {
+        ppih: 0x4e40,
+        profile: Profile::High,
+        name: "High 4444.12",
+        max_components: 4,
+        max_bit_depth: 12,
+        full_chroma: true,
+    },
+];
+
+impl ProfileVariant {
+    fn admits(&self, num_components: u8, bit_depth: u8, chroma_sampling: (u8, u8)) -> bool {
+        let chroma = match chroma_sampling {
+            _ if num_components == 1 => true,
+            (1, 1) => self.full_chroma,
+            (2, 1) => true,
+            _ => false,
+        };
+        chroma && num_components <= self.max_components && bit_depth <= self.max_bit_depth
+    }
+}
+
+/// High byte of Plev for every level (ISO/IEC 21122-2 Table 3)
+///
+/// Level1 to Level4 cover the 2K, 4K, 8K and 10K frames of the 2k-1, 4k-1, 8k-1 and
+/// 10k-1 levels. Level5 exceeds every level of the standard and has no code.
+fn level_code(level: Level) -> Option<u8> {
+    match level {
+        Level::Level1 => Some(0x20),
+        Level::Level2 => Some(0x30),
+        Level::Level3 => Some(0x40),
+        Level::Level4 => Some(0x50),
+        Level::Level5 => None,
+    }
+}
+
+fn level_name(code: u8) -> Option<&'static str> {
+    match code {
+        0x20 => Some("2k-1"),
+        0x30 => Some("4k-1"),
+        0x40 => Some("8k-1"),
+        0x50 => Some("10k-1"),
+        _ => None,
+    }
+}
+
+/// Profile, level and sublevel of a codestream: the Ppih and Plev fields of its PIH
+/// marker, 0 where unrestricted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileLevel {
+    pub ppih: u16,
+    pub plev: u16,
+}
+
+impl ProfileLevel {
+    /// Codes for frames of the given components coded in `profile` at `level`
+    ///
+    /// The smallest variant of the profile admitting the components is signalled.
+    /// Components no variant can carry, such as 16-bit samples or 4:2:0 chroma, are
+    /// rejected, they need [`Profile::Unrestricted`]. So is [`Level::Level5`], which no
+    /// level code describes.
+    pub fn new(
+        profile: Profile,
+        level: Level,
+        sublevel: Sublevel,
+        num_components: u8,
+        bit_depth: u8,
+        chroma_sampling: (u8, u8),
+    ) -> Result<Self, ProfileError> {
+        validate_profile_level_combination(profile, level)?;
+        let level_code = level_code(level).ok_or(ProfileError::UnsupportedLevel(level))?;
+        let ppih = if profile == Profile::Unrestricted {
+            0
+        } else {
+            PROFILE_VARIANTS
+                .iter()
+                .find(|variant| {
+                    variant.profile == profile
+                        && variant.admits(num_components, bit_depth, chroma_sampling)
+                })
+                .map(|variant| variant.ppih)
+                .ok_or_else(|| {
+                    ProfileError::InvalidConfiguration(format!(
+                    "No {:?} profile carries {} components of {} bits with {}x{} chroma sampling",
+                    profile, num_components, bit_depth, chroma_sampling.0, chroma_sampling.1
+                ))
+                })?
+        };
+        let plev = u16::from_be_bytes([level_code, sublevel.code()]);
+        Ok(Self { ppih, plev })
+    }
+
+    fn variant(&self) -> Option<&'static ProfileVariant> {
+        PROFILE_VARIANTS
+            .iter()
+            .find(|variant| variant.ppih == self.ppih)
+    }
+
+    /// The signalled profile, `None` if unknown
+    pub fn profile(&self) -> Option<Profile> {
+        if self.ppih == 0 {
+            return Some(Profile::Unrestricted);
+        }
+        self.variant().map(|variant| variant.profile)
+    }
+
+    /// The signalled level, `None` if unrestricted or unknown
+    pub fn level(&self) -> Option<Level> {
+        let code = (self.plev >> 8) as u8;
+        [Level::Level1, Level::Level2, Level::Level3, Level::Level4]
+            .into_iter()
+            .find(|&level| level_code(level) == Some(code))
+    }
+
+    /// The signalled sublevel, `None` if unknown
+    pub fn sublevel(&self) -> Option<Sublevel> {
+        Sublevel::from_code(self.plev as u8)
+    }
+
+    /// Check the main header of a codestream against the signalled profile and level
+    ///
+    /// Unrestricted and unknown codes admit everything.
+    pub fn check_frame(
+        &self,
+        width: u32,
+        height: u32,
+        num_components: u8,
+        bit_depth: u8,
+        chroma_sampling: (u8, u8),
+    ) -> Result<(), ProfileError> {
+        if let Some(variant) = self.variant() {
+            if !variant.admits(num_components, bit_depth, chroma_sampling) {
+                return Err(ProfileError::InvalidConfiguration(format!(
+                    "{} components of {} bits with {}x{} chroma sampling exceed the {} profile",
+                    num_components, bit_depth, chroma_sampling.0, chroma_sampling.1, variant.name
+                )));
+            }
+        }
+        if let Some(level) = self.level() {
+            // High admits every level, and the limits of a level do not depend on the profile
+            check_resolution(
+                self.profile().unwrap_or(Profile::High),
+                level,
+                width,
+                height,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Check the size of a complete codestream against the signalled sublevel
+    pub fn check_size(&self, width: u32, height: u32, bytes: usize) -> Result<(), ProfileError> {
+        let Some(max) = self.sublevel().and_then(Sublevel::max_bits_per_pixel) else {
+            return Ok(());
+        };
+        let bits_per_pixel = bytes as f64 * 8.0 / (width as f64 * height as f64);
+        if bits_per_pixel > max {
+            return Err(ProfileError::InvalidConfiguration(format!(
+                "{:.2} coded bits per pixel exceed the {} bpp sublevel",
+                bits_per_pixel, max
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProfileLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.variant() {
+            Some(variant) => f.write_str(variant.name)?,
+            None if self.ppih == 0 => f.write_str("unrestricted profile")?,
+            None => write!(f, "unknown profile 0x{:04x}", self.ppih)?,
+        }
+        let level = (self.plev >> 8) as u8;
+        match level_name(level) {
+            Some(name) => write!(f, ", level {}", name)?,
+            None if level == 0 => f.write_str(", unrestricted level")?,
+            None => write!(f, ", unknown level 0x{:02x}", level)?,
+        }
+        match self.sublevel() {
+            Some(Sublevel::Full) => f.write_str(", full sublevel"),
+            Some(sublevel) => write!(
+                f,
+                ", {} bpp sublevel",
+                sublevel.max_bits_per_pixel().unwrap_or_default()
+            ),
+            None if self.plev as u8 == 0 => f.write_str(", unrestricted sublevel"),
+            None => write!(f, ", unknown sublevel 0x{:02x}", self.plev as u8),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -166,4 +546,115 @@ mod tests {
             (30720, 17280)
         );
     }
+
+    #[test]
+    fn test_resolution_and_sample_rate_checks() {
+        assert!(check_resolution(Profile::Main, Level::Level1, 1920, 1080).is_ok());
+        assert!(check_resolution(Profile::Main, Level::Level1, 1080, 1920).is_ok());
+        assert!(check_resolution(Profile::Main, Level::Level1, 1921, 8).is_err());
+        assert!(check_resolution(Profile::Main, Level::Level1, 1920, 1088).is_err());
+        assert!(check_resolution(Profile::Light, Level::Level3, 64, 64).is_err());
+
+        assert!(check_sample_rate(Profile::Main, Level::Level1, 1920, 1080, 60.0).is_ok());
+        assert!(check_sample_rate(Profile::Main, Level::Level1, 1920, 1080, 120.0).is_err());
+        assert!(check_sample_rate(Profile::Main, Level::Level2, 1920, 1080, 120.0).is_ok());
+    }
+
+    #[test]
+    fn test_sublevels() {
+        assert_eq!(Sublevel::for_bits_per_pixel(2.5), Sublevel::Bpp3);
+        assert_eq!(Sublevel::for_bits_per_pixel(6.0), Sublevel::Bpp6);
+        assert_eq!(Sublevel::for_bits_per_pixel(8.0), Sublevel::Bpp9);
+        assert_eq!(Sublevel::for_bits_per_pixel(12.5), Sublevel::Full);
+        for sublevel in Sublevel::ALL {
+            assert_eq!(Sublevel::from_code(sublevel.code()), Some(sublevel));
+        }
+        assert_eq!(Sublevel::from_code(0), None);
+    }
+
+    #[test]
+    fn test_profile_level_codes() {
+        let codes = |profile, level, components, bit_depth, chroma| {
+            ProfileLevel::new(
+                profile,
+                level,
+                Sublevel::Full,
+                components,
+                bit_depth,
+                chroma,
+            )
+            .unwrap()
+        };
+        let main = codes(Profile::Main, Level::Level2, 3, 10, (2, 1));
+        assert_eq!((main.ppih, main.plev), (0x3540, 0x3080));
+        assert_eq!(main.profile(), Some(Profile::Main));
+        assert_eq!(main.level(), Some(Level::Level2));
+        assert_eq!(main.sublevel(), Some(Sublevel::Full));
+        assert_eq!(main.to_string(), "Main 422.10, level 4k-1, full sublevel");
+
+        assert_eq!(
+            codes(Profile::Main, Level::Level1, 3, 8, (1, 1)).ppih,
+            0x3a40
+        );
+        assert_eq!(
+            codes(Profile::Main, Level::Level1, 4, 8, (1, 1)).ppih,
+            0x3e40
+        );
+        assert_eq!(
+            codes(Profile::Light, Level::Level1, 1, 8, (1, 1)).ppih,
+            0x1500
+        );
+        assert_eq!(
+            codes(Profile::High, Level::Level1, 3, 8, (2, 1)).ppih,
+            0x4a40
+        );
+
+        // No variant carries 16-bit samples or 4:2:0 chroma, only the unrestricted profile
+        assert!(matches!(
+            ProfileLevel::new(Profile::Main, Level::Level1, Sublevel::Full, 3, 16, (1, 1)),
+            Err(ProfileError::InvalidConfiguration(_))
+        ));
+        assert!(matches!(
+            ProfileLevel::new(Profile::High, Level::Level4, Sublevel::Full, 3, 8, (2, 2)),
+            Err(ProfileError::InvalidConfiguration(_))
+        ));
+        let unrestricted = codes(Profile::Unrestricted, Level::Level4, 3, 16, (2, 2));
+        assert_eq!((unrestricted.ppih, unrestricted.plev), (0, 0x5080));
+        assert_eq!(unrestricted.profile(), Some(Profile::Unrestricted));
+        assert_eq!(unrestricted.level(), Some(Level::Level4));
+
+        // Level5 lies beyond every level code
+        assert!(matches!(
+            ProfileLevel::new(Profile::High, Level::Level5, Sublevel::Full, 3, 8, (1, 1)),
+            Err(ProfileError::UnsupportedLevel(Level::Level5))
+        ));
+
+        assert!(
+            ProfileLevel::new(Profile::Light, Level::Level4, Sublevel::Full, 3, 8, (1, 1)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_signalled_limits_are_checked() {
+        let main =
+            ProfileLevel::new(Profile::Main, Level::Level1, Sublevel::Bpp6, 3, 10, (2, 1)).unwrap();
+        assert!(main.check_frame(1920, 1080, 3, 10, (2, 1)).is_ok());
+        assert!(main.check_frame(3840, 2160, 3, 10, (2, 1)).is_err());
+        assert!(main.check_frame(64, 64, 3, 12, (2, 1)).is_err());
+        assert!(main.check_frame(64, 64, 3, 8, (1, 1)).is_err());
+        assert!(main.check_size(64, 64, 64 * 64 * 6 / 8).is_ok());
+        assert!(main.check_size(64, 64, 64 * 64 * 6 / 8 + 1).is_err());
+
+        // Unrestricted and unknown codes admit everything
+        for codes in [
+            ProfileLevel::default(),
+            ProfileLevel {
+                ppih: 0x7777,
+                plev: 0x7777,
+            },
+        ] {
+            assert!(codes.check_frame(65535, 65535, 8, 16, (2, 2)).is_ok());
+            assert!(codes.check_size(8, 8, 1 << 20).is_ok());
+        }
+    }
 }
@@ -1,31 +1,83 @@
-use anyhow::Result;
+use crate::error::{JpegXsError, Result};
+use crate::precinct::PrecinctLayout;
 
-pub fn quantize(coeffs: &[f32], qp: u8) -> Result<Vec<i32>> {
-    if qp == 0 {
-        return Err(anyhow::anyhow!("Quantization parameter cannot be zero"));
+/// Quantize the band lines of one precinct into `quantized` with the precinct's Q and R
+///
+/// `coeffs` are fixed-point wavelet coefficients with the Fq fractional bits of the
+/// picture header; each band drops its T[b] least significant bitplanes, rounding to
+/// the nearest step. Only the coefficients of `precinct` are written, so precincts may
+/// use different parameters, as chosen by rate control.
+pub fn quantize_precinct(
+    coeffs: &[i32],
+    quantized: &mut [i32],
+    layout: &PrecinctLayout,
+    precinct: usize,
+    params: &QuantizationParameters,
+    component: usize,
+) -> Result<()> {
+    let num_components = precinct_components(layout, params, component)?;
+    for (beta, range) in layout.band_lines(precinct, component) {
+        let truncation = params
+            .truncation(beta * num_components + component)
+            .min(MAX_TRUNCATION);
+        let half_step = (1u32 << truncation) >> 1;
+        for (q, &coeff) in quantized[range.clone()].iter_mut().zip(&coeffs[range]) {
+            let magnitude = ((coeff.unsigned_abs() + half_step) >> truncation) as i32;
+            *q = if coeff < 0 { -magnitude } else { magnitude };
+        }
     }
+    Ok(())
+}
 
-    let scale = 1.0 / (qp as f32);
-    let mut result = Vec::with_capacity(coeffs.len());
-
-    for &coeff in coeffs {
-        let quantized = (coeff * scale).round() as i32;
-        result.push(quantized);
+/// Inverse of [`quantize_precinct`]: scale the quantized values back by their step
+///
+/// A value whose bitplanes reach past the `wavelet_precision` Bw of the picture header
+/// once shifted up by T[b] cannot come from a valid codestream, and is rejected rather
+/// than overflowing the coefficient.
+pub fn dequantize_precinct(
+    quantized: &[i32],
+    coeffs: &mut [i32],
+    layout: &PrecinctLayout,
+    precinct: usize,
+    params: &QuantizationParameters,
+    component: usize,
+    wavelet_precision: u8,
+) -> Result<()> {
+    let num_components = precinct_components(layout, params, component)?;
+    let precision = wavelet_precision.min(MAX_COEFFICIENT_BITS);
+    for (beta, range) in layout.band_lines(precinct, component) {
+        let band = beta * num_components + component;
+        let truncation = params.truncation(band).min(MAX_TRUNCATION);
+        let max_magnitude = (1u32 << precision.saturating_sub(truncation)) - 1;
+        for (coeff, &q) in coeffs[range.clone()].iter_mut().zip(&quantized[range]) {
+            if q.unsigned_abs() > max_magnitude {
+                return Err(JpegXsError::Corrupt(format!(
+                    "Coefficient {} of band {} with T[b]={} exceeds Bw={}",
+                    q, band, truncation, wavelet_precision
+                )));
+            }
+            *coeff = q << truncation;
+        }
     }
-
-    Ok(result)
+    Ok(())
 }
 
-pub fn dequantize(coeffs: &[i32], qp: u8) -> Result<Vec<f32>> {
-    let scale = qp as f32;
-    let mut result = Vec::with_capacity(coeffs.len());
-
-    for &coeff in coeffs {
-        let dequantized = coeff as f32 * scale;
-        result.push(dequantized);
+/// Number of components covered by `params`, checked against the precinct layout
+fn precinct_components(
+    layout: &PrecinctLayout,
+    params: &QuantizationParameters,
+    component: usize,
+) -> Result<usize> {
+    let num_components = params.num_bands() / layout.num_bands();
+    if params.num_bands() != layout.num_bands() * num_components || component >= num_components {
+        return Err(JpegXsError::Corrupt(format!(
+            "Weights table has {} bands, no weights for component {} of {} subbands",
+            params.num_bands(),
+            component,
+            layout.num_bands()
+        )));
     }
-
-    Ok(result)
+    Ok(num_components)
 }
 
 // Quality-to-QP mapping table for cleaner maintenance and testing
@@ -43,34 +95,173 @@ const QUALITY_TO_QP_TABLE: &[(f32, u8, &str)] = &[
     (0.0, 32, "Minimum quality (maximum compression ~15:1)"),
 ];
 
-pub fn compute_quantization_parameters(quality: f32) -> Result<Vec<u8>> {
+/// Map an encoder quality setting to the base quantization step
+pub fn quality_to_qp(quality: f32) -> Result<u8> {
     if quality <= 0.0 || quality > 1.0 {
-        return Err(anyhow::anyhow!(
+        return Err(JpegXsError::InvalidParameter(format!(
             "Invalid quality parameter: {} (must be greater than 0.0 and at most 1.0)",
             quality
-        ));
+        )));
     }
 
     // Find the appropriate QP using the lookup table
     // Higher quality -> Lower QP -> Less compression loss
     // Lower quality -> Higher QP -> More compression gain
-    let base_qp = QUALITY_TO_QP_TABLE
+    Ok(QUALITY_TO_QP_TABLE
         .iter()
         .find(|(min_quality, _, _)| quality >= *min_quality)
         .map(|(_, qp, _)| *qp)
-        .unwrap_or(32); // Fallback to maximum compression
+        .unwrap_or(32)) // Fallback to maximum compression
+}
+
+/// Frame quantization settings: precinct Q/R plus the WGT gains and priorities
+///
+/// Band `b` is indexed as `beta * num_components + component` per ISO/IEC 21122-1 Table A.25.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantizationParameters {
+    /// Q[p]: Number of bitplanes truncated from a band with zero gain
+    pub quantization: u8,
+    /// R[p]: Bands with a priority below this value keep one extra bitplane
+    pub refinement: u8,
+    /// G[b]: Gain of each band in bitplanes
+    pub gains: Vec<u8>,
+    /// P[b]: Refinement priority of each band (0 is refined first)
+    pub priorities: Vec<u8>,
+}
+
+impl QuantizationParameters {
+    /// Rebuild parameters from values parsed out of the WGT marker and precinct header
+    pub fn from_band_weights(
+        quantization: u8,
+        refinement: u8,
+        gains: &[u8],
+        priorities: &[u8],
+    ) -> Result<Self> {
+        if gains.len() != priorities.len() {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Band gain and priority counts differ: {} vs {}",
+                gains.len(),
+                priorities.len()
+            )));
+        }
+        Ok(Self {
+            quantization,
+            refinement,
+            gains: gains.to_vec(),
+            priorities: priorities.to_vec(),
+        })
+    }
+
+    /// Number of bands described by the parameters
+    pub fn num_bands(&self) -> usize {
+        self.gains.len()
+    }
+
+    /// Truncation position T[b] = max(Q - G[b] - r[b], 0), r[b] = 1 when P[b] < R
+    /// ISO/IEC 21122-1 Annex C.6.2
+    pub fn truncation(&self, band: usize) -> u8 {
+        let refine = u8::from(self.priorities[band] < self.refinement);
+        self.quantization
+            .saturating_sub(self.gains[band])
+            .saturating_sub(refine)
+    }
+
+    /// Quantization step of a band, 2^T[b]
+    pub fn step(&self, band: usize) -> u32 {
+        1 << self.truncation(band).min(MAX_TRUNCATION)
+    }
+
+    /// Smallest Q at which every band, refined or not, has reached the largest step
+    pub fn max_quantization(&self) -> u8 {
+        MAX_TRUNCATION + self.gains.iter().copied().max().unwrap_or(0) + 1
+    }
+}
+
+/// Largest truncation, which clears every bitplane of the Bw-bit coefficients of
+/// 16-bit samples
+const MAX_TRUNCATION: u8 = 25;
+
+/// Magnitude bits of an `i32` coefficient, the largest Bw dequantization can honour
+const MAX_COEFFICIENT_BITS: u8 = 31;
+
+/// Gain of each band in band index order, see [`crate::dwt::subband_regions`]
+///
+/// The gain grows by one bitplane for every two low-pass filtering steps between the
+/// band and the image, so coarse bands that spread over many pixels are kept finer.
+pub fn band_gains(levels_x: u8, levels_y: u8) -> Vec<u8> {
+    use crate::dwt::SubbandOrientation;
+
+    crate::dwt::subband_regions(1, 1, levels_x, levels_y)
+        .iter()
+        .map(|band| {
+            let previous = band.level - 1;
+            let (low_x, low_y) = match band.orientation {
+                SubbandOrientation::Ll => (band.level, levels_y),
+                SubbandOrientation::Hl => (previous, band.level.min(levels_y)),
+                SubbandOrientation::Lh => (band.level, previous),
+                SubbandOrientation::Hh => (previous, previous),
+            };
+            (low_x + low_y) / 2
+        })
+        .collect()
+}
+
+pub fn compute_quantization_parameters(
+    quality: f32,
+    levels_x: u8,
+    levels_y: u8,
+    num_components: u8,
+) -> Result<QuantizationParameters> {
+    let base_qp = quality_to_qp(quality)?;
+
+    // One gain per subband of the NL,x / NL,y decomposition, shared by all components
+    crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+    let band_gains = band_gains(levels_x, levels_y);
+    let num_components = num_components as usize;
+
+    let mut gains = Vec::with_capacity(band_gains.len() * num_components);
+    for &gain in &band_gains {
+        gains.extend(std::iter::repeat_n(gain, num_components));
+    }
+
+    // Refine visually important bands first: higher gain, then lower band index, then luma
+    let mut order: Vec<usize> = (0..gains.len()).collect();
+    order.sort_by_key(|&band| (std::cmp::Reverse(gains[band]), band));
+    let mut priorities = vec![0u8; gains.len()];
+    for (rank, &band) in order.iter().enumerate() {
+        priorities[band] = rank.min(u8::MAX as usize) as u8;
+    }
 
-    // Return quantization parameters for DWT subbands
-    // Real JPEG XS would have different QPs for different subbands:
-    // - Lower QPs for low-frequency (visually important) subbands
-    // - Higher QPs for high-frequency (less visually important) subbands
+    // Power-of-two steps: Q bitplanes for the finest bands, and R refines a share of the
+    // bands by one bitplane to approximate non power-of-two base steps, the share by which
+    // the rounded-up step overshoots. The coefficients carry Fq fractional bits, which a
+    // base step of one sample drops as well.
+    let log_qp = (base_qp as f32).log2();
+    let quantization = log_qp.ceil() as u8 + crate::dwt::FRACTIONAL_BITS;
+    let refinement = ((log_qp.ceil() - log_qp) * gains.len() as f32).round() as u8;
 
-    // TODO: Make DWT levels configurable via encoder config
-    // For now, assume 4-level DWT which produces 13 subbands (4*3 + 1 = 13)
-    const DWT_LEVELS: usize = 4;
-    const NUM_SUBBANDS: usize = 3 * DWT_LEVELS + 1; // 3 detail bands per level + 1 final LL subband
+    Ok(QuantizationParameters {
+        quantization,
+        refinement,
+        gains,
+        priorities,
+    })
+}
 
-    Ok(vec![base_qp; NUM_SUBBANDS])
+/// Parameters of lossless coding: Q = R = 0, so every band keeps all its bitplanes
+///
+/// Lossless coefficients have no fractional bits (Fq = 0). The WGT gains and
+/// priorities are the usual ones, they just never truncate anything.
+pub fn lossless_parameters(
+    levels_x: u8,
+    levels_y: u8,
+    num_components: u8,
+) -> Result<QuantizationParameters> {
+    Ok(QuantizationParameters {
+        quantization: 0,
+        refinement: 0,
+        ..compute_quantization_parameters(1.0, levels_x, levels_y, num_components)?
+    })
 }
 
 #[cfg(test)]
@@ -80,21 +271,144 @@ mod tests {
     #[test]
     fn test_quality_to_qp_mapping() {
         // Test specific quality thresholds
-        assert_eq!(compute_quantization_parameters(0.95).unwrap()[0], 1);
-        assert_eq!(compute_quantization_parameters(0.9).unwrap()[0], 1);
-        assert_eq!(compute_quantization_parameters(0.89).unwrap()[0], 2);
-        assert_eq!(compute_quantization_parameters(0.8).unwrap()[0], 2);
-        assert_eq!(compute_quantization_parameters(0.79).unwrap()[0], 3);
-        assert_eq!(compute_quantization_parameters(0.5).unwrap()[0], 6);
-        assert_eq!(compute_quantization_parameters(0.1).unwrap()[0], 24);
-        assert_eq!(compute_quantization_parameters(0.05).unwrap()[0], 32);
+        assert_eq!(quality_to_qp(0.95).unwrap(), 1);
+        assert_eq!(quality_to_qp(0.9).unwrap(), 1);
+        assert_eq!(quality_to_qp(0.89).unwrap(), 2);
+        assert_eq!(quality_to_qp(0.8).unwrap(), 2);
+        assert_eq!(quality_to_qp(0.79).unwrap(), 3);
+        assert_eq!(quality_to_qp(0.5).unwrap(), 6);
+        assert_eq!(quality_to_qp(0.1).unwrap(), 24);
+        assert_eq!(quality_to_qp(0.05).unwrap(), 32);
     }
 
     #[test]
     fn test_invalid_quality_parameters() {
-        assert!(compute_quantization_parameters(0.0).is_err());
-        assert!(compute_quantization_parameters(-0.1).is_err());
-        assert!(compute_quantization_parameters(1.1).is_err());
+        assert!(compute_quantization_parameters(0.0, 5, 2, 3).is_err());
+        assert!(compute_quantization_parameters(-0.1, 5, 2, 3).is_err());
+        assert!(compute_quantization_parameters(1.1, 5, 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_subband_count_follows_decomposition_levels() {
+        // 2*NL,y + NL,x + 1 bands per component
+        let params = |lx, ly, nc| compute_quantization_parameters(0.5, lx, ly, nc);
+        assert_eq!(params(5, 2, 1).unwrap().num_bands(), 10);
+        assert_eq!(params(4, 4, 1).unwrap().num_bands(), 13);
+        assert_eq!(params(5, 2, 3).unwrap().num_bands(), 30);
+        assert_eq!(params(1, 0, 3).unwrap().priorities.len(), 6);
+        assert!(params(0, 0, 3).is_err());
+        assert!(params(2, 3, 3).is_err());
+    }
+
+    #[test]
+    fn test_band_gains_favour_low_frequencies() {
+        // LL and the horizontal-only bands of a 5/2 decomposition carry the highest gains
+        assert_eq!(band_gains(5, 2), vec![3, 3, 2, 2, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(band_gains(1, 1), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_band_truncation_and_refinement() {
+        let params = compute_quantization_parameters(0.5, 5, 2, 3).unwrap();
+        assert_eq!(params.quantization, 3 + crate::dwt::FRACTIONAL_BITS); // ceil(log2(6)) + Fq
+
+        // Priorities are a permutation of the band indices
+        let mut sorted = params.priorities.clone();
+        sorted.sort_unstable();
+        assert!(sorted.iter().enumerate().all(|(i, &p)| p as usize == i));
+
+        // Finest HH bands of every component: step 8, or 4 when refined, in samples
+        for band in 27..30 {
+            let expected = if params.priorities[band] < params.refinement {
+                4 << crate::dwt::FRACTIONAL_BITS
+            } else {
+                8 << crate::dwt::FRACTIONAL_BITS
+            };
+            assert_eq!(params.step(band), expected);
+        }
+
+        // LL is refined first and keeps the finest step
+        assert_eq!(params.priorities[0], 0);
+        assert!(params.step(0) <= params.step(29));
+
+        // Highest quality truncates no integer bits
+        let finest = compute_quantization_parameters(0.95, 5, 2, 3).unwrap();
+        assert!((0..finest.num_bands())
+            .all(|band| finest.step(band) <= 1 << crate::dwt::FRACTIONAL_BITS));
+    }
+
+    #[test]
+    fn test_refinement_approximates_the_base_step() {
+        // R is the share of the bands by which 2^ceil(log2(QP)) overshoots QP
+        for (quality, qp) in [(0.5, 6.0f32), (0.3, 12.0), (0.1, 24.0)] {
+            let params = compute_quantization_parameters(quality, 5, 2, 3).unwrap();
+            let expected = ((qp.log2().ceil() - qp.log2()) * 30.0).round() as u8;
+            assert_eq!(params.refinement, expected, "QP={}", qp);
+            assert_eq!(params.refinement, 12, "QP={}", qp);
+        }
+
+        // Power-of-two steps refine nothing
+        for quality in [0.95, 0.8, 0.6, 0.2] {
+            let params = compute_quantization_parameters(quality, 5, 2, 3).unwrap();
+            assert_eq!(params.refinement, 0, "quality {}", quality);
+        }
+    }
+
+    #[test]
+    fn test_lossless_parameters_keep_every_bitplane() {
+        let params = lossless_parameters(5, 2, 3).unwrap();
+        assert_eq!(params.num_bands(), 3 * crate::dwt::subband_count(5, 2));
+        assert!((0..params.num_bands()).all(|band| params.step(band) == 1));
+    }
+
+    #[test]
+    fn test_precinct_quantization_rounds_fixed_point_coefficients() {
+        let layout = PrecinctLayout::new(8, 2, 1, 1, 1, 0).unwrap();
+        let params = QuantizationParameters {
+            quantization: 3,
+            refinement: 0,
+            gains: vec![0; 4],
+            priorities: vec![0, 1, 2, 3],
+        };
+        let coeffs: Vec<i32> = vec![0, 3, 4, -4, -5, 12, -12, 100, 7, -7, 8, -9, 1, 2, 3, 4];
+        let mut quantized = vec![0i32; coeffs.len()];
+        quantize_precinct(&coeffs, &mut quantized, &layout, 0, &params, 0).unwrap();
+        assert_eq!(
+            quantized,
+            vec![0, 0, 1, -1, -1, 2, -2, 13, 1, -1, 1, -1, 0, 0, 0, 1]
+        );
+
+        let mut reconstructed = vec![0i32; coeffs.len()];
+        dequantize_precinct(&quantized, &mut reconstructed, &layout, 0, &params, 0, 20).unwrap();
+        assert!(coeffs
+            .iter()
+            .zip(&reconstructed)
+            .all(|(&c, &r)| (c - r).abs() <= 4));
+    }
+
+    #[test]
+    fn test_dequantization_rejects_coefficients_beyond_bw() {
+        let layout = PrecinctLayout::new(8, 2, 1, 1, 1, 0).unwrap();
+        let params = QuantizationParameters {
+            quantization: 25,
+            refinement: 0,
+            gains: vec![0; 4],
+            priorities: vec![0, 1, 2, 3],
+        };
+        let mut coeffs = vec![0i32; 16];
+        let dequantize = |quantized: &[i32], coeffs: &mut [i32], bw| {
+            dequantize_precinct(quantized, coeffs, &layout, 0, &params, 0, bw)
+        };
+
+        // 2^25 fits 31 bits, but not 20, and 2^35 fits no i32
+        assert!(dequantize(&[1; 16], &mut coeffs, 31).is_ok());
+        assert_eq!(coeffs[0], 1 << 25);
+        for (quantized, bw) in [(1, 20), (1 << 10, 31), (-(1 << 10), 255)] {
+            assert!(matches!(
+                dequantize(&[quantized; 16], &mut coeffs, bw),
+                Err(JpegXsError::Corrupt(_))
+            ));
+        }
     }
 
     #[test]
@@ -102,9 +416,9 @@ mod tests {
         // Verify the lookup table covers expected quality ranges
         for &(min_quality, qp, _) in QUALITY_TO_QP_TABLE {
             if min_quality > 0.0 {
-                let result = compute_quantization_parameters(min_quality).unwrap();
+                let result = quality_to_qp(min_quality).unwrap();
                 assert_eq!(
-                    result[0], qp,
+                    result, qp,
                     "Quality {} should map to QP {}",
                     min_quality, qp
                 );
@@ -0,0 +1,338 @@
+// JPEG XS rate control based on ISO/IEC 21122-1:2024 Annex C.6
+//
+// The frame budget is split evenly across slices. Within a slice every precinct gets
+// an equal share of what is left, and picks the finest quantization Q[p] / R[p] whose
+// coded size fits that share. The last precinct of a slice is padded so that every
+// slice, and therefore the whole codestream, has exactly the requested size.
+
+use crate::error::{JpegXsError, Result};
+use crate::precinct::{self, PrecinctLayout};
+use crate::profile::{self, ProfileError};
+use crate::quant::{self, QuantizationParameters};
+use crate::types::{Level, Profile, RateTarget};
+
+/// EOC marker closing the codestream
+const EOC_LENGTH: usize = 2;
+
+/// SLH marker segment in front of every slice: marker, Lslh and Yslh
+const SLICE_HEADER_LENGTH: usize = 6;
+
+/// Codestream size in bytes for a rate target, checked against the profile/level limit
+///
+/// [`RateTarget::Mbps`] is checked against the maximum bitrate of the level. A
+/// bits-per-pixel target carries no frame rate, so the frame it gives is checked against
+/// the per-frame limit of [`profile::get_max_frame_bits`] instead.
+pub fn frame_budget(
+    target: RateTarget,
+    width: u32,
+    height: u32,
+    profile: Profile,
+    level: Level,
+) -> Result<usize> {
+    let bits = match target {
+        RateTarget::BitsPerPixel(bpp) => {
+            if !(bpp > 0.0 && bpp.is_finite()) {
+                return Err(JpegXsError::InvalidParameter(format!(
+                    "Invalid bits per pixel target: {}",
+                    bpp
+                )));
+            }
+            let bits = bpp as f64 * width as f64 * height as f64;
+            let max_bits = profile::get_max_frame_bits(profile, level)?;
+            if bits > max_bits as f64 {
+                return Err(JpegXsError::ProfileViolation(
+                    ProfileError::InvalidConfiguration(format!(
+                        "{} bpp gives {:.0} bits per {}x{} frame, over the {} bit limit of {:?} {:?}",
+                        bpp, bits, width, height, max_bits, profile, level
+                    )),
+                ));
+            }
+            bits
+        }
+        RateTarget::Mbps { mbps, frame_rate } => {
+            if !(mbps > 0.0 && mbps.is_finite() && frame_rate > 0.0 && frame_rate.is_finite()) {
+                return Err(JpegXsError::InvalidParameter(format!(
+                    "Invalid bitrate target: {} Mbps at {} fps",
+                    mbps, frame_rate
+                )));
+            }
+            let max_mbps = profile::get_max_bitrate_mbps(profile, level)?;
+            if mbps > max_mbps as f32 {
+                return Err(JpegXsError::ProfileViolation(
+                    ProfileError::InvalidConfiguration(format!(
+                        "Bitrate target of {} Mbps exceeds the {} Mbps limit of {:?} {:?}",
+                        mbps, max_mbps, profile, level
+                    )),
+                ));
+            }
+            mbps as f64 * 1_000_000.0 / frame_rate as f64
+        }
+    };
+    Ok((bits / 8.0).floor() as usize)
+}
+
+/// Byte budget of every slice of a frame
+#[derive(Debug, Clone)]
+pub struct RateController {
+    slice_budgets: Vec<usize>,
+}
+
+impl RateController {
+    /// Split `frame_bytes` across `num_slices` slices after the main header
+    pub fn new(frame_bytes: usize, header_bytes: usize, num_slices: usize) -> Result<Self> {
+        let overhead = header_bytes + num_slices * SLICE_HEADER_LENGTH + EOC_LENGTH;
+        let entropy_bytes = frame_bytes.checked_sub(overhead).ok_or_else(|| {
+            JpegXsError::InvalidParameter(format!(
+                "Rate target of {} bytes does not cover the {} bytes of headers",
+                frame_bytes, overhead
+            ))
+        })?;
+
+        let num_slices = num_slices.max(1);
+        let slice_budgets = (0..num_slices)
+            .map(|slice| {
+                entropy_bytes / num_slices + usize::from(slice < entropy_bytes % num_slices)
+            })
+            .collect();
+        Ok(Self { slice_budgets })
+    }
+
+    /// Entropy coded bytes available to a slice
+    pub fn slice_budget(&self, slice: usize) -> usize {
+        self.slice_budgets[slice]
+    }
+
+    /// Encode the precincts of a slice into exactly its byte budget
+    ///
+    /// `coeffs` are the unquantized fixed-point wavelet planes; `params` provides the WGT gains and
+    /// priorities, its Q and R are replaced per precinct.
+    pub fn encode_slice(
+        &self,
+        layout: &PrecinctLayout,
+        coeffs: &[&[i32]],
+        params: &QuantizationParameters,
+        slice: usize,
+        precincts: std::ops::Range<usize>,
+    ) -> Result<Vec<u8>> {
+        let mut encoder = self.begin_slice(slice, precincts.len());
+        let mut data = Vec::with_capacity(encoder.budget);
+        for precinct in precincts {
+            data.extend(encoder.push_precinct(layout, coeffs, params, precinct)?);
+        }
+        data.extend(encoder.finish()?);
+        Ok(data)
+    }
+
+    /// Start coding the `num_precincts` precincts of a slice one at a time
+    pub fn begin_slice(&self, slice: usize, num_precincts: usize) -> SliceEncoder {
+        SliceEncoder {
+            budget: self.slice_budget(slice),
+            remaining: num_precincts,
+            written: 0,
+            last: Vec::new(),
+            quantized: Vec::new(),
+        }
+    }
+}
+
+/// Rate controlled coding of a slice, one precinct at a time
+///
+/// Every precinct gets an equal share of what is left of the slice budget. A coded
+/// precinct is final once the next one has been coded; the last precinct is held back
+/// until [`SliceEncoder::finish`] pads it to fill the budget exactly.
+#[derive(Debug, Clone)]
+pub struct SliceEncoder {
+    budget: usize,
+    remaining: usize,
+    written: usize,
+    last: Vec<u8>,
+    quantized: Vec<Vec<i32>>,
+}
+
+impl SliceEncoder {
+    /// Code the next precinct of the slice, returning the bytes that are now final
+    ///
+    /// `layout` and `coeffs` only need to cover `precinct`, so a caller may pass the
+    /// planes of a few precinct rows along with a layout of the same height.
+    pub fn push_precinct(
+        &mut self,
+        layout: &PrecinctLayout,
+        coeffs: &[&[i32]],
+        params: &QuantizationParameters,
+        precinct: usize,
+    ) -> Result<Vec<u8>> {
+        if self.remaining == 0 {
+            return Err(JpegXsError::InvalidParameter(
+                "Slice has no precinct left to code".into(),
+            ));
+        }
+        let done = std::mem::take(&mut self.last);
+        self.written += done.len();
+
+        self.quantized.resize_with(coeffs.len(), Vec::new);
+        for (quantized, plane) in self.quantized.iter_mut().zip(coeffs) {
+            quantized.resize(plane.len(), 0);
+        }
+        let share = (self.budget - self.written) / self.remaining;
+        self.last =
+            encode_precinct_within(layout, coeffs, &mut self.quantized, params, precinct, share)?;
+        self.remaining -= 1;
+        Ok(done)
+    }
+
+    /// Pad the last precinct so that the slice fills its budget, and return it
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        if self.remaining > 0 {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Slice finished with {} precincts left to code",
+                self.remaining
+            )));
+        }
+        let padding = self.budget - self.written - self.last.len();
+        precinct::pad_precinct(&mut self.last, padding)?;
+        Ok(self.last)
+    }
+}
+
+/// Encode a precinct with the finest Q[p] / R[p] that fits `budget` bytes
+///
+/// Q and R are searched as a single truncation index k = Q * Nb - R: every step of k
+/// drops one more band by a bitplane in refinement priority order, so the coded size
+/// shrinks (almost) monotonically with k and a binary search applies.
+fn encode_precinct_within(
+    layout: &PrecinctLayout,
+    coeffs: &[&[i32]],
+    quantized: &mut [Vec<i32>],
+    params: &QuantizationParameters,
+    precinct: usize,
+    budget: usize,
+) -> Result<Vec<u8>> {
+    let num_bands = params.num_bands();
+    let mut encode = |k: usize| -> Result<Vec<u8>> {
+        let quantization = k.div_ceil(num_bands);
+        let candidate = QuantizationParameters {
+            quantization: quantization as u8,
+            refinement: (quantization * num_bands - k) as u8,
+            ..params.clone()
+        };
+        for (component, plane) in coeffs.iter().enumerate() {
+            quant::quantize_precinct(
+                plane,
+                &mut quantized[component],
+                layout,
+                precinct,
+                &candidate,
+                component,
+            )?;
+        }
+        let planes: Vec<&[i32]> = quantized.iter().map(|plane| plane.as_slice()).collect();
+        precinct::encode_precinct(
+            layout,
+            &planes,
+            precinct,
+            candidate.quantization,
+            candidate.refinement,
+        )
+    };
+
+    let (mut low, mut high) = (0, params.max_quantization() as usize * num_bands);
+    let mut best = encode(high)?;
+    if best.len() > budget {
+        return Err(JpegXsError::InvalidParameter(format!(
+            "Rate target too low: precinct {} needs at least {} bytes, {} available",
+            precinct,
+            best.len(),
+            budget
+        )));
+    }
+    while low < high {
+        let mid = (low + high) / 2;
+        let data = encode(mid)?;
+        if data.len() <= budget {
+            high = mid;
+            best = data;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_budget() {
+        let budget = |target| frame_budget(target, 1920, 1080, Profile::Main, Level::Level1);
+        assert_eq!(budget(RateTarget::BitsPerPixel(1.5)).unwrap(), 388_800);
+        assert_eq!(
+            budget(RateTarget::Mbps {
+                mbps: 120.0,
+                frame_rate: 60.0
+            })
+            .unwrap(),
+            250_000
+        );
+
+        // Main profile level 1 allows at most 200 Mbps
+        assert!(budget(RateTarget::Mbps {
+            mbps: 400.0,
+            frame_rate: 60.0
+        })
+        .is_err());
+        assert!(budget(RateTarget::BitsPerPixel(0.0)).is_err());
+
+        // and at most 200 / 60 Mbit per frame, about 1.6 bpp at 1080p
+        assert!(matches!(
+            budget(RateTarget::BitsPerPixel(2.0)),
+            Err(JpegXsError::ProfileViolation(_))
+        ));
+        assert!(frame_budget(
+            RateTarget::BitsPerPixel(2.0),
+            1920,
+            1080,
+            Profile::Main,
+            Level::Level2
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_slice_budgets_cover_the_frame() {
+        let controller = RateController::new(1001, 50, 3).unwrap();
+        let total: usize = (0..3).map(|slice| controller.slice_budget(slice)).sum();
+        assert_eq!(total + 50 + 3 * SLICE_HEADER_LENGTH + EOC_LENGTH, 1001);
+        assert_eq!(controller.slice_budget(0), 311);
+        assert_eq!(controller.slice_budget(2), 310);
+
+        assert!(RateController::new(60, 50, 3).is_err());
+    }
+
+    #[test]
+    fn test_slice_fills_its_budget_exactly() {
+        let (width, height) = (64u32, 16u32);
+        let planes: Vec<Vec<i32>> = (0..3)
+            .map(|c| {
+                (0..width * height)
+                    .map(|i| (((i * 37 + c * 11) % 97) as i32 - 48) << crate::dwt::FRACTIONAL_BITS)
+                    .collect()
+            })
+            .collect();
+        let coeffs: Vec<&[i32]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        let layout = PrecinctLayout::new(width, height, 2, 1, 3, 0).unwrap();
+        let params = quant::compute_quantization_parameters(0.9, 2, 1, 3).unwrap();
+
+        // Well below the lossless size of the planes
+        let controller = RateController::new(1200, 0, 1).unwrap();
+        let budget = controller.slice_budget(0);
+        let data = controller
+            .encode_slice(&layout, &coeffs, &params, 0, 0..layout.num_precincts())
+            .unwrap();
+        assert_eq!(data.len(), budget);
+
+        let (_, headers) = precinct::decode_precincts(&layout, &data).unwrap();
+        assert_eq!(headers.len(), 8);
+        assert!(headers.iter().any(|header| header.quantization > 0));
+    }
+}
@@ -0,0 +1,463 @@
+// Long-lived encoding and decoding sessions for video sequences
+//
+// `encode_frame` and `decode_frame` set everything up from scratch for every frame. A
+// session keeps its configuration, the coding setup of the current frame geometry and
+// the working planes of the pipeline across frames, so coding a sequence of equally
+// sized frames only allocates the codestreams and the decoded images it hands out.
+//
+// The components and slices of a frame are coded in parallel on rayon, either on the
+// global pool or on a pool of the configured size owned by the session.
+
+use crate::accel::CodecDwt;
+use crate::error::{JpegXsError, Result};
+use crate::types::{
+    Bitstream, CodestreamMetadata, DecoderConfig, EncoderConfig, FrameInfo, ImageOwned16,
+    ImageOwned8, ImageView16, ImageView8,
+};
+use crate::{CodingSetup, DecodeScratch, EncodeScratch, PixelFormat};
+
+/// Encoder for a sequence of frames sharing one configuration
+///
+/// The main header and quantization setup are derived once and reused as long as the
+/// frame size and format stay the same; a frame of another size or format sets them up
+/// anew. Every frame is coded exactly as [`crate::encode_frame`] codes it.
+///
+/// # Example
+/// ```rust,ignore
+/// use jpegxs_core::{Encoder, EncoderConfig};
+///
+/// let mut encoder = Encoder::new(EncoderConfig::default());
+/// for bitstream in encoder.encode_sequence(frames) {
+///     send(bitstream?.data);
+/// }
+/// # Ok::<(), jpegxs_core::JpegXsError>(())
+/// ```
+pub struct Encoder {
+    config: EncoderConfig,
+    setup: Option<CodingSetup>,
+    scratch: EncodeScratch,
+    workers: Workers,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> Self {
+        Self {
+            workers: Workers::new(config.threads),
+            config,
+            setup: None,
+            scratch: EncodeScratch::default(),
+        }
+    }
+
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
+    }
+
+    /// Replace the comments written into the main header from the next frame on, e.g.
+    /// to carry a per-frame timecode
+    pub fn set_metadata(&mut self, metadata: CodestreamMetadata) {
+        if metadata != self.config.metadata {
+            self.config.metadata = metadata;
+            self.setup = None;
+        }
+    }
+
+    /// Encode an 8-bit frame, see [`crate::encode_frame`] for the supported formats
+    pub fn encode(&mut self, input: ImageView8) -> Result<Bitstream> {
+        crate::fill_planes8(&input, &mut self.scratch.planes)?;
+        self.encode_planes(input.width, input.height, input.format)
+    }
+
+    /// Encode a frame with 10 to 16 bits per sample, see [`crate::encode_frame16`]
+    pub fn encode16(&mut self, input: ImageView16) -> Result<Bitstream> {
+        crate::fill_planes16(&input, &mut self.scratch.planes)?;
+        self.encode_planes(input.width, input.height, input.format)
+    }
+
+    /// Encode every frame of `frames` in turn, yielding one codestream per frame
+    pub fn encode_sequence<'a, I>(&mut self, frames: I) -> EncodeSequence<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = ImageView8<'a>>,
+    {
+        EncodeSequence {
+            encoder: self,
+            frames: frames.into_iter(),
+        }
+    }
+
+    fn encode_planes(&mut self, width: u32, height: u32, format: PixelFormat) -> Result<Bitstream> {
+        let setup = match &mut self.setup {
+            Some(setup) if setup.is_for(width, height, format) => setup,
+            setup => setup.insert(CodingSetup::new(width, height, format, &self.config)?),
+        };
+        let scratch = &mut self.scratch;
+        self.workers
+            .install(|| crate::encode_planes(setup, scratch))?
+    }
+}
+
+/// Iterator returned by [`Encoder::encode_sequence`]
+pub struct EncodeSequence<'e, I> {
+    encoder: &'e mut Encoder,
+    frames: I,
+}
+
+impl<'a, I> Iterator for EncodeSequence<'_, I>
+where
+    I: Iterator<Item = ImageView8<'a>>,
+{
+    type Item = Result<Bitstream>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.frames.next()?;
+        Some(self.encoder.encode(frame))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+}
+
+/// Decoder for a sequence of codestreams sharing one configuration
+///
+/// Keeps the coefficient and sample planes of the pipeline from one frame to the next.
+/// Every frame is decoded exactly as [`crate::decode_frame`] and its variants decode it.
+pub struct Decoder {
+    config: DecoderConfig,
+    info: Option<FrameInfo>,
+    scratch: DecodeScratch,
+    workers: Workers,
+}
+
+impl Decoder {
+    pub fn new(config: DecoderConfig) -> Self {
+        Self {
+            workers: Workers::new(config.threads),
+            config,
+            info: None,
+            scratch: DecodeScratch::default(),
+        }
+    }
+
+    pub fn config(&self) -> &DecoderConfig {
+        &self.config
+    }
+
+    /// Coding parameters and comments of the last decoded frame
+    pub fn info(&self) -> Option<&FrameInfo> {
+        self.info.as_ref()
+    }
+
+    /// Decode to the 8-bit format matching the coded components, see [`crate::decode_frame`]
+    pub fn decode(&mut self, bitstream: &Bitstream) -> Result<ImageOwned8> {
+        let info = self.decode_components(bitstream)?;
+        let output_format = crate::default_output_format(&info);
+        crate::components_to_format8(&self.scratch.planes, &info, output_format)
+    }
+
+    /// Decode to an 8-bit format, see [`crate::decode_frame_to_format`]
+    pub fn decode_to_format(
+        &mut self,
+        bitstream: &Bitstream,
+        output_format: PixelFormat,
+    ) -> Result<ImageOwned8> {
+        crate::check_output_format8(output_format)?;
+        let info = self.decode_components(bitstream)?;
+        crate::components_to_format8(&self.scratch.planes, &info, output_format)
+    }
+
+    /// Decode to a format with 10 to 16 bits per sample, see
+    /// [`crate::decode_frame16_to_format`]
+    pub fn decode16_to_format(
+        &mut self,
+        bitstream: &Bitstream,
+        output_format: PixelFormat,
+    ) -> Result<ImageOwned16> {
+        crate::check_output_format16(output_format)?;
+        let info = self.decode_components(bitstream)?;
+        crate::components_to_format16(&self.scratch.planes, &info, output_format)
+    }
+
+    fn decode_components(&mut self, bitstream: &Bitstream) -> Result<FrameInfo> {
+        let dwt = CodecDwt::select(self.config.backend)?;
+        let strict = self.config.strict_mode;
+        let scratch = &mut self.scratch;
+        let info = self
+            .workers
+            .install(|| crate::decode_components_into(bitstream, scratch, dwt, strict))??;
+        self.info = Some(info.clone());
+        Ok(info)
+    }
+}
+
+/// Threads a session codes its frames on
+///
+/// The pool is started with the first frame, so that creating a session cannot fail.
+pub(crate) struct Workers {
+    threads: usize,
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Workers {
+    pub(crate) fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            pool: None,
+        }
+    }
+
+    /// Run `op` on the session's pool, or on rayon's global pool without a thread count
+    pub(crate) fn install<R: Send>(&mut self, op: impl FnOnce() -> R + Send) -> Result<R> {
+        if self.threads == 0 {
+            return Ok(op());
+        }
+        let pool = match &mut self.pool {
+            Some(pool) => pool,
+            pool => pool.insert(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(self.threads)
+                    .build()
+                    .map_err(|e| {
+                        JpegXsError::Backend(format!(
+                            "Failed to start {} worker threads: {}",
+                            self.threads, e
+                        ))
+                    })?,
+            ),
+        };
+        Ok(pool.install(op))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Comment, Profile, RateTarget};
+
+    fn test_frame(width: u32, height: u32, seed: usize) -> Vec<u8> {
+        (0..width as usize * height as usize * 3)
+            .map(|i| ((i * 31 + seed * 17 + (i / 97) * 5) % 256) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_sequence_matches_single_frames() {
+        let config = EncoderConfig {
+            decomposition_levels_x: 3,
+            decomposition_levels_y: 1,
+            slice_height: 2,
+            ..Default::default()
+        };
+        let rate_config = EncoderConfig {
+            rate_target: Some(RateTarget::BitsPerPixel(8.0)),
+            ..config.clone()
+        };
+
+        // The frame size changes halfway through the sequence
+        let frames: Vec<(u32, u32, Vec<u8>)> = (0..4)
+            .map(|seed| {
+                let (width, height) = if seed < 2 { (48, 16) } else { (32, 24) };
+                (width, height, test_frame(width, height, seed))
+            })
+            .collect();
+        let views: Vec<ImageView8> = frames
+            .iter()
+            .map(|(width, height, data)| ImageView8 {
+                data,
+                width: *width,
+                height: *height,
+                format: PixelFormat::Rgb8,
+            })
+            .collect();
+
+        for config in [config, rate_config] {
+            let mut encoder = Encoder::new(config.clone());
+            let mut decoder = Decoder::new(DecoderConfig::default());
+            let bitstreams = encoder
+                .encode_sequence(views.iter().copied())
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(bitstreams.len(), views.len());
+
+            for (view, bitstream) in views.iter().zip(&bitstreams) {
+                let single = crate::encode_frame(*view, &config).unwrap();
+                assert_eq!(bitstream.data, single.data);
+
+                let decoded = decoder
+                    .decode_to_format(bitstream, PixelFormat::Rgb8)
+                    .unwrap();
+                let expected = crate::decode_frame_to_format(
+                    bitstream,
+                    &DecoderConfig::default(),
+                    PixelFormat::Rgb8,
+                )
+                .unwrap();
+                assert_eq!(decoded.data, expected.data);
+                assert_eq!((decoded.width, decoded.height), (view.width, view.height));
+            }
+        }
+    }
+
+    #[test]
+    fn test_session_reuses_setup_and_planes() {
+        let data = test_frame(64, 32, 0);
+        let input = ImageView8 {
+            data: &data,
+            width: 64,
+            height: 32,
+            format: PixelFormat::Rgb8,
+        };
+        let mut encoder = Encoder::new(EncoderConfig::default());
+        let first = encoder.encode(input).unwrap();
+        let header = encoder.setup.as_ref().unwrap().bitstream.data().as_ptr();
+        let plane = encoder.scratch.coeffs[0].as_ptr();
+
+        let second = encoder.encode(input).unwrap();
+        assert_eq!(first.data, second.data);
+        assert_eq!(
+            encoder.setup.as_ref().unwrap().bitstream.data().as_ptr(),
+            header
+        );
+        assert_eq!(encoder.scratch.coeffs[0].as_ptr(), plane);
+
+        let mut decoder = Decoder::new(DecoderConfig::default());
+        decoder.decode(&first).unwrap();
+        let plane = decoder.scratch.planes[0].as_ptr();
+        decoder.decode(&second).unwrap();
+        assert_eq!(decoder.scratch.planes[0].as_ptr(), plane);
+    }
+
+    #[test]
+    fn test_metadata_changes_per_frame() {
+        let data = test_frame(32, 16, 0);
+        let input = ImageView8 {
+            data: &data,
+            width: 32,
+            height: 16,
+            format: PixelFormat::Rgb8,
+        };
+        let mut encoder = Encoder::new(EncoderConfig::default());
+        let mut decoder = Decoder::new(DecoderConfig::default());
+        assert!(decoder.info().is_none());
+
+        for frame in 0..3 {
+            let metadata = CodestreamMetadata {
+                comments: vec![Comment::text(format!("TC 10:00:00:{frame:02}"))],
+            };
+            encoder.set_metadata(metadata.clone());
+            let bitstream = encoder.encode(input).unwrap();
+            let config = EncoderConfig {
+                metadata: metadata.clone(),
+                ..Default::default()
+            };
+            assert_eq!(
+                bitstream.data,
+                crate::encode_frame(input, &config).unwrap().data
+            );
+
+            decoder.decode(&bitstream).unwrap();
+            assert_eq!(decoder.info().unwrap().metadata, metadata);
+        }
+    }
+
+    #[test]
+    fn test_thread_count_does_not_change_output() {
+        let (width, height) = (96, 42);
+        let rgb = test_frame(width, height, 3);
+        let yuv420: Vec<u8> = rgb[..(width * height * 3 / 2) as usize].to_vec();
+        let inputs = [
+            ImageView8 {
+                data: &rgb,
+                width,
+                height,
+                format: PixelFormat::Rgb8,
+            },
+            ImageView8 {
+                data: &yuv420,
+                width,
+                height,
+                format: PixelFormat::Yuv420p8,
+            },
+        ];
+        let base = EncoderConfig {
+            decomposition_levels_x: 3,
+            decomposition_levels_y: 2,
+            slice_height: 2,
+            precinct_width: 1,
+            profile: Profile::Unrestricted,
+            ..Default::default()
+        };
+        let configs = [
+            base.clone(),
+            EncoderConfig {
+                rate_target: Some(RateTarget::BitsPerPixel(8.0)),
+                ..base.clone()
+            },
+            EncoderConfig {
+                lossless: true,
+                ..base
+            },
+        ];
+
+        for input in inputs {
+            for config in &configs {
+                let encode = |threads| {
+                    let config = EncoderConfig {
+                        threads,
+                        ..config.clone()
+                    };
+                    Encoder::new(config).encode(input).unwrap()
+                };
+                let decode = |bitstream: &Bitstream, threads| {
+                    let config = DecoderConfig {
+                        threads,
+                        ..Default::default()
+                    };
+                    Decoder::new(config).decode(bitstream).unwrap()
+                };
+
+                let single = encode(1);
+                let single_decoded = decode(&single, 1);
+                for threads in [0, 2, 4, 7] {
+                    let bitstream = encode(threads);
+                    assert_eq!(bitstream.data, single.data, "{threads} encoder threads");
+                    assert_eq!(
+                        decode(&single, threads).data,
+                        single_decoded.data,
+                        "{threads} decoder threads"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_session_reports_errors_per_frame() {
+        let mut encoder = Encoder::new(EncoderConfig::default());
+        let data = test_frame(16, 8, 0);
+        let frames = [
+            ImageView8 {
+                data: &data[..10],
+                width: 16,
+                height: 8,
+                format: PixelFormat::Rgb8,
+            },
+            ImageView8 {
+                data: &data,
+                width: 16,
+                height: 8,
+                format: PixelFormat::Rgb8,
+            },
+        ];
+        let results: Vec<_> = encoder.encode_sequence(frames).collect();
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+
+        let mut decoder = Decoder::new(DecoderConfig::default());
+        let bitstream = results[1].as_ref().unwrap();
+        assert!(decoder
+            .decode16_to_format(bitstream, PixelFormat::Rgb8)
+            .is_err());
+        assert!(decoder.decode(bitstream).is_ok());
+    }
+}
@@ -0,0 +1,1123 @@
+// JPEG XS line-based streaming encoder and decoder
+//
+// Image lines come in as horizontal strips and leave as coded precincts, and the other
+// way round. The lifting steps of the 5/3 transform only reach one sample up and down
+// per step, so the coefficients of a precinct row depend on a bounded number of precinct
+// rows around it, and so do the samples reconstructed from them: transforming a window
+// with SUPPORT_ROWS precinct rows of margin above and below gives exactly the full-frame
+// result for the rows in between. Only that window is kept in memory, and the output is
+// byte for byte what `encode_frame` and `decode_frame` produce.
+
+use crate::accel::{AccelPreference, CodecDwt};
+use crate::error::{JpegXsError, Result};
+use crate::precinct::PrecinctLayout;
+use crate::rate::SliceEncoder;
+use crate::session::Workers;
+use crate::types::{DecoderConfig, EncoderConfig, FrameInfo, ImageView8, PixelFormat};
+use crate::{conformance, precinct, quant, CodingSetup};
+use jpegxs_core_clean::{markers, JpegXsDecoder, MarkerSegmentIter, ParseError};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Precinct rows of margin around the rows taken from a transformed window
+///
+/// A window edge that is not a picture edge is extended symmetrically, which corrupts
+/// the two lines closest to it at every level; at the deepest vertical level these are
+/// the lines of the two precinct rows next to the edge.
+const SUPPORT_ROWS: u32 = 2;
+
+/// Encoder taking a frame a few lines at a time
+///
+/// Every call to [`StreamingEncoder::push_lines`] returns the part of the codestream
+/// that became final: the main header first, then the slices and precincts whose
+/// wavelet support has been pushed. [`StreamingEncoder::finish`] returns the EOC marker,
+/// so concatenating all returned bytes gives the complete codestream.
+///
+/// Only 8-bit formats are accepted. Peak memory is the lines of a window of
+/// 2 * SUPPORT_ROWS + 1 precinct rows plus the lines of a single push.
+///
+/// # Example
+/// ```rust,ignore
+/// use jpegxs_core::{EncoderConfig, PixelFormat, StreamingEncoder};
+///
+/// let mut encoder = StreamingEncoder::new(1920, 1080, PixelFormat::Rgb8, &EncoderConfig::default())?;
+/// let mut codestream = Vec::new();
+/// for strip in rgb_data.chunks(1920 * 3 * 8) {
+///     codestream.extend(encoder.push_lines(strip, 8)?);
+/// }
+/// codestream.extend(encoder.finish()?);
+/// # Ok::<(), jpegxs_core::JpegXsError>(())
+/// ```
+pub struct StreamingEncoder {
+    setup: CodingSetup,
+    /// Image lines pushed so far
+    lines_received: u32,
+    /// Next precinct row to code
+    next_row: u32,
+    /// First image line still buffered
+    first_line: u32,
+    /// Transform input of every component from `first_line` on
+    lines: Vec<Vec<i32>>,
+    /// Rate control of the slice being coded, with a rate target only
+    slice: Option<SliceEncoder>,
+}
+
+impl StreamingEncoder {
+    /// Start coding a `width` x `height` frame of `format` samples
+    ///
+    /// The configuration is interpreted exactly as [`crate::encode_frame`] does.
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        config: &EncoderConfig,
+    ) -> Result<Self> {
+        if format.bit_depth() > 8 {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "{:?} has {} bits per sample, the streaming encoder takes 8-bit lines",
+                format,
+                format.bit_depth()
+            )));
+        }
+        let (sampling_x, sampling_y) = format.chroma_sampling();
+        if !width.is_multiple_of(sampling_x as u32) || !height.is_multiple_of(sampling_y as u32) {
+            return Err(JpegXsError::DimensionMismatch(format!(
+                "A {}x{} frame cannot be coded as {:?}",
+                width, height, format
+            )));
+        }
+
+        let setup = CodingSetup::new(width, height, format, config)?;
+        let num_components = setup.sampling.len();
+        Ok(Self {
+            setup,
+            lines_received: 0,
+            next_row: 0,
+            first_line: 0,
+            lines: vec![Vec::new(); num_components],
+            slice: None,
+        })
+    }
+
+    /// Push the next `lines` image lines and return the codestream bytes now final
+    ///
+    /// `data` holds the lines in the layout of the frame format, as if they were a frame
+    /// of their own: interleaved formats hold `lines` rows of pixels, planar formats the
+    /// rows of every plane one plane after the other. Formats with vertically subsampled
+    /// chroma take lines in pairs.
+    pub fn push_lines(&mut self, data: &[u8], lines: u32) -> Result<Vec<u8>> {
+        let header = &self.setup.picture_header;
+        let (width, height) = (header.width as u32, header.height as u32);
+        if lines > height - self.lines_received {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Cannot push {} lines after {} of the {} lines of the frame",
+                lines, self.lines_received, height
+            )));
+        }
+        let format = self.setup.format;
+        if !lines.is_multiple_of(format.chroma_sampling().1 as u32) {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Lines of {:?} must be pushed in pairs",
+                format
+            )));
+        }
+
+        if lines > 0 {
+            let strip = ImageView8 {
+                data,
+                width,
+                height: lines,
+                format,
+            };
+            let mut planes = crate::split_frame8(&strip)?;
+            self.setup.prepare_samples(&mut planes)?;
+            for (buffer, plane) in self.lines.iter_mut().zip(planes) {
+                buffer.extend(plane);
+            }
+            self.lines_received += lines;
+            self.code_ready_rows()?;
+        }
+        Ok(self.setup.bitstream.take_data())
+    }
+
+    /// Close the codestream once every line has been pushed, returning its last bytes
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        let height = self.setup.picture_header.height as u32;
+        if self.lines_received < height {
+            return Err(JpegXsError::InvalidParameter(format!(
+                "Frame finished after {} of its {} lines",
+                self.lines_received, height
+            )));
+        }
+        self.setup.bitstream.finalize();
+        Ok(self.setup.bitstream.take_data())
+    }
+
+    /// Code every precinct row whose wavelet support has been pushed
+    fn code_ready_rows(&mut self) -> Result<()> {
+        let header = &self.setup.picture_header;
+        let (width, height) = (header.width as u32, header.height as u32);
+        let levels_x = header.decomposition_levels_x;
+        let precinct_lines = 1u32 << header.decomposition_levels_y;
+        let ready_rows = if self.lines_received == height {
+            height.div_ceil(precinct_lines)
+        } else {
+            (self.lines_received / precinct_lines).saturating_sub(SUPPORT_ROWS)
+        };
+        if ready_rows <= self.next_row {
+            return Ok(());
+        }
+
+        // Transform a window reaching SUPPORT_ROWS precinct rows beyond the rows to code;
+        // it starts at the first buffered line, SUPPORT_ROWS above the next row
+        let window_row = self.next_row.saturating_sub(SUPPORT_ROWS);
+        let window_end = ((ready_rows + SUPPORT_ROWS) * precinct_lines).min(self.lines_received);
+        let layout = self.setup.strip_layout(window_end - self.first_line)?;
+        let mut coeffs = Vec::with_capacity(self.lines.len());
+        for (component, buffer) in self.lines.iter().enumerate() {
+            let (component_width, component_height) = layout.component_size(component);
+            let samples = layout.plane_size(component);
+            let mut plane = vec![0i32; samples];
+            self.setup.dwt.forward_multilevel(
+                &buffer[..samples],
+                &mut plane,
+                component_width,
+                component_height,
+                levels_x,
+                layout.component_levels_y(component),
+            )?;
+            coeffs.push(plane);
+        }
+        let coeffs: Vec<&[i32]> = coeffs.iter().map(|plane| plane.as_slice()).collect();
+
+        let mut quantized: Vec<Vec<i32>> =
+            coeffs.iter().map(|plane| vec![0i32; plane.len()]).collect();
+        let precincts_per_row = layout.precincts_per_row();
+        for row in self.next_row..ready_rows {
+            for column in 0..precincts_per_row {
+                let window_precinct = (row - window_row) as usize * precincts_per_row + column;
+                let frame_precinct = row as usize * precincts_per_row + column;
+                self.code_precinct(
+                    &layout,
+                    &coeffs,
+                    &mut quantized,
+                    window_precinct,
+                    frame_precinct,
+                )?;
+            }
+        }
+        self.next_row = ready_rows;
+
+        // Drop the lines that no later window reaches
+        let first_line = self.next_row.saturating_sub(SUPPORT_ROWS) * precinct_lines;
+        for (buffer, &(sampling_x, sampling_y)) in self.lines.iter_mut().zip(&self.setup.sampling) {
+            let component_width = width.div_ceil(sampling_x as u32);
+            let dropped = (first_line - self.first_line) / sampling_y as u32 * component_width;
+            buffer.drain(..(dropped as usize).min(buffer.len()));
+        }
+        self.first_line = first_line;
+        Ok(())
+    }
+
+    /// Code precinct `frame_precinct`, found at `window_precinct` in the window `layout`
+    fn code_precinct(
+        &mut self,
+        layout: &precinct::PrecinctLayout,
+        coeffs: &[&[i32]],
+        quantized: &mut [Vec<i32>],
+        window_precinct: usize,
+        frame_precinct: usize,
+    ) -> Result<()> {
+        let header = &self.setup.picture_header;
+        let params = &self.setup.params;
+        let slice_size = header.slice_height.max(1) as usize * header.precincts_per_row();
+        let slice = frame_precinct / slice_size;
+        let precincts = header.slice_precincts(slice);
+
+        if frame_precinct == precincts.start {
+            self.setup.bitstream.write_slice_header(slice as u16);
+            self.slice = self
+                .setup
+                .rate_controller
+                .as_ref()
+                .map(|controller| controller.begin_slice(slice, precincts.len()));
+        }
+
+        let data = match self.slice.as_mut() {
+            Some(encoder) => {
+                let mut data = encoder.push_precinct(layout, coeffs, params, window_precinct)?;
+                if frame_precinct + 1 == precincts.end {
+                    if let Some(encoder) = self.slice.take() {
+                        data.extend(encoder.finish()?);
+                    }
+                }
+                data
+            }
+            None if self.setup.lossless => precinct::encode_precinct(
+                layout,
+                coeffs,
+                window_precinct,
+                params.quantization,
+                params.refinement,
+            )?,
+            None => {
+                for (component, plane) in coeffs.iter().enumerate() {
+                    quant::quantize_precinct(
+                        plane,
+                        &mut quantized[component],
+                        layout,
+                        window_precinct,
+                        params,
+                        component,
+                    )?;
+                }
+                let planes: Vec<&[i32]> = quantized.iter().map(|plane| plane.as_slice()).collect();
+                precinct::encode_precinct(
+                    layout,
+                    &planes,
+                    window_precinct,
+                    params.quantization,
+                    params.refinement,
+                )?
+            }
+        };
+        self.setup.bitstream.write_entropy_coded_data(&data);
+        Ok(())
+    }
+}
+
+/// Decoded lines of a frame, as handed out by [`StreamingDecoder::push`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineBand {
+    /// First image line of the band
+    pub first_line: u32,
+    /// Number of image lines in the band
+    pub num_lines: u32,
+    /// Samples of every component in `0..2^bit_depth`, at the coded size of the
+    /// component: a vertically subsampled component holds `num_lines / 2` lines
+    pub planes: Vec<Vec<u16>>,
+}
+
+/// Decoder taking a codestream in chunks as they arrive
+///
+/// Every call to [`StreamingDecoder::push`] decodes the precincts that are complete and
+/// returns the image lines whose wavelet support has been decoded, top to bottom, so a
+/// frame can be shown before its last byte is in. The lines of all bands together are
+/// exactly the samples [`crate::decode_frame`] reconstructs.
+///
+/// The configuration is interpreted as [`crate::decode_frame`] does, except that a
+/// strict decoder can only check the main header ahead of decoding: the slices are
+/// checked as they are decoded.
+///
+/// # Example
+/// ```rust,ignore
+/// use jpegxs_core::StreamingDecoder;
+///
+/// let mut decoder = StreamingDecoder::new();
+/// for packet in network_packets {
+///     for band in decoder.push(&packet)? {
+///         display(band.first_line, &band.planes);
+///     }
+/// }
+/// assert!(decoder.is_complete());
+/// # Ok::<(), jpegxs_core::JpegXsError>(())
+/// ```
+pub struct StreamingDecoder {
+    config: DecoderConfig,
+    workers: Workers,
+    /// Received bytes not consumed yet
+    data: Vec<u8>,
+    /// Frame state, once the main header is in
+    frame: Option<FrameDecoder>,
+    /// Whether the EOC marker has been read
+    complete: bool,
+    /// Codestream bytes consumed and dropped from `data`
+    position: usize,
+}
+
+/// Decoding state of a frame whose main header has been parsed
+struct FrameDecoder {
+    decoder: JpegXsDecoder,
+    info: FrameInfo,
+    layout: PrecinctLayout,
+    dwt: CodecDwt,
+    /// Next precinct to arrive, in codestream order
+    next_precinct: usize,
+    /// Whether the SLH marker of the slice holding `next_precinct` has been read
+    in_slice: bool,
+    /// Precinct row being received
+    row: Option<PrecinctRow>,
+    /// Received precinct rows from `first_row` on
+    rows: VecDeque<PrecinctRow>,
+    first_row: u32,
+    /// Next precinct row to reconstruct
+    next_row: u32,
+}
+
+/// Planes of a single precinct row, laid out as a frame of its own
+struct PrecinctRow {
+    layout: PrecinctLayout,
+    /// Quantized coefficients, only kept while the row is being received
+    quantized: Vec<Vec<i32>>,
+    /// Dequantized fixed-point coefficients
+    coeffs: Vec<Vec<i32>>,
+}
+
+impl StreamingDecoder {
+    pub fn new() -> Self {
+        Self::with_config(DecoderConfig::default())
+    }
+
+    pub fn with_config(config: DecoderConfig) -> Self {
+        Self {
+            workers: Workers::new(config.threads),
+            config,
+            data: Vec::new(),
+            frame: None,
+            complete: false,
+            position: 0,
+        }
+    }
+
+    pub fn config(&self) -> &DecoderConfig {
+        &self.config
+    }
+
+    /// Frame parameters, available as soon as the main header has arrived
+    pub fn info(&self) -> Option<&FrameInfo> {
+        self.frame.as_ref().map(|frame| &frame.info)
+    }
+
+    /// Whether the whole codestream has been decoded, up to its EOC marker in strict
+    /// mode and up to its last precinct otherwise
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Append the next bytes of the codestream and return the lines now decoded
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<LineBand>> {
+        let strict = self.config.strict_mode;
+        if self.complete {
+            if strict && !bytes.is_empty() {
+                return Err(JpegXsError::Corrupt(
+                    "Unexpected data after the EOC marker".into(),
+                ));
+            }
+            return Ok(Vec::new());
+        }
+        self.data.extend_from_slice(bytes);
+
+        let mut bands = Vec::new();
+        let mut offset = 0;
+        while !self.complete {
+            let data = &self.data[offset..];
+            let Some(frame) = self.frame.as_mut() else {
+                let Some(length) = main_header_length(data)? else {
+                    break;
+                };
+                if strict {
+                    conformance::validate_main_header(data)?;
+                }
+                let header = data[..length].to_vec();
+                self.frame = Some(FrameDecoder::new(header, self.config.backend)?);
+                offset += length;
+                continue;
+            };
+
+            if frame.next_precinct == frame.layout.num_precincts() {
+                // A lenient decoder ignores whatever follows the last precinct
+                if !strict {
+                    offset = self.data.len();
+                    self.complete = true;
+                    break;
+                }
+                if data.len() < 2 {
+                    break;
+                }
+                let marker = u16::from_be_bytes([data[0], data[1]]);
+                if marker != markers::EOC {
+                    return Err(JpegXsError::InvalidMarker {
+                        marker,
+                        offset: self.position + offset,
+                    });
+                }
+                if data.len() > 2 {
+                    return Err(JpegXsError::Corrupt(
+                        "Unexpected data after the EOC marker".into(),
+                    ));
+                }
+                offset += 2;
+                self.complete = true;
+            } else if !frame.in_slice {
+                if let Some(length) = unknown_segment_length(data).filter(|_| !strict) {
+                    if data.len() < length {
+                        break;
+                    }
+                    offset += length;
+                    continue;
+                }
+                if data.len() < jpegxs_core_clean::SLICE_HEADER_SIZE {
+                    break;
+                }
+                frame.read_slice_header(data, self.position + offset)?;
+                offset += jpegxs_core_clean::SLICE_HEADER_SIZE;
+            } else {
+                let Some(length) = precinct::precinct_length(&frame.layout, data) else {
+                    break;
+                };
+                if data.len() < length {
+                    break;
+                }
+                frame.decode_precinct(&data[..length])?;
+                offset += length;
+                bands.extend(frame.reconstruct_ready_rows(&mut self.workers)?);
+            }
+        }
+        self.data.drain(..offset);
+        self.position += offset;
+        Ok(bands)
+    }
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Length of the main header at the start of `data`, `None` until it is complete
+///
+/// The main header runs from SOC up to the first SLH marker, over marker segments that
+/// each give their own length.
+fn main_header_length(data: &[u8]) -> Result<Option<usize>> {
+    if data.len() >= 2 && data[..2] != markers::SOC.to_be_bytes() {
+        return Err(JpegXsError::InvalidMarker {
+            marker: u16::from_be_bytes([data[0], data[1]]),
+            offset: 0,
+        });
+    }
+    for segment in MarkerSegmentIter::new(data) {
+        match segment {
+            Ok((markers::SLH, offset, _)) => return Ok(Some(offset)),
+            Ok((markers::EOC, offset, _)) => {
+                return Err(JpegXsError::InvalidMarker {
+                    marker: markers::EOC,
+                    offset,
+                })
+            }
+            Ok(_) => {}
+            Err(ParseError::Truncated(_)) => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Length of the marker segment ahead of a slice at the start of `data`, when it is
+/// neither SLH nor EOC
+///
+/// A lenient decoder skips these segments by their length field, like
+/// [`JpegXsDecoder::set_tolerant`] does.
+fn unknown_segment_length(data: &[u8]) -> Option<usize> {
+    let [0xff, code, high, low, ..] = *data else {
+        return None;
+    };
+    let marker = u16::from_be_bytes([0xff, code]);
+    let length = u16::from_be_bytes([high, low]) as usize;
+    (marker != markers::SLH && marker != markers::EOC && length >= 2).then_some(2 + length)
+}
+
+impl FrameDecoder {
+    fn new(header: Vec<u8>, backend: AccelPreference) -> Result<Self> {
+        let (decoder, info) = crate::parse_main_header_data(header)?;
+        let picture_header = decoder.picture_header();
+        let layout = PrecinctLayout::with_sampling(
+            info.width,
+            info.height,
+            picture_header.decomposition_levels_x,
+            picture_header.decomposition_levels_y,
+            &info.sampling,
+            picture_header.precinct_width,
+        )?;
+        crate::check_band_weights(&decoder, &layout)?;
+        Ok(Self {
+            decoder,
+            info,
+            layout,
+            dwt: CodecDwt::select(backend)?,
+            next_precinct: 0,
+            in_slice: false,
+            row: None,
+            rows: VecDeque::new(),
+            first_row: 0,
+            next_row: 0,
+        })
+    }
+
+    fn precinct_lines(&self) -> u32 {
+        1 << self.decoder.picture_header().decomposition_levels_y
+    }
+
+    /// Layout of a strip of `lines` image lines, coded like the frame
+    fn strip_layout(&self, lines: u32) -> Result<PrecinctLayout> {
+        let header = self.decoder.picture_header();
+        PrecinctLayout::with_sampling(
+            self.info.width,
+            lines,
+            header.decomposition_levels_x,
+            header.decomposition_levels_y,
+            &self.info.sampling,
+            header.precinct_width,
+        )
+    }
+
+    /// Read the SLH marker segment at the start of `data`, found at codestream `offset`
+    fn read_slice_header(&mut self, data: &[u8], offset: usize) -> Result<()> {
+        let index = jpegxs_core_clean::read_slice_header(data).map_err(|error| error.at(offset))?;
+        let slice = self.next_precinct / self.slice_size();
+        if index as usize != slice {
+            return Err(JpegXsError::Corrupt(format!(
+                "Expected slice {}, found slice {}",
+                slice, index
+            )));
+        }
+        self.in_slice = true;
+        Ok(())
+    }
+
+    fn slice_size(&self) -> usize {
+        let header = self.decoder.picture_header();
+        header.slice_height.max(1) as usize * header.precincts_per_row()
+    }
+
+    /// Decode and dequantize the next precinct, whose coded bytes are `data`
+    fn decode_precinct(&mut self, data: &[u8]) -> Result<()> {
+        let precincts_per_row = self.layout.precincts_per_row();
+        let row = (self.next_precinct / precincts_per_row) as u32;
+        let column = self.next_precinct % precincts_per_row;
+
+        // Every precinct row is decoded into planes of its own
+        if column == 0 {
+            let lines = self
+                .precinct_lines()
+                .min(self.info.height - row * self.precinct_lines());
+            let layout = self.strip_layout(lines)?;
+            let planes: Vec<Vec<i32>> = (0..layout.num_components())
+                .map(|component| vec![0i32; layout.plane_size(component)])
+                .collect();
+            self.row = Some(PrecinctRow {
+                layout,
+                quantized: planes.clone(),
+                coeffs: planes,
+            });
+        }
+        let Some(current) = self.row.as_mut() else {
+            return Err(JpegXsError::Corrupt(format!(
+                "Precinct row {} not started",
+                row
+            )));
+        };
+
+        let layout = &current.layout;
+        let (header, _) = precinct::decode_precinct(layout, data, &mut current.quantized, column)?;
+        let params = crate::band_params(&self.decoder, &header)?;
+        let wavelet_precision = self.decoder.picture_header().wavelet_precision;
+        for (component, (quantized, coeffs)) in current
+            .quantized
+            .iter()
+            .zip(&mut current.coeffs)
+            .enumerate()
+        {
+            quant::dequantize_precinct(
+                quantized,
+                coeffs,
+                layout,
+                column,
+                &params,
+                component,
+                wavelet_precision,
+            )?;
+        }
+
+        self.next_precinct += 1;
+        if column + 1 == precincts_per_row {
+            if let Some(mut done) = self.row.take() {
+                done.quantized = Vec::new();
+                self.rows.push_back(done);
+            }
+        }
+        if self.next_precinct.is_multiple_of(self.slice_size())
+            || self.next_precinct == self.layout.num_precincts()
+        {
+            self.in_slice = false;
+        }
+        Ok(())
+    }
+
+    /// Reconstruct every precinct row whose wavelet support has been decoded
+    fn reconstruct_ready_rows(&mut self, workers: &mut Workers) -> Result<Option<LineBand>> {
+        let precinct_lines = self.precinct_lines();
+        let num_rows = self.info.height.div_ceil(precinct_lines);
+        let decoded_rows = self.first_row + self.rows.len() as u32;
+        let ready_rows = if decoded_rows == num_rows {
+            num_rows
+        } else {
+            decoded_rows.saturating_sub(SUPPORT_ROWS)
+        };
+        if ready_rows <= self.next_row {
+            return Ok(None);
+        }
+
+        // Gather the coefficients of the window rows, starting SUPPORT_ROWS above the next
+        // row and reaching SUPPORT_ROWS below the last row to reconstruct
+        let window_rows = (ready_rows + SUPPORT_ROWS).min(decoded_rows) - self.first_row;
+        let window_lines = ((self.first_row + window_rows) * precinct_lines).min(self.info.height)
+            - self.first_row * precinct_lines;
+        let window = self.strip_layout(window_lines)?;
+        let precincts_per_row = window.precincts_per_row();
+        let mut planes: Vec<Vec<i32>> = (0..window.num_components())
+            .map(|component| vec![0i32; window.plane_size(component)])
+            .collect();
+        for (row, received) in self.rows.iter().take(window_rows as usize).enumerate() {
+            for column in 0..precincts_per_row {
+                precinct::copy_precinct_lines(
+                    &received.layout,
+                    &received.coeffs,
+                    column,
+                    &window,
+                    &mut planes,
+                    row * precincts_per_row + column,
+                );
+            }
+        }
+
+        // Inverse transform of the window, keeping the lines of the ready rows
+        let header = self.decoder.picture_header();
+        let first_line = self.next_row * precinct_lines;
+        let end_line = (ready_rows * precinct_lines).min(self.info.height);
+        let skipped_lines = first_line - self.first_row * precinct_lines;
+        let (dwt, info, window) = (self.dwt, &self.info, &window);
+        let mut samples = workers.install(|| {
+            planes
+                .par_iter()
+                .enumerate()
+                .map(|(component, coeffs)| {
+                    let (width, height) = window.component_size(component);
+                    let mut plane = vec![0i32; coeffs.len()];
+                    dwt.inverse_multilevel(
+                        coeffs,
+                        &mut plane,
+                        width,
+                        height,
+                        header.decomposition_levels_x,
+                        window.component_levels_y(component),
+                    )?;
+                    let sampling_y = info.sampling[component].1 as u32;
+                    let start = (skipped_lines / sampling_y * width) as usize;
+                    let end =
+                        (end_line - first_line).div_ceil(sampling_y) as usize * width as usize;
+                    plane.drain(..start);
+                    plane.truncate(end);
+                    Ok(plane)
+                })
+                .collect::<Result<Vec<_>>>()
+        })??;
+        crate::restore_samples(&mut samples, header.fractional_bits, &self.info)?;
+
+        // Drop the rows that no later window reaches
+        self.next_row = ready_rows;
+        let first_row = self.next_row.saturating_sub(SUPPORT_ROWS);
+        self.rows.drain(..(first_row - self.first_row) as usize);
+        self.first_row = first_row;
+
+        Ok(Some(LineBand {
+            first_line,
+            num_lines: end_line - first_line,
+            planes: samples
+                .into_iter()
+                .map(|plane| plane.into_iter().map(|sample| sample as u16).collect())
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Profile, RateTarget};
+
+    fn test_frame(width: u32, height: u32, format: PixelFormat) -> Vec<u8> {
+        let (sampling_x, sampling_y) = format.chroma_sampling();
+        let luma = (width * height) as usize;
+        let chroma = (width / sampling_x as u32 * height / sampling_y as u32) as usize;
+        let len = match format.num_components() {
+            1 => luma,
+            4 => luma * 4,
+            _ => luma + 2 * chroma,
+        };
+        (0..len)
+            .map(|i| ((i * 37 + (i / width as usize) * 11) % 251) as u8)
+            .collect()
+    }
+
+    /// Push `data` in strips of `strip_lines` lines and collect the codestream
+    fn encode_streaming(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        config: &EncoderConfig,
+        strip_lines: u32,
+    ) -> Result<Vec<u8>> {
+        let mut encoder = StreamingEncoder::new(width, height, format, config)?;
+        let mut codestream = Vec::new();
+        let mut line = 0;
+        while line < height {
+            let lines = strip_lines.min(height - line);
+            let strip = strip(data, width, height, format, line, lines);
+            codestream.extend(encoder.push_lines(&strip, lines)?);
+            line += lines;
+        }
+        codestream.extend(encoder.finish()?);
+        Ok(codestream)
+    }
+
+    /// Lines `first..first + lines` of a frame, every plane cut separately
+    fn strip(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        first: u32,
+        lines: u32,
+    ) -> Vec<u8> {
+        let (sampling_x, sampling_y) = format.chroma_sampling();
+        let mut planes: Vec<(usize, u32)> = match format {
+            PixelFormat::Rgb8 | PixelFormat::Bgr8 => vec![(width as usize * 3, 1)],
+            PixelFormat::Rgba8 => vec![(width as usize * 4, 1)],
+            _ => vec![(width as usize, 1)],
+        };
+        if planes[0].0 == width as usize {
+            let chroma = (width / sampling_x as u32) as usize;
+            for component in 1..format.num_components() as usize {
+                planes.push(match component {
+                    1 | 2 => (chroma, sampling_y as u32),
+                    _ => (width as usize, 1),
+                });
+            }
+        }
+
+        let mut strip = Vec::new();
+        let mut offset = 0;
+        for (row_len, sampling_y) in planes {
+            let rows = (height / sampling_y) as usize;
+            let plane = &data[offset..offset + rows * row_len];
+            let (start, end) = (
+                (first / sampling_y) as usize,
+                ((first + lines) / sampling_y) as usize,
+            );
+            strip.extend_from_slice(&plane[start * row_len..end * row_len]);
+            offset += rows * row_len;
+        }
+        strip
+    }
+
+    const FORMATS: [PixelFormat; 6] = [
+        PixelFormat::Yuv444p8,
+        PixelFormat::Yuv422p8,
+        PixelFormat::Yuv420p8,
+        PixelFormat::Rgb8,
+        PixelFormat::Gray8,
+        PixelFormat::Rgba8,
+    ];
+
+    /// Configurations covering several decomposition depths, precinct columns, slices,
+    /// lossless coding and rate control, unrestricted so that 4:2:0 input is admitted
+    fn test_configs() -> [EncoderConfig; 4] {
+        [
+            EncoderConfig {
+                profile: Profile::Unrestricted,
+                ..Default::default()
+            },
+            EncoderConfig {
+                decomposition_levels_x: 3,
+                decomposition_levels_y: 2,
+                slice_height: 1,
+                profile: Profile::Unrestricted,
+                ..Default::default()
+            },
+            EncoderConfig {
+                decomposition_levels_x: 4,
+                decomposition_levels_y: 3,
+                precinct_width: 1,
+                lossless: true,
+                profile: Profile::Unrestricted,
+                ..Default::default()
+            },
+            EncoderConfig {
+                decomposition_levels_x: 5,
+                decomposition_levels_y: 2,
+                slice_height: 2,
+                rate_target: Some(RateTarget::BitsPerPixel(4.0)),
+                profile: Profile::Unrestricted,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_streaming_matches_frame_encoder() {
+        let (width, height) = (40u32, 38u32);
+        for format in FORMATS {
+            let data = test_frame(width, height, format);
+            let input = ImageView8 {
+                data: &data,
+                width,
+                height,
+                format,
+            };
+            for config in &test_configs() {
+                let frame = crate::encode_frame(input, config).unwrap();
+                for strip_lines in [2, 6, 16, height] {
+                    let streamed =
+                        encode_streaming(&data, width, height, format, config, strip_lines)
+                            .unwrap();
+                    assert!(
+                        streamed == frame.data,
+                        "{:?} with {:?} in strips of {} lines",
+                        format,
+                        config,
+                        strip_lines
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_keeps_a_few_precinct_rows() {
+        let (width, height) = (64u32, 128u32);
+        let config = EncoderConfig {
+            decomposition_levels_x: 2,
+            decomposition_levels_y: 2,
+            ..Default::default()
+        };
+        let data = test_frame(width, height, PixelFormat::Gray8);
+        let mut encoder =
+            StreamingEncoder::new(width, height, PixelFormat::Gray8, &config).unwrap();
+
+        let precinct_lines = 4;
+        let mut emitted = Vec::new();
+        for (line, row) in data.chunks(width as usize).enumerate() {
+            emitted.push(encoder.push_lines(row, 1).unwrap().len());
+            let buffered = encoder.lines[0].len() / width as usize;
+            assert!(buffered <= ((2 * SUPPORT_ROWS + 1) * precinct_lines) as usize);
+
+            // Precincts leave as soon as the lines below them are in
+            if line as u32 >= (SUPPORT_ROWS + 1) * precinct_lines {
+                assert!(emitted.iter().skip(1).sum::<usize>() > 0);
+            }
+        }
+        assert!(encoder.finish().unwrap().len() == 2);
+    }
+
+    #[test]
+    fn test_streaming_rejects_misuse() {
+        let config = EncoderConfig {
+            profile: Profile::Unrestricted,
+            ..Default::default()
+        };
+        assert!(StreamingEncoder::new(16, 16, PixelFormat::Gray16, &config).is_err());
+        assert!(StreamingEncoder::new(16, 15, PixelFormat::Yuv420p8, &config).is_err());
+
+        let mut encoder = StreamingEncoder::new(16, 4, PixelFormat::Yuv420p8, &config).unwrap();
+        assert!(encoder.push_lines(&[0; 24], 1).is_err());
+        assert!(encoder.push_lines(&[0; 48], 2).is_ok());
+        assert!(encoder.push_lines(&[0; 96], 4).is_err());
+        assert!(encoder.finish().is_err());
+    }
+
+    /// Push `codestream` in chunks of `chunk` bytes and stitch the bands together
+    fn decode_streaming(
+        codestream: &[u8],
+        chunk: usize,
+        config: &DecoderConfig,
+    ) -> Result<(FrameInfo, Vec<Vec<i32>>)> {
+        let mut decoder = StreamingDecoder::with_config(config.clone());
+        let mut bands = Vec::new();
+        for bytes in codestream.chunks(chunk) {
+            bands.extend(decoder.push(bytes)?);
+        }
+        assert!(decoder.is_complete());
+        let info = decoder.info().unwrap().clone();
+
+        let mut planes = vec![Vec::new(); info.num_components as usize];
+        let mut next_line = 0;
+        for band in bands {
+            assert_eq!(band.first_line, next_line);
+            next_line += band.num_lines;
+            for (plane, lines) in planes.iter_mut().zip(band.planes) {
+                plane.extend(lines.into_iter().map(i32::from));
+            }
+        }
+        assert_eq!(next_line, info.height);
+        Ok((info, planes))
+    }
+
+    #[test]
+    fn test_streaming_decoder_matches_frame_decoder() {
+        let (width, height) = (40u32, 38u32);
+        for format in FORMATS {
+            let data = test_frame(width, height, format);
+            let input = ImageView8 {
+                data: &data,
+                width,
+                height,
+                format,
+            };
+            for config in &test_configs() {
+                let bitstream = crate::encode_frame(input, config).unwrap();
+                let mut frame = crate::DecodeScratch::default();
+                let frame_info =
+                    crate::decode_components_into(&bitstream, &mut frame, CodecDwt::Scalar, true)
+                        .unwrap();
+                for chunk in [1, 13, 256, bitstream.data.len()] {
+                    let (info, planes) =
+                        decode_streaming(&bitstream.data, chunk, &DecoderConfig::default())
+                            .unwrap();
+                    assert_eq!(info, frame_info);
+                    assert!(
+                        planes == frame.planes,
+                        "{:?} with {:?} in chunks of {} bytes",
+                        format,
+                        config,
+                        chunk
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_decoder_starts_before_the_last_byte() {
+        let (width, height) = (64u32, 128u32);
+        let data = test_frame(width, height, PixelFormat::Gray8);
+        let config = EncoderConfig {
+            decomposition_levels_x: 2,
+            decomposition_levels_y: 2,
+            ..Default::default()
+        };
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format: PixelFormat::Gray8,
+        };
+        let codestream = crate::encode_frame(input, &config).unwrap().data;
+
+        let mut decoder = StreamingDecoder::new();
+        let half = codestream.len() / 2;
+        let bands = decoder.push(&codestream[..half]).unwrap();
+        assert_eq!(decoder.info().unwrap().height, height);
+        assert!(bands.iter().map(|band| band.num_lines).sum::<u32>() >= height / 4);
+        assert!(decoder.frame.as_ref().unwrap().rows.len() <= 2 * SUPPORT_ROWS as usize + 1);
+        assert!(!decoder.is_complete());
+
+        decoder.push(&codestream[half..]).unwrap();
+        assert!(decoder.is_complete());
+    }
+
+    #[test]
+    fn test_streaming_decoder_config() {
+        let (width, height) = (48u32, 40u32);
+        let data = test_frame(width, height, PixelFormat::Yuv422p8);
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format: PixelFormat::Yuv422p8,
+        };
+        let bitstream = crate::encode_frame(input, &EncoderConfig::default()).unwrap();
+        let (_, expected) =
+            decode_streaming(&bitstream.data, 64, &DecoderConfig::default()).unwrap();
+        for threads in [1, 3] {
+            let config = DecoderConfig {
+                strict_mode: true,
+                threads,
+                backend: AccelPreference::Scalar,
+            };
+            let (_, planes) = decode_streaming(&bitstream.data, 64, &config).unwrap();
+            assert!(planes == expected, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_streaming_decoder_rejects_invalid_data() {
+        let mut decoder = StreamingDecoder::new();
+        assert!(decoder.push(&[0xff, 0x11]).is_err());
+
+        // Bytes after the EOC marker
+        let data = test_frame(16, 8, PixelFormat::Gray8);
+        let input = ImageView8 {
+            data: &data,
+            width: 16,
+            height: 8,
+            format: PixelFormat::Gray8,
+        };
+        let codestream = crate::encode_frame(input, &EncoderConfig::default())
+            .unwrap()
+            .data;
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        let mut trailing = codestream.clone();
+        trailing.push(0);
+        assert!(StreamingDecoder::with_config(strict.clone())
+            .push(&trailing)
+            .is_err());
+        let mut decoder = StreamingDecoder::with_config(strict.clone());
+        decoder.push(&codestream).unwrap();
+        assert!(decoder.push(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_lenient_streaming_decoder_skips_unknown_segments() {
+        let (width, height) = (16u32, 64u32);
+        let data = test_frame(width, height, PixelFormat::Gray8);
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format: PixelFormat::Gray8,
+        };
+        let codestream = crate::encode_frame(input, &EncoderConfig::default())
+            .unwrap()
+            .data;
+        let (_, expected) = decode_streaming(&codestream, 7, &DecoderConfig::default()).unwrap();
+
+        // An unknown marker segment ahead of the second slice, and data after EOC
+        let slh = markers::SLH.to_be_bytes();
+        let second_slice = codestream
+            .windows(6)
+            .position(|bytes| bytes == [slh[0], slh[1], 0, 4, 0, 1])
+            .unwrap();
+        let mut modified = codestream[..second_slice].to_vec();
+        modified.extend_from_slice(&[0xff, 0x5f, 0x00, 0x05, 1, 2, 3]);
+        modified.extend_from_slice(&codestream[second_slice..]);
+        modified.extend_from_slice(&[0, 1, 2]);
+
+        for chunk in [1, 5, modified.len()] {
+            let (_, planes) =
+                decode_streaming(&modified, chunk, &DecoderConfig::default()).unwrap();
+            assert!(planes == expected, "chunks of {} bytes", chunk);
+        }
+        let strict = DecoderConfig {
+            strict_mode: true,
+            ..Default::default()
+        };
+        assert!(StreamingDecoder::with_config(strict)
+            .push(&modified)
+            .is_err());
+    }
+}
@@ -1,3 +1,5 @@
+pub use crate::accel::AccelPreference;
+use crate::profile::ProfileLevel;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy)]
@@ -16,6 +18,25 @@ pub struct ImageOwned8 {
     pub format: PixelFormat,
 }
 
+/// Image with more than 8 bits per sample, one `u16` per sample
+///
+/// Samples hold the value in their low [`PixelFormat::bit_depth`] bits.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView16<'a> {
+    pub data: &'a [u16],
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageOwned16 {
+    pub data: Vec<u16>,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PixelFormat {
     Yuv422p8,
@@ -24,6 +45,90 @@ pub enum PixelFormat {
     Bgr8,
     Rgb8Planar,
     Yuv420p8,
+    /// Planar YUV 4:2:2, 10 bits per sample
+    Yuv422p10le,
+    /// Planar YUV 4:4:4, 12 bits per sample
+    Yuv444p12le,
+    /// Interleaved RGB, 16 bits per sample
+    Rgb16,
+    /// Single luma / intensity plane, 8 bits per sample
+    Gray8,
+    /// Single luma / intensity plane, 16 bits per sample
+    Gray16,
+    /// Interleaved RGB with alpha, 8 bits per sample
+    Rgba8,
+    /// Planar YUV 4:4:4 followed by a full resolution alpha plane, 8 bits per sample
+    Yuva444p8,
+}
+
+impl PixelFormat {
+    /// Number of coded components, Nc in the PIH marker
+    pub fn num_components(self) -> u8 {
+        match self {
+            PixelFormat::Gray8 | PixelFormat::Gray16 => 1,
+            PixelFormat::Rgba8 | PixelFormat::Yuva444p8 => 4,
+            _ => 3,
+        }
+    }
+
+    /// Chroma sampling factors (sx, sy) of the second and third component
+    pub fn chroma_sampling(self) -> (u8, u8) {
+        match self {
+            PixelFormat::Yuv422p8 | PixelFormat::Yuv422p10le => (2, 1),
+            PixelFormat::Yuv420p8 => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// Bits per sample, B[c] in the CDT marker
+    pub fn bit_depth(self) -> u8 {
+        match self {
+            PixelFormat::Yuv422p10le => 10,
+            PixelFormat::Yuv444p12le => 12,
+            PixelFormat::Rgb16 | PixelFormat::Gray16 => 16,
+            _ => 8,
+        }
+    }
+
+    /// Whether the samples are R, G, B rather than Y, U, V
+    pub fn is_rgb(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Rgb8
+                | PixelFormat::Bgr8
+                | PixelFormat::Rgb8Planar
+                | PixelFormat::Rgb16
+                | PixelFormat::Rgba8
+        )
+    }
+}
+
+/// Coding parameters of a codestream, as found in its main header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Nc from the PIH marker
+    pub num_components: u8,
+    /// B[c] from the CDT marker, shared by all components
+    pub bit_depth: u8,
+    /// Sampling factors (sx, sy) of every component from the CDT marker
+    pub sampling: Vec<(u8, u8)>,
+    /// Whether the first three components are R, G, B coded with the RCT (Cpih = 1)
+    pub color_transform: bool,
+    /// Pixel format holding the components as coded, if there is one
+    pub format: Option<PixelFormat>,
+    /// Profile, level and sublevel from the PIH marker, which the frame stays within
+    pub profile_level: ProfileLevel,
+    /// COM marker segments of the main header
+    pub metadata: CodestreamMetadata,
+}
+
+impl FrameInfo {
+    /// Sampling factors (sx, sy) of the second and third component, (1, 1) without them
+    pub fn chroma_sampling(&self) -> (u8, u8) {
+        self.sampling.get(1).copied().unwrap_or((1, 1))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,14 +140,145 @@ pub struct Bitstream {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncoderConfig {
     pub quality: f32,
+    /// Profile signalled in the PIH marker (Ppih in ISO/IEC 21122-2)
     pub profile: Profile,
+    /// Level signalled in the PIH marker (Plev); frames beyond its resolution, or beyond
+    /// its sample rate at the frame rate of a bitrate target, are rejected
     pub level: Level,
+    /// Number of horizontal wavelet decompositions (NL,x in ISO/IEC 21122-1)
+    #[serde(default = "default_decomposition_levels_x")]
+    pub decomposition_levels_x: u8,
+    /// Number of vertical wavelet decompositions (NL,y in ISO/IEC 21122-1)
+    #[serde(default = "default_decomposition_levels_y")]
+    pub decomposition_levels_y: u8,
+    /// Height of a slice in precincts (Hsl in ISO/IEC 21122-1), each slice gets an SLH marker
+    #[serde(default = "default_slice_height")]
+    pub slice_height: u16,
+    /// Width of a precinct in units of 8 LL band columns (Cw in ISO/IEC 21122-1),
+    /// 0 for precincts as wide as the image
+    #[serde(default)]
+    pub precinct_width: u16,
+    /// Constant-size output: when set, rate control picks each precinct's quantization
+    /// to fill this budget exactly and `quality` is ignored
+    #[serde(default)]
+    pub rate_target: Option<RateTarget>,
+    /// Mathematically lossless coding: integer 5/3 transform without quantization, so
+    /// decoding restores every sample exactly; `quality` is ignored
+    #[serde(default)]
+    pub lossless: bool,
+    /// Worker threads for the components and slices of a frame, 0 for rayon's global
+    /// pool; the codestream does not depend on it
+    #[serde(default)]
+    pub threads: usize,
+    /// Back-end of the wavelet transform, overridden by `JPEGXS_ACCEL`; the codestream
+    /// does not depend on it
+    #[serde(default)]
+    pub backend: AccelPreference,
+    /// Comments written into the main header of every frame
+    #[serde(default)]
+    pub metadata: CodestreamMetadata,
+}
+
+/// Size target for rate-controlled encoding
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RateTarget {
+    /// Coded bits per picture pixel, headers included
+    BitsPerPixel(f32),
+    /// Stream bitrate in megabits per second at the given frame rate
+    Mbps { mbps: f32, frame_rate: f32 },
+}
+
+/// Informative data a codestream carries beside the picture
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodestreamMetadata {
+    /// One COM marker segment each, in codestream order
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+}
+
+impl CodestreamMetadata {
+    /// The data of every text comment that is valid UTF-8
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.comments.iter().filter_map(Comment::as_text)
+    }
+}
+
+/// Contents of a COM marker segment (ISO/IEC 21122-1 A.4.11)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// At most 65531 bytes, what remains of the segment after Lcom and Rcom
+    pub data: Vec<u8>,
+}
+
+impl Comment {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            kind: CommentKind::Text,
+            data: text.into().into_bytes(),
+        }
+    }
+
+    pub fn binary(data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind: CommentKind::Binary,
+            data: data.into(),
+        }
+    }
+
+    /// The data of a text comment, if it is valid UTF-8
+    pub fn as_text(&self) -> Option<&str> {
+        match self.kind {
+            CommentKind::Text => std::str::from_utf8(&self.data).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Type of a comment, the Rcom field of its COM marker segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommentKind {
+    /// Rcom = 0: vendor-specific binary data
+    Binary,
+    /// Rcom = 1: text, UTF-8 for the comments this encoder writes
+    Text,
+    /// Any other Rcom value
+    Other(u16),
+}
+
+impl CommentKind {
+    pub fn rcom(self) -> u16 {
+        match self {
+            CommentKind::Binary => 0,
+            CommentKind::Text => 1,
+            CommentKind::Other(rcom) => rcom,
+        }
+    }
+
+    pub fn from_rcom(rcom: u16) -> Self {
+        match rcom {
+            0 => CommentKind::Binary,
+            1 => CommentKind::Text,
+            rcom => CommentKind::Other(rcom),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DecoderConfig {
+    /// Check the whole codestream against ISO/IEC 21122-1 before decoding it, and reject
+    /// it at the first violation. Otherwise unknown marker segments ahead of and between
+    /// the slices are skipped, and a missing EOC or data after it is accepted
     #[serde(default)]
     pub strict_mode: bool,
+    /// Worker threads for the components and slices of a frame, 0 for rayon's global
+    /// pool; the decoded samples do not depend on it
+    #[serde(default)]
+    pub threads: usize,
+    /// Back-end of the wavelet transform, overridden by `JPEGXS_ACCEL`; the decoded
+    /// samples do not depend on it
+    #[serde(default)]
+    pub backend: AccelPreference,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -50,6 +286,9 @@ pub enum Profile {
     Light,
     Main,
     High,
+    /// No profile restriction, signalled as Ppih = 0: every component layout the codec
+    /// supports, such as 4:2:0 chroma or 16-bit samples, within the limits of the level
+    Unrestricted,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -58,6 +297,8 @@ pub enum Level {
     Level2,
     Level3,
     Level4,
+    /// Beyond every level of ISO/IEC 21122-2, so no codestream can signal it and the
+    /// encoder rejects it
     Level5,
 }
 
@@ -67,6 +308,37 @@ impl Default for EncoderConfig {
             quality: 0.9,
             profile: Profile::Main,
             level: Level::Level1,
+            decomposition_levels_x: default_decomposition_levels_x(),
+            decomposition_levels_y: default_decomposition_levels_y(),
+            slice_height: default_slice_height(),
+            precinct_width: 0,
+            rate_target: None,
+            lossless: false,
+            threads: 0,
+            backend: AccelPreference::default(),
+            metadata: CodestreamMetadata::default(),
         }
     }
 }
+
+impl EncoderConfig {
+    /// Slice height in precincts giving 16-line slices, each precinct covering
+    /// 2^NL,y lines
+    pub fn slice_height_for_levels(decomposition_levels_y: u8) -> u16 {
+        16 >> decomposition_levels_y.min(4)
+    }
+}
+
+/// Five horizontal decompositions, the usual NL,x of ISO/IEC 21122-2 configurations
+fn default_decomposition_levels_x() -> u8 {
+    5
+}
+
+/// Two vertical decompositions keep precincts at four lines for low latency
+fn default_decomposition_levels_y() -> u8 {
+    2
+}
+
+fn default_slice_height() -> u16 {
+    EncoderConfig::slice_height_for_levels(default_decomposition_levels_y())
+}
@@ -0,0 +1,526 @@
+// Portable GPU DWT implementation on wgpu (Vulkan, Metal, DX12 or OpenGL ES)
+// Runs the 5/3 lifting kernels as compute shaders on any adapter wgpu finds,
+// including software rasterizers (lavapipe, llvmpipe) on GPU-less machines
+//
+// Only the f32 transforms of `AccelDwt` run here. `encode_frame` and `decode_frame`
+// use the integer transform of `CodecDwt`, which has no GPU back-end, so the codec
+// itself never reaches this module.
+
+use crate::error::{JpegXsError, Result};
+
+/// Invocations per workgroup of the lifting kernels, one row or column each
+const WORKGROUP_SIZE: u32 = 64;
+
+/// WGSL lifting kernels, the same arithmetic as [`crate::dwt::dwt_53_forward_1d`] and
+/// [`crate::dwt::dwt_53_inverse_1d`]
+///
+/// Divisions by 2 and 4 are written as multiplications by 0.5 and 0.25, which are
+/// exact in f32, so the kernels match the scalar transform bit for bit.
+const DWT_SHADER: &str = r#"
+struct Params {
+    width: u32,
+    height: u32,
+    vertical: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<storage, read> src: array<f32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<f32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+// Number of lines of the pass: rows, or columns for the vertical pass
+fn line_count() -> u32 {
+    if (params.vertical != 0u) {
+        return params.width;
+    }
+    return params.height;
+}
+
+// First sample, distance between samples and length of a line
+fn line_layout(line: u32) -> vec3<u32> {
+    if (params.vertical != 0u) {
+        return vec3<u32>(line, params.width, params.height);
+    }
+    return vec3<u32>(line * params.width, 1u, params.width);
+}
+
+// Symmetric extension of the right boundary, X[Z] = X[Z - 2]
+fn mirror_right(index: u32, len: u32) -> u32 {
+    if (index >= len) {
+        return len - 2u;
+    }
+    return index;
+}
+
+// Forward 5/3 lifting of one line into the separated low-pass / high-pass layout
+@compute @workgroup_size(64)
+fn forward(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= line_count()) {
+        return;
+    }
+    let line = line_layout(id.x);
+    let base = line.x;
+    let stride = line.y;
+    let len = line.z;
+    if (len < 2u) {
+        for (var i = 0u; i < len; i++) {
+            dst[base + i * stride] = src[base + i * stride];
+        }
+        return;
+    }
+    let low_len = (len + 1u) / 2u;
+    let high_len = len / 2u;
+
+    // Predict step: odd samples become the high-pass half
+    for (var k = 0u; k < high_len; k++) {
+        let i = 2u * k + 1u;
+        let left = src[base + (i - 1u) * stride];
+        let right = src[base + mirror_right(i + 1u, len) * stride];
+        dst[base + (low_len + k) * stride] = src[base + i * stride] - (left + right) * 0.5;
+    }
+
+    // Update step: even samples become the low-pass half
+    for (var k = 0u; k < low_len; k++) {
+        var left = 0.0;
+        if (k > 0u) {
+            left = dst[base + (low_len + k - 1u) * stride];
+        }
+        var right = 0.0;
+        if (k < high_len) {
+            right = dst[base + (low_len + k) * stride];
+        }
+        dst[base + k * stride] = src[base + 2u * k * stride] + floor((left + right + 2.0) * 0.25);
+    }
+}
+
+// Inverse 5/3 lifting of one separated line back into interleaved samples
+@compute @workgroup_size(64)
+fn inverse(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= line_count()) {
+        return;
+    }
+    let line = line_layout(id.x);
+    let base = line.x;
+    let stride = line.y;
+    let len = line.z;
+    if (len < 2u) {
+        for (var i = 0u; i < len; i++) {
+            dst[base + i * stride] = src[base + i * stride];
+        }
+        return;
+    }
+    let low_len = (len + 1u) / 2u;
+    let high_len = len / 2u;
+
+    // Undo the update step: the low-pass half restores the even samples
+    for (var k = 0u; k < low_len; k++) {
+        var left = 0.0;
+        if (k > 0u) {
+            left = src[base + (low_len + k - 1u) * stride];
+        }
+        var right = 0.0;
+        if (k < high_len) {
+            right = src[base + (low_len + k) * stride];
+        }
+        dst[base + 2u * k * stride] = src[base + k * stride] - floor((left + right + 2.0) * 0.25);
+    }
+
+    // Undo the predict step: the high-pass half restores the odd samples
+    for (var k = 0u; k < high_len; k++) {
+        let i = 2u * k + 1u;
+        let left = dst[base + (i - 1u) * stride];
+        let right = dst[base + mirror_right(i + 1u, len) * stride];
+        dst[base + i * stride] = src[base + (low_len + k) * stride] + (left + right) * 0.5;
+    }
+}
+"#;
+
+/// Device, queue and compiled kernels of an adapter
+struct WgpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    info: wgpu::AdapterInfo,
+    bind_group_layout: wgpu::BindGroupLayout,
+    forward: wgpu::ComputePipeline,
+    inverse: wgpu::ComputePipeline,
+}
+
+/// Direction of a lifting pass over the image
+#[derive(Clone, Copy)]
+enum Pass {
+    Rows,
+    Columns,
+}
+
+/// Portable GPU DWT interface on wgpu
+///
+/// Unlike [`crate::gpu_dwt::GpuDwt`] it is not tied to Metal: it takes whichever
+/// adapter wgpu reports, and [`WgpuDwt::with_software_adapter`] forces a software
+/// one so the GPU path can be tested on machines without a GPU.
+pub struct WgpuDwt {
+    context: Option<WgpuContext>,
+}
+
+impl WgpuDwt {
+    /// Initialize on the best adapter, falling back to a software adapter
+    pub fn new() -> Self {
+        let context = WgpuContext::request(false).or_else(|| WgpuContext::request(true));
+        Self::from_context(context)
+    }
+
+    /// Initialize on a software adapter only, e.g. lavapipe or llvmpipe
+    pub fn with_software_adapter() -> Self {
+        Self::from_context(WgpuContext::request(true))
+    }
+
+    fn from_context(context: Option<WgpuContext>) -> Self {
+        match &context {
+            Some(context) => log::info!(
+                "wgpu DWT: using {} ({:?}, {:?})",
+                context.info.name,
+                context.info.backend,
+                context.info.device_type
+            ),
+            None => log::info!("wgpu DWT: no compute adapter found, using CPU implementation"),
+        }
+        Self { context }
+    }
+
+    /// Check if an adapter is available
+    pub fn is_available(&self) -> bool {
+        self.context.is_some()
+    }
+
+    /// Name of the adapter in use, if any
+    pub fn adapter_name(&self) -> Option<&str> {
+        self.context
+            .as_ref()
+            .map(|context| context.info.name.as_str())
+    }
+
+    /// Whether the adapter is a software implementation running on the CPU
+    pub fn is_software(&self) -> bool {
+        self.context
+            .as_ref()
+            .is_some_and(|context| context.info.device_type == wgpu::DeviceType::Cpu)
+    }
+
+    /// GPU 2D DWT forward transform, rows then columns like [`crate::dwt::dwt_53_forward_2d`]
+    pub fn dwt_53_forward_2d_wgpu(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        match &self.context {
+            Some(context) => context.run(
+                &context.forward,
+                [Pass::Rows, Pass::Columns],
+                input,
+                output,
+                width,
+                height,
+            ),
+            None => crate::dwt::dwt_53_forward_2d(input, output, width, height),
+        }
+    }
+
+    /// GPU 2D DWT inverse transform, columns then rows like [`crate::dwt::dwt_53_inverse_2d`]
+    pub fn dwt_53_inverse_2d_wgpu(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        match &self.context {
+            Some(context) => context.run(
+                &context.inverse,
+                [Pass::Columns, Pass::Rows],
+                input,
+                output,
+                width,
+                height,
+            ),
+            None => crate::dwt::dwt_53_inverse_2d(input, output, width, height),
+        }
+    }
+}
+
+impl Default for WgpuDwt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WgpuContext {
+    /// Open a device on the first adapter matching `software`, with compiled kernels
+    fn request(software: bool) -> Option<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: software,
+            compatible_surface: None,
+        }))
+        .ok()?;
+        let info = adapter.get_info();
+        if !adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            log::debug!("wgpu DWT: {} has no compute shaders", info.name);
+            return None;
+        }
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("jpegxs-dwt"),
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            memory_hints: wgpu::MemoryHints::Performance,
+            trace: wgpu::Trace::Off,
+        }))
+        .map_err(|e| log::debug!("wgpu DWT: cannot open {}: {}", info.name, e))
+        .ok()?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("jpegxs-dwt-53"),
+            source: wgpu::ShaderSource::Wgsl(DWT_SHADER.into()),
+        });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("jpegxs-dwt-53"),
+            entries: &[
+                storage(0, true),
+                storage(1, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("jpegxs-dwt-53"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let forward = pipeline("forward");
+        let inverse = pipeline("inverse");
+
+        Some(Self {
+            device,
+            queue,
+            info,
+            bind_group_layout,
+            forward,
+            inverse,
+        })
+    }
+
+    /// Run `pipeline` over the image once per pass, input -> scratch -> output
+    fn run(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        passes: [Pass; 2],
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        let size = std::mem::size_of_val(input) as wgpu::BufferAddress;
+        let max_size = self.device.limits().max_storage_buffer_binding_size as u64;
+        if size > max_size {
+            return Err(JpegXsError::Backend(format!(
+                "{}x{} exceeds the {} byte storage buffers of {}",
+                width, height, max_size, self.info.name
+            )));
+        }
+
+        let buffer = |label, usage| {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let source = buffer(
+            "jpegxs-dwt-source",
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let scratch = buffer("jpegxs-dwt-scratch", wgpu::BufferUsages::STORAGE);
+        let result = buffer(
+            "jpegxs-dwt-result",
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let readback = buffer(
+            "jpegxs-dwt-readback",
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+        let samples: Vec<u8> = input
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        self.queue.write_buffer(&source, 0, &samples);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("jpegxs-dwt-53"),
+            });
+        for (pass, (from, to)) in passes
+            .into_iter()
+            .zip([(&source, &scratch), (&scratch, &result)])
+        {
+            let (vertical, lines) = match pass {
+                Pass::Rows => (0u32, height),
+                Pass::Columns => (1u32, width),
+            };
+            let params = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("jpegxs-dwt-params"),
+                size: 16,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let words = [width, height, vertical, 0];
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            self.queue.write_buffer(&params, 0, &bytes);
+
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("jpegxs-dwt-53"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: from.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: to.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            });
+            let mut compute = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("jpegxs-dwt-53"),
+                timestamp_writes: None,
+            });
+            compute.set_pipeline(pipeline);
+            compute.set_bind_group(0, &bind_group, &[]);
+            compute.dispatch_workgroups(lines.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&result, 0, &readback, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |mapped| {
+                let _ = sender.send(mapped);
+            });
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .map_err(|e| JpegXsError::Backend(format!("wgpu DWT: device lost: {}", e)))?;
+        receiver
+            .recv()
+            .map_err(|_| JpegXsError::Backend("wgpu DWT: readback was dropped".into()))?
+            .map_err(|e| JpegXsError::Backend(format!("wgpu DWT: readback failed: {}", e)))?;
+
+        let mapped = readback.slice(..).get_mapped_range();
+        for (sample, bytes) in output.iter_mut().zip(mapped.chunks_exact(4)) {
+            *sample = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_signal(width: u32, height: u32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| ((i * 37 + (i / 13) * 11) % 251) as f32 - 128.0)
+            .collect()
+    }
+
+    #[test]
+    #[ignore = "needs a software wgpu adapter (lavapipe or llvmpipe)"]
+    fn test_wgpu_dwt_matches_scalar() {
+        let wgpu_dwt = WgpuDwt::with_software_adapter();
+        assert!(wgpu_dwt.is_available(), "no software wgpu adapter");
+        assert!(wgpu_dwt.is_software());
+
+        for &(width, height) in &[(64u32, 32u32), (37, 19), (1, 9), (130, 1), (2, 2)] {
+            let input = test_signal(width, height);
+            let size = input.len();
+            let mut expected = vec![0.0f32; size];
+            let mut coeffs = vec![0.0f32; size];
+            crate::dwt::dwt_53_forward_2d(&input, &mut expected, width, height).unwrap();
+            wgpu_dwt
+                .dwt_53_forward_2d_wgpu(&input, &mut coeffs, width, height)
+                .unwrap();
+            assert_eq!(coeffs, expected, "{}x{} forward", width, height);
+
+            let mut reconstructed = vec![0.0f32; size];
+            crate::dwt::dwt_53_inverse_2d(&coeffs, &mut expected, width, height).unwrap();
+            wgpu_dwt
+                .dwt_53_inverse_2d_wgpu(&coeffs, &mut reconstructed, width, height)
+                .unwrap();
+            assert_eq!(reconstructed, expected, "{}x{} inverse", width, height);
+        }
+    }
+
+    #[test]
+    fn test_wgpu_dwt_fallback() {
+        let wgpu_dwt = WgpuDwt::new();
+        let input = vec![1.0, 2.0, 3.0, 4.0];
+        let mut output = vec![0.0; 4];
+
+        // Works with or without an adapter (falls back to CPU)
+        assert!(wgpu_dwt
+            .dwt_53_forward_2d_wgpu(&input, &mut output, 2, 2)
+            .is_ok());
+        assert!(wgpu_dwt
+            .dwt_53_forward_2d_wgpu(&input, &mut output, 3, 2)
+            .is_err());
+    }
+}
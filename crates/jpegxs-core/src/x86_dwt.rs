@@ -0,0 +1,746 @@
+// x86_64 SIMD-accelerated DWT implementation
+// AVX2 lifts 8 f32 samples per instruction and SSE4.1 lifts 4, chosen at runtime with
+// is_x86_feature_detected! so a single binary runs on any x86_64 server
+
+use crate::error::{JpegXsError, Result};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Instruction set extension used by [`X86Dwt`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X86SimdLevel {
+    /// 256-bit vectors, 8 samples at a time
+    Avx2,
+    /// 128-bit vectors with the SSE4.1 floor instruction, 4 samples at a time
+    Sse41,
+}
+
+/// x86_64 SIMD-accelerated 5/3 DWT
+///
+/// A signal is first split into its even (low) and odd (high) samples, then both lifting
+/// steps run on the two halves: along a row the neighbours of a sample are the adjacent
+/// entries of the other half, down a column they are the adjacent rows of the other
+/// half. Either way every load and store is contiguous. The arithmetic follows
+/// [`crate::dwt::dwt_53_forward_2d`] operation for operation, so the coefficients are
+/// bit-identical to the scalar transform.
+pub struct X86Dwt {
+    level: Option<X86SimdLevel>,
+}
+
+impl X86Dwt {
+    /// Initialize x86 SIMD acceleration with the widest extension the CPU supports
+    pub fn new() -> Self {
+        let level = Self::detect_simd_level();
+        match level {
+            Some(level) => log::info!("x86 DWT: {:?} SIMD acceleration enabled", level),
+            None => log::info!("x86 DWT: AVX2/SSE4.1 not available, falling back to scalar"),
+        }
+
+        Self { level }
+    }
+
+    /// Detect AVX2 and SSE4.1 support at runtime
+    fn detect_simd_level() -> Option<X86SimdLevel> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                Some(X86SimdLevel::Avx2)
+            } else if is_x86_feature_detected!("sse4.1") {
+                Some(X86SimdLevel::Sse41)
+            } else {
+                None
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            None
+        }
+    }
+
+    /// Check if x86 SIMD acceleration is available
+    pub fn is_available(&self) -> bool {
+        self.level.is_some()
+    }
+
+    /// Extension the transforms run on, `None` when they fall back to scalar
+    pub fn level(&self) -> Option<X86SimdLevel> {
+        self.level
+    }
+
+    /// Pin the transforms to `level`, `None` when the CPU does not support it
+    pub fn with_level(level: X86SimdLevel) -> Option<Self> {
+        Self::supports(level).then_some(Self { level: Some(level) })
+    }
+
+    /// Whether the CPU supports `level`
+    pub fn supports(level: X86SimdLevel) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        {
+            match level {
+                X86SimdLevel::Avx2 => is_x86_feature_detected!("avx2"),
+                X86SimdLevel::Sse41 => is_x86_feature_detected!("sse4.1"),
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let _ = level;
+            false
+        }
+    }
+
+    /// x86 SIMD-accelerated 2D DWT forward transform
+    pub fn dwt_53_forward_2d_x86(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let Some(level) = self.level else {
+            // Fallback to CPU implementation
+            return crate::dwt::dwt_53_forward_2d(input, output, width, height);
+        };
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        if input.is_empty() {
+            return Ok(());
+        }
+        let width = width as usize;
+
+        // Step 1: Horizontal 5/3 DWT on all rows
+        let mut rows = vec![0.0f32; input.len()];
+        for (row, split) in input.chunks_exact(width).zip(rows.chunks_exact_mut(width)) {
+            deinterleave(row, split, 1);
+            lift_forward(split, 1, level);
+        }
+
+        // Step 2: Vertical 5/3 DWT on all columns, lifting whole rows at a time
+        deinterleave(&rows, output, width);
+        lift_forward(output, width, level);
+
+        Ok(())
+    }
+
+    /// x86 SIMD-accelerated 2D DWT inverse transform
+    pub fn dwt_53_inverse_2d_x86(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let Some(level) = self.level else {
+            // Fallback to CPU implementation
+            return crate::dwt::dwt_53_inverse_2d(input, output, width, height);
+        };
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        if input.is_empty() {
+            return Ok(());
+        }
+        let width = width as usize;
+
+        // Step 1: Vertical 5/3 inverse DWT on all columns, lifting whole rows at a time
+        let mut columns = input.to_vec();
+        lift_inverse(&mut columns, width, level);
+        interleave(&columns, output, width);
+
+        // Step 2: Horizontal 5/3 inverse DWT on all rows
+        let mut split = vec![0.0f32; width];
+        for row in output.chunks_exact_mut(width) {
+            split.copy_from_slice(row);
+            lift_inverse(&mut split, 1, level);
+            interleave(&split, row, 1);
+        }
+
+        Ok(())
+    }
+
+    /// x86 SIMD-accelerated version of [`crate::dwt::dwt_53_forward_multilevel_int`],
+    /// the integer transform of the codec, with bit-identical coefficients
+    pub fn dwt_53_forward_multilevel_int_x86(
+        &self,
+        input: &[i32],
+        output: &mut [i32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        let Some(level) = self.level else {
+            return crate::dwt::dwt_53_forward_multilevel_int(
+                input, output, width, height, levels_x, levels_y,
+            );
+        };
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+
+        output.copy_from_slice(input);
+        let dims = crate::dwt::decomposition_dims(width, height, levels_x, levels_y);
+        let stride = width as usize;
+        let mut split = Vec::new();
+        for decomposition in 1..=levels_x {
+            let (w, h) = dims[decomposition as usize - 1];
+            let (w, h) = (w as usize, h as usize);
+            for row in output.chunks_mut(stride).take(h) {
+                split.resize(w, 0);
+                deinterleave(&row[..w], &mut split, 1);
+                lift_forward(&mut split, 1, level);
+                row[..w].copy_from_slice(&split);
+            }
+            if decomposition <= levels_y {
+                // Lines in Mallat order are the deinterleaved rows of the region
+                split.resize(w * h, 0);
+                for (y, line) in split.chunks_exact_mut(w).enumerate() {
+                    let source = if y < h.div_ceil(2) {
+                        2 * y
+                    } else {
+                        2 * (y - h.div_ceil(2)) + 1
+                    };
+                    line.copy_from_slice(&output[source * stride..source * stride + w]);
+                }
+                lift_forward(&mut split, w, level);
+                for (y, line) in split.chunks_exact(w).enumerate() {
+                    output[y * stride..y * stride + w].copy_from_slice(line);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// x86 SIMD-accelerated version of [`crate::dwt::dwt_53_inverse_multilevel_int`]
+    pub fn dwt_53_inverse_multilevel_int_x86(
+        &self,
+        input: &[i32],
+        output: &mut [i32],
+        width: u32,
+        height: u32,
+        levels_x: u8,
+        levels_y: u8,
+    ) -> Result<()> {
+        let Some(level) = self.level else {
+            return crate::dwt::dwt_53_inverse_multilevel_int(
+                input, output, width, height, levels_x, levels_y,
+            );
+        };
+        if input.len() != (width * height) as usize || output.len() != input.len() {
+            return Err(JpegXsError::DimensionMismatch(
+                "Invalid buffer sizes".into(),
+            ));
+        }
+        crate::dwt::validate_decomposition_levels(levels_x, levels_y)?;
+
+        output.copy_from_slice(input);
+        let dims = crate::dwt::decomposition_dims(width, height, levels_x, levels_y);
+        let stride = width as usize;
+        let mut split = Vec::new();
+        for decomposition in (1..=levels_x).rev() {
+            let (w, h) = dims[decomposition as usize - 1];
+            let (w, h) = (w as usize, h as usize);
+            if decomposition <= levels_y {
+                split.resize(w * h, 0);
+                for (y, line) in split.chunks_exact_mut(w).enumerate() {
+                    line.copy_from_slice(&output[y * stride..y * stride + w]);
+                }
+                lift_inverse(&mut split, w, level);
+                for (y, line) in split.chunks_exact(w).enumerate() {
+                    let target = if y < h.div_ceil(2) {
+                        2 * y
+                    } else {
+                        2 * (y - h.div_ceil(2)) + 1
+                    };
+                    output[target * stride..target * stride + w].copy_from_slice(line);
+                }
+            }
+            for row in output.chunks_mut(stride).take(h) {
+                split.resize(w, 0);
+                split.copy_from_slice(&row[..w]);
+                lift_inverse(&mut split, 1, level);
+                interleave(&split, &mut row[..w], 1);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for X86Dwt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Move the even samples of `signal` to the front of `split` and the odd ones behind
+/// them, each sample being a run of `run` values
+fn deinterleave<T: Copy>(signal: &[T], split: &mut [T], run: usize) {
+    let mid = (signal.len() / run).div_ceil(2) * run;
+    let (low, high) = split.split_at_mut(mid);
+    for (k, pair) in signal.chunks(2 * run).enumerate() {
+        low[k * run..(k + 1) * run].copy_from_slice(&pair[..run]);
+        if pair.len() > run {
+            high[k * run..(k + 1) * run].copy_from_slice(&pair[run..]);
+        }
+    }
+}
+
+/// Inverse of [`deinterleave`]
+fn interleave<T: Copy>(split: &[T], signal: &mut [T], run: usize) {
+    let mid = (split.len() / run).div_ceil(2) * run;
+    let (low, high) = split.split_at(mid);
+    for (k, pair) in signal.chunks_mut(2 * run).enumerate() {
+        pair[..run].copy_from_slice(&low[k * run..(k + 1) * run]);
+        if pair.len() > run {
+            pair[run..].copy_from_slice(&high[k * run..(k + 1) * run]);
+        }
+    }
+}
+
+/// Whether a lifting step adds its prediction to the samples or subtracts it
+#[derive(Clone, Copy)]
+enum Sign {
+    Add,
+    Subtract,
+}
+
+/// Lifting step applied by [`Lifting::lift`]
+#[derive(Clone, Copy)]
+enum Step {
+    /// `(a + b) / 2`, or `(a + b) >> 1` on integers
+    Predict,
+    /// `floor((a + b + 2) / 4)`, or `(a + b + 2) >> 2` on integers
+    Update,
+}
+
+/// Sample type with vectorized lifting kernels
+trait Lifting: Copy + Default {
+    /// Whether the update step mirrors the high-pass signal at its ends like the integer
+    /// transform of the standard, rather than padding it with zeros like the f32 reference
+    const MIRRORED_UPDATE: bool;
+
+    /// `samples[i] +/-= step(a[i], b[i])` on the widest vectors of `level`
+    fn lift(
+        samples: &mut [Self],
+        a: &[Self],
+        b: &[Self],
+        sign: Sign,
+        step: Step,
+        level: X86SimdLevel,
+    );
+}
+
+/// Forward 5/3 lifting of a deinterleaved signal of runs of `run` values
+fn lift_forward<T: Lifting>(split: &mut [T], run: usize, level: X86SimdLevel) {
+    if split.len() < 2 * run {
+        return;
+    }
+    // Predict step first: it reads the original even samples
+    predict(split, run, Sign::Subtract, level);
+    update(split, run, Sign::Add, level);
+}
+
+/// Inverse 5/3 lifting of a deinterleaved signal of runs of `run` values
+fn lift_inverse<T: Lifting>(split: &mut [T], run: usize, level: X86SimdLevel) {
+    if split.len() < 2 * run {
+        return;
+    }
+    // Undo the update step first: the predict step reads the restored even samples
+    update(split, run, Sign::Subtract, level);
+    predict(split, run, Sign::Add, level);
+}
+
+/// Predict step on the high half: H[k] -/+= (L[k] + L[k+1]) / 2, with the symmetric
+/// extension L[mid] = L[mid-1] for an even number of samples
+fn predict<T: Lifting>(split: &mut [T], run: usize, sign: Sign, level: X86SimdLevel) {
+    let samples = split.len() / run;
+    let (mid, half) = (samples.div_ceil(2), samples / 2);
+    let (low, high) = split.split_at_mut(mid * run);
+    let inner = half.min(mid - 1) * run;
+    T::lift(
+        &mut high[..inner],
+        &low[..inner],
+        &low[run..inner + run],
+        sign,
+        Step::Predict,
+        level,
+    );
+    if half == mid {
+        let last = &low[inner..inner + run];
+        T::lift(&mut high[inner..], last, last, sign, Step::Predict, level);
+    }
+}
+
+/// Update step on the low half: L[k] +/-= floor((H[k-1] + H[k] + 2) / 4). The missing
+/// H[-1] and, for an odd number of samples, H[half] are mirrored or 0 depending on `T`
+fn update<T: Lifting>(split: &mut [T], run: usize, sign: Sign, level: X86SimdLevel) {
+    let samples = split.len() / run;
+    let (mid, half) = (samples.div_ceil(2), samples / 2);
+    let (low, high) = split.split_at_mut(mid * run);
+    let zeros = vec![T::default(); run];
+    let first = &high[..run];
+    let before_first = if T::MIRRORED_UPDATE { first } else { &zeros };
+    T::lift(
+        &mut low[..run],
+        before_first,
+        first,
+        sign,
+        Step::Update,
+        level,
+    );
+    T::lift(
+        &mut low[run..half * run],
+        &high[..(half - 1) * run],
+        &high[run..half * run],
+        sign,
+        Step::Update,
+        level,
+    );
+    if mid > half {
+        let last = &high[(half - 1) * run..half * run];
+        let after_last = if T::MIRRORED_UPDATE { last } else { &zeros };
+        T::lift(
+            &mut low[half * run..],
+            last,
+            after_last,
+            sign,
+            Step::Update,
+            level,
+        );
+    }
+}
+
+impl Lifting for f32 {
+    const MIRRORED_UPDATE: bool = false;
+
+    fn lift(
+        samples: &mut [f32],
+        a: &[f32],
+        b: &[f32],
+        sign: Sign,
+        step: Step,
+        level: X86SimdLevel,
+    ) {
+        assert!(a.len() == samples.len() && b.len() == samples.len());
+        #[cfg(target_arch = "x86_64")]
+        {
+            // SAFETY: the extension was detected at runtime and the operands have the
+            // length of `samples`, checked above
+            unsafe {
+                match level {
+                    X86SimdLevel::Avx2 => lift_f32_avx2(samples, a, b, sign, step),
+                    X86SimdLevel::Sse41 => lift_f32_sse41(samples, a, b, sign, step),
+                }
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let _ = level;
+            lift_f32_scalar(samples, a, b, sign, step);
+        }
+    }
+}
+
+impl Lifting for i32 {
+    const MIRRORED_UPDATE: bool = true;
+
+    fn lift(
+        samples: &mut [i32],
+        a: &[i32],
+        b: &[i32],
+        sign: Sign,
+        step: Step,
+        level: X86SimdLevel,
+    ) {
+        assert!(a.len() == samples.len() && b.len() == samples.len());
+        #[cfg(target_arch = "x86_64")]
+        {
+            // SAFETY: the extension was detected at runtime and the operands have the
+            // length of `samples`, checked above
+            unsafe {
+                match level {
+                    X86SimdLevel::Avx2 => lift_i32_avx2(samples, a, b, sign, step),
+                    X86SimdLevel::Sse41 => lift_i32_sse41(samples, a, b, sign, step),
+                }
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let _ = level;
+            lift_i32_scalar(samples, a, b, sign, step);
+        }
+    }
+}
+
+/// Scalar lifting with the exact operation order of [`crate::dwt::dwt_53_forward_1d`]
+fn lift_f32_scalar(samples: &mut [f32], a: &[f32], b: &[f32], sign: Sign, step: Step) {
+    for ((sample, &a), &b) in samples.iter_mut().zip(a).zip(b) {
+        let prediction = match step {
+            Step::Predict => (a + b) / 2.0,
+            Step::Update => ((a + b + 2.0) / 4.0).floor(),
+        };
+        match sign {
+            Sign::Add => *sample += prediction,
+            Sign::Subtract => *sample -= prediction,
+        }
+    }
+}
+
+/// Scalar lifting with the `>>` rounding of [`crate::dwt::dwt_53_forward_multilevel_int`]
+fn lift_i32_scalar(samples: &mut [i32], a: &[i32], b: &[i32], sign: Sign, step: Step) {
+    for ((sample, &a), &b) in samples.iter_mut().zip(a).zip(b) {
+        let prediction = match step {
+            Step::Predict => (a + b) >> 1,
+            Step::Update => (a + b + 2) >> 2,
+        };
+        match sign {
+            Sign::Add => *sample += prediction,
+            Sign::Subtract => *sample -= prediction,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn lift_f32_avx2(samples: &mut [f32], a: &[f32], b: &[f32], sign: Sign, step: Step) {
+    let vectors = samples.len() / 8;
+    let two = _mm256_set1_ps(2.0);
+    let four = _mm256_set1_ps(4.0);
+    for i in (0..vectors * 8).step_by(8) {
+        let sum = _mm256_add_ps(
+            _mm256_loadu_ps(a.as_ptr().add(i)),
+            _mm256_loadu_ps(b.as_ptr().add(i)),
+        );
+        let prediction = match step {
+            Step::Predict => _mm256_div_ps(sum, two),
+            Step::Update => _mm256_floor_ps(_mm256_div_ps(_mm256_add_ps(sum, two), four)),
+        };
+        let sample = _mm256_loadu_ps(samples.as_ptr().add(i));
+        let lifted = match sign {
+            Sign::Add => _mm256_add_ps(sample, prediction),
+            Sign::Subtract => _mm256_sub_ps(sample, prediction),
+        };
+        _mm256_storeu_ps(samples.as_mut_ptr().add(i), lifted);
+    }
+    let tail = vectors * 8;
+    lift_f32_scalar(&mut samples[tail..], &a[tail..], &b[tail..], sign, step);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn lift_f32_sse41(samples: &mut [f32], a: &[f32], b: &[f32], sign: Sign, step: Step) {
+    let vectors = samples.len() / 4;
+    let two = _mm_set1_ps(2.0);
+    let four = _mm_set1_ps(4.0);
+    for i in (0..vectors * 4).step_by(4) {
+        let sum = _mm_add_ps(
+            _mm_loadu_ps(a.as_ptr().add(i)),
+            _mm_loadu_ps(b.as_ptr().add(i)),
+        );
+        let prediction = match step {
+            Step::Predict => _mm_div_ps(sum, two),
+            Step::Update => _mm_floor_ps(_mm_div_ps(_mm_add_ps(sum, two), four)),
+        };
+        let sample = _mm_loadu_ps(samples.as_ptr().add(i));
+        let lifted = match sign {
+            Sign::Add => _mm_add_ps(sample, prediction),
+            Sign::Subtract => _mm_sub_ps(sample, prediction),
+        };
+        _mm_storeu_ps(samples.as_mut_ptr().add(i), lifted);
+    }
+    let tail = vectors * 4;
+    lift_f32_scalar(&mut samples[tail..], &a[tail..], &b[tail..], sign, step);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn lift_i32_avx2(samples: &mut [i32], a: &[i32], b: &[i32], sign: Sign, step: Step) {
+    let vectors = samples.len() / 8;
+    let two = _mm256_set1_epi32(2);
+    for i in (0..vectors * 8).step_by(8) {
+        let sum = _mm256_add_epi32(
+            _mm256_loadu_si256(a.as_ptr().add(i).cast()),
+            _mm256_loadu_si256(b.as_ptr().add(i).cast()),
+        );
+        let prediction = match step {
+            Step::Predict => _mm256_srai_epi32::<1>(sum),
+            Step::Update => _mm256_srai_epi32::<2>(_mm256_add_epi32(sum, two)),
+        };
+        let sample = _mm256_loadu_si256(samples.as_ptr().add(i).cast());
+        let lifted = match sign {
+            Sign::Add => _mm256_add_epi32(sample, prediction),
+            Sign::Subtract => _mm256_sub_epi32(sample, prediction),
+        };
+        _mm256_storeu_si256(samples.as_mut_ptr().add(i).cast(), lifted);
+    }
+    let tail = vectors * 8;
+    lift_i32_scalar(&mut samples[tail..], &a[tail..], &b[tail..], sign, step);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn lift_i32_sse41(samples: &mut [i32], a: &[i32], b: &[i32], sign: Sign, step: Step) {
+    let vectors = samples.len() / 4;
+    let two = _mm_set1_epi32(2);
+    for i in (0..vectors * 4).step_by(4) {
+        let sum = _mm_add_epi32(
+            _mm_loadu_si128(a.as_ptr().add(i).cast()),
+            _mm_loadu_si128(b.as_ptr().add(i).cast()),
+        );
+        let prediction = match step {
+            Step::Predict => _mm_srai_epi32::<1>(sum),
+            Step::Update => _mm_srai_epi32::<2>(_mm_add_epi32(sum, two)),
+        };
+        let sample = _mm_loadu_si128(samples.as_ptr().add(i).cast());
+        let lifted = match sign {
+            Sign::Add => _mm_add_epi32(sample, prediction),
+            Sign::Subtract => _mm_sub_epi32(sample, prediction),
+        };
+        _mm_storeu_si128(samples.as_mut_ptr().add(i).cast(), lifted);
+    }
+    let tail = vectors * 4;
+    lift_i32_scalar(&mut samples[tail..], &a[tail..], &b[tail..], sign, step);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every extension this CPU supports, AVX2 included or not
+    fn supported_levels() -> Vec<X86SimdLevel> {
+        let mut levels = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                levels.push(X86SimdLevel::Avx2);
+            }
+            if is_x86_feature_detected!("sse4.1") {
+                levels.push(X86SimdLevel::Sse41);
+            }
+        }
+        levels
+    }
+
+    #[test]
+    fn test_x86_matches_scalar_bit_exactly() {
+        for level in supported_levels() {
+            let dwt = X86Dwt { level: Some(level) };
+            for &(width, height) in &[(1u32, 1u32), (2, 1), (1, 5), (8, 8), (17, 9), (64, 33)] {
+                let size = (width * height) as usize;
+                let input: Vec<f32> = (0..size)
+                    .map(|i| ((i * 37) % 251) as f32 - 128.0 + (i % 3) as f32 * 0.25)
+                    .collect();
+
+                let mut expected = vec![0.0f32; size];
+                let mut actual = vec![0.0f32; size];
+                crate::dwt::dwt_53_forward_2d(&input, &mut expected, width, height).unwrap();
+                dwt.dwt_53_forward_2d_x86(&input, &mut actual, width, height)
+                    .unwrap();
+                assert_eq!(actual, expected, "{:?} forward {}x{}", level, width, height);
+
+                crate::dwt::dwt_53_inverse_2d(&input, &mut expected, width, height).unwrap();
+                dwt.dwt_53_inverse_2d_x86(&input, &mut actual, width, height)
+                    .unwrap();
+                assert_eq!(actual, expected, "{:?} inverse {}x{}", level, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_x86_integer_transform_matches_scalar() {
+        for level in supported_levels() {
+            let dwt = X86Dwt::with_level(level).unwrap();
+            for &(width, height, levels_x, levels_y) in &[
+                (1u32, 1u32, 1u8, 0u8),
+                (2, 2, 1, 1),
+                (37, 19, 3, 3),
+                (64, 32, 5, 2),
+                (20, 9, 4, 1),
+                (33, 17, 2, 0),
+            ] {
+                let size = (width * height) as usize;
+                let input: Vec<i32> = (0..size)
+                    .map(|i| (((i * 37) % 1021) as i32 - 512) << crate::dwt::FRACTIONAL_BITS)
+                    .collect();
+
+                let mut expected = vec![0i32; size];
+                let mut actual = vec![0i32; size];
+                crate::dwt::dwt_53_forward_multilevel_int(
+                    &input,
+                    &mut expected,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+                dwt.dwt_53_forward_multilevel_int_x86(
+                    &input,
+                    &mut actual,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+                assert_eq!(actual, expected, "{:?} forward {}x{}", level, width, height);
+
+                let mut restored = vec![0i32; size];
+                dwt.dwt_53_inverse_multilevel_int_x86(
+                    &actual,
+                    &mut restored,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+                assert_eq!(restored, input, "{:?} inverse {}x{}", level, width, height);
+
+                // The inverse also matches on coefficients no forward transform produced
+                crate::dwt::dwt_53_inverse_multilevel_int(
+                    &input,
+                    &mut expected,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+                dwt.dwt_53_inverse_multilevel_int_x86(
+                    &input,
+                    &mut actual,
+                    width,
+                    height,
+                    levels_x,
+                    levels_y,
+                )
+                .unwrap();
+                assert_eq!(actual, expected, "{:?} inverse {}x{}", level, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_x86_rejects_invalid_buffers() {
+        let dwt = X86Dwt::new();
+        let input = vec![0.0f32; 63];
+        let mut output = vec![0.0f32; 64];
+        assert!(dwt
+            .dwt_53_forward_2d_x86(&input, &mut output, 8, 8)
+            .is_err());
+        assert!(dwt
+            .dwt_53_inverse_2d_x86(&input, &mut output, 8, 8)
+            .is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use jpegxs_core::{
+    accel::{AccelPreference, ACCEL_ENV_VAR},
+    decode_frame, encode_frame,
+    error::Result,
+    types::{Bitstream, DecoderConfig, EncoderConfig, ImageView8, PixelFormat},
+    x86_dwt::{X86Dwt, X86SimdLevel},
+    JpegXsError,
+};
+use std::sync::Mutex;
+
+// The tests of this file set JPEGXS_ACCEL, which every encoder and decoder of the
+// process reads, so they run one at a time
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn test_image(width: u32, height: u32) -> Vec<u8> {
+    (0..width * height * 3)
+        .map(|i| ((i * 29 + (i / 71) * 13) % 256) as u8)
+        .collect()
+}
+
+fn encode(data: &[u8], backend: AccelPreference) -> Result<Bitstream> {
+    let input = ImageView8 {
+        data,
+        width: 80,
+        height: 36,
+        format: PixelFormat::Rgb8,
+    };
+    let config = EncoderConfig {
+        decomposition_levels_x: 4,
+        decomposition_levels_y: 2,
+        backend,
+        ..Default::default()
+    };
+    encode_frame(input, &config)
+}
+
+fn decode(bitstream: &Bitstream, backend: AccelPreference) -> Result<Vec<u8>> {
+    let config = DecoderConfig {
+        backend,
+        ..Default::default()
+    };
+    Ok(decode_frame(bitstream, &config)?.data)
+}
+
+fn available_backends() -> Vec<AccelPreference> {
+    let mut backends = vec![AccelPreference::Auto, AccelPreference::Scalar];
+    if X86Dwt::supports(X86SimdLevel::Avx2) {
+        backends.push(AccelPreference::Avx2);
+    }
+    if X86Dwt::supports(X86SimdLevel::Sse41) {
+        backends.push(AccelPreference::Sse41);
+    }
+    backends
+}
+
+#[test]
+fn test_every_backend_codes_identically() -> Result<()> {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let data = test_image(80, 36);
+
+    let reference = encode(&data, AccelPreference::Scalar)?;
+    let decoded = decode(&reference, AccelPreference::Scalar)?;
+    for backend in available_backends() {
+        let bitstream = encode(&data, backend)?;
+        assert_eq!(bitstream.data, reference.data, "{} encoder", backend);
+        assert_eq!(decode(&reference, backend)?, decoded, "{} decoder", backend);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unavailable_backend_is_an_error() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let data = test_image(80, 36);
+
+    // NEON and GPU have no integer transform for the codec, so they cannot be configured
+    for name in ["neon", "gpu"] {
+        let error = name.parse::<AccelPreference>().unwrap_err();
+        assert!(
+            matches!(error, JpegXsError::InvalidParameter(_)),
+            "{:?}",
+            error
+        );
+        assert!(error.to_string().contains("sse4.1"), "{}", error);
+    }
+    for (backend, level) in [
+        (AccelPreference::Avx2, X86SimdLevel::Avx2),
+        (AccelPreference::Sse41, X86SimdLevel::Sse41),
+    ] {
+        if !X86Dwt::supports(level) {
+            let error = encode(&data, backend).unwrap_err().to_string();
+            assert!(error.contains("not available"), "{}", error);
+        }
+    }
+}
+
+#[test]
+fn test_environment_overrides_the_config() -> Result<()> {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let data = test_image(80, 36);
+    let reference = encode(&data, AccelPreference::Scalar)?;
+
+    std::env::set_var(ACCEL_ENV_VAR, "gpu");
+    let unsupported = encode(&data, AccelPreference::Scalar);
+    std::env::set_var(ACCEL_ENV_VAR, "warp-drive");
+    let unknown = decode(&reference, AccelPreference::Auto);
+    std::env::set_var(ACCEL_ENV_VAR, "SCALAR");
+    let pinned_scalar = encode(&data, AccelPreference::Avx2);
+    std::env::remove_var(ACCEL_ENV_VAR);
+
+    assert!(matches!(unsupported, Err(JpegXsError::InvalidParameter(_))));
+    let error = unknown.unwrap_err();
+    assert!(
+        matches!(error, JpegXsError::InvalidParameter(_)),
+        "{:?}",
+        error
+    );
+    assert!(error.to_string().contains(ACCEL_ENV_VAR), "{}", error);
+    assert_eq!(pinned_scalar?.data, reference.data);
+    Ok(())
+}
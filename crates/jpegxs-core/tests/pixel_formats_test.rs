@@ -1,7 +1,8 @@
 use anyhow::Result;
 use jpegxs_core::{
-    decode_frame_to_format, encode_frame,
-    types::{DecoderConfig, EncoderConfig, ImageView8, PixelFormat},
+    decode_frame, decode_frame16_to_format, decode_frame_to_format, encode_frame, encode_frame16,
+    probe_frame,
+    types::{DecoderConfig, EncoderConfig, ImageView16, ImageView8, PixelFormat, Profile},
 };
 
 fn create_test_data(format: PixelFormat, width: u32, height: u32) -> Vec<u8> {
@@ -98,9 +99,64 @@ fn create_test_data(format: PixelFormat, width: u32, height: u32) -> Vec<u8> {
             }
             data
         }
+        PixelFormat::Gray8 => {
+            // Single plane
+            (0..pixel_count).map(|i| (i % 256) as u8).collect()
+        }
+        PixelFormat::Rgba8 => {
+            // RGBA interleaved, alpha ramping across the rows
+            let mut data = Vec::with_capacity(pixel_count * 4);
+            for i in 0..pixel_count {
+                data.push((i % 256) as u8); // R
+                data.push(((i + 85) % 256) as u8); // G
+                data.push(((i + 170) % 256) as u8); // B
+                data.push((i / width as usize * 255 / height as usize) as u8); // A
+            }
+            data
+        }
+        PixelFormat::Yuva444p8 => {
+            // Y, U, V planes followed by the alpha plane
+            let mut data = create_test_data(PixelFormat::Yuv444p8, width, height);
+            data.extend(
+                (0..pixel_count).map(|i| (i / width as usize * 255 / height as usize) as u8),
+            );
+            data
+        }
+        PixelFormat::Yuv422p10le
+        | PixelFormat::Yuv444p12le
+        | PixelFormat::Rgb16
+        | PixelFormat::Gray16 => {
+            panic!("{:?} is not an 8-bit format", format)
+        }
     }
 }
 
+/// Smooth test pattern spanning the full range of a high bit depth format
+fn create_test_data16(format: PixelFormat, width: u32, height: u32) -> Vec<u16> {
+    let max_value = (1u32 << format.bit_depth()) - 1;
+    let (sx, _) = format.chroma_sampling();
+    let chroma_count = (width / sx as u32 * height) as usize;
+    let sample_count =
+        (width * height) as usize + (format.num_components() as usize - 1) * chroma_count;
+    (0..sample_count)
+        .map(|i| {
+            let (x, y) = (i as u32 % width, i as u32 / width % height);
+            ((x + 2 * y) * max_value / (width + 2 * height)) as u16
+        })
+        .collect()
+}
+
+fn calculate_psnr16(original: &[u16], reconstructed: &[u16], bit_depth: u8) -> f64 {
+    let mse = original
+        .iter()
+        .zip(reconstructed)
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum::<f64>()
+        / original.len() as f64;
+    let peak = ((1u32 << bit_depth) - 1) as f64;
+    20.0 * peak.log10() - 10.0 * mse.log10()
+}
+
 fn calculate_psnr(original: &[u8], reconstructed: &[u8]) -> f64 {
     if original.len() != reconstructed.len() {
         return 0.0;
@@ -185,7 +241,7 @@ fn test_yuv422p8_encoding() -> Result<()> {
     assert_eq!(output.height, height);
     assert_eq!(output.format, PixelFormat::Yuv422p8);
 
-    // Check PSNR (slightly lower due to upsampling/downsampling)
+    // Check PSNR (chroma is coded at its native 4:2:2 resolution)
     let psnr = calculate_psnr(&data, &output.data);
     assert!(psnr > 8.0, "PSNR too low for YUV422p8: {}", psnr);
 
@@ -206,7 +262,8 @@ fn test_yuv420p8_encoding() -> Result<()> {
     };
 
     let config = EncoderConfig {
-        quality: 0.98, // Very high quality for better PSNR
+        quality: 0.98,                  // Very high quality for better PSNR
+        profile: Profile::Unrestricted, // No profile carries 4:2:0 chroma
         ..Default::default()
     };
     let bitstream = encode_frame(input, &config)?;
@@ -218,7 +275,7 @@ fn test_yuv420p8_encoding() -> Result<()> {
     assert_eq!(output.height, height);
     assert_eq!(output.format, PixelFormat::Yuv420p8);
 
-    // Check PSNR (lower due to significant subsampling)
+    // Check PSNR (chroma is coded at its native 4:2:0 resolution)
     let psnr = calculate_psnr(&data, &output.data);
     assert!(
         psnr > 11.0,
@@ -476,3 +533,199 @@ fn test_cross_format_decoding() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_high_bit_depth_roundtrip() -> Result<()> {
+    let width = 64;
+    let height = 32;
+
+    for format in [
+        PixelFormat::Yuv422p10le,
+        PixelFormat::Yuv444p12le,
+        PixelFormat::Rgb16,
+        PixelFormat::Gray16,
+    ] {
+        let data = create_test_data16(format, width, height);
+        let input = ImageView16 {
+            data: &data,
+            width,
+            height,
+            format,
+        };
+        let config = EncoderConfig {
+            quality: 0.98,
+            profile: Profile::Unrestricted,
+            ..Default::default()
+        };
+        let bitstream = encode_frame16(input, &config)?;
+
+        let output = decode_frame16_to_format(&bitstream, &DecoderConfig::default(), format)?;
+        assert_eq!(output.format, format);
+        assert_eq!(output.data.len(), data.len());
+
+        // The full sample range survives: no clamping to 8 bits on the way
+        let max_value = (1u32 << format.bit_depth()) - 1;
+        assert!(output.data.iter().all(|&sample| sample as u32 <= max_value));
+        assert!(output.data.iter().any(|&sample| sample > 255));
+
+        let psnr = calculate_psnr16(&data, &output.data, format.bit_depth());
+        assert!(psnr > 50.0, "PSNR too low for {:?}: {}", format, psnr);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_high_bit_depth_format_conversion() -> Result<()> {
+    let width = 64;
+    let height = 32;
+    let data = create_test_data16(PixelFormat::Yuv422p10le, width, height);
+    let input = ImageView16 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Yuv422p10le,
+    };
+    let bitstream = encode_frame16(input, &EncoderConfig::default())?;
+    let decoder_config = DecoderConfig::default();
+
+    // 10-bit 4:2:2 to 12-bit 4:4:4: samples scaled by 4, chroma duplicated
+    let yuv444 = decode_frame16_to_format(&bitstream, &decoder_config, PixelFormat::Yuv444p12le)?;
+    assert_eq!(yuv444.data.len(), (width * height * 3) as usize);
+
+    // 8-bit output rounds the samples down to 8 bits
+    let yuv422 = decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Yuv422p8)?;
+    let expected: Vec<u8> = data.iter().map(|&sample| (sample / 4) as u8).collect();
+    let psnr = calculate_psnr(&expected, &yuv422.data);
+    assert!(psnr > 40.0, "PSNR too low for 8-bit output: {}", psnr);
+
+    // YUV is not turned into 16-bit RGB
+    assert!(decode_frame16_to_format(&bitstream, &decoder_config, PixelFormat::Rgb16).is_err());
+    assert!(decode_frame16_to_format(&bitstream, &decoder_config, PixelFormat::Yuv422p8).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_high_bit_depth_input_validation() {
+    let width = 16;
+    let height = 16;
+    let mut data = create_test_data16(PixelFormat::Yuv422p10le, width, height);
+    let config = EncoderConfig::default();
+
+    // 8-bit formats go through encode_frame
+    let input = ImageView16 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Yuv422p8,
+    };
+    assert!(encode_frame16(input, &config).is_err());
+
+    // Samples beyond 10 bits are rejected rather than wrapped
+    data[5] = 1024;
+    let input = ImageView16 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Yuv422p10le,
+    };
+    assert!(encode_frame16(input, &config).is_err());
+}
+
+#[test]
+fn test_component_count_roundtrip() -> Result<()> {
+    let width = 64;
+    let height = 32;
+    let config = EncoderConfig {
+        quality: 0.98,
+        ..Default::default()
+    };
+    let decoder_config = DecoderConfig::default();
+
+    for (format, num_components) in [
+        (PixelFormat::Gray8, 1),
+        (PixelFormat::Rgba8, 4),
+        (PixelFormat::Yuva444p8, 4),
+    ] {
+        let data = create_test_data(format, width, height);
+        let input = ImageView8 {
+            data: &data,
+            width,
+            height,
+            format,
+        };
+        let bitstream = encode_frame(input, &config)?;
+
+        // The decoder reports the coded components and their pixel format
+        let info = probe_frame(&bitstream)?;
+        assert_eq!(info.num_components, num_components);
+        assert_eq!(info.sampling, vec![(1, 1); num_components as usize]);
+        assert_eq!(info.color_transform, format == PixelFormat::Rgba8);
+        assert_eq!(info.format, Some(format));
+
+        let output = decode_frame(&bitstream, &decoder_config)?;
+        assert_eq!(output.format, format);
+        assert_eq!(output.data.len(), data.len());
+
+        let psnr = calculate_psnr(&data, &output.data);
+        assert!(psnr > 30.0, "PSNR too low for {:?}: {}", format, psnr);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_component_count_conversions() -> Result<()> {
+    let width = 32;
+    let height = 16;
+    let pixel_count = (width * height) as usize;
+    let decoder_config = DecoderConfig::default();
+
+    // Decoding RGBA to RGB drops the alpha component
+    let data = create_test_data(PixelFormat::Rgba8, width, height);
+    let input = ImageView8 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+    };
+    let bitstream = encode_frame(input, &EncoderConfig::default())?;
+    let rgba = decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Rgba8)?;
+    let rgb = decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Rgb8)?;
+    let without_alpha: Vec<u8> = rgba
+        .data
+        .chunks_exact(4)
+        .flat_map(|pixel| pixel[..3].to_vec())
+        .collect();
+    assert_eq!(rgb.data, without_alpha);
+
+    // YUVA output of an RGBA stream keeps the alpha plane as coded
+    let yuva = decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Yuva444p8)?;
+    let alpha: Vec<u8> = rgba.data.chunks_exact(4).map(|pixel| pixel[3]).collect();
+    assert_eq!(&yuva.data[pixel_count * 3..], alpha.as_slice());
+
+    // Gray streams have no colour, three-component streams no alpha
+    let data = create_test_data(PixelFormat::Gray8, width, height);
+    let input = ImageView8 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Gray8,
+    };
+    let bitstream = encode_frame(input, &EncoderConfig::default())?;
+    assert!(decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Rgb8).is_err());
+
+    let data = create_test_data(PixelFormat::Yuv444p8, width, height);
+    let input = ImageView8 {
+        data: &data,
+        width,
+        height,
+        format: PixelFormat::Yuv444p8,
+    };
+    let bitstream = encode_frame(input, &EncoderConfig::default())?;
+    assert!(decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Rgba8).is_err());
+    assert!(decode_frame_to_format(&bitstream, &decoder_config, PixelFormat::Gray8).is_err());
+
+    Ok(())
+}
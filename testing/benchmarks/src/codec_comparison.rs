@@ -413,6 +413,7 @@ fn encode_jpegxs(img: &DynamicImage, quality: f32) -> Result<Vec<u8>> {
         quality,
         profile: jpegxs_core::types::Profile::Main,
         level: jpegxs_core::types::Level::Level1,
+        ..Default::default()
     };
 
     let bitstream = jpegxs_core::encode_frame(image_view, &config)?;
@@ -425,7 +426,10 @@ fn decode_jpegxs(data: &[u8]) -> Result<DynamicImage> {
         size_bits: data.len() * 8,
     };
 
-    let config = jpegxs_core::types::DecoderConfig { strict_mode: false };
+    let config = jpegxs_core::types::DecoderConfig {
+        strict_mode: false,
+        ..Default::default()
+    };
     let decoded = jpegxs_core::decode_frame(&bitstream, &config)?;
 
     // Convert YUV back to RGB
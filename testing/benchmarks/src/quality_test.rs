@@ -105,8 +105,7 @@ fn test_quality_level(
     original_size_kb: f64,
 ) -> Result<QualityTestResult> {
     // Get quantization parameter that would be used
-    let qps = jpegxs_core::quant::compute_quantization_parameters(quality)?;
-    let qp = qps[0];
+    let qp = jpegxs_core::quant::quality_to_qp(quality)?;
 
     // Encode with this quality
     let encode_start = Instant::now();
@@ -153,6 +152,7 @@ fn encode_jpegxs(img: &DynamicImage, quality: f32) -> Result<Vec<u8>> {
         quality,
         profile: jpegxs_core::types::Profile::Main,
         level: jpegxs_core::types::Level::Level1,
+        ..Default::default()
     };
 
     let bitstream = jpegxs_core::encode_frame(image_view, &config)?;
@@ -165,7 +165,10 @@ fn decode_jpegxs(data: &[u8]) -> Result<DynamicImage> {
         size_bits: data.len() * 8,
     };
 
-    let config = jpegxs_core::types::DecoderConfig { strict_mode: false };
+    let config = jpegxs_core::types::DecoderConfig {
+        strict_mode: false,
+        ..Default::default()
+    };
     let decoded = jpegxs_core::decode_frame(&bitstream, &config)?;
 
     // Convert YUV back to RGB